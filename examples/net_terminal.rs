@@ -0,0 +1,236 @@
+//! Line editing on a terminal implemented by the application,
+//! over the byte streams of a telnet connection (any `Read` and `Write` would do):
+//! `cargo run --example net_terminal` then `telnet localhost 2324`.
+extern crate rustyline;
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use rustyline::error::ReadlineError;
+use rustyline::highlight::ColorDepth;
+use rustyline::preprocess::{InputPreprocessor, TelnetFilter};
+use rustyline::remote::WindowSize;
+use rustyline::{decode_keys, Config, CursorShape, Editor, Key, KeyPress, RawMode, RawReader, Term};
+
+// IAC WILL ECHO, IAC WILL SUPPRESS-GO-AHEAD (character mode), IAC DO NAWS (window size)
+const NEGOTIATION: &'static [u8] = b"\xff\xfb\x01\xff\xfb\x03\xff\xfd\x1f";
+
+/// Input of the client: the keys decoded but not read yet are kept for the next line.
+struct NetInput<R> {
+    stream: R,
+    filter: TelnetFilter,
+    keys: VecDeque<KeyPress>,
+}
+
+impl<R: Read> NetInput<R> {
+    /// Decode the keys of the next chunk of bytes received.
+    fn fill(&mut self) -> rustyline::Result<()> {
+        let mut buf = [0; 256];
+        let mut bytes = Vec::new();
+        while bytes.is_empty() {
+            let n = try!(self.stream.read(&mut buf));
+            if n == 0 {
+                return Err(ReadlineError::Eof);
+            }
+            self.filter.process(&buf[..n], &mut bytes);
+        }
+        // the escape sequences sent in one packet are decoded together
+        self.keys.extend(decode_keys(&bytes));
+        Ok(())
+    }
+}
+
+struct NetReader<R>(Rc<RefCell<NetInput<R>>>);
+
+impl<R: Read> RawReader for NetReader<R> {
+    fn next_key(&mut self, _: i32) -> rustyline::Result<KeyPress> {
+        let mut input = self.0.borrow_mut();
+        while input.keys.is_empty() {
+            try!(input.fill());
+        }
+        Ok(input.keys.pop_front().unwrap())
+    }
+
+    fn next_char(&mut self) -> rustyline::Result<char> {
+        let key = try!(self.next_key(0));
+        Ok(match key.key {
+            Key::Char(c) if key.ctrl => ((c as u8) & 0x1f) as char,
+            Key::Char(c) => c,
+            Key::Enter => '\r',
+            Key::Tab => '\t',
+            Key::Esc => '\x1b',
+            _ => '\0',
+        })
+    }
+
+    /// Only the keys already received are available: the stream is not polled.
+    fn poll(&mut self, timeout_ms: i32) -> rustyline::Result<bool> {
+        if !self.0.borrow().keys.is_empty() {
+            return Ok(true);
+        }
+        thread::sleep(Duration::from_millis(cmp::max(0, timeout_ms) as u64));
+        Ok(false)
+    }
+}
+
+/// The client terminal is in character mode during the whole session.
+struct NetMode;
+
+impl RawMode for NetMode {
+    fn disable_raw_mode(&self) -> rustyline::Result<()> {
+        Ok(())
+    }
+}
+
+/// Output of the client, which has no line discipline:
+/// a line feed also returns the cursor to the first column.
+struct NetWriter<W>(Rc<RefCell<W>>);
+
+impl<W: Write> Write for NetWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut output = self.0.borrow_mut();
+        for (i, line) in buf.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                try!(output.write_all(b"\r\n"));
+            }
+            try!(output.write_all(line));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Terminal of a telnet client, resized by its NAWS subnegotiations
+/// (its clones share the streams).
+struct NetTerminal<R, W> {
+    input: Rc<RefCell<NetInput<R>>>,
+    output: Rc<RefCell<W>>,
+    size: WindowSize,
+}
+
+impl<R: Read, W: Write> NetTerminal<R, W> {
+    fn new(input: R, output: W) -> NetTerminal<R, W> {
+        let size = WindowSize::default();
+        NetTerminal {
+            input: Rc::new(RefCell::new(NetInput {
+                stream: input,
+                filter: TelnetFilter::with_window_size(size.clone()),
+                keys: VecDeque::new(),
+            })),
+            output: Rc::new(RefCell::new(output)),
+            size: size,
+        }
+    }
+}
+
+impl<R, W> Clone for NetTerminal<R, W> {
+    fn clone(&self) -> NetTerminal<R, W> {
+        NetTerminal {
+            input: self.input.clone(),
+            output: self.output.clone(),
+            size: self.size.clone(),
+        }
+    }
+}
+
+impl<R: Read, W: Write + 'static> Term for NetTerminal<R, W> {
+    type Reader = NetReader<R>;
+    type Mode = NetMode;
+
+    fn is_unsupported(&self) -> bool {
+        false
+    }
+
+    fn is_stdin_tty(&self) -> bool {
+        true
+    }
+
+    fn colors_enabled(&self) -> bool {
+        true
+    }
+
+    fn color_depth(&self) -> ColorDepth {
+        ColorDepth::Ansi16
+    }
+
+    fn get_columns(&self) -> usize {
+        self.size.get().0
+    }
+
+    fn get_rows(&self) -> usize {
+        self.size.get().1
+    }
+
+    fn sigwinch(&self) -> bool {
+        self.size.take_changed()
+    }
+
+    fn enable_raw_mode(&self) -> rustyline::Result<NetMode> {
+        Ok(NetMode)
+    }
+
+    fn create_reader(&self) -> rustyline::Result<NetReader<R>> {
+        Ok(NetReader(self.input.clone()))
+    }
+
+    fn create_writer(&self) -> Box<Write> {
+        Box::new(NetWriter(self.output.clone()))
+    }
+
+    fn clear_screen(&mut self, w: &mut Write) -> rustyline::Result<()> {
+        try!(w.write_all(b"\x1b[H\x1b[2J"));
+        try!(w.flush());
+        Ok(())
+    }
+
+    fn enter_alternate_screen(&mut self, _: &mut Write) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn leave_alternate_screen(&mut self, _: &mut Write) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn set_cursor_shape(&self, w: &mut Write, shape: CursorShape) -> rustyline::Result<()> {
+        try!(write!(w, "\x1b[{} q", shape as u8));
+        try!(w.flush());
+        Ok(())
+    }
+}
+
+fn serve(mut stream: TcpStream) -> rustyline::Result<()> {
+    try!(stream.write_all(NEGOTIATION));
+    let input = try!(stream.try_clone());
+    let term = NetTerminal::new(input, stream);
+    let mut rl = Editor::<()>::with_terminal(Config::default(), term);
+    loop {
+        match rl.readline("net> ") {
+            Ok(line) => {
+                rl.add_history_entry(line.as_ref());
+                println!("Line: {}", line);
+            }
+            Err(ReadlineError::Interrupted) |
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn main() {
+    let listener = TcpListener::bind("127.0.0.1:2324").unwrap();
+    for stream in listener.incoming() {
+        let stream = stream.unwrap();
+        thread::spawn(move || if let Err(err) = serve(stream) {
+            println!("Error: {:?}", err);
+        });
+    }
+}
@@ -0,0 +1,218 @@
+//! Line editing for the clients of a telnet server, on a terminal reading the socket
+//! with a time out so that the keys can be waited for (idle hook, pasted text):
+//! `cargo run --example telnet_server` then `telnet localhost 2323`.
+extern crate rustyline;
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use rustyline::error::ReadlineError;
+use rustyline::highlight::ColorDepth;
+use rustyline::preprocess::{InputPreprocessor, TelnetFilter};
+use rustyline::remote::WindowSize;
+use rustyline::{decode_keys, Config, CursorShape, Editor, KeyPress, RawMode, RawReader, Term};
+
+// IAC WILL ECHO, IAC WILL SUPPRESS-GO-AHEAD (character mode), IAC DO NAWS (window size)
+const NEGOTIATION: &'static [u8] = b"\xff\xfb\x01\xff\xfb\x03\xff\xfd\x1f";
+
+/// Socket of a client, with the keys received but not read yet.
+struct Connection {
+    stream: TcpStream,
+    filter: TelnetFilter,
+    keys: VecDeque<KeyPress>,
+}
+
+impl Connection {
+    /// Wait at most `timeout_ms` (forever when negative) for the next packet
+    /// and decode its keys (`false` when the time ran out).
+    fn receive(&mut self, timeout_ms: i32) -> rustyline::Result<bool> {
+        // a zero time out is rejected by the socket
+        let timeout = if timeout_ms < 0 {
+            None
+        } else {
+            Some(Duration::from_millis(cmp::max(1, timeout_ms) as u64))
+        };
+        try!(self.stream.set_read_timeout(timeout));
+        let mut buf = [0; 256];
+        let n = match self.stream.read(&mut buf) {
+            Ok(0) => return Err(ReadlineError::Eof),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock ||
+                          e.kind() == io::ErrorKind::TimedOut => return Ok(false),
+            Err(e) => return Err(ReadlineError::Io(e)),
+        };
+        let mut bytes = Vec::new();
+        self.filter.process(&buf[..n], &mut bytes);
+        // the escape sequences sent in one packet are decoded together
+        self.keys.extend(decode_keys(&bytes));
+        Ok(true)
+    }
+}
+
+struct TelnetReader(Rc<RefCell<Connection>>);
+
+impl RawReader for TelnetReader {
+    fn next_key(&mut self, _: i32) -> rustyline::Result<KeyPress> {
+        let mut connection = self.0.borrow_mut();
+        while connection.keys.is_empty() {
+            try!(connection.receive(-1));
+        }
+        Ok(connection.keys.pop_front().unwrap())
+    }
+
+    fn poll(&mut self, timeout_ms: i32) -> rustyline::Result<bool> {
+        let mut connection = self.0.borrow_mut();
+        if connection.keys.is_empty() {
+            try!(connection.receive(timeout_ms));
+        }
+        Ok(!connection.keys.is_empty())
+    }
+}
+
+/// The client terminal is in character mode during the whole session.
+struct TelnetMode;
+
+impl RawMode for TelnetMode {
+    fn disable_raw_mode(&self) -> rustyline::Result<()> {
+        Ok(())
+    }
+}
+
+/// Output of the client, which has no line discipline:
+/// a line feed also returns the cursor to the first column.
+struct TelnetWriter(Rc<RefCell<Connection>>);
+
+impl Write for TelnetWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut connection = self.0.borrow_mut();
+        for (i, line) in buf.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                try!(connection.stream.write_all(b"\r\n"));
+            }
+            try!(connection.stream.write_all(line));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().stream.flush()
+    }
+}
+
+/// Terminal of a telnet client, resized by its NAWS subnegotiations.
+#[derive(Clone)]
+struct TelnetTerminal {
+    connection: Rc<RefCell<Connection>>,
+    size: WindowSize,
+}
+
+impl TelnetTerminal {
+    fn new(stream: TcpStream) -> TelnetTerminal {
+        let size = WindowSize::default();
+        TelnetTerminal {
+            connection: Rc::new(RefCell::new(Connection {
+                stream: stream,
+                filter: TelnetFilter::with_window_size(size.clone()),
+                keys: VecDeque::new(),
+            })),
+            size: size,
+        }
+    }
+}
+
+impl Term for TelnetTerminal {
+    type Reader = TelnetReader;
+    type Mode = TelnetMode;
+
+    fn is_unsupported(&self) -> bool {
+        false
+    }
+
+    fn is_stdin_tty(&self) -> bool {
+        true
+    }
+
+    fn colors_enabled(&self) -> bool {
+        true
+    }
+
+    fn color_depth(&self) -> ColorDepth {
+        ColorDepth::Ansi16
+    }
+
+    fn get_columns(&self) -> usize {
+        self.size.get().0
+    }
+
+    fn get_rows(&self) -> usize {
+        self.size.get().1
+    }
+
+    fn sigwinch(&self) -> bool {
+        self.size.take_changed()
+    }
+
+    fn enable_raw_mode(&self) -> rustyline::Result<TelnetMode> {
+        Ok(TelnetMode)
+    }
+
+    fn create_reader(&self) -> rustyline::Result<TelnetReader> {
+        Ok(TelnetReader(self.connection.clone()))
+    }
+
+    fn create_writer(&self) -> Box<Write> {
+        Box::new(TelnetWriter(self.connection.clone()))
+    }
+
+    fn clear_screen(&mut self, w: &mut Write) -> rustyline::Result<()> {
+        try!(w.write_all(b"\x1b[H\x1b[2J"));
+        try!(w.flush());
+        Ok(())
+    }
+
+    fn enter_alternate_screen(&mut self, _: &mut Write) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn leave_alternate_screen(&mut self, _: &mut Write) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn set_cursor_shape(&self, w: &mut Write, shape: CursorShape) -> rustyline::Result<()> {
+        try!(write!(w, "\x1b[{} q", shape as u8));
+        try!(w.flush());
+        Ok(())
+    }
+}
+
+fn serve(mut stream: TcpStream) -> rustyline::Result<()> {
+    try!(stream.write_all(NEGOTIATION));
+    let mut rl = Editor::<()>::with_terminal(Config::default(), TelnetTerminal::new(stream));
+    loop {
+        match rl.readline("remote> ") {
+            Ok(line) => {
+                rl.add_history_entry(line.as_ref());
+                println!("Line: {}", line);
+            }
+            Err(ReadlineError::Interrupted) |
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn main() {
+    let listener = TcpListener::bind("127.0.0.1:2323").unwrap();
+    for stream in listener.incoming() {
+        let stream = stream.unwrap();
+        thread::spawn(move || if let Err(err) = serve(stream) {
+            println!("Error: {:?}", err);
+        });
+    }
+}
@@ -0,0 +1,11 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rustyline;
+
+// `cargo fuzz run decode_keys`: the escape sequence parser must neither panic nor hang,
+// and each key consumes at least one byte.
+fuzz_target!(|data: &[u8]| {
+    let keys = rustyline::decode_keys(data);
+    assert!(keys.len() <= data.len());
+});
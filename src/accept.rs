@@ -0,0 +1,58 @@
+//! Hooks called when a line is accepted
+//! (to transform it, or to veto it and keep editing)
+
+/// Outcome of `AcceptHook::before_accept`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Acceptance {
+    /// Return the line as is
+    Accept,
+    /// Return this text instead of the line
+    Replace(String),
+    /// Keep editing the line, with an optional message displayed below it
+    /// until the next key is pressed
+    Reject(Option<String>),
+}
+
+/// Hooks called when the user accepts the line.
+pub trait AcceptHook {
+    /// Called before the `line` is returned by an interactive edition
+    /// (the rows of a line continued with a backslash joined by newlines).
+    /// By default, the line is accepted.
+    fn before_accept(&self, line: &str) -> Acceptance {
+        let _ = line;
+        Acceptance::Accept
+    }
+    /// Called with the line finally returned (by any kind of input).
+    fn after_accept(&self, line: &str) {
+        let _ = line;
+    }
+}
+
+impl AcceptHook for () {}
+
+/// Remove the whitespaces at the end of the accepted lines.
+#[derive(Debug, Default)]
+pub struct TrimTrailingWhitespace;
+
+impl AcceptHook for TrimTrailingWhitespace {
+    fn before_accept(&self, line: &str) -> Acceptance {
+        let trimmed = line.trim_right();
+        if trimmed.len() == line.len() {
+            Acceptance::Accept
+        } else {
+            Acceptance::Replace(String::from(trimmed))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AcceptHook, Acceptance, TrimTrailingWhitespace};
+
+    #[test]
+    fn trim_trailing_whitespace() {
+        let hook = TrimTrailingWhitespace;
+        assert_eq!(Acceptance::Accept, hook.before_accept("ls -l"));
+        assert_eq!(Acceptance::Replace(String::from(" ls")), hook.before_accept(" ls \t "));
+    }
+}
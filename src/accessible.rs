@@ -0,0 +1,209 @@
+//! Linear rendering for screen readers (see `Config::accessible`):
+//! the changes of the line are echoed like on a teletype, with backspaces
+//! instead of cursor movements, so that they are read in order.
+use std::cmp;
+use unicode_width::UnicodeWidthStr;
+
+/// Environment variable enabling the accessible rendering (when set and not empty).
+pub const ACCESSIBLE_ENV_VAR: &'static str = "RUSTYLINE_ACCESSIBLE";
+
+/// What has been echoed of the current line.
+#[derive(Debug)]
+pub struct Echo {
+    prompt: Option<String>, // None when nothing has been echoed on the current row
+    line: String,
+    pos: usize, // position of the cursor in `line`
+    below: Vec<String>, // rows already announced below the line
+}
+
+impl Echo {
+    pub fn new() -> Echo {
+        Echo {
+            prompt: None,
+            line: String::new(),
+            pos: 0,
+            below: Vec::new(),
+        }
+    }
+
+    /// Forget what has been echoed: the next update starts on the current row.
+    pub fn reset(&mut self) {
+        self.prompt = None;
+        self.below.clear();
+    }
+
+    /// Append to `ab` what turns the echoed line into `prompt` and `line` (cursor at `pos`).
+    /// The rows of `below` which have not been announced yet are printed first,
+    /// one per line, and then the whole line again.
+    pub fn update(&mut self,
+                  ab: &mut String,
+                  prompt: &str,
+                  line: &str,
+                  pos: usize,
+                  below: &[String]) {
+        let announced: Vec<&String> =
+            below.iter().filter(|row| !self.below.contains(row)).collect();
+        let reprint = match self.prompt {
+            // the rows below are announced by the next update
+            None => {
+                self.echo_line(ab, prompt, line, pos);
+                return;
+            }
+            Some(ref echoed) => echoed != prompt || !announced.is_empty(),
+        };
+        self.below = below.to_vec();
+        if !reprint && self.echo_changes(ab, line, pos) {
+            return;
+        }
+        // leave the echoed line at its end
+        ab.push_str(&self.line[self.pos..]);
+        for row in announced {
+            ab.push_str("\r\n");
+            ab.push_str(row);
+        }
+        ab.push_str("\r\n");
+        self.echo_line(ab, prompt, line, pos);
+    }
+
+    /// Echo the whole line on the current row.
+    fn echo_line(&mut self, ab: &mut String, prompt: &str, line: &str, pos: usize) {
+        ab.push_str(prompt);
+        ab.push_str(line);
+        self.prompt = Some(String::from(prompt));
+        self.line = String::from(line);
+        self.pos = line.len();
+        if !line[pos..].contains('\n') {
+            backspace(ab, &line[pos..]);
+            self.pos = pos;
+        }
+    }
+
+    /// Echo only the changed end of the line, or move the cursor over the unchanged line.
+    /// Return `false` when the line must be reprinted (the changes span several rows).
+    fn echo_changes(&mut self, ab: &mut String, line: &str, pos: usize) -> bool {
+        let start = if self.line == line {
+            pos
+        } else {
+            common_prefix_len(&self.line, line)
+        };
+        let moved_over = if self.pos > start {
+            &self.line[start..self.pos]
+        } else {
+            ""
+        };
+        let changed = self.line != line;
+        if moved_over.contains('\n') ||
+           (changed && (self.line[start..].contains('\n') || line[pos..].contains('\n'))) {
+            return false;
+        }
+        if self.pos > start {
+            backspace(ab, &self.line[start..self.pos]);
+        } else {
+            ab.push_str(&self.line[self.pos..start]);
+        }
+        if changed {
+            // erase the old end of the line
+            let width = self.line[start..].width();
+            for _ in 0..width {
+                ab.push(' ');
+            }
+            for _ in 0..width {
+                ab.push('\x08');
+            }
+            ab.push_str(&line[start..]);
+            backspace(ab, &line[pos..]);
+            self.line = String::from(line);
+        }
+        self.pos = pos;
+        true
+    }
+}
+
+/// Move the cursor back over `s`.
+fn backspace(ab: &mut String, s: &str) {
+    for _ in 0..s.width() {
+        ab.push('\x08');
+    }
+}
+
+/// Length of the longest common prefix of `a` and `b` (on a char boundary).
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|&((_, ca), cb)| ca != cb)
+        .map_or(cmp::min(a.len(), b.len()), |((i, _), _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Echo;
+
+    fn update(echo: &mut Echo, line: &str, pos: usize) -> String {
+        let mut ab = String::new();
+        echo.update(&mut ab, "> ", line, pos, &[]);
+        ab
+    }
+
+    #[test]
+    fn echo() {
+        let mut echo = Echo::new();
+        assert_eq!("> ab\x08", update(&mut echo, "ab", 1));
+        // typed at the end
+        assert_eq!("b", update(&mut echo, "ab", 2));
+        assert_eq!("c", update(&mut echo, "abc", 3));
+        // deleted at the end
+        assert_eq!("\x08 \x08", update(&mut echo, "ab", 2));
+        // moved
+        assert_eq!("\x08\x08", update(&mut echo, "ab", 0));
+        assert_eq!("a", update(&mut echo, "ab", 1));
+        // inserted in the middle
+        assert_eq!(" \x08xb\x08", update(&mut echo, "axb", 2));
+        // unchanged
+        assert_eq!("", update(&mut echo, "axb", 2));
+        // wide chars
+        assert_eq!("\x08  \x08\x08中\x08\x08", update(&mut echo, "a中", 1));
+    }
+
+    #[test]
+    fn reprint() {
+        let mut echo = Echo::new();
+        assert_eq!("> ab", update(&mut echo, "ab", 2));
+        let mut ab = String::new();
+        echo.update(&mut ab, "> ", "ab", 1, &[String::from("ab  abc")]);
+        assert_eq!("\r\nab  abc\r\n> ab\x08", ab);
+        // already announced
+        assert_eq!("b", {
+            let mut ab = String::new();
+            echo.update(&mut ab, "> ", "ab", 2, &[String::from("ab  abc")]);
+            ab
+        });
+        // new prompt
+        let mut ab = String::new();
+        echo.update(&mut ab, "(search)`a': ", "ab", 2, &[]);
+        assert_eq!("\r\n(search)`a': ab", ab);
+        // multi-line buffer
+        let mut echo = Echo::new();
+        assert_eq!("> a\nb", update(&mut echo, "a\nb", 0));
+        // on the same row
+        assert_eq!("\x08 \x08c", update(&mut echo, "a\nc", 3));
+        // on the previous row
+        assert_eq!("\r\n> x\nc", update(&mut echo, "x\nc", 1));
+        echo.reset();
+        assert_eq!("> x", update(&mut echo, "x", 1));
+    }
+
+    #[test]
+    fn announced_after_first_echo() {
+        let status = [String::from("[status]")];
+        let mut echo = Echo::new();
+        let mut ab = String::new();
+        echo.update(&mut ab, "> ", "a", 1, &status);
+        assert_eq!("> a", ab);
+        let mut ab = String::new();
+        echo.update(&mut ab, "> ", "ab", 2, &status);
+        assert_eq!("\r\n[status]\r\n> ab", ab);
+        let mut ab = String::new();
+        echo.update(&mut ab, "> ", "abc", 3, &status);
+        assert_eq!("c", ab);
+    }
+}
@@ -0,0 +1,268 @@
+//! Auto-pairing and balance checking of brackets and quotes
+use line_buffer::LineBuffer;
+
+/// Brackets and quotes paired by default.
+pub static DEFAULT_PAIRS: &'static [(char, char)] = &[('(', ')'),
+                                                     ('[', ']'),
+                                                     ('{', '}'),
+                                                     ('"', '"'),
+                                                     ('\'', '\'')];
+
+/// Handle the insertion of `ch`:
+/// - an opening char is inserted with its closing char, the cursor between them,
+/// - a closing char typed over an auto-inserted one just moves the cursor right.
+///
+/// `pending` is the number of auto-inserted closing chars after the cursor.
+/// Return `false` when `ch` must be inserted as usual.
+pub fn insert(line: &mut LineBuffer,
+              pairs: &[(char, char)],
+              pending: &mut usize,
+              ch: char)
+              -> bool {
+    if *pending > 0 && line.char_at_cursor() == Some(ch) &&
+       pairs.iter().any(|&(_, close)| close == ch) {
+        *pending -= 1;
+        return line.move_right();
+    }
+    let close = match pairs.iter().find(|&&(open, _)| open == ch) {
+        Some(&(_, close)) => close,
+        None => return false,
+    };
+    // don't pair before a word
+    if line.char_at_cursor().map_or(false, char::is_alphanumeric) {
+        return false;
+    }
+    // don't pair quotes after a word (e.g. an apostrophe)
+    if ch == close && line.char_before_cursor().map_or(false, char::is_alphanumeric) {
+        return false;
+    }
+    if line.split_insert(&ch.to_string(), &close.to_string()).is_none() {
+        return false;
+    }
+    *pending += 1;
+    true
+}
+
+/// Delete both chars of an empty auto-inserted pair surrounding the cursor.
+/// Return `false` when a single char must be deleted as usual.
+pub fn backspace(line: &mut LineBuffer, pairs: &[(char, char)], pending: &mut usize) -> bool {
+    if *pending == 0 {
+        return false;
+    }
+    let (before, after) = match (line.char_before_cursor(), line.char_at_cursor()) {
+        (Some(before), Some(after)) => (before, after),
+        _ => return false,
+    };
+    if !pairs.iter().any(|&pair| pair == (before, after)) {
+        return false;
+    }
+    *pending -= 1;
+    line.backspace() && line.delete()
+}
+
+/// Return the position of the bracket matching the one at `pos`, if any.
+/// Quotes are ignored.
+pub fn matching_bracket(line: &str, pos: usize, pairs: &[(char, char)]) -> Option<usize> {
+    let c = match line[pos..].chars().next() {
+        Some(c) => c,
+        None => return None,
+    };
+    for &(open, close) in pairs.iter().filter(|&&(open, close)| open != close) {
+        if c == open {
+            return match_forward(line, pos, open, close);
+        } else if c == close {
+            return match_backward(line, pos, open, close);
+        }
+    }
+    None
+}
+
+/// Return the positions of the `open` and `close` brackets enclosing `pos`
+/// (the brackets at `pos` included).
+pub fn enclosing_pair(line: &str, pos: usize, open: char, close: char) -> Option<(usize, usize)> {
+    let start = match line[pos..].chars().next() {
+        Some(c) if c == open => Some(pos),
+        Some(c) if c == close => match_backward(line, pos, open, close),
+        _ => {
+            let mut depth = 0;
+            let mut start = None;
+            for (i, c) in line[..pos].char_indices().rev() {
+                if c == close {
+                    depth += 1;
+                } else if c == open {
+                    if depth == 0 {
+                        start = Some(i);
+                        break;
+                    }
+                    depth -= 1;
+                }
+            }
+            start
+        }
+    };
+    start.and_then(|start| match_forward(line, start, open, close).map(|end| (start, end)))
+}
+
+/// Return the positions of the `quote`s enclosing `pos`
+/// (quotes escaped by a backslash are ignored).
+pub fn enclosing_quotes(line: &str, pos: usize, quote: char) -> Option<(usize, usize)> {
+    let mut start = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            match start {
+                None => start = Some(i),
+                Some(start) if start <= pos && pos <= i => return Some((start, i)),
+                Some(_) => start = None,
+            }
+        }
+    }
+    None
+}
+
+fn match_forward(line: &str, pos: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in line[pos..].char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(pos + i);
+            }
+        }
+    }
+    None
+}
+
+fn match_backward(line: &str, pos: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in line[..pos + close.len_utf8()].char_indices().rev() {
+        if c == close {
+            depth += 1;
+        } else if c == open {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Tell if all the brackets and quotes of `line` are closed, in the right order.
+/// Brackets are ignored inside quotes, where `\\` escapes the next char.
+pub fn is_balanced(line: &str, pairs: &[(char, char)]) -> bool {
+    let mut stack = Vec::new();
+    let mut escaped = false;
+    for ch in line.chars() {
+        let quote = match stack.last() {
+            Some(&(open, close)) if open == close => Some(close),
+            _ => None,
+        };
+        if let Some(quote) = quote {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == quote {
+                stack.pop();
+            }
+            continue;
+        }
+        if let Some(&pair) = pairs.iter().find(|&&(open, _)| open == ch) {
+            stack.push(pair);
+        } else if pairs.iter().any(|&(_, close)| close == ch) {
+            match stack.pop() {
+                Some((_, close)) if close == ch => {}
+                _ => return false,
+            }
+        }
+    }
+    stack.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use line_buffer::LineBuffer;
+    use super::DEFAULT_PAIRS;
+
+    #[test]
+    fn insert() {
+        let mut line = LineBuffer::init("f", 1);
+        let mut pending = 0;
+        assert!(super::insert(&mut line, DEFAULT_PAIRS, &mut pending, '('));
+        assert_eq!("f()", line.as_str());
+        assert_eq!(2, line.pos());
+        assert_eq!(1, pending);
+
+        assert!(!super::insert(&mut line, DEFAULT_PAIRS, &mut pending, 'x'));
+        line.insert('x');
+        assert!(super::insert(&mut line, DEFAULT_PAIRS, &mut pending, ')'));
+        assert_eq!("f(x)", line.as_str());
+        assert_eq!(4, line.pos());
+        assert_eq!(0, pending);
+
+        // closing char not auto-inserted
+        assert!(!super::insert(&mut line, DEFAULT_PAIRS, &mut pending, ')'));
+
+        let mut line = LineBuffer::init("don", 3);
+        assert!(!super::insert(&mut line, DEFAULT_PAIRS, &mut pending, '\''));
+        let mut line = LineBuffer::init("word", 0);
+        assert!(!super::insert(&mut line, DEFAULT_PAIRS, &mut pending, '['));
+    }
+
+    #[test]
+    fn backspace() {
+        let mut line = LineBuffer::init("f ", 2);
+        let mut pending = 0;
+        assert!(super::insert(&mut line, DEFAULT_PAIRS, &mut pending, '"'));
+        assert!(super::backspace(&mut line, DEFAULT_PAIRS, &mut pending));
+        assert_eq!("f ", line.as_str());
+        assert_eq!(2, line.pos());
+        assert_eq!(0, pending);
+
+        let mut line = LineBuffer::init("[]", 1);
+        assert!(!super::backspace(&mut line, DEFAULT_PAIRS, &mut pending));
+    }
+
+    #[test]
+    fn matching_bracket() {
+        let line = "f(a[0], (b))";
+        assert_eq!(Some(11), super::matching_bracket(line, 1, DEFAULT_PAIRS));
+        assert_eq!(Some(1), super::matching_bracket(line, 11, DEFAULT_PAIRS));
+        assert_eq!(Some(5), super::matching_bracket(line, 3, DEFAULT_PAIRS));
+        assert_eq!(None, super::matching_bracket(line, 0, DEFAULT_PAIRS));
+        assert_eq!(None, super::matching_bracket("(", 0, DEFAULT_PAIRS));
+    }
+
+    #[test]
+    fn enclosing() {
+        let line = "f(a, (b), c)";
+        assert_eq!(Some((1, 11)), super::enclosing_pair(line, 3, '(', ')'));
+        assert_eq!(Some((5, 7)), super::enclosing_pair(line, 6, '(', ')'));
+        assert_eq!(Some((5, 7)), super::enclosing_pair(line, 7, '(', ')'));
+        assert_eq!(Some((1, 11)), super::enclosing_pair(line, 9, '(', ')'));
+        assert_eq!(None, super::enclosing_pair(line, 0, '(', ')'));
+
+        let line = r#"a "b \" c" "d""#;
+        assert_eq!(Some((2, 9)), super::enclosing_quotes(line, 4, '"'));
+        assert_eq!(Some((11, 13)), super::enclosing_quotes(line, 12, '"'));
+        assert_eq!(None, super::enclosing_quotes(line, 10, '"'));
+    }
+
+    #[test]
+    fn is_balanced() {
+        assert!(super::is_balanced("", DEFAULT_PAIRS));
+        assert!(super::is_balanced("f(x[0], {y})", DEFAULT_PAIRS));
+        assert!(super::is_balanced("print(\")\\\"(\")", DEFAULT_PAIRS));
+        assert!(!super::is_balanced("f(x", DEFAULT_PAIRS));
+        assert!(!super::is_balanced("f(x]", DEFAULT_PAIRS));
+        assert!(!super::is_balanced("x)", DEFAULT_PAIRS));
+        assert!(!super::is_balanced("'abc", DEFAULT_PAIRS));
+    }
+}
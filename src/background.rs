@@ -0,0 +1,299 @@
+//! Completions and hints computed in a background thread (`async` feature),
+//! so that slow sources (network, database catalog) don't freeze the edition.
+//!
+//! While the candidates are fetched, a spinner is displayed below the line
+//! and the next keystroke cancels the completion.
+//! While a hint is computed, the hint of the previous line is kept
+//! if it still matches what has been typed since.
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use completion::Completer;
+use hint::Hinter;
+use history::HistoryBackend;
+use line_buffer::LineBuffer;
+use super::Result;
+
+/// Tells a background computation that its result is no longer needed.
+#[derive(Clone, Debug, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn new() -> Cancellation {
+        Cancellation::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Tell if the computation should be given up.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Result of a computation running in a background thread.
+/// The computation is cancelled when it is dropped.
+pub struct Pending<T> {
+    rx: Receiver<T>,
+    cancellation: Cancellation,
+}
+
+impl<T: Send + 'static> Pending<T> {
+    /// Run `f` in a new thread.
+    pub fn spawn<F>(f: F) -> Pending<T>
+        where F: FnOnce(&Cancellation) -> T + Send + 'static
+    {
+        let (tx, rx) = mpsc::channel();
+        let cancellation = Cancellation::new();
+        let token = cancellation.clone();
+        thread::spawn(move || {
+            let result = f(&token);
+            if !token.is_cancelled() {
+                let _ = tx.send(result);
+            }
+        });
+        Pending {
+            rx: rx,
+            cancellation: cancellation,
+        }
+    }
+}
+
+impl<T> Pending<T> {
+    /// Wait at most `timeout` for the result.
+    /// `RecvTimeoutError::Disconnected` is returned when the computation panicked
+    /// or when the result has already been received.
+    pub fn wait(&self, timeout: Duration) -> ::std::result::Result<T, RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+}
+
+impl<T> Drop for Pending<T> {
+    fn drop(&mut self) {
+        self.cancellation.cancel();
+    }
+}
+
+/// Completer called in a background thread.
+pub trait AsyncCompleter: Send + Sync + 'static {
+    /// Like `Completer::complete`, but should regularly check `cancellation`
+    /// and give up when it is cancelled (the result is then discarded).
+    fn complete(&self,
+                line: &str,
+                pos: usize,
+                cancellation: &Cancellation)
+                -> Result<(usize, Vec<String>)>;
+    /// See `Completer::word_start`.
+    fn word_start(&self, line: &str, pos: usize) -> usize {
+        ().word_start(line, pos)
+    }
+    /// See `Completer::word_end`.
+    fn word_end(&self, line: &str, pos: usize) -> usize {
+        let _ = line;
+        pos
+    }
+    /// See `Completer::update`.
+    fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
+        let end = line.pos();
+        line.replace(start, end, elected)
+    }
+}
+
+/// Adapts an `AsyncCompleter` to be given to the editor.
+pub struct AsyncCompletion<C: AsyncCompleter>(Arc<C>);
+
+impl<C: AsyncCompleter> AsyncCompletion<C> {
+    pub fn new(completer: C) -> AsyncCompletion<C> {
+        AsyncCompletion(Arc::new(completer))
+    }
+}
+
+impl<C: AsyncCompleter> Completer for AsyncCompletion<C> {
+    /// Blocking completion, when the candidates are not fetched by the editor.
+    fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+        self.0.complete(line, pos, &Cancellation::new())
+    }
+    fn word_start(&self, line: &str, pos: usize) -> usize {
+        self.0.word_start(line, pos)
+    }
+    fn word_end(&self, line: &str, pos: usize) -> usize {
+        self.0.word_end(line, pos)
+    }
+    fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
+        self.0.update(line, start, elected)
+    }
+    fn spawn(&self, line: &str, pos: usize) -> Option<Pending<Result<(usize, Vec<String>)>>> {
+        let completer = self.0.clone();
+        let line = String::from(line);
+        Some(Pending::spawn(move |cancellation| completer.complete(&line, pos, cancellation)))
+    }
+}
+
+/// Hinter called in a background thread.
+pub trait AsyncHinter: Send + Sync + 'static {
+    /// Like `Hinter::hint` (without the history), but should regularly check
+    /// `cancellation` and give up when it is cancelled (the result is then discarded).
+    fn hint(&self, line: &str, pos: usize, cancellation: &Cancellation) -> Option<String>;
+}
+
+/// Adapts an `AsyncHinter` to be given to the editor.
+pub struct AsyncHints<H: AsyncHinter> {
+    hinter: Arc<H>,
+    state: RefCell<HintState>,
+}
+
+#[derive(Default)]
+struct HintState {
+    line: String, // Line and cursor position of the last hint received
+    pos: usize,
+    hint: Option<String>,
+    // Line and cursor position of the hint being computed
+    pending: Option<(String, usize, Pending<Option<String>>)>,
+}
+
+impl HintState {
+    /// Part of the last hint received still matching `line`:
+    /// what has been typed since is removed from it.
+    fn stale(&self, line: &str, pos: usize) -> Option<String> {
+        let hint = match self.hint {
+            Some(ref hint) if pos == line.len() && line.starts_with(self.line.as_str()) => hint,
+            _ => return None,
+        };
+        let typed = &line[self.line.len()..];
+        if hint.len() > typed.len() && hint.starts_with(typed) {
+            Some(String::from(&hint[typed.len()..]))
+        } else {
+            None
+        }
+    }
+
+    /// Store the pending hint if it has been received.
+    fn receive(&mut self) {
+        let hint = match self.pending {
+            Some((_, _, ref pending)) => {
+                match pending.wait(Duration::from_millis(0)) {
+                    Ok(hint) => hint,
+                    Err(RecvTimeoutError::Timeout) => return,
+                    Err(RecvTimeoutError::Disconnected) => None,
+                }
+            }
+            None => return,
+        };
+        let (line, pos, _) = self.pending.take().unwrap();
+        self.line = line;
+        self.pos = pos;
+        self.hint = hint;
+    }
+}
+
+impl<H: AsyncHinter> AsyncHints<H> {
+    pub fn new(hinter: H) -> AsyncHints<H> {
+        AsyncHints {
+            hinter: Arc::new(hinter),
+            state: RefCell::new(HintState::default()),
+        }
+    }
+}
+
+impl<H: AsyncHinter> Hinter for AsyncHints<H> {
+    fn hint(&self, line: &str, pos: usize, _: &HistoryBackend) -> Option<String> {
+        let mut state = self.state.borrow_mut();
+        state.receive();
+        if state.line == line && state.pos == pos && state.pending.is_none() {
+            return state.hint.clone();
+        }
+        let requested = match state.pending {
+            Some((ref l, p, _)) => l == line && p == pos,
+            None => false,
+        };
+        if !requested {
+            // the previous computation (if any) is cancelled when dropped
+            let hinter = self.hinter.clone();
+            let request = String::from(line);
+            let pending = Pending::spawn(move |cancellation| {
+                hinter.hint(&request, pos, cancellation)
+            });
+            state.pending = Some((String::from(line), pos, pending));
+        }
+        state.stale(line, pos)
+    }
+    fn is_pending(&self) -> bool {
+        let mut state = self.state.borrow_mut();
+        state.receive();
+        state.pending.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::thread;
+    use std::time::Duration;
+
+    use history::History;
+    use hint::Hinter;
+    use super::{AsyncHinter, AsyncHints, Cancellation, Pending};
+
+    #[test]
+    fn pending() {
+        let pending = Pending::spawn(|_: &Cancellation| 42);
+        assert_eq!(Ok(42), pending.wait(Duration::from_secs(5)));
+        assert_eq!(Err(RecvTimeoutError::Disconnected),
+                   pending.wait(Duration::from_secs(5)));
+
+        let pending = Pending::spawn(|cancellation: &Cancellation| {
+            while !cancellation.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        assert_eq!(Err(RecvTimeoutError::Timeout),
+                   pending.wait(Duration::from_millis(10)));
+        pending.cancel();
+        assert_eq!(Err(RecvTimeoutError::Disconnected),
+                   pending.wait(Duration::from_secs(5)));
+    }
+
+    struct CommitHinter;
+
+    impl AsyncHinter for CommitHinter {
+        fn hint(&self, line: &str, _: usize, _: &Cancellation) -> Option<String> {
+            if "git commit".starts_with(line) && line.len() < 10 {
+                Some(String::from(&"git commit"[line.len()..]))
+            } else {
+                None
+            }
+        }
+    }
+
+    fn wait(hinter: &AsyncHints<CommitHinter>) {
+        while hinter.is_pending() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn async_hints() {
+        let history = History::new();
+        let hinter = AsyncHints::new(CommitHinter);
+        assert_eq!(None, hinter.hint("git", 3, &history));
+        wait(&hinter);
+        assert_eq!(Some(String::from(" commit")), hinter.hint("git", 3, &history));
+        // stale hint while the next one is computed
+        assert_eq!(Some(String::from("commit")), hinter.hint("git ", 4, &history));
+        wait(&hinter);
+        assert_eq!(Some(String::from("commit")), hinter.hint("git ", 4, &history));
+        assert_eq!(None, hinter.hint("git x", 5, &history));
+        wait(&hinter);
+        assert_eq!(None, hinter.hint("git x", 5, &history));
+    }
+}
@@ -45,9 +45,17 @@ pub struct Chars<R> {
     inner: R,
 }
 
+impl<R> Chars<R> {
+    /// Reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
 #[derive(Debug)]
 pub enum CharsError {
-    NotUtf8,
+    /// Bytes which are not the UTF-8 encoding of a char
+    NotUtf8(Vec<u8>),
     Other(io::Error),
 }
 
@@ -66,14 +74,15 @@ impl<R: Read> Iterator for Chars<R> {
             return Some(Ok(first_byte as char));
         }
         if width == 0 {
-            return Some(Err(CharsError::NotUtf8));
+            return Some(Err(CharsError::NotUtf8(vec![first_byte])));
         }
         let mut buf = [first_byte, 0, 0, 0];
         {
             let mut start = 1;
             while start < width {
                 match self.inner.read(&mut buf[start..width]) {
-                    Ok(0) => return Some(Err(CharsError::NotUtf8)),
+                    // the end of input (like a closed terminal) cut the char
+                    Ok(0) => return None,
                     Ok(n) => start += n,
                     Err(e) => return Some(Err(CharsError::Other(e))),
                 }
@@ -81,7 +90,7 @@ impl<R: Read> Iterator for Chars<R> {
         }
         Some(match str::from_utf8(&buf[..width]).ok() {
             Some(s) => Ok(s.chars().next().unwrap()),
-            None => Err(CharsError::NotUtf8),
+            None => Err(CharsError::NotUtf8(buf[..width].to_vec())),
         })
     }
 }
@@ -89,13 +98,13 @@ impl<R: Read> Iterator for Chars<R> {
 impl error::Error for CharsError {
     fn description(&self) -> &str {
         match *self {
-            CharsError::NotUtf8 => "invalid utf8 encoding",
+            CharsError::NotUtf8(_) => "invalid utf8 encoding",
             CharsError::Other(ref e) => error::Error::description(e),
         }
     }
     fn cause(&self) -> Option<&error::Error> {
         match *self {
-            CharsError::NotUtf8 => None,
+            CharsError::NotUtf8(_) => None,
             CharsError::Other(ref e) => e.cause(),
         }
     }
@@ -104,8 +113,27 @@ impl error::Error for CharsError {
 impl fmt::Display for CharsError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            CharsError::NotUtf8 => "byte stream did not contain valid utf8".fmt(f),
+            CharsError::NotUtf8(_) => "byte stream did not contain valid utf8".fmt(f),
             CharsError::Other(ref e) => e.fmt(f),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{chars, CharsError};
+
+    #[test]
+    fn end_of_input() {
+        let mut iter = chars(&b"a\xc3\xa9\xff\xc3"[..]);
+        assert_eq!('a', iter.next().unwrap().unwrap());
+        assert_eq!('é', iter.next().unwrap().unwrap());
+        match iter.next() {
+            Some(Err(CharsError::NotUtf8(bytes))) => assert_eq!(vec![0xff], bytes),
+            _ => panic!("invalid byte not reported"),
+        }
+        // char cut by the end of input
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+    }
+}
@@ -0,0 +1,152 @@
+//! Compatibility with GNU readline (emacs mode): each case of `compat.txt`
+//! is typed on the fake terminal and the final line and cursor position
+//! are compared with the ones of readline.
+//!
+//! A case is written `<input> => "<line>" @ <cursor>`, the input being
+//! the bytes sent by an xterm, with the escapes `\e` (Esc), `\C-x` (Ctrl-x),
+//! `\M-x` (Esc x), `\xNN`, `\\` and `\"` (also allowed in the expected line).
+//! Enter is typed after the input. `#` starts a comment line, and `!` a known
+//! difference with readline, checked to still differ (remove the `!` once fixed).
+use std::cell::Cell;
+use std::rc::Rc;
+
+use consts::{self, Key, KeyPress};
+use keymap::Cmd;
+use line_buffer::LineBuffer;
+use Editor;
+
+const CORPUS: &'static str = include_str!("compat.txt");
+
+/// Input, expected line and cursor position (in bytes) of a case.
+#[derive(Debug)]
+struct Case {
+    known_difference: bool,
+    input: String,
+    line: String,
+    pos: usize,
+}
+
+/// Parse a case (`None` for the empty and comment lines).
+fn parse_case(text: &str) -> Option<Case> {
+    let text = text.trim();
+    if text.is_empty() || text.starts_with('#') {
+        return None;
+    }
+    let known_difference = text.starts_with('!');
+    let text = text.trim_left_matches('!');
+    let arrow = text.rfind(" => ").expect(text);
+    let (input, expected) = (&text[..arrow], &text[arrow + 4..]);
+    let at = expected.rfind(" @ ").expect(text);
+    let line = expected[..at].trim();
+    assert!(line.len() >= 2 && line.starts_with('"') && line.ends_with('"'),
+            "{}",
+            text);
+    Some(Case {
+        known_difference: known_difference,
+        input: unescape(input),
+        line: unescape(&line[1..line.len() - 1]),
+        pos: expected[at + 3..].trim().parse().expect(text),
+    })
+}
+
+fn unescape(text: &str) -> String {
+    let mut bytes = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('e') => bytes.push('\x1b'),
+            Some('C') => {
+                assert_eq!(Some('-'), chars.next(), "{}", text);
+                let c = chars.next().expect(text);
+                bytes.push(if c == '?' {
+                    '\x7f'
+                } else {
+                    ((c as u8) & 0x1f) as char
+                });
+            }
+            Some('M') => {
+                assert_eq!(Some('-'), chars.next(), "{}", text);
+                bytes.push('\x1b'); // followed by the (maybe escaped) next char
+            }
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).expect(text) as char);
+            }
+            Some(c) => bytes.push(c), // '\\', '"'
+            None => panic!("{}", text),
+        }
+    }
+    bytes
+}
+
+/// Keys decoded from the bytes of an xterm
+/// (the CSI sequences of the arrows, Home, End and Delete, and Esc as Meta).
+fn decode(bytes: &str) -> Vec<KeyPress> {
+    let mut keys = Vec::new();
+    let mut chars = bytes.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            keys.push(consts::char_to_key_press(c));
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                let mut seq = String::new();
+                while let Some(c) = chars.next() {
+                    seq.push(c);
+                    if c.is_alphabetic() || c == '~' {
+                        break;
+                    }
+                }
+                keys.push(match seq.as_str() {
+                    "A" => key!(Key::Up),
+                    "B" => key!(Key::Down),
+                    "C" => key!(Key::Right),
+                    "D" => key!(Key::Left),
+                    "H" => key!(Key::Home),
+                    "F" => key!(Key::End),
+                    "3~" => key!(Key::Delete),
+                    _ => panic!("unknown sequence: {:?}", seq),
+                });
+            }
+            Some('\x7f') |
+            Some('\x08') => keys.push(alt!('\x7f')),
+            Some(c) => keys.push(alt!(c)),
+            None => keys.push(key!(Key::Esc)),
+        }
+    }
+    keys
+}
+
+#[test]
+fn corpus() {
+    let mut failures = Vec::new();
+    let mut count = 0;
+    for case in CORPUS.lines().filter_map(parse_case) {
+        count += 1;
+        let mut keys = decode(&case.input);
+        // the cursor position is recorded before the line is accepted
+        keys.push(key!(Key::F(12)));
+        keys.push(key!(Key::Enter));
+        let pos = Rc::new(Cell::new(0));
+        let recorded = pos.clone();
+        let mut editor = Editor::<()>::new();
+        editor.add_command("record-pos", move |_: KeyPress, line: &mut LineBuffer| {
+            recorded.set(line.pos());
+            true
+        });
+        editor.bind_sequence(key!(Key::F(12)), Cmd::User(String::from("record-pos")));
+        editor.term.keys.extend_from_slice(&keys);
+        let line = editor.readline(">> ").unwrap();
+        let same = line == case.line && pos.get() == case.pos;
+        if same == case.known_difference {
+            failures.push(format!("{:?}: {:?} @ {}", case, line, pos.get()));
+        }
+    }
+    assert!(count > 0);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
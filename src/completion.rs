@@ -1,10 +1,15 @@
 //! Completion API
 use std::borrow::Cow::{self, Borrowed, Owned};
-use std::collections::BTreeSet;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::{self, Path};
+use std::time::{Duration, Instant};
 
 use super::Result;
+use shell_words;
+#[cfg(feature = "async")]
+use background::Pending;
 use line_buffer::LineBuffer;
 
 // TODO: let the implementers choose/find word boudaries ???
@@ -17,11 +22,37 @@ pub trait Completer {
     /// returns the start position and the completion candidates for the partial word to be completed.
     /// "ls /usr/loc" => Ok((3, vec!["/usr/local/"]))
     fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)>;
+    /// Takes the currently edited `line` with the cursor `pos`ition and
+    /// returns the start position of the word to be completed,
+    /// used as the cache key by `CachedCompleter`.
+    /// By default, the word starts after the last space, quote or shell operator.
+    /// "ls /usr/loc|" => 3
+    fn word_start(&self, line: &str, pos: usize) -> usize {
+        let break_chars = DEFAULT_BREAK_CHARS.iter().cloned().collect();
+        extract_word(line, pos, ESCAPE_CHAR, &break_chars).0
+    }
+    /// Takes the currently edited `line` with the cursor `pos`ition and
+    /// returns the end position of the word to be completed,
+    /// so that the whole word spanning the cursor is replaced by the elected candidate.
+    /// By default, the text after the cursor is kept.
+    /// "ls /usr/lo|cal/bin" => 14
+    fn word_end(&self, line: &str, pos: usize) -> usize {
+        let _ = line;
+        pos
+    }
     /// Updates the edited `line` with the `elected` candidate.
     fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
         let end = line.pos();
         line.replace(start, end, elected)
     }
+    /// Starts computing the candidates in a background thread
+    /// (see `background::AsyncCompletion`).
+    /// By default, `None`: the candidates are computed by `complete`.
+    #[cfg(feature = "async")]
+    fn spawn(&self, line: &str, pos: usize) -> Option<Pending<Result<(usize, Vec<String>)>>> {
+        let _ = (line, pos);
+        None
+    }
 }
 
 impl Completer for () {
@@ -37,9 +68,19 @@ impl<'c, C: ?Sized + Completer> Completer for &'c C {
     fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
         (**self).complete(line, pos)
     }
+    fn word_start(&self, line: &str, pos: usize) -> usize {
+        (**self).word_start(line, pos)
+    }
+    fn word_end(&self, line: &str, pos: usize) -> usize {
+        (**self).word_end(line, pos)
+    }
     fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
         (**self).update(line, start, elected)
     }
+    #[cfg(feature = "async")]
+    fn spawn(&self, line: &str, pos: usize) -> Option<Pending<Result<(usize, Vec<String>)>>> {
+        (**self).spawn(line, pos)
+    }
 }
 macro_rules! box_completer {
     ($($id: ident)*) => {
@@ -48,9 +89,20 @@ macro_rules! box_completer {
                 fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
                     (**self).complete(line, pos)
                 }
+                fn word_start(&self, line: &str, pos: usize) -> usize {
+                    (**self).word_start(line, pos)
+                }
+                fn word_end(&self, line: &str, pos: usize) -> usize {
+                    (**self).word_end(line, pos)
+                }
                 fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
                     (**self).update(line, start, elected)
                 }
+                #[cfg(feature = "async")]
+                fn spawn(&self, line: &str, pos: usize)
+                         -> Option<Pending<Result<(usize, Vec<String>)>>> {
+                    (**self).spawn(line, pos)
+                }
             }
         )*
     }
@@ -60,6 +112,15 @@ use std::rc::Rc;
 use std::sync::Arc;
 box_completer! { Box Rc Arc }
 
+/// To be called for contextual help (function signature, flags documentation...).
+pub trait Descriptor {
+    /// Takes the currently edited `line` with the cursor `pos`ition and
+    /// returns a short description to be displayed below the line
+    /// until the next keystroke.
+    /// "sum(|" => Some("sum(iterable, start=0)")
+    fn describe(&self, line: &str, pos: usize) -> Option<String>;
+}
+
 pub struct FilenameCompleter {
     break_chars: BTreeSet<char>,
 }
@@ -90,11 +151,129 @@ impl Default for FilenameCompleter {
 
 impl Completer for FilenameCompleter {
     fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+        // a path after an opening quote is completed inside the quotes
+        if let Some(word) = shell_words::split(&line[..pos]).pop() {
+            if let Some(quote) = word.open_quote {
+                let matches = try!(filename_complete(&word.value, None, &self.break_chars));
+                let matches = matches.iter()
+                    .map(|path| format!("{}{}", quote, shell_words::escape_quoted(path, quote)))
+                    .collect();
+                return Ok((word.start, matches));
+            }
+        }
         let (start, path) = extract_word(line, pos, ESCAPE_CHAR, &self.break_chars);
         let path = unescape(path, ESCAPE_CHAR);
         let matches = try!(filename_complete(&path, ESCAPE_CHAR, &self.break_chars));
         Ok((start, matches))
     }
+    fn word_start(&self, line: &str, pos: usize) -> usize {
+        match shell_words::split(&line[..pos]).pop() {
+            Some(ref word) if word.open_quote.is_some() => word.start,
+            _ => extract_word(line, pos, ESCAPE_CHAR, &self.break_chars).0,
+        }
+    }
+    fn word_end(&self, line: &str, pos: usize) -> usize {
+        extract_word_end(line, pos, ESCAPE_CHAR, &self.break_chars)
+    }
+}
+
+/// Completer remembering the candidates of the words already completed
+/// by a slow completer (file system, network, ...).
+///
+/// The words are delimited by the wrapped completer (see `Completer::word_start`).
+/// Wrap it in a `Rc` to keep access to the invalidation methods
+/// once it has been given to the editor.
+pub struct CachedCompleter<C> {
+    completer: C,
+    ttl: Option<Duration>,
+    cache: RefCell<HashMap<String, CacheEntry>>, // by word (see `Completer::word_start`)
+}
+
+struct CacheEntry {
+    len: usize, // Length of the text replaced by the candidates, before the cursor
+    candidates: Vec<String>,
+    time: Instant,
+}
+
+impl<C: Completer> CachedCompleter<C> {
+    /// Cache the results of `completer` until they are invalidated.
+    pub fn new(completer: C) -> CachedCompleter<C> {
+        CachedCompleter {
+            completer: completer,
+            ttl: None,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Cache the results of `completer` for at most `ttl`.
+    pub fn with_ttl(completer: C, ttl: Duration) -> CachedCompleter<C> {
+        CachedCompleter { ttl: Some(ttl), ..CachedCompleter::new(completer) }
+    }
+
+    /// Forget the candidates of `word`.
+    pub fn invalidate(&self, word: &str) {
+        self.cache.borrow_mut().remove(word);
+    }
+
+    /// Forget all the candidates.
+    pub fn invalidate_all(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    /// Return the wrapped completer.
+    pub fn get_ref(&self) -> &C {
+        &self.completer
+    }
+}
+
+impl<C: Completer> Completer for CachedCompleter<C> {
+    fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+        let start = self.completer.word_start(line, pos);
+        if start > pos {
+            return self.completer.complete(line, pos);
+        }
+        let word = &line[start..pos];
+        let cached = match self.cache.borrow().get(word) {
+            Some(entry) => {
+                let fresh = match self.ttl {
+                    Some(ttl) => entry.time.elapsed() < ttl,
+                    None => true,
+                };
+                if fresh {
+                    pos.checked_sub(entry.len).map(|start| (start, entry.candidates.clone()))
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+        if let Some(result) = cached {
+            return Ok(result);
+        }
+        let (start, candidates) = try!(self.completer.complete(line, pos));
+        match pos.checked_sub(start) {
+            Some(len) => {
+                self.cache.borrow_mut().insert(String::from(word),
+                                               CacheEntry {
+                                                   len: len,
+                                                   candidates: candidates.clone(),
+                                                   time: Instant::now(),
+                                               });
+            }
+            // the candidates do not replace the text before the cursor
+            None => self.invalidate(word),
+        }
+        Ok((start, candidates))
+    }
+    fn word_start(&self, line: &str, pos: usize) -> usize {
+        self.completer.word_start(line, pos)
+    }
+    fn word_end(&self, line: &str, pos: usize) -> usize {
+        self.completer.word_end(line, pos)
+    }
+    fn update(&self, line: &mut LineBuffer, start: usize, elected: &str) {
+        self.completer.update(line, start, elected)
+    }
 }
 
 /// Remove escape char
@@ -229,6 +408,27 @@ pub fn extract_word<'l>(line: &'l str,
     }
 }
 
+/// Given a `line` and a cursor `pos`ition,
+/// try to find forward the end of a word.
+/// Return `line.len()` if no break char has been found.
+pub fn extract_word_end(line: &str,
+                        pos: usize,
+                        esc_char: Option<char>,
+                        break_chars: &BTreeSet<char>)
+                        -> usize {
+    let mut escaped = pos > 0 && esc_char.is_some() && line[..pos].ends_with(esc_char.unwrap());
+    for (i, c) in line[pos..].char_indices() {
+        if escaped {
+            escaped = false;
+        } else if Some(c) == esc_char {
+            escaped = true;
+        } else if break_chars.contains(&c) {
+            return pos + i;
+        }
+    }
+    line.len()
+}
+
 pub fn longest_common_prefix(candidates: &[String]) -> Option<&str> {
     if candidates.is_empty() {
         return None;
@@ -258,7 +458,87 @@ pub fn longest_common_prefix(candidates: &[String]) -> Option<&str> {
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
     use std::collections::BTreeSet;
+    use std::time::Duration;
+
+    use super::{CachedCompleter, Completer, FilenameCompleter};
+    use super::super::Result;
+
+    struct CountingCompleter(Cell<usize>);
+
+    impl Completer for CountingCompleter {
+        fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+            self.0.set(self.0.get() + 1);
+            let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+            Ok((start, vec![String::from("select"), String::from("set")]))
+        }
+    }
+
+    /// Completer of the column names after a table name and a dot.
+    struct ColumnCompleter(Cell<usize>);
+
+    impl Completer for ColumnCompleter {
+        fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+            self.0.set(self.0.get() + 1);
+            let start = self.word_start(line, pos);
+            Ok((start, vec![String::from(&line[..pos])]))
+        }
+        fn word_start(&self, line: &str, pos: usize) -> usize {
+            line[..pos].rfind(|c| c == ' ' || c == '.').map_or(0, |i| i + 1)
+        }
+    }
+
+    #[test]
+    pub fn quoted_filename() {
+        let completer = FilenameCompleter::new();
+        let line = "cat \"src/shell_w";
+        let (start, candidates) = completer.complete(line, line.len()).unwrap();
+        assert_eq!(4, start);
+        assert_eq!(vec![String::from("\"src/shell_words.rs")], candidates);
+    }
+
+    #[test]
+    pub fn cached_completer() {
+        let completer = CachedCompleter::new(CountingCompleter(Cell::new(0)));
+        assert_eq!(2, completer.complete("a se", 4).unwrap().0);
+        // same word in another line
+        let (start, candidates) = completer.complete("b c se", 6).unwrap();
+        assert_eq!(4, start);
+        assert_eq!(2, candidates.len());
+        assert_eq!(1, completer.get_ref().0.get());
+        completer.complete("a s", 3).unwrap();
+        assert_eq!(2, completer.get_ref().0.get());
+
+        completer.invalidate("se");
+        completer.complete("a se", 4).unwrap();
+        assert_eq!(3, completer.get_ref().0.get());
+        completer.invalidate_all();
+        completer.complete("a s", 3).unwrap();
+        assert_eq!(4, completer.get_ref().0.get());
+
+        let completer = CachedCompleter::with_ttl(CountingCompleter(Cell::new(0)),
+                                                  Duration::from_secs(0));
+        completer.complete("se", 2).unwrap();
+        completer.complete("se", 2).unwrap();
+        assert_eq!(2, completer.get_ref().0.get());
+    }
+
+    #[test]
+    pub fn cached_completer_word() {
+        // the words of the wrapped completer
+        let completer = CachedCompleter::new(ColumnCompleter(Cell::new(0)));
+        assert_eq!((2, vec![String::from("t.na")]), completer.complete("t.na", 4).unwrap());
+        assert_eq!((9, vec![String::from("t.na")]), completer.complete("select u.na", 11).unwrap());
+        assert_eq!(1, completer.get_ref().0.get());
+        // candidates replacing more text than there is before the cursor
+        let completer = CachedCompleter::new(CountingCompleter(Cell::new(0)));
+        completer.complete("a b", 3).unwrap();
+        completer.cache.borrow_mut().get_mut("b").unwrap().len = 4;
+        assert_eq!(0, completer.complete("b", 1).unwrap().0);
+        assert_eq!(2, completer.get_ref().0.get());
+        assert_eq!(1, completer.cache.borrow()["b"].len);
+    }
 
     #[test]
     pub fn extract_word() {
@@ -271,6 +551,18 @@ mod tests {
                    super::extract_word(line, line.len(), Some('\\'), &break_chars));
     }
 
+    #[test]
+    pub fn extract_word_end() {
+        let break_chars: BTreeSet<char> = super::DEFAULT_BREAK_CHARS.iter().cloned().collect();
+        let line = "ls /usr/lo > out";
+        assert_eq!(10, super::extract_word_end(line, 6, Some('\\'), &break_chars));
+        let line = "ls /User\\ Information";
+        assert_eq!(line.len(),
+                   super::extract_word_end(line, 5, Some('\\'), &break_chars));
+        assert_eq!(line.len(),
+                   super::extract_word_end(line, 9, Some('\\'), &break_chars));
+    }
+
     #[test]
     pub fn unescape() {
         use std::borrow::Cow::{self, Borrowed, Owned};
@@ -1,18 +1,84 @@
 //! Customize line editor
 use std::default::Default;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+use auto_pair::DEFAULT_PAIRS;
+use line_buffer::MAX_LINE;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Config {
     /// Maximum number of entries in History.
     max_history_size: usize,
     history_duplicates: HistoryDuplicates,
     history_ignore_space: bool,
+    /// Glob patterns of the lines neither added to the history nor saved.
+    history_exclude: Vec<String>,
+    /// Keep only the most recent occurrence of each entry when saving.
+    history_dedup_on_save: bool,
+    /// Ctrl-R lists the fuzzy matches instead of searching incrementally.
+    fuzzy_search: bool,
     completion_type: CompletionType,
     /// When listing completion alternatives, only display
     /// one screen of possibilities at a time.
     completion_prompt_limit: usize,
     /// Duration (milliseconds) Rustyline will wait for a character when reading an ambiguous key sequence.
     keyseq_timeout: i32,
+    esc_mode: EscMode,
+    /// Duration (milliseconds) without any key after which the idle hook is called.
+    idle_timeout: i32,
+    /// Restore the terminal mode when the process is killed by a signal.
+    restore_terminal_on_signal: bool,
+    /// Ctrl-C returns the edited line with the interruption.
+    interrupt_with_line: bool,
+    /// Ctrl-Z returns instead of suspending the process.
+    report_suspend: bool,
+    /// A resize of the terminal ends the edition.
+    report_resize: bool,
+    /// Undo all changes to history lines when a line is accepted.
+    revert_all_at_newline: bool,
+    /// String inserted by the insert-comment command.
+    comment_begin: &'static str,
+    /// Insert the closing char when an opening bracket or quote is typed.
+    auto_pair: bool,
+    auto_pairs: &'static [(char, char)],
+    /// Refuse to accept a line with unbalanced brackets or quotes.
+    check_balance: bool,
+    /// Home/End move within the current line of a multi-line buffer.
+    line_wise_home_end: bool,
+    /// Up/Down move between the lines of a multi-line buffer and/or in the history.
+    up_down: UpDown,
+    /// Continue the line on a new row when Enter is pressed after a backslash.
+    line_continuation: LineContinuation,
+    /// Prompt of the rows continuing a line.
+    continuation_prompt: &'static str,
+    /// Display of a prompt wider than the terminal.
+    long_prompt: LongPrompt,
+    /// Display of the tab characters of the line.
+    tab_display: TabDisplay,
+    /// Ask the terminal to mark the start and the end of the pasted text.
+    bracketed_paste: bool,
+    /// Remove the escape sequences (like colors) of the pasted text.
+    strip_pasted_ansi: bool,
+    /// Insert the Enter read with the pasted keys instead of accepting the line.
+    confirm_paste: bool,
+    /// Maximum length of the line, in bytes.
+    max_line_len: usize,
+    color_mode: ColorMode,
+    /// Use the alternate screen buffer while the editor is alive.
+    alternate_screen: bool,
+    /// Prefer `COLUMNS` and `LINES` to the size reported by the terminal.
+    prefer_env_size: bool,
+    /// Stream the prompt and the edited line are rendered to.
+    output_stream: OutputStreamType,
+    /// Read and render on `/dev/tty` when stdin or the output stream is redirected.
+    dev_tty: bool,
+    /// Echo the changes of the line linearly, for screen readers.
+    accessible: bool,
+    bell_style: BellStyle,
+    term_support: TermSupport,
+    /// Cursor shape while editing the line.
+    cursor_shape: CursorShape,
+    /// Cursor shape while searching the history.
+    search_cursor_shape: CursorShape,
 }
 
 impl Config {
@@ -37,6 +103,26 @@ impl Config {
         self.history_ignore_space
     }
 
+    /// Glob patterns (`*` for any chars, `?` for one char) matching the whole lines
+    /// which are neither added to the history nor saved, like `"rm -rf*"`.
+    /// By default, no line is excluded.
+    pub fn history_exclude(&self) -> &[String] {
+        &self.history_exclude
+    }
+
+    /// Tell if only the most recent occurrence of each entry is saved in the history file.
+    /// By default, all the entries are saved.
+    pub fn history_dedup_on_save(&self) -> bool {
+        self.history_dedup_on_save
+    }
+
+    /// Tell if Ctrl-R lists the history entries matching the typed chars
+    /// in order (fuzzy search) to select one.
+    /// By default, it searches the history incrementally.
+    pub fn fuzzy_search(&self) -> bool {
+        self.fuzzy_search
+    }
+
     pub fn completion_type(&self) -> CompletionType {
         self.completion_type
     }
@@ -48,6 +134,217 @@ impl Config {
     pub fn keyseq_timeout(&self) -> i32 {
         self.keyseq_timeout
     }
+
+    /// Tell how a lone Esc is told apart from the Meta prefix.
+    /// By default, with `keyseq_timeout`.
+    pub fn esc_mode(&self) -> EscMode {
+        self.esc_mode
+    }
+
+    /// Time (in milliseconds, negative for ever) to wait for the key following Esc
+    /// before reading a lone Esc, according to `esc_mode`.
+    pub fn esc_timeout(&self) -> i32 {
+        match self.esc_mode {
+            EscMode::Timeout => self.keyseq_timeout,
+            EscMode::Escape => 0,
+            EscMode::Meta => -1,
+        }
+    }
+
+    /// Time (in milliseconds) without any key pressed after which the idle hook is called,
+    /// then called again (see `Editor::set_idle_hook`). By default, one second.
+    pub fn idle_timeout(&self) -> i32 {
+        self.idle_timeout
+    }
+
+    /// Tell if signal handlers restoring the terminal mode are installed.
+    /// By default, they are not.
+    pub fn restore_terminal_on_signal(&self) -> bool {
+        self.restore_terminal_on_signal
+    }
+
+    /// Tell if Ctrl-C returns `ReadlineError::InterruptedWithLine`
+    /// with the edited line (when it is not empty).
+    /// By default, `ReadlineError::Interrupted` is returned.
+    pub fn interrupt_with_line(&self) -> bool {
+        self.interrupt_with_line
+    }
+
+    /// Tell if Ctrl-Z returns `ReadlineError::Suspended` instead of suspending the process.
+    /// By default, the process is suspended (unix only).
+    pub fn report_suspend(&self) -> bool {
+        self.report_suspend
+    }
+
+    /// Tell if a resize of the terminal returns `ReadlineError::WindowResized`.
+    /// By default, the line is redrawn and the edition goes on.
+    pub fn report_resize(&self) -> bool {
+        self.report_resize
+    }
+
+    /// Tell if changes made to recalled history lines are undone when a line is accepted.
+    /// By default, they are.
+    pub fn revert_all_at_newline(&self) -> bool {
+        self.revert_all_at_newline
+    }
+
+    /// String inserted at the beginning of the line by the insert-comment command (Meta-#).
+    /// By default, "#".
+    pub fn comment_begin(&self) -> &'static str {
+        self.comment_begin
+    }
+
+    /// Tell if brackets and quotes are automatically paired.
+    /// By default, they are not.
+    pub fn auto_pair(&self) -> bool {
+        self.auto_pair
+    }
+
+    /// (opening, closing) chars paired when `auto_pair` is enabled.
+    /// By default, `()`, `[]`, `{}`, `""` and `''`.
+    pub fn auto_pairs(&self) -> &'static [(char, char)] {
+        self.auto_pairs
+    }
+
+    /// Tell if lines with unbalanced brackets or quotes (of `auto_pairs`) are rejected on accept.
+    /// By default, they are accepted.
+    pub fn check_balance(&self) -> bool {
+        self.check_balance
+    }
+
+    /// Tell if Ctrl-A/Ctrl-E (Home/End) move to the start/end of the current line
+    /// of a multi-line buffer instead of the whole buffer.
+    /// By default, they move to the start/end of the whole buffer.
+    pub fn line_wise_home_end(&self) -> bool {
+        self.line_wise_home_end
+    }
+
+    /// Tell what Up/Down (Ctrl-P/Ctrl-N) do in a multi-line buffer.
+    /// By default, they move between its lines, and in the history from the first/last line.
+    pub fn up_down(&self) -> UpDown {
+        self.up_down
+    }
+
+    /// Tell if Enter after a trailing backslash continues the line on a new row.
+    /// By default, the line is accepted.
+    pub fn line_continuation(&self) -> LineContinuation {
+        self.line_continuation
+    }
+
+    /// Tell the prompt displayed before the rows continuing a line.
+    /// By default, "> ".
+    pub fn continuation_prompt(&self) -> &'static str {
+        self.continuation_prompt
+    }
+
+    /// Tell how a single line prompt wider than the terminal is displayed.
+    /// By default, it is truncated to half of the terminal width.
+    pub fn long_prompt(&self) -> LongPrompt {
+        self.long_prompt
+    }
+
+    /// Tell how the tab characters of the line are displayed.
+    /// By default, each one is displayed as 4 spaces.
+    pub fn tab_display(&self) -> TabDisplay {
+        self.tab_display
+    }
+
+    /// Tell if the pasted text is inserted as a whole, without its chars being
+    /// interpreted as keys (bracketed paste mode, unix only).
+    /// By default, the pasted chars are read as typed ones.
+    pub fn bracketed_paste(&self) -> bool {
+        self.bracketed_paste
+    }
+
+    /// Tell if the escape sequences (like the colors copied from a terminal output)
+    /// are removed from the text pasted in bracketed paste mode.
+    /// By default, they are inserted (and displayed in caret notation).
+    pub fn strip_pasted_ansi(&self) -> bool {
+        self.strip_pasted_ansi
+    }
+
+    /// Tell if an Enter read in the same burst as the previous keys (a paste without
+    /// bracketed paste mode, or the keys following an injected end of paste) is
+    /// inserted as a newline, so that a pasted command runs only after a typed Enter.
+    /// By default, it accepts the line (as do the keys typed ahead, which are inserted).
+    pub fn confirm_paste(&self) -> bool {
+        self.confirm_paste
+    }
+
+    /// Tell the maximum length of the line, in bytes: the chars or the text
+    /// inserted beyond it are rejected with a beep, and the lines read without
+    /// line editing (unsupported terminal, not a tty) or continued on several rows
+    /// are truncated to it.
+    /// By default, 4096.
+    pub fn max_line_len(&self) -> usize {
+        self.max_line_len
+    }
+
+    /// Tell if colors should be used (by the highlighter).
+    /// By default, they are when the output is a terminal.
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Tell if the alternate screen buffer is used.
+    /// By default, it is not.
+    ///
+    /// It never is on Windows: the editor writes to the standard output,
+    /// which stays on the screen buffer of the console it was opened on.
+    pub fn alternate_screen(&self) -> bool {
+        self.alternate_screen && cfg!(unix)
+    }
+
+    /// Tell if the `COLUMNS` and `LINES` environment variables override
+    /// the size reported by the terminal.
+    /// By default, they are only used when the size cannot be queried.
+    pub fn prefer_env_size(&self) -> bool {
+        self.prefer_env_size
+    }
+
+    /// Tell the stream the prompt and the edited line are rendered to.
+    /// By default, stdout.
+    pub fn output_stream(&self) -> OutputStreamType {
+        self.output_stream
+    }
+
+    /// Tell if the controlling terminal (`/dev/tty`) is used for the input and the rendering
+    /// when stdin or the output stream is redirected.
+    /// By default, it is not.
+    pub fn dev_tty(&self) -> bool {
+        self.dev_tty
+    }
+
+    /// Tell if the line is rendered for screen readers (see `Editor::is_accessible`).
+    /// By default, it is redrawn in place.
+    pub fn accessible(&self) -> bool {
+        self.accessible
+    }
+
+    /// Tell how the user is warned when there is nothing to complete, no match, ...
+    /// By default, the terminal bell is rung.
+    pub fn bell_style(&self) -> BellStyle {
+        self.bell_style
+    }
+
+    /// Tell if the terminal is assumed to support line editing.
+    /// By default, it is unless `TERM` names an unsupported terminal
+    /// (see `Editor::add_unsupported_term`).
+    pub fn term_support(&self) -> TermSupport {
+        self.term_support
+    }
+
+    /// Tell the cursor shape used while editing the line.
+    /// By default, the shape of the terminal is kept.
+    pub fn cursor_shape(&self) -> CursorShape {
+        self.cursor_shape
+    }
+
+    /// Tell the cursor shape used during an incremental search in the history.
+    /// By default, the shape of the terminal is kept.
+    pub fn search_cursor_shape(&self) -> CursorShape {
+        self.search_cursor_shape
+    }
 }
 
 impl Default for Config {
@@ -56,13 +353,53 @@ impl Default for Config {
             max_history_size: 100,
             history_duplicates: HistoryDuplicates::IgnoreConsecutive,
             history_ignore_space: false,
+            history_exclude: Vec::new(),
+            history_dedup_on_save: false,
+            fuzzy_search: false,
             completion_type: CompletionType::Circular, // TODO Validate
             completion_prompt_limit: 100,
             keyseq_timeout: 500,
+            esc_mode: EscMode::Timeout,
+            idle_timeout: 1000,
+            restore_terminal_on_signal: false,
+            interrupt_with_line: false,
+            report_suspend: false,
+            report_resize: false,
+            revert_all_at_newline: true,
+            comment_begin: "#",
+            auto_pair: false,
+            auto_pairs: DEFAULT_PAIRS,
+            check_balance: false,
+            line_wise_home_end: false,
+            up_down: UpDown::HistoryAtEdges,
+            line_continuation: LineContinuation::Disabled,
+            continuation_prompt: "> ",
+            long_prompt: LongPrompt::Truncate,
+            tab_display: TabDisplay::Spaces(4),
+            bracketed_paste: false,
+            strip_pasted_ansi: false,
+            confirm_paste: false,
+            max_line_len: MAX_LINE,
+            color_mode: ColorMode::Enabled,
+            alternate_screen: false,
+            prefer_env_size: false,
+            output_stream: OutputStreamType::Stdout,
+            dev_tty: false,
+            accessible: false,
+            bell_style: BellStyle::Audible,
+            term_support: TermSupport::Detect,
+            cursor_shape: CursorShape::Default,
+            search_cursor_shape: CursorShape::Default,
         }
     }
 }
 
+/// Source of the configuration re-applied by the re-read-init-file command (Ctrl-X Ctrl-R).
+pub trait ConfigLoader {
+    /// Return the new configuration, or `None` to keep the current one.
+    fn load(&self) -> Option<Config>;
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum HistoryDuplicates {
     AlwaysAdd,
@@ -79,6 +416,121 @@ pub enum CompletionType {
     List,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Activate highlighting if the platform/terminal is supported
+    /// and the `NO_COLOR` environment variable is not set.
+    Enabled,
+    /// Activate highlighting even if the output is not a terminal.
+    Forced,
+    /// Deactivate highlighting.
+    Disabled,
+}
+
+/// Behaviour of Up/Down (Ctrl-P/Ctrl-N) in a multi-line buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpDown {
+    /// Only move between the lines of the buffer.
+    Lines,
+    /// Always move in the history.
+    History,
+    /// Move between the lines of the buffer, and in the history
+    /// when the cursor is on the first (Up) or last (Down) line.
+    HistoryAtEdges,
+}
+
+/// Handling of Enter after a trailing (unescaped) backslash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineContinuation {
+    /// Accept the line, backslash included.
+    Disabled,
+    /// Continue on a new row; the rows are joined with newlines, backslashes removed.
+    Strip,
+    /// Continue on a new row; the rows are joined with newlines, backslashes kept.
+    Keep,
+}
+
+/// Display of a single line prompt wider than the terminal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LongPrompt {
+    /// Keep the start of the prompt, on half of the terminal width, followed by an ellipsis
+    /// (and without its styles), so that the input stays visible.
+    Truncate,
+    /// Wrap the whole prompt over several rows, the input starting after its end.
+    Wrap,
+}
+
+/// Display of the tab characters of the line
+/// (the terminal would move the cursor to its next tab stop, which the editor cannot track)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TabDisplay {
+    /// This number of spaces.
+    Spaces(usize),
+    /// `^I`, the caret notation of Ctrl-I.
+    Caret,
+}
+
+/// Warning given when there is nothing to complete, no match, ...
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BellStyle {
+    /// Ring the terminal bell.
+    Audible,
+    /// Flash the prompt in reverse video until the next refresh (unix only,
+    /// the bell is rung for screen readers).
+    Visible,
+    /// No warning.
+    None,
+}
+
+/// Standard stream the editor renders to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputStreamType {
+    Stdout,
+    /// Render to stderr, so that the program output on stdout can be piped
+    /// while the prompt and the edited line still reach the terminal.
+    Stderr,
+}
+
+/// Support of line editing by the terminal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TermSupport {
+    /// Unsupported if `TERM` is one of the unsupported terminals (unix only).
+    Detect,
+    /// Always edit the line, even if `TERM` is unsupported
+    /// (like inside Emacs vterm which sets `TERM=dumb` but handles escape sequences).
+    Supported,
+    /// Always read the line without editing it (like for a dumb terminal).
+    Unsupported,
+}
+
+/// Meaning of the Esc key (unix only: Windows consoles report Alt by itself)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EscMode {
+    /// Esc followed by another key within `keyseq_timeout` is the Meta prefix,
+    /// otherwise it is a lone Esc.
+    Timeout,
+    /// Esc is a lone Esc unless the next characters are already there
+    /// (escape sequence of a special key): no delay, for vi users.
+    Escape,
+    /// Esc is always the Meta prefix: the next key is awaited,
+    /// so that Meta bindings are kept over slow links.
+    Meta,
+}
+
+/// Cursor shapes (DECSCUSR values on unix).
+/// Windows consoles only distinguish block from underline (bar) cursors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorShape {
+    /// Shape of the terminal (restored when the line is accepted).
+    Default = 0,
+    BlinkingBlock = 1,
+    Block = 2,
+    BlinkingUnderline = 3,
+    Underline = 4,
+    BlinkingBar = 5,
+    Bar = 6,
+}
+
 #[derive(Debug)]
 pub struct Builder {
     p: Config,
@@ -113,6 +565,30 @@ impl Builder {
         self
     }
 
+    /// Set the glob patterns (`*` for any chars, `?` for one char) matching
+    /// the whole lines which are neither added to the history nor saved,
+    /// like `&["*password*", "rm -rf*"]` (or the patterns read from a configuration file).
+    pub fn history_exclude<S: AsRef<str>>(mut self, patterns: &[S]) -> Builder {
+        self.p.history_exclude = patterns.iter().map(|p| String::from(p.as_ref())).collect();
+        self
+    }
+
+    /// Whether only the most recent occurrence of each entry is saved in the history file
+    /// (the older duplicates are kept in memory).
+    /// By default, all the entries are saved.
+    pub fn history_dedup_on_save(mut self, yes: bool) -> Builder {
+        self.p.history_dedup_on_save = yes;
+        self
+    }
+
+    /// Whether Ctrl-R lists the history entries matching the typed chars
+    /// in order (fuzzy search) to select one.
+    /// By default, it searches the history incrementally.
+    pub fn fuzzy_search(mut self, yes: bool) -> Builder {
+        self.p.fuzzy_search = yes;
+        self
+    }
+
     /// Set `completion_type`.
     pub fn completion_type(mut self, completion_type: CompletionType) -> Builder {
         self.p.completion_type = completion_type;
@@ -130,6 +606,252 @@ impl Builder {
         self
     }
 
+    /// Choose how a lone Esc is told apart from the Meta prefix.
+    /// By default, with `keyseq_timeout`.
+    pub fn esc_mode(mut self, esc_mode: EscMode) -> Builder {
+        self.p.esc_mode = esc_mode;
+        self
+    }
+
+    /// Set `idle_timeout` in milliseconds.
+    pub fn idle_timeout(mut self, idle_timeout_ms: i32) -> Builder {
+        self.p.idle_timeout = idle_timeout_ms;
+        self
+    }
+
+    /// Install handlers for SIGINT, SIGTERM, SIGQUIT and SIGHUP (unix only)
+    /// which restore the terminal mode before the process dies.
+    /// Applications with their own handlers should call
+    /// `rustyline::restore_terminal` from them instead.
+    /// By default, no handler is installed.
+    pub fn restore_terminal_on_signal(mut self, yes: bool) -> Builder {
+        self.p.restore_terminal_on_signal = yes;
+        self
+    }
+
+    /// Whether Ctrl-C returns `ReadlineError::InterruptedWithLine`
+    /// with the edited line (when it is not empty).
+    /// By default, `ReadlineError::Interrupted` is returned.
+    pub fn interrupt_with_line(mut self, yes: bool) -> Builder {
+        self.p.interrupt_with_line = yes;
+        self
+    }
+
+    /// Whether Ctrl-Z returns `ReadlineError::Suspended`, leaving the suspension
+    /// to the application.
+    /// By default, the process is suspended (unix only).
+    pub fn report_suspend(mut self, yes: bool) -> Builder {
+        self.p.report_suspend = yes;
+        self
+    }
+
+    /// Whether a resize of the terminal returns `ReadlineError::WindowResized`
+    /// (the edited line is lost).
+    /// By default, the line is redrawn and the edition goes on.
+    pub fn report_resize(mut self, yes: bool) -> Builder {
+        self.p.report_resize = yes;
+        self
+    }
+
+    /// Tell if changes made to recalled history lines are undone when a line is accepted.
+    /// Otherwise, the modified lines are kept in the history
+    /// (except the accepted one which is restored to its original state).
+    /// By default, they are undone.
+    pub fn revert_all_at_newline(mut self, yes: bool) -> Builder {
+        self.p.revert_all_at_newline = yes;
+        self
+    }
+
+    /// Set the string inserted at the beginning of the line by the insert-comment command.
+    pub fn comment_begin(mut self, comment_begin: &'static str) -> Builder {
+        self.p.comment_begin = comment_begin;
+        self
+    }
+
+    /// Automatically insert the closing char when an opening bracket or quote is typed,
+    /// skip over it when it is typed and delete it with its opening char
+    /// when the pair is empty.
+    /// By default, brackets and quotes are not paired.
+    pub fn auto_pair(mut self, yes: bool) -> Builder {
+        self.p.auto_pair = yes;
+        self
+    }
+
+    /// Set the (opening, closing) chars paired when `auto_pair` is enabled.
+    pub fn auto_pairs(mut self, pairs: &'static [(char, char)]) -> Builder {
+        self.p.auto_pairs = pairs;
+        self
+    }
+
+    /// Refuse to accept a line whose brackets or quotes (of `auto_pairs`) do not balance:
+    /// Enter just beeps and the edition goes on. Meta-Enter accepts the line anyway.
+    /// By default, lines are accepted as is.
+    pub fn check_balance(mut self, yes: bool) -> Builder {
+        self.p.check_balance = yes;
+        self
+    }
+
+    /// Whether Ctrl-A/Ctrl-E (Home/End) move to the start/end of the current line
+    /// of a multi-line buffer.
+    /// By default, they move to the start/end of the whole buffer.
+    pub fn line_wise_home_end(mut self, yes: bool) -> Builder {
+        self.p.line_wise_home_end = yes;
+        self
+    }
+
+    /// Set what Up/Down (Ctrl-P/Ctrl-N) do in a multi-line buffer.
+    /// By default, they move between its lines, and in the history from the first/last line.
+    pub fn up_down(mut self, up_down: UpDown) -> Builder {
+        self.p.up_down = up_down;
+        self
+    }
+
+    /// Whether Enter after a trailing backslash continues the line on a new row
+    /// (with the `continuation_prompt`) instead of accepting it.
+    /// By default, the line is accepted.
+    pub fn line_continuation(mut self, continuation: LineContinuation) -> Builder {
+        self.p.line_continuation = continuation;
+        self
+    }
+
+    /// Set the prompt displayed before the rows continuing a line.
+    /// By default, "> ".
+    pub fn continuation_prompt(mut self, prompt: &'static str) -> Builder {
+        self.p.continuation_prompt = prompt;
+        self
+    }
+
+    /// Set how a single line prompt wider than the terminal is displayed.
+    /// By default, it is truncated to half of the terminal width.
+    pub fn long_prompt(mut self, long_prompt: LongPrompt) -> Builder {
+        self.p.long_prompt = long_prompt;
+        self
+    }
+
+    /// Set how the tab characters of the line are displayed.
+    /// By default, each one is displayed as 4 spaces.
+    pub fn tab_display(mut self, tab_display: TabDisplay) -> Builder {
+        self.p.tab_display = tab_display;
+        self
+    }
+
+    /// Whether the pasted text is inserted as a whole, without its chars being
+    /// interpreted as keys (bracketed paste mode, unix only).
+    /// By default, the pasted chars are read as typed ones.
+    pub fn bracketed_paste(mut self, yes: bool) -> Builder {
+        self.p.bracketed_paste = yes;
+        self
+    }
+
+    /// Whether the escape sequences (like the colors copied from a terminal output)
+    /// are removed from the text pasted in bracketed paste mode.
+    /// By default, they are inserted (and displayed in caret notation).
+    pub fn strip_pasted_ansi(mut self, yes: bool) -> Builder {
+        self.p.strip_pasted_ansi = yes;
+        self
+    }
+
+    /// Whether an Enter read in the same burst as the previous keys (a paste without
+    /// bracketed paste mode, or the keys following an injected end of paste) is
+    /// inserted as a newline, so that a pasted command runs only after a typed Enter.
+    /// By default, it accepts the line (as do the keys typed ahead, which are inserted).
+    pub fn confirm_paste(mut self, yes: bool) -> Builder {
+        self.p.confirm_paste = yes;
+        self
+    }
+
+    /// Set the maximum length of the line, in bytes: the chars or the text
+    /// inserted beyond it are rejected with a beep, and the lines read without
+    /// line editing (unsupported terminal, not a tty) or continued on several rows
+    /// are truncated to it.
+    /// By default, 4096.
+    pub fn max_line_len(mut self, len: usize) -> Builder {
+        self.p.max_line_len = len;
+        self
+    }
+
+    /// Set `color_mode`.
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Builder {
+        self.p.color_mode = color_mode;
+        self
+    }
+
+    /// Switch to the alternate screen buffer (smcup) at the first `readline`
+    /// and back to the main one (rmcup) when the editor is dropped,
+    /// so that the user's shell content is restored on exit
+    /// (unix only, see `Config::alternate_screen`).
+    /// By default, the main screen buffer is used.
+    pub fn alternate_screen(mut self, yes: bool) -> Builder {
+        self.p.alternate_screen = yes;
+        self
+    }
+
+    /// Whether or not the `COLUMNS` and `LINES` environment variables
+    /// override the size reported by the terminal.
+    /// By default, they are only used when the size cannot be queried.
+    pub fn prefer_env_size(mut self, yes: bool) -> Builder {
+        self.p.prefer_env_size = yes;
+        self
+    }
+
+    /// Choose the stream the prompt and the edited line are rendered to
+    /// (its size and colors support are the ones queried).
+    /// By default, stdout.
+    pub fn output_stream(mut self, stream: OutputStreamType) -> Builder {
+        self.p.output_stream = stream;
+        self
+    }
+
+    /// Whether or not the controlling terminal (`/dev/tty`) is opened for the input
+    /// and the rendering when stdin or the output stream is redirected (unix only),
+    /// so that the line can be edited inside a pipeline (`foo | app | bar`).
+    /// `readline` fails if the process has no controlling terminal.
+    /// By default, the line is read from stdin without editing when it is redirected.
+    pub fn dev_tty(mut self, yes: bool) -> Builder {
+        self.p.dev_tty = yes;
+        self
+    }
+
+    /// Whether or not the line is rendered for screen readers: its changes are echoed
+    /// linearly (with backspaces, without moving the cursor between rows),
+    /// the rows below it are announced one per line, the completion candidates too,
+    /// and the hints are not displayed.
+    /// The `RUSTYLINE_ACCESSIBLE` environment variable also enables it.
+    /// By default, the line is redrawn in place.
+    pub fn accessible(mut self, yes: bool) -> Builder {
+        self.p.accessible = yes;
+        self
+    }
+
+    /// Choose how the user is warned when there is nothing to complete, no match, ...
+    /// By default, the terminal bell is rung.
+    pub fn bell_style(mut self, bell_style: BellStyle) -> Builder {
+        self.p.bell_style = bell_style;
+        self
+    }
+
+    /// Whether the terminal supports line editing, or if `TERM` decides.
+    /// By default, `TERM` decides.
+    pub fn term_support(mut self, support: TermSupport) -> Builder {
+        self.p.term_support = support;
+        self
+    }
+
+    /// Set the cursor shape used while editing the line
+    /// (the original shape is restored when the line is accepted).
+    /// By default, the shape of the terminal is kept.
+    pub fn cursor_shape(mut self, shape: CursorShape) -> Builder {
+        self.p.cursor_shape = shape;
+        self
+    }
+
+    /// Set the cursor shape used during an incremental search in the history.
+    /// By default, the shape of the terminal is kept.
+    pub fn search_cursor_shape(mut self, shape: CursorShape) -> Builder {
+        self.p.search_cursor_shape = shape;
+        self
+    }
+
     pub fn build(self) -> Config {
         self.p
     }
@@ -1,12 +1,14 @@
-#[derive(Debug, Clone, PartialEq, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
 pub enum Key {
     Backspace,
+    BracketedPaste, // start of a pasted text (see `Config::bracketed_paste`)
     Char(char),
     Delete,
     Down,
     End,
     Enter, // Ctrl('M')
     Esc,
+    F(u8), // function key (only decoded by `Editor::bind_escape_sequence`)
     Home,
     Insert,
     Left,
@@ -19,7 +21,17 @@ pub enum Key {
     Up,
 }
 
-#[derive(Debug, Clone, PartialEq, Copy)]
+/// Key with modifiers.
+/// Ctrl keys are decoded as upper case characters (`Key::Char('A')` for Ctrl-a).
+/// The terminals send the same control character for several keys:
+/// Ctrl-@, Ctrl-Space and Ctrl-2 are decoded as `Key::Null`, Ctrl-[ and Ctrl-3 as `Key::Esc`,
+/// `Ctrl-\` and Ctrl-4 as `Ctrl-\`, Ctrl-] and Ctrl-5 as Ctrl-], Ctrl-^ and Ctrl-6 as Ctrl-^,
+/// Ctrl-_, Ctrl-/ and Ctrl-7 as Ctrl-_, Ctrl-8 as `Key::Backspace`,
+/// and the other Ctrl digits as the digits themselves (bind the first key of each group).
+/// Meta/Alt keys keep the case of their character (`Key::Char('b')` for Meta-b),
+/// and the lower-case ones get the binding of the upper-case ones unless bound
+/// (Meta-b moves backward like Meta-B, see `Editor::bind_sequence`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
 pub struct KeyPress {
     pub key: Key,
     pub alt: bool,
@@ -75,18 +87,25 @@ pub fn char_to_key_press(c: char) -> KeyPress {
         '\x0c' => ctrl!('L'),
         '\x0d' => key!(Key::Enter), // '\r' (13)
         '\x0e' => ctrl!('N'),
+        '\x0f' => ctrl!('O'),
         '\x10' => ctrl!('P'),
+        '\x11' => ctrl!('Q'),
         '\x12' => ctrl!('R'),
         '\x13' => ctrl!('S'),
         '\x14' => ctrl!('T'),
         '\x15' => ctrl!('U'),
         '\x16' => ctrl!('V'),
         '\x17' => ctrl!('W'),
+        '\x18' => ctrl!('X'),
         '\x19' => ctrl!('Y'),
         '\x1a' => ctrl!('Z'),
         '\x1b' => key!(Key::Esc),
+        '\x1c' => ctrl!('\\'),
+        '\x1d' => ctrl!(']'),
+        '\x1e' => ctrl!('^'),
+        '\x1f' => ctrl!('_'),
         '\x7f' => key!(Key::Backspace), // TODO Validate
-        _ => key!(Key::Null),
+        _ => key!(Key::Unknown),
     }
 }
 
@@ -97,5 +116,13 @@ mod tests {
     #[test]
     fn char_to_key() {
         assert_eq!(key!(Key::Esc), char_to_key_press('\x1b'));
+        assert_eq!(key!(Key::Null), char_to_key_press('\x00'));
+        assert_eq!(ctrl!('O'), char_to_key_press('\x0f'));
+        assert_eq!(ctrl!('Q'), char_to_key_press('\x11'));
+        assert_eq!(ctrl!('\\'), char_to_key_press('\x1c'));
+        assert_eq!(ctrl!(']'), char_to_key_press('\x1d'));
+        assert_eq!(ctrl!('^'), char_to_key_press('\x1e'));
+        assert_eq!(ctrl!('_'), char_to_key_press('\x1f'));
+        assert_eq!(key!(Key::Unknown), char_to_key_press('\u{85}'));
     }
 }
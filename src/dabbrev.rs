@@ -0,0 +1,79 @@
+//! Dynamic abbreviation expansion (like Emacs' dabbrev)
+use std::collections::HashSet;
+
+use history::HistoryBackend;
+
+/// Tell if `c` is part of a word to expand.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Return the start of the word ending at `pos` in `line`.
+pub fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_word_char(c))
+        .last()
+        .map_or(pos, |(i, _)| i)
+}
+
+/// Words starting with `prefix`, in the order they should be proposed:
+/// words of `line` before the abbreviation (nearest first), words after it,
+/// then words of the history (most recent first).
+/// `start` and `pos` delimit the abbreviation itself in `line`.
+pub fn expansions(line: &str,
+                  start: usize,
+                  pos: usize,
+                  prefix: &str,
+                  history: &HistoryBackend)
+                  -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut expansions = Vec::new();
+    {
+        let mut add = |word: &str| {
+            if word.len() > prefix.len() && word.starts_with(prefix) &&
+               seen.insert(String::from(word)) {
+                expansions.push(String::from(word));
+            }
+        };
+        for word in words(&line[..start]).into_iter().rev() {
+            add(word);
+        }
+        for word in words(&line[pos..]) {
+            add(word);
+        }
+        for entry in history.iter().rev() {
+            for word in words(entry).into_iter().rev() {
+                add(word);
+            }
+        }
+    }
+    expansions
+}
+
+fn words(s: &str) -> Vec<&str> {
+    s.split(|c: char| !is_word_char(c)).filter(|w| !w.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use history::History;
+
+    #[test]
+    fn word_start() {
+        assert_eq!(4, super::word_start("foo bar", 7));
+        assert_eq!(8, super::word_start("foo bar ", 8));
+        assert_eq!(0, super::word_start("foo_bar", 5));
+    }
+
+    #[test]
+    fn expansions() {
+        let mut history = History::new();
+        history.add("select item from items");
+        history.add("insert into items");
+        let line = "it item_count it iterator";
+        assert_eq!(vec!["item_count", "iterator", "items", "item"],
+                   super::expansions(line, 14, 16, "it", &history));
+    }
+}
@@ -0,0 +1,339 @@
+//! Decoding of the bytes sent by the terminal as keys (the escape sequences
+//! of xterm and VT100), shared by the unix backend and `decode_keys`:
+//! the CSI sequences are parsed generically, then their keys looked up in the tables
+use char_iter::{self, CharsError};
+use consts::{self, Key, KeyPress};
+use error::ReadlineError;
+use key_table::KeyTable;
+use ::Result;
+
+/// Chars of the input, read one at a time while a sequence is decoded.
+pub trait CharSource {
+    /// Next char (`ReadlineError::Eof` at the end of input).
+    fn next_char(&mut self) -> Result<char>;
+    /// Tell if the next char is available without waiting.
+    fn poll(&mut self) -> Result<bool>;
+}
+
+/// Keys of the CSI sequences ending with `~` (VT220 style), by their first parameter.
+const TILDE_KEYS: &'static [(u32, Key)] = &[(1, Key::Home),
+                                             (3, Key::Delete),
+                                             (4, Key::End), // xterm
+                                             (5, Key::PageUp),
+                                             (6, Key::PageDown),
+                                             (7, Key::Home),
+                                             (8, Key::End)];
+
+/// Keys of the CSI (without parameter or with 1 as first parameter) and SS3 sequences
+/// (xterm style), by their final byte.
+const FINAL_KEYS: &'static [(char, Key)] = &[('A', Key::Up),
+                                              ('B', Key::Down),
+                                              ('C', Key::Right),
+                                              ('D', Key::Left),
+                                              ('F', Key::End),
+                                              ('H', Key::Home)];
+
+/// Parameters and final byte of a CSI sequence: `Esc [ <parameters> <intermediates> <final>`.
+#[derive(Debug, PartialEq)]
+struct Csi {
+    params: Vec<u32>, // 0 when omitted
+    private: bool, // private parameters or intermediate bytes, never decoded
+    final_byte: char,
+}
+
+/// Read a CSI sequence up to its final byte (`None` if ended by an invalid char).
+fn read_csi<S: CharSource>(source: &mut S) -> Result<Option<Csi>> {
+    let mut params = vec![0u32];
+    let mut private = false;
+    let mut intermediate = false;
+    loop {
+        let c = try!(source.next_char());
+        match c {
+            '0'...'9' if !intermediate => {
+                let param = params.last_mut().unwrap();
+                *param = param.saturating_mul(10).saturating_add(c as u32 - '0' as u32);
+            }
+            ';' if !intermediate => params.push(0),
+            // ':', '<', '=', '>', '?' or a parameter after an intermediate byte
+            '0'...'?' => private = true,
+            ' '...'/' => {
+                intermediate = true;
+                private = true;
+            }
+            '@'...'~' => {
+                return Ok(Some(Csi {
+                    params: params,
+                    private: private,
+                    final_byte: c,
+                }))
+            }
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Modifiers encoded by xterm as the parameter `1 + (shift | alt << 1 | ctrl << 2)`
+/// (0 when omitted).
+fn modifiers(param: u32) -> Option<KeyPress> {
+    let bits = match param {
+        0 | 1 => 0,
+        2...8 => param - 1,
+        _ => return None,
+    };
+    let mut key = key!(Key::Unknown);
+    key.shift = bits & 1 != 0;
+    key.alt = bits & 2 != 0;
+    key.ctrl = bits & 4 != 0;
+    Some(key)
+}
+
+fn lookup<T: PartialEq>(table: &[(T, Key)], code: T) -> Option<Key> {
+    table.iter().find(|entry| entry.0 == code).map(|entry| entry.1)
+}
+
+/// Key of a CSI sequence (`Key::Unknown` if not in the tables).
+fn csi_key(csi: &Csi) -> KeyPress {
+    if csi.private || csi.params.len() > 2 {
+        return key!(Key::Unknown);
+    }
+    if csi.final_byte == '~' && csi.params == [200] {
+        return key!(Key::BracketedPaste);
+    }
+    let key = if csi.final_byte == '~' {
+        lookup(TILDE_KEYS, csi.params[0])
+    } else if csi.params[0] <= 1 {
+        lookup(FINAL_KEYS, csi.final_byte)
+    } else {
+        None
+    };
+    match (key, modifiers(csi.params.get(1).cloned().unwrap_or(0))) {
+        (Some(key), Some(mut press)) => {
+            press.key = key;
+            press
+        }
+        _ => key!(Key::Unknown),
+    }
+}
+
+/// Decode the sequence following an Esc: a known escape sequence, Esc followed by a char
+/// (Meta), or `Key::Unknown` (an unknown CSI sequence being read up to its final byte
+/// so that its tail is not inserted in the line).
+pub fn escape_sequence<S: CharSource>(source: &mut S) -> Result<KeyPress> {
+    match try!(source.next_char()) {
+        '[' => Ok(match try!(read_csi(source)) {
+            Some(csi) => csi_key(&csi),
+            None => key!(Key::Unknown),
+        }),
+        'O' => {
+            let mut press = key!(Key::Unknown);
+            if let Some(key) = lookup(FINAL_KEYS, try!(source.next_char())) {
+                press.key = key;
+            }
+            Ok(press)
+        }
+        '\x08' => Ok(alt!('\x08') ), // Backspace
+        '\r' => Ok(alt!(Key::Enter) ),
+        '\x1b' => Ok(alt!(Key::Esc) ),
+        '\x7f' => Ok(alt!('\x7f') ), // Delete
+        // Meta followed by any other char (in its case) or Ctrl key
+        c => {
+            let mut key = consts::char_to_key_press(c);
+            key.alt = true;
+            Ok(key)
+        }
+    }
+}
+
+/// Match the chars following an Esc against the sequences registered by the application
+/// (the longest one wins). Return its key, if any, and the chars read after it,
+/// to be read again.
+pub fn custom_sequence<S: CharSource>(source: &mut S,
+                                      table: &KeyTable)
+                                      -> Result<(Option<KeyPress>, String)> {
+    let mut node = match table.root().child('\x1b') {
+        Some(node) => node,
+        None => return Ok((None, String::new())),
+    };
+    let mut read = String::new();
+    let mut matched = None; // key and length of the longest sequence
+    loop {
+        if let Some(key) = node.key() {
+            matched = Some((key, read.len()));
+            // a longer sequence is not awaited
+            if node.is_leaf() || !try!(source.poll()) {
+                break;
+            }
+        }
+        let c = match source.next_char() {
+            Ok(c) => c,
+            // cut sequence
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        };
+        read.push(c);
+        node = match node.child(c) {
+            Some(child) => child,
+            None => break,
+        };
+    }
+    Ok(match matched {
+        Some((key, len)) => (Some(key), String::from(&read[len..])),
+        None => (None, read),
+    })
+}
+
+/// Chars of a byte slice.
+struct Bytes<'b> {
+    chars: char_iter::Chars<&'b [u8]>,
+    replay: Vec<char>, // chars to read again, the next one last
+}
+
+impl<'b> Bytes<'b> {
+    fn is_empty(&self) -> bool {
+        self.replay.is_empty() && self.chars.get_ref().is_empty()
+    }
+}
+
+impl<'b> CharSource for Bytes<'b> {
+    fn next_char(&mut self) -> Result<char> {
+        if let Some(c) = self.replay.pop() {
+            return Ok(c);
+        }
+        match self.chars.next() {
+            Some(Ok(c)) => Ok(c),
+            Some(Err(CharsError::NotUtf8(bytes))) => Err(ReadlineError::InvalidUtf8(bytes)),
+            Some(Err(CharsError::Other(err))) => Err(ReadlineError::Io(err)),
+            None => Err(ReadlineError::Eof),
+        }
+    }
+
+    fn poll(&mut self) -> Result<bool> {
+        Ok(!self.is_empty())
+    }
+}
+
+/// Decode `bytes`, received at once from a terminal, as keys: like the unix backend,
+/// except for the sequences registered with `Editor::bind_escape_sequence`.
+/// An Esc ending `bytes` is a single Esc, and the invalid UTF-8 and the sequences
+/// cut by the end of `bytes` are decoded as `Key::Unknown`.
+pub fn decode_keys(bytes: &[u8]) -> Vec<KeyPress> {
+    decode_keys_with(bytes, &KeyTable::default())
+}
+
+/// Decode `bytes` as keys, with the sequences of `table`.
+fn decode_keys_with(bytes: &[u8], table: &KeyTable) -> Vec<KeyPress> {
+    let mut source = Bytes {
+        chars: char_iter::chars(bytes),
+        replay: Vec::new(),
+    };
+    let mut keys = Vec::new();
+    loop {
+        let key = match source.next_char() {
+            Ok(c) => consts::char_to_key_press(c),
+            Err(ReadlineError::Eof) => return keys,
+            Err(_) => key!(Key::Unknown),
+        };
+        if key == key!(Key::Esc) && !source.is_empty() {
+            let key = match custom_sequence(&mut source, table) {
+                Ok((key, rest)) => {
+                    source.replay.extend(rest.chars().rev());
+                    key
+                }
+                Err(_) => Some(key!(Key::Unknown)),
+            };
+            keys.push(key.unwrap_or_else(|| {
+                escape_sequence(&mut source).unwrap_or(key!(Key::Unknown))
+            }));
+        } else {
+            keys.push(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use consts::{Key, KeyPress};
+    use key_table::KeyTable;
+    use super::{decode_keys, decode_keys_with, FINAL_KEYS, TILDE_KEYS};
+
+    #[test]
+    fn decode() {
+        assert_eq!(vec![key!('a'), key!(Key::Up), ctrl!(Key::Left), key!(Key::Delete)],
+                   decode_keys(b"a\x1b[A\x1b[1;5D\x1b[3~"));
+        assert_eq!(vec![alt!('b'), alt!('\x7f'), key!(Key::Esc)],
+                   decode_keys(b"\x1bb\x1b\x7f\x1b"));
+        // the tail of an unknown CSI sequence is not inserted
+        assert_eq!(vec![key!(Key::Unknown), key!('x')], decode_keys(b"\x1b[12;5Zx"));
+        assert_eq!(vec![key!(Key::Unknown), key!('x')], decode_keys(b"\x1b[200;1~x"));
+        // cut sequence, invalid UTF-8
+        assert_eq!(vec![key!('x'), key!(Key::Unknown)], decode_keys(b"x\x1b[1;"));
+        assert_eq!(vec![key!(Key::Unknown), key!('\u{e9}')], decode_keys(b"\xff\xc3\xa9"));
+        assert_eq!(vec![key!(Key::Unknown), key!('a')], decode_keys(b"\x1b\xffa"));
+        assert!(decode_keys(b"").is_empty());
+    }
+
+    #[test]
+    fn tables() {
+        for modifier in 1..9 {
+            let bits = modifier - 1;
+            let mut expected = key!(Key::Unknown);
+            expected.shift = bits & 1 != 0;
+            expected.alt = bits & 2 != 0;
+            expected.ctrl = bits & 4 != 0;
+            for &(code, key) in TILDE_KEYS {
+                expected.key = key;
+                let seq = format!("\x1b[{};{}~", code, modifier);
+                assert_eq!(vec![expected], decode_keys(seq.as_bytes()), "{:?}", seq);
+                if modifier == 1 {
+                    let seq = format!("\x1b[{}~", code);
+                    assert_eq!(vec![expected], decode_keys(seq.as_bytes()), "{:?}", seq);
+                }
+            }
+            for &(c, key) in FINAL_KEYS {
+                expected.key = key;
+                let seq = format!("\x1b[1;{}{}", modifier, c);
+                assert_eq!(vec![expected], decode_keys(seq.as_bytes()), "{:?}", seq);
+                if modifier == 1 {
+                    for seq in &[format!("\x1b[{}", c), format!("\x1bO{}", c)] {
+                        assert_eq!(vec![expected], decode_keys(seq.as_bytes()), "{:?}", seq);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn custom_sequences() {
+        let mut table = KeyTable::default();
+        table.insert("\x1b[1", key!(Key::Home));
+        table.insert("\x1b[15~", key!(Key::F(5)));
+        table.insert("\x1bOP", key!(Key::F(1)));
+        // bound sequences, the longest one winning
+        assert_eq!(vec![key!(Key::F(5)), key!(Key::F(1))],
+                   decode_keys_with(b"\x1b[15~\x1bOP", &table));
+        // partial match: the chars after the shorter sequence are read again
+        assert_eq!(vec![key!(Key::Home), key!('5'), key!('x')],
+                   decode_keys_with(b"\x1b[15x", &table));
+        assert_eq!(vec![key!(Key::Home)], decode_keys_with(b"\x1b[1", &table));
+        // mismatch: the chars are decoded again as a known sequence or a Meta key
+        assert_eq!(vec![key!(Key::Up), alt!('b'), key!(Key::Delete)],
+                   decode_keys_with(b"\x1bOA\x1bb\x1b[3~", &table));
+        assert_eq!(vec![key!(Key::Esc)], decode_keys_with(b"\x1b", &table));
+    }
+
+    #[test]
+    fn unknown_csi() {
+        // unknown modifiers, parameters, private or intermediate bytes
+        for seq in &["\x1b[1;9A", "\x1b[2;5A", "\x1b[1;5;1A", "\x1b[9~", "\x1b[?1~", "\x1b[3 ~",
+                     "\x1b[1:5A", "\x1b[99999999999~"] {
+            assert_eq!(vec![key!(Key::Unknown), key!('x')],
+                       decode_keys(format!("{}x", seq).as_bytes()),
+                       "{:?}",
+                       seq);
+        }
+        assert_eq!(vec![key!(Key::BracketedPaste)], decode_keys(b"\x1b[200~"));
+        // ended by an invalid char
+        assert_eq!(vec![key!(Key::Unknown), key!('x')], decode_keys(b"\x1b[1\x07x"));
+        assert_eq!(vec![key!(Key::Unknown), key!('x')], decode_keys(b"\x1bOZx"));
+    }
+}
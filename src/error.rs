@@ -4,8 +4,10 @@ use std::char;
 use std::io;
 use std::error;
 use std::fmt;
-#[cfg(unix)]
+#[cfg(all(unix, feature = "nix"))]
 use nix;
+#[cfg(feature = "sqlite")]
+use rusqlite;
 
 #[cfg(unix)]
 use char_iter;
@@ -16,20 +18,34 @@ use char_iter;
 pub enum ReadlineError {
     /// I/O Error
     Io(io::Error),
-    /// EOF (Ctrl-D)
+    /// EOF (Ctrl-D), or end of input (closed terminal, disconnected remote client)
     Eof,
     /// Ctrl-C
     Interrupted,
+    /// Ctrl-C with the edited line, when it is not empty
+    /// (see `Config::interrupt_with_line`)
+    InterruptedWithLine(String),
+    /// Ctrl-Z, when the suspension is left to the application
+    /// (see `Config::report_suspend`)
+    Suspended,
+    /// The terminal was resized, when it is reported to the application
+    /// (see `Config::report_resize`)
+    WindowResized,
+    /// Input bytes which are not valid UTF-8
+    InvalidUtf8(Vec<u8>),
     /// Chars Error
     #[cfg(unix)]
     Char(char_iter::CharsError),
-    /// Unix Error from syscall
-    #[cfg(unix)]
+    /// Unix Error from syscall (with nix, see the `minimal` feature)
+    #[cfg(all(unix, feature = "nix"))]
     Errno(nix::Error),
     #[cfg(windows)]
     WindowResize,
     #[cfg(windows)]
     Decode(char::DecodeUtf16Error),
+    /// Error of the SQLite history
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
 }
 
 impl fmt::Display for ReadlineError {
@@ -38,14 +54,20 @@ impl fmt::Display for ReadlineError {
             ReadlineError::Io(ref err) => err.fmt(f),
             ReadlineError::Eof => write!(f, "EOF"),
             ReadlineError::Interrupted => write!(f, "Interrupted"),
+            ReadlineError::InterruptedWithLine(ref line) => write!(f, "Interrupted: {:?}", line),
+            ReadlineError::Suspended => write!(f, "Suspended"),
+            ReadlineError::WindowResized => write!(f, "Window resized"),
+            ReadlineError::InvalidUtf8(ref bytes) => write!(f, "Invalid UTF-8: {:?}", bytes),
             #[cfg(unix)]
             ReadlineError::Char(ref err) => err.fmt(f),
-            #[cfg(unix)]
+            #[cfg(all(unix, feature = "nix"))]
             ReadlineError::Errno(ref err) => write!(f, "Errno: {}", err.errno().desc()),
             #[cfg(windows)]
             ReadlineError::WindowResize => write!(f, "WindowResize"),
             #[cfg(windows)]
             ReadlineError::Decode(ref err) => err.fmt(f),
+            #[cfg(feature = "sqlite")]
+            ReadlineError::Sqlite(ref err) => err.fmt(f),
         }
     }
 }
@@ -55,15 +77,36 @@ impl error::Error for ReadlineError {
         match *self {
             ReadlineError::Io(ref err) => err.description(),
             ReadlineError::Eof => "EOF",
-            ReadlineError::Interrupted => "Interrupted",
+            ReadlineError::Interrupted |
+            ReadlineError::InterruptedWithLine(_) => "Interrupted",
+            ReadlineError::Suspended => "Suspended",
+            ReadlineError::WindowResized => "Window resized",
+            ReadlineError::InvalidUtf8(_) => "Invalid UTF-8",
             #[cfg(unix)]
             ReadlineError::Char(ref err) => err.description(),
-            #[cfg(unix)]
+            #[cfg(all(unix, feature = "nix"))]
             ReadlineError::Errno(ref err) => err.errno().desc(),
             #[cfg(windows)]
             ReadlineError::WindowResize => "WindowResize",
             #[cfg(windows)]
             ReadlineError::Decode(ref err) => err.description(),
+            #[cfg(feature = "sqlite")]
+            ReadlineError::Sqlite(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ReadlineError::Io(ref err) => Some(err),
+            #[cfg(unix)]
+            ReadlineError::Char(ref err) => Some(err),
+            #[cfg(all(unix, feature = "nix"))]
+            ReadlineError::Errno(ref err) => Some(err),
+            #[cfg(windows)]
+            ReadlineError::Decode(ref err) => Some(err),
+            #[cfg(feature = "sqlite")]
+            ReadlineError::Sqlite(ref err) => Some(err),
+            _ => None,
         }
     }
 }
@@ -74,7 +117,7 @@ impl From<io::Error> for ReadlineError {
     }
 }
 
-#[cfg(unix)]
+#[cfg(all(unix, feature = "nix"))]
 impl From<nix::Error> for ReadlineError {
     fn from(err: nix::Error) -> ReadlineError {
         ReadlineError::Errno(err)
@@ -84,7 +127,10 @@ impl From<nix::Error> for ReadlineError {
 #[cfg(unix)]
 impl From<char_iter::CharsError> for ReadlineError {
     fn from(err: char_iter::CharsError) -> ReadlineError {
-        ReadlineError::Char(err)
+        match err {
+            char_iter::CharsError::NotUtf8(bytes) => ReadlineError::InvalidUtf8(bytes),
+            err => ReadlineError::Char(err),
+        }
     }
 }
 
@@ -94,3 +140,36 @@ impl From<char::DecodeUtf16Error> for ReadlineError {
         ReadlineError::Decode(err)
     }
 }
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for ReadlineError {
+    fn from(err: rusqlite::Error) -> ReadlineError {
+        ReadlineError::Sqlite(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::io;
+    use super::ReadlineError;
+
+    #[test]
+    fn cause() {
+        let err = ReadlineError::from(io::Error::new(io::ErrorKind::Other, "oops"));
+        assert_eq!("oops", err.cause().unwrap().to_string());
+        assert!(ReadlineError::Eof.cause().is_none());
+        assert_eq!("Interrupted: \"ls\"",
+                   ReadlineError::InterruptedWithLine(String::from("ls")).to_string());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8() {
+        use char_iter::CharsError;
+        match ReadlineError::from(CharsError::NotUtf8(vec![0xc3, 0x28])) {
+            ReadlineError::InvalidUtf8(bytes) => assert_eq!(vec![0xc3, 0x28], bytes),
+            err => panic!("{:?}", err),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Hook called for each self-inserted char
+//! (to reject or transform it before the line is modified)
+
+/// Filter of the chars typed by the user.
+pub trait InsertFilter {
+    /// Called before `ch` is inserted at `pos` in `line`.
+    /// Return the char to insert instead, or `None` to reject it (the bell rings).
+    /// The chars inserted with `Cmd::QuotedInsert` are not filtered.
+    fn filter(&self, line: &str, pos: usize, ch: char) -> Option<char>;
+}
+
+impl<F> InsertFilter for F
+    where F: Fn(&str, usize, char) -> Option<char>
+{
+    fn filter(&self, line: &str, pos: usize, ch: char) -> Option<char> {
+        self(line, pos, ch)
+    }
+}
+
+/// Reject the non-ASCII chars (like in a host name).
+#[derive(Debug, Default)]
+pub struct AsciiOnly;
+
+impl InsertFilter for AsciiOnly {
+    fn filter(&self, _: &str, _: usize, ch: char) -> Option<char> {
+        if (ch as u32) < 0x80 { Some(ch) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsciiOnly, InsertFilter};
+
+    #[test]
+    fn ascii_only() {
+        assert_eq!(Some('a'), AsciiOnly.filter("", 0, 'a'));
+        assert_eq!(None, AsciiOnly.filter("", 0, 'é'));
+    }
+
+    #[test]
+    fn closure() {
+        let upper_hex = |_: &str, _: usize, ch: char| if ch.is_digit(16) {
+            ch.to_uppercase().next()
+        } else {
+            None
+        };
+        assert_eq!(Some('F'), upper_hex.filter("0x", 2, 'f'));
+        assert_eq!(None, upper_hex.filter("0x", 2, 'g'));
+    }
+}
@@ -0,0 +1,162 @@
+//! Fuzzy history search listing the matching entries below the edited line
+use std::cmp;
+use std::collections::HashSet;
+
+use consts::{Key, KeyPress};
+use history::HistoryBackend;
+use tty::RawReader;
+use super::Result;
+use {State, calculate_position};
+
+/// Score of `entry` when the chars of `pattern` appear in it in order
+/// (case insensitive), or `None`.
+/// Consecutive chars and chars at the start of a word score higher.
+pub fn score(pattern: &str, entry: &str) -> Option<usize> {
+    let mut score = 0;
+    let mut pattern_chars = pattern.chars().flat_map(char::to_lowercase).peekable();
+    // previous char of the entry, and whether it was matched
+    let mut prev: Option<(char, bool)> = None;
+    for c in entry.chars() {
+        let matched = match pattern_chars.peek() {
+            Some(&p) => c.to_lowercase().eq(Some(p).into_iter()),
+            None => break,
+        };
+        if matched {
+            pattern_chars.next();
+            score += 1;
+            match prev {
+                Some((_, true)) => score += 2,
+                Some((p, false)) if !p.is_alphanumeric() => score += 1,
+                None => score += 1,
+                _ => {}
+            }
+        }
+        prev = Some((c, matched));
+    }
+    if pattern_chars.peek().is_some() {
+        None
+    } else {
+        Some(score)
+    }
+}
+
+/// Index of the last occurrence of the distinct history entries matching `pattern`,
+/// the best scored first (the most recent first when the scores are equal).
+pub fn filter(history: &HistoryBackend, pattern: &str) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    let mut matches = Vec::new();
+    for index in (0..history.len()).rev() {
+        let entry = &history[index];
+        if !seen.insert(entry.as_str()) {
+            continue;
+        }
+        if let Some(score) = score(pattern, entry) {
+            matches.push((score, index));
+        }
+    }
+    // stable sort: the most recent first among equal scores
+    matches.sort_by(|a, b| b.0.cmp(&a.0));
+    matches.into_iter().map(|(_, index)| index).collect()
+}
+
+/// Let the user select a history entry by typing some of its chars:
+/// Up/Down (or Ctrl-P/Ctrl-N, Ctrl-S/Ctrl-R) move the selection,
+/// Enter accepts it and Esc (or Ctrl-G) cancels.
+/// The list is initially filtered by the edited line.
+pub fn select<R: RawReader>(rdr: &mut R,
+                            s: &mut State,
+                            history: &HistoryBackend,
+                            keyseq_timeout: i32)
+                            -> Result<Option<usize>> {
+    let mut pattern = s.line.as_str().to_owned();
+    let mut selected = 0;
+    let mut choice = None;
+    loop {
+        let matches = filter(history, &pattern);
+        // keep the edited line, the status bar and the search prompt visible
+        let input_rows = calculate_position(&s.displayed().0, s.prompt_size, s.cols).row + 1;
+        let reserved = input_rows + s.status.len() + 1;
+        let height = cmp::max(1, s.term.get_rows().saturating_sub(reserved));
+        let top = if selected < height { 0 } else { selected + 1 - height };
+        s.menu.clear();
+        s.menu.push(format!("History ({}/{}): {}", matches.len(), history.len(), pattern));
+        for (i, &index) in matches.iter().enumerate().skip(top).take(height) {
+            let marker = if i == selected { '>' } else { ' ' };
+            // one row per entry
+            s.menu.push(format!("{} {}", marker, history[index].replace('\n', " ")));
+        }
+        try!(s.refresh_line());
+
+        match try!(rdr.next_key(keyseq_timeout)) {
+            key!(Key::Enter) |
+            ctrl!('J') => {
+                if let Some(&index) = matches.get(selected) {
+                    choice = Some(index);
+                    break;
+                }
+                try!(s.beep());
+            }
+            key!(Key::Up) |
+            ctrl!('P') |
+            ctrl!('S') => {
+                selected = selected.saturating_sub(1);
+            }
+            key!(Key::Down) |
+            ctrl!('N') |
+            ctrl!('R') => {
+                if selected + 1 < matches.len() {
+                    selected += 1;
+                }
+            }
+            key!(Key::Backspace) |
+            ctrl!('H') => {
+                pattern.pop();
+                selected = 0;
+            }
+            key!(c) => {
+                pattern.push(c);
+                selected = 0;
+            }
+            key!(Key::Esc) |
+            ctrl!('G') |
+            ctrl!('C') => break,
+            _ => try!(s.beep()),
+        }
+    }
+    s.menu.clear();
+    try!(s.refresh_line());
+    Ok(choice)
+}
+
+#[cfg(test)]
+mod tests {
+    use history::History;
+    use super::{filter, score};
+
+    #[test]
+    fn fuzzy_score() {
+        assert_eq!(Some(0), score("", "ls"));
+        assert_eq!(None, score("sl", "ls"));
+        assert!(score("gco", "git checkout").is_some());
+        assert!(score("GCO", "git checkout").is_some());
+        assert!(score("che", "git checkout") > score("che", "cache entry"));
+        assert!(score("gc", "git commit") > score("gc", "tag cut"));
+    }
+
+    #[test]
+    fn filter_history() {
+        let mut history = History::new();
+        history.add("git commit");
+        history.add("cargo check");
+        history.add("git checkout");
+        history.add("git commit");
+        history.add("ls");
+        let entries = |matches: Vec<usize>| -> Vec<String> {
+            matches.into_iter().map(|i| history[i].clone()).collect()
+        };
+        assert_eq!(vec!["ls", "git commit", "git checkout", "cargo check"],
+                   entries(filter(&history, "")));
+        assert_eq!(vec!["git checkout", "cargo check"], entries(filter(&history, "chk")));
+        assert_eq!(vec!["git commit", "git checkout"], entries(filter(&history, "gitc")));
+    }
+}
@@ -0,0 +1,374 @@
+//! Syntax highlighting
+use std::borrow::Cow;
+
+use auto_pair::{self, DEFAULT_PAIRS};
+
+/// Syntax highlighter with ANSI color.
+/// The highlighted text must have the same display width as the original one
+/// (only escape sequences may be added, see `Style::paint`).
+/// It is only used when colors are enabled (see `Config::color_mode`).
+pub trait Highlighter {
+    /// Takes the currently edited `line` with the cursor `pos`ition and
+    /// returns the highlighted version (with ANSI color).
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let _ = pos;
+        Cow::Borrowed(line)
+    }
+
+    /// Takes the `hint` and returns the highlighted version (with ANSI color).
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Borrowed(hint)
+    }
+
+    /// Takes the text of the line matched by the incremental search (Ctrl-R)
+    /// and returns the highlighted version, in bold by default.
+    fn highlight_search_match<'m>(&self, matched: &'m str) -> Cow<'m, str> {
+        Cow::Owned(format!("\x1b[1m{}\x1b[22m", matched))
+    }
+
+    /// Takes the text of the active region (between the mark and the cursor)
+    /// and returns the highlighted version, in reverse video by default.
+    fn highlight_region<'r>(&self, region: &'r str) -> Cow<'r, str> {
+        Cow::Owned(format!("\x1b[7m{}\x1b[27m", region))
+    }
+}
+
+impl Highlighter for () {}
+
+/// Highlight the bracket matching the one under (or just before) the cursor.
+pub struct MatchingBracketHighlighter {
+    style: Style,
+}
+
+impl MatchingBracketHighlighter {
+    pub fn new() -> MatchingBracketHighlighter {
+        MatchingBracketHighlighter::with_style(Style::new().bold().fg(Color::Ansi(4)))
+    }
+
+    pub fn with_style(style: Style) -> MatchingBracketHighlighter {
+        MatchingBracketHighlighter { style: style }
+    }
+}
+
+impl Default for MatchingBracketHighlighter {
+    fn default() -> MatchingBracketHighlighter {
+        MatchingBracketHighlighter::new()
+    }
+}
+
+impl Highlighter for MatchingBracketHighlighter {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let before = line[..pos].chars().next_back().map(|c| pos - c.len_utf8());
+        let matching = auto_pair::matching_bracket(line, pos, DEFAULT_PAIRS).or_else(|| {
+            before.and_then(|pos| auto_pair::matching_bracket(line, pos, DEFAULT_PAIRS))
+        });
+        match matching {
+            Some(m) => {
+                let end = m + line[m..].chars().next().map_or(0, |c| c.len_utf8());
+                let bracket = self.style.paint(&line[m..end]);
+                Cow::Owned(format!("{}{}{}", &line[..m], bracket, &line[end..]))
+            }
+            None => Cow::Borrowed(line),
+        }
+    }
+}
+
+/// Number of colors supported by the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    /// 8 colors and their bright variants
+    Ansi16,
+    /// xterm 256-color palette
+    Ansi256,
+    /// 24-bit RGB colors
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Guess the color depth from the `TERM` and `COLORTERM` environment variables.
+    pub fn from_env(term: Option<&str>, colorterm: Option<&str>) -> ColorDepth {
+        match colorterm {
+            Some("truecolor") | Some("24bit") => return ColorDepth::TrueColor,
+            _ => {}
+        }
+        match term {
+            Some(term) if term.ends_with("-direct") => ColorDepth::TrueColor,
+            Some(term) if term.contains("256color") => ColorDepth::Ansi256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+}
+
+/// Terminal color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    /// One of the 16 basic colors (0-7 normal, 8-15 bright)
+    Ansi(u8),
+    /// One of the 256 colors of the xterm palette
+    Fixed(u8),
+    /// 24-bit color
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn push_sgr(&self, base: u8, sgr: &mut Vec<String>) {
+        match *self {
+            Color::Ansi(n) if n < 8 => sgr.push((base + n).to_string()),
+            Color::Ansi(n) => sgr.push((base + 60 + (n & 7)).to_string()),
+            Color::Fixed(n) => sgr.push(format!("{};5;{}", base + 8, n)),
+            Color::Rgb(r, g, b) => sgr.push(format!("{};2;{};{};{}", base + 8, r, g, b)),
+        }
+    }
+}
+
+/// Text style (colors and attributes) used by `Highlighter` implementations.
+/// Colors unsupported by the terminal are downgraded when the line is displayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    dim: bool,
+    underline: bool,
+}
+
+impl Style {
+    pub fn new() -> Style {
+        Style::default()
+    }
+
+    /// Set the foreground color.
+    pub fn fg(mut self, color: Color) -> Style {
+        self.fg = Some(color);
+        self
+    }
+
+    /// Set the background color.
+    pub fn bg(mut self, color: Color) -> Style {
+        self.bg = Some(color);
+        self
+    }
+
+    pub fn bold(mut self) -> Style {
+        self.bold = true;
+        self
+    }
+
+    pub fn dim(mut self) -> Style {
+        self.dim = true;
+        self
+    }
+
+    pub fn underline(mut self) -> Style {
+        self.underline = true;
+        self
+    }
+
+    /// Return `text` surrounded by the ANSI escape sequences of this style.
+    pub fn paint(&self, text: &str) -> String {
+        let mut sgr = Vec::new();
+        if self.bold {
+            sgr.push(String::from("1"));
+        }
+        if self.dim {
+            sgr.push(String::from("2"));
+        }
+        if self.underline {
+            sgr.push(String::from("4"));
+        }
+        if let Some(fg) = self.fg {
+            fg.push_sgr(30, &mut sgr);
+        }
+        if let Some(bg) = self.bg {
+            bg.push_sgr(40, &mut sgr);
+        }
+        if sgr.is_empty() {
+            return String::from(text);
+        }
+        format!("\x1b[{}m{}\x1b[0m", sgr.join(";"), text)
+    }
+}
+
+static ANSI_PALETTE: [(u8, u8, u8); 16] = [(0, 0, 0),
+                                           (205, 0, 0),
+                                           (0, 205, 0),
+                                           (205, 205, 0),
+                                           (0, 0, 238),
+                                           (205, 0, 205),
+                                           (0, 205, 205),
+                                           (229, 229, 229),
+                                           (127, 127, 127),
+                                           (255, 0, 0),
+                                           (0, 255, 0),
+                                           (255, 255, 0),
+                                           (92, 92, 255),
+                                           (255, 0, 255),
+                                           (0, 255, 255),
+                                           (255, 255, 255)];
+
+/// RGB value of the color `n` of the xterm 256-color palette.
+fn fixed_to_rgb(n: u8) -> (u8, u8, u8) {
+    if n < 16 {
+        ANSI_PALETTE[n as usize]
+    } else if n < 232 {
+        let level = |v: u8| if v == 0 { 0 } else { 55 + 40 * v };
+        let i = n - 16;
+        (level(i / 36), level(i / 6 % 6), level(i % 6))
+    } else {
+        let gray = 8 + 10 * (n - 232);
+        (gray, gray, gray)
+    }
+}
+
+/// Nearest color of the xterm 6x6x6 color cube.
+fn rgb_to_fixed(r: u8, g: u8, b: u8) -> u8 {
+    let index = |v: u8| if v < 48 {
+        0
+    } else if v < 115 {
+        1
+    } else {
+        (v - 35) / 40
+    };
+    16 + 36 * index(r) + 6 * index(g) + index(b)
+}
+
+/// Nearest basic color.
+fn rgb_to_ansi(r: u8, g: u8, b: u8) -> u8 {
+    let distance = |&(pr, pg, pb): &(u8, u8, u8)| {
+        let d = |x: u8, y: u8| (x as i32 - y as i32) * (x as i32 - y as i32);
+        d(r, pr) + d(g, pg) + d(b, pb)
+    };
+    let mut best = 0;
+    for (i, color) in ANSI_PALETTE.iter().enumerate() {
+        if distance(color) < distance(&ANSI_PALETTE[best]) {
+            best = i;
+        }
+    }
+    best as u8
+}
+
+/// Rewrite the 256-color and RGB escape sequences of `s`
+/// which are not supported by a terminal with `depth` colors.
+pub fn downgrade(s: &str, depth: ColorDepth) -> Cow<str> {
+    if depth == ColorDepth::TrueColor || !s.contains("\x1b[") {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("\x1b[") {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 2..];
+        let end = match rest.find(|c: char| !(c.is_digit(10) || c == ';')) {
+            Some(end) => end,
+            None => rest.len(),
+        };
+        if rest[end..].starts_with('m') {
+            out.push_str("\x1b[");
+            out.push_str(&downgrade_sgr(&rest[..end], depth));
+        } else {
+            out.push_str("\x1b[");
+            out.push_str(&rest[..end]);
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+fn downgrade_sgr(params: &str, depth: ColorDepth) -> String {
+    let params: Vec<&str> = params.split(';').collect();
+    let mut sgr = Vec::new();
+    let mut i = 0;
+    while i < params.len() {
+        let param = params[i];
+        i += 1;
+        let base = match param {
+            "38" => 30,
+            "48" => 40,
+            _ => {
+                sgr.push(String::from(param));
+                continue;
+            }
+        };
+        let arg = |j: usize| params.get(j).and_then(|p| p.parse::<u8>().ok()).unwrap_or(0);
+        let color = match params.get(i).cloned() {
+            Some("5") => {
+                i += 2;
+                let n = arg(i - 1);
+                if depth >= ColorDepth::Ansi256 || n < 16 {
+                    Color::Fixed(n)
+                } else {
+                    let (r, g, b) = fixed_to_rgb(n);
+                    Color::Ansi(rgb_to_ansi(r, g, b))
+                }
+            }
+            Some("2") => {
+                i += 4;
+                let (r, g, b) = (arg(i - 3), arg(i - 2), arg(i - 1));
+                if depth >= ColorDepth::Ansi256 {
+                    Color::Fixed(rgb_to_fixed(r, g, b))
+                } else {
+                    Color::Ansi(rgb_to_ansi(r, g, b))
+                }
+            }
+            _ => {
+                sgr.push(String::from(param));
+                continue;
+            }
+        };
+        match color {
+            Color::Fixed(n) if n < 16 && depth < ColorDepth::Ansi256 => {
+                Color::Ansi(n).push_sgr(base, &mut sgr)
+            }
+            _ => color.push_sgr(base, &mut sgr),
+        }
+    }
+    sgr.join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, ColorDepth, Highlighter, MatchingBracketHighlighter, Style};
+
+    #[test]
+    fn matching_bracket() {
+        let highlighter = MatchingBracketHighlighter::with_style(Style::new().bold());
+        assert_eq!("f(x\x1b[1m)\x1b[0m", highlighter.highlight("f(x)", 1));
+        assert_eq!("f\x1b[1m(\x1b[0mx)", highlighter.highlight("f(x)", 4));
+        assert_eq!("f(x)", highlighter.highlight("f(x)", 0));
+    }
+
+    #[test]
+    fn color_depth_from_env() {
+        assert_eq!(ColorDepth::TrueColor,
+                   ColorDepth::from_env(Some("xterm"), Some("truecolor")));
+        assert_eq!(ColorDepth::Ansi256,
+                   ColorDepth::from_env(Some("xterm-256color"), None));
+        assert_eq!(ColorDepth::Ansi16, ColorDepth::from_env(Some("xterm"), None));
+        assert_eq!(ColorDepth::Ansi16, ColorDepth::from_env(None, None));
+    }
+
+    #[test]
+    fn paint() {
+        assert_eq!("text", Style::new().paint("text"));
+        assert_eq!("\x1b[1;31mtext\x1b[0m",
+                   Style::new().bold().fg(Color::Ansi(1)).paint("text"));
+        let style = Style::new().underline().fg(Color::Ansi(12)).bg(Color::Fixed(208));
+        assert_eq!("\x1b[4;94;48;5;208mtext\x1b[0m", style.paint("text"));
+        assert_eq!("\x1b[2;38;2;1;2;3mtext\x1b[0m",
+                   Style::new().dim().fg(Color::Rgb(1, 2, 3)).paint("text"));
+    }
+
+    #[test]
+    fn downgrade() {
+        let s = "a\x1b[1;38;2;255;0;0mb\x1b[0m";
+        assert_eq!(s, super::downgrade(s, ColorDepth::TrueColor));
+        assert_eq!("a\x1b[1;38;5;196mb\x1b[0m",
+                   super::downgrade(s, ColorDepth::Ansi256));
+        assert_eq!("a\x1b[1;91mb\x1b[0m", super::downgrade(s, ColorDepth::Ansi16));
+        assert_eq!("\x1b[48;5;9m \x1b[2J",
+                   super::downgrade("\x1b[48;5;9m \x1b[2J", ColorDepth::Ansi256));
+        assert_eq!("\x1b[101m ", super::downgrade("\x1b[48;5;9m ", ColorDepth::Ansi16));
+        assert_eq!("\x1b[37m ", super::downgrade("\x1b[38;5;250m ", ColorDepth::Ansi16));
+    }
+}
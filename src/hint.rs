@@ -0,0 +1,116 @@
+//! Hints (suggestions displayed at the right of the cursor)
+use history::HistoryBackend;
+
+/// Hints provider
+pub trait Hinter {
+    /// Takes the currently edited `line` with the cursor `pos`ition
+    /// (and the `history` of the editor) and
+    /// returns the string that should be displayed after the line, or `None`
+    /// if no hint is available for the text the user currently typed.
+    fn hint(&self, line: &str, pos: usize, history: &HistoryBackend) -> Option<String>;
+    /// Tells if a hint is being computed in a background thread
+    /// (see `background::AsyncHints`), or requested to a language server
+    /// (see `LspBridge`): the line is refreshed once it is ready.
+    #[cfg(any(feature = "async", feature = "lsp"))]
+    fn is_pending(&self) -> bool {
+        false
+    }
+}
+
+impl Hinter for () {
+    fn hint(&self, _: &str, _: usize, _: &HistoryBackend) -> Option<String> {
+        None
+    }
+}
+
+/// Suggest the end of the most recent history entry starting with the line
+/// (like fish autosuggestions) when the cursor is at the end of the line,
+/// or of the best scored one when the history is ranked (see `History::set_scorer`).
+/// The entries are searched with `HistoryBackend::search_prefix`
+/// (in the whole database of a history keeping only its recent entries in memory).
+#[derive(Debug, Default)]
+pub struct HistoryHinter {}
+
+impl HistoryHinter {
+    pub fn new() -> HistoryHinter {
+        HistoryHinter {}
+    }
+}
+
+impl Hinter for HistoryHinter {
+    fn hint(&self, line: &str, pos: usize, history: &HistoryBackend) -> Option<String> {
+        if line.is_empty() || pos < line.len() {
+            return None;
+        }
+        let filter = |entry: &str| entry.len() > line.len() && entry.starts_with(line);
+        if history.is_ranked() {
+            return history.ranked_matches(&filter)
+                .first()
+                .map(|&index| String::from(&history[index][pos..]));
+        }
+        // the entry equal to the line may come first
+        history.search_prefix(line, 2)
+            .ok()
+            .and_then(|entries| entries.into_iter().find(|entry| filter(entry)))
+            .map(|entry| String::from(&entry[pos..]))
+    }
+}
+
+/// Length (in bytes) of the first word of `hint`, including the separators before it:
+/// the part of the hint inserted by `Cmd::AcceptHintWord`.
+pub fn first_word_len(hint: &str) -> usize {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    let start = hint.find(is_word_char).unwrap_or(hint.len());
+    match hint[start..].find(|c: char| !is_word_char(c)) {
+        Some(len) => start + len,
+        None => hint.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use history::{Frecency, History, HistoryBackend, VecStore};
+    use super::{Hinter, HistoryHinter};
+
+    #[test]
+    fn history_hinter() {
+        let mut history = History::new();
+        history.add("git commit");
+        history.add("git checkout");
+        history.add("ls");
+        let hinter = HistoryHinter::new();
+        assert_eq!(Some(String::from("eckout")), hinter.hint("git ch", 6, &history));
+        assert_eq!(Some(String::from(" checkout")), hinter.hint("git", 3, &history));
+        assert_eq!(Some(String::from("mmit")), hinter.hint("git co", 6, &history));
+        assert_eq!(None, hinter.hint("ls", 2, &history));
+        assert_eq!(None, hinter.hint("git", 1, &history));
+        assert_eq!(None, hinter.hint("", 0, &history));
+
+        history.add("git checkout");
+        history.add("ls");
+        history.add("git checkout");
+        history.add("git commit");
+        assert_eq!(Some(String::from("ommit")), hinter.hint("git c", 5, &history));
+        history.set_scorer(Some(Box::new(Frecency::default())));
+        assert_eq!(Some(String::from("heckout")), hinter.hint("git c", 5, &history));
+    }
+
+    #[test]
+    fn history_hinter_store() {
+        let mut history = VecStore::new(&["git commit", "ls"]);
+        history.history.set_max_len(1);
+        let hinter = HistoryHinter::new();
+        // only kept by the store
+        assert_eq!(Some(String::from("t commit")), hinter.hint("gi", 2, &history));
+        history.add("git");
+        assert_eq!(Some(String::from(" commit")), hinter.hint("git", 3, &history));
+    }
+
+    #[test]
+    fn first_word_len() {
+        assert_eq!(6, super::first_word_len("commit --amend"));
+        assert_eq!(8, super::first_word_len(" --amend"));
+        assert_eq!(2, super::first_word_len("/ "));
+        assert_eq!(0, super::first_word_len(""));
+    }
+}
@@ -1,16 +1,26 @@
 //! History API
 
-use std::collections::VecDeque;
-use std::collections::vec_deque;
+use std::cmp::{self, Ordering};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::iter::DoubleEndedIterator;
-use std::ops::Index;
+use std::ops::{Index, Range};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::usize;
 #[cfg(unix)]
 use libc;
 
 use super::Result;
+use error::ReadlineError;
 use config::{Config, HistoryDuplicates};
+use json::{self, Value, object};
+
+/// First line of the history files whose entries are escaped
+/// (only written when an entry spans several lines).
+const ESCAPED_HEADER: &'static str = "#V2";
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Direction {
@@ -18,12 +28,189 @@ pub enum Direction {
     Reverse,
 }
 
+/// Formats of the history files of other programs (see `History::export` and `History::import`).
+///
+/// The entries have no time in the history: they are all exported with the time
+/// of the export, and the times of the imported ones are dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryFormat {
+    /// One entry per line, like the files of GNU readline
+    /// (an entry spanning several lines is imported back as several ones).
+    Readline,
+    /// Each entry preceded by a `#<seconds>` line, as bash writes them with `HISTTIMEFORMAT`
+    /// (the lines up to the next time are one entry).
+    Bash,
+    /// Extended history of zsh: `: <seconds>:<duration>;<entry>`,
+    /// the lines of an entry ending with a backslash,
+    /// and the bytes used by zsh internally escaped ("metafied") like zsh does.
+    Zsh,
+    /// One JSON object per line: `{"command":"<entry>","time":<seconds>}`.
+    JsonLines,
+}
+
+/// Score of a history entry, to rank the matches of the incremental search
+/// and of `HistoryHinter` (see `History::set_scorer`).
+pub trait HistoryScorer {
+    /// `count` is the number of occurrences of the entry in the history
+    /// and `age` the number of entries added after its last occurrence
+    /// (0 for the last entry). The highest score comes first.
+    fn score(&self, count: usize, age: usize) -> f64;
+}
+
+/// Frequency weighted by recency (frecency): the number of occurrences
+/// is halved every `half_life` entries added since the last one.
+#[derive(Clone, Copy, Debug)]
+pub struct Frecency {
+    half_life: usize,
+}
+
+impl Frecency {
+    pub fn new(half_life: usize) -> Frecency {
+        Frecency { half_life: half_life }
+    }
+}
+
+impl Default for Frecency {
+    fn default() -> Frecency {
+        Frecency::new(25)
+    }
+}
+
+impl HistoryScorer for Frecency {
+    fn score(&self, count: usize, age: usize) -> f64 {
+        let half_lives = age as f64 / cmp::max(self.half_life, 1) as f64;
+        count as f64 / 2f64.powf(half_lives)
+    }
+}
+
+/// Entries recalled, searched and hinted by the editor (`History` by default),
+/// to plug the history of an application (its database, a remote sync service...)
+/// or one shared by several processes (see `sqlite_history`) with `Editor::set_history`.
+pub trait HistoryBackend {
+    /// Return the number of entries.
+    fn len(&self) -> usize;
+    /// Return the entry at position `index`, starting from 0 (the oldest one).
+    fn get(&self, index: usize) -> Option<&String>;
+    /// Add a new entry, unless it is ignored. Return `true` if it has been added.
+    fn add(&mut self, line: &str) -> bool;
+    /// Replace the entry at position `index`.
+    /// Return `false` when there is no such entry.
+    /// By default, the entries are not modified.
+    fn set(&mut self, _: usize, _: &str) -> bool {
+        false
+    }
+    /// Remove all the entries.
+    fn clear(&mut self);
+    /// Add the entries of the file at `path`.
+    fn load(&mut self, path: &Path) -> Result<()>;
+    /// Save the entries in the file at `path`.
+    fn save(&self, path: &Path) -> Result<()>;
+    /// Return true if there is no entry.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Return the index of the nearest entry containing `term`, from `start` (inclusive)
+    /// in the direction `dir`.
+    /// By default, the entries are scanned one by one.
+    fn search(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+        if term.is_empty() || start >= self.len() {
+            return None;
+        }
+        let contains = |index: &usize| self.get(*index).map_or(false, |e| e.contains(term));
+        match dir {
+            Direction::Reverse => (0..start + 1).rev().find(contains),
+            Direction::Forward => (start..self.len()).find(contains),
+        }
+    }
+    /// Tell if the matches of the searches are ranked (see `ranked_matches`).
+    fn is_ranked(&self) -> bool {
+        false
+    }
+    /// Return the index of the last occurrence of the distinct entries matching
+    /// `filter`, the best ranked first.
+    /// By default, the most recent first.
+    fn ranked_matches(&self, filter: &Fn(&str) -> bool) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        (0..self.len())
+            .rev()
+            .filter(|&index| {
+                let entry = self.get(index).unwrap();
+                filter(entry) && seen.insert(entry)
+            })
+            .collect()
+    }
+    /// Tell if only the most recent entries are kept in memory (see `get`),
+    /// the older ones being only found by `search_prefix` and `search_backward`.
+    fn is_truncated(&self) -> bool {
+        false
+    }
+    /// Return the distinct entries starting with `prefix`,
+    /// most recently used first (at most `limit`).
+    /// By default, the entries are scanned one by one.
+    fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+        let mut seen = HashSet::new();
+        Ok((0..self.len())
+            .rev()
+            .filter_map(|index| self.get(index))
+            .filter(|entry| entry.starts_with(prefix) && seen.insert(*entry))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+    /// Return the entries containing `term`, most recent first (at most `limit`).
+    /// By default, the entries are scanned one by one.
+    fn search_backward(&self, term: &str, limit: usize) -> Result<Vec<String>> {
+        Ok((0..self.len())
+            .rev()
+            .filter_map(|index| self.get(index))
+            .filter(|entry| entry.contains(term))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+    /// Take the last error met recording the entries (by `add`, `clear`...),
+    /// which are kept in memory anyway.
+    /// By default, the entries are only kept in memory.
+    fn take_error(&mut self) -> Option<ReadlineError> {
+        None
+    }
+    /// Rank the matches of the searches with `scorer`.
+    /// By default, the scorer is ignored.
+    fn set_scorer(&mut self, _: Option<Box<HistoryScorer>>) {}
+    /// Apply the history settings of `config`.
+    /// By default, they are ignored.
+    fn apply_config(&mut self, _: &Config) {}
+}
+
+impl<'h> HistoryBackend + 'h {
+    /// Return a forward iterator.
+    pub fn iter(&self) -> Iter {
+        Iter {
+            history: self,
+            range: 0..self.len(),
+        }
+    }
+}
+
+impl<'h> Index<usize> for HistoryBackend + 'h {
+    type Output = String;
+
+    fn index(&self, index: usize) -> &String {
+        self.get(index).expect("Out of bounds access")
+    }
+}
+
 /// Current state of the history.
 pub struct History {
     entries: VecDeque<String>,
     max_len: usize,
     ignore_space: bool,
     ignore_dups: bool,
+    exclude: Vec<String>,
+    last_excluded: bool,
+    dedup_on_save: bool,
+    pruned: Cell<usize>,
+    scorer: Option<Box<HistoryScorer>>,
 }
 
 impl History {
@@ -31,12 +218,29 @@ impl History {
         Self::with_config(Config::default())
     }
     pub fn with_config(config: Config) -> History {
-        History {
+        let mut history = History {
             entries: VecDeque::new(),
-            max_len: config.max_history_size(),
-            ignore_space: config.history_duplicates() == HistoryDuplicates::IgnoreConsecutive,
-            ignore_dups: config.history_ignore_space(),
-        }
+            max_len: 0,
+            ignore_space: false,
+            ignore_dups: false,
+            exclude: Vec::new(),
+            last_excluded: false,
+            dedup_on_save: false,
+            pruned: Cell::new(0),
+            scorer: None,
+        };
+        history.apply_config(&config);
+        history
+    }
+
+    /// Apply the history settings of `config`, keeping the existing entries
+    /// (the oldest ones are dropped when the maximum length shrinks).
+    pub fn apply_config(&mut self, config: &Config) {
+        self.set_max_len(config.max_history_size());
+        self.ignore_space = config.history_ignore_space();
+        self.ignore_dups = config.history_duplicates() == HistoryDuplicates::IgnoreConsecutive;
+        self.exclude = config.history_exclude().to_vec();
+        self.dedup_on_save = config.history_dedup_on_save();
     }
 
     /// Return the history entry at position `index`, starting from 0.
@@ -51,7 +255,8 @@ impl History {
 
     /// Add a new entry in the history.
     pub fn add<S: AsRef<str> + Into<String>>(&mut self, line: S) -> bool {
-        if self.max_len == 0 {
+        self.last_excluded = self.is_excluded(line.as_ref());
+        if self.max_len == 0 || self.last_excluded {
             return false;
         }
         if line.as_ref().is_empty() ||
@@ -72,6 +277,28 @@ impl History {
         true
     }
 
+    /// Tell if `line` matches one of the exclude patterns (see `Config::history_exclude`).
+    pub fn is_excluded(&self, line: &str) -> bool {
+        self.exclude.iter().any(|pattern| glob_match(pattern, line))
+    }
+
+    /// Tell if the last line passed to `add` was rejected by an exclude pattern.
+    pub fn last_excluded(&self) -> bool {
+        self.last_excluded
+    }
+
+    /// Replace the history entry at position `index`.
+    /// Return `false` when there is no such entry.
+    pub fn set<S: Into<String>>(&mut self, index: usize, line: S) -> bool {
+        match self.entries.get_mut(index) {
+            Some(entry) => {
+                *entry = line.into();
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Returns the number of entries in the history.
     pub fn len(&self) -> usize {
         self.entries.len()
@@ -99,27 +326,54 @@ impl History {
         }
     }
 
-    /// Save the history in the specified file.
+    /// Save the history in the specified file
+    /// (without the older duplicates when `Config::history_dedup_on_save` is set).
     pub fn save<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Result<()> {
         use std::io::{BufWriter, Write};
 
+        self.pruned.set(0);
         if self.is_empty() {
             return Ok(());
         }
+        // the entries added before the exclude patterns were set
+        let mut entries: Vec<&String> =
+            self.entries.iter().filter(|e| !self.is_excluded(e)).collect();
+        if self.dedup_on_save {
+            let len = entries.len();
+            entries = dedup_keep_newest(entries);
+            self.pruned.set(len - entries.len());
+        }
         let old_umask = umask();
         let f = File::create(path);
         restore_umask(old_umask);
         let file = try!(f);
         fix_perm(&file);
         let mut wtr = BufWriter::new(file);
-        for entry in &self.entries {
-            try!(wtr.write_all(&entry.as_bytes()));
+        // the plain format (one entry per line) is kept when it is not ambiguous
+        let escaped = entries.iter().any(|entry| entry.contains('\n')) ||
+                      entries.first().map_or(false, |entry| *entry == ESCAPED_HEADER);
+        if escaped {
+            try!(wtr.write_all(ESCAPED_HEADER.as_bytes()));
+            try!(wtr.write_all(b"\n"));
+        }
+        for entry in entries {
+            if escaped {
+                try!(wtr.write_all(escape(entry).as_bytes()));
+            } else {
+                try!(wtr.write_all(entry.as_bytes()));
+            }
             try!(wtr.write_all(b"\n"));
         }
         Ok(())
     }
 
-    /// Load the history from the specified file.
+    /// Number of older duplicates left out of the file by the last `save`.
+    pub fn pruned(&self) -> usize {
+        self.pruned.get()
+    }
+
+    /// Load the history from the specified file
+    /// (with one entry per line, or with escaped entries if it starts with `#V2`).
     ///
     /// # Failure
     /// Will return `Err` if path does not already exist.
@@ -128,12 +382,121 @@ impl History {
 
         let file = try!(File::open(&path));
         let rdr = BufReader::new(file);
-        for line in rdr.lines() {
-            self.add(try!(line).as_ref()); // TODO truncate to MAX_LINE
+        let mut escaped = false;
+        for (i, line) in rdr.lines().enumerate() {
+            let line = try!(line);
+            if i == 0 && line == ESCAPED_HEADER {
+                escaped = true;
+                continue;
+            }
+            if escaped {
+                self.add(unescape(&line)); // TODO truncate to MAX_LINE
+            } else {
+                self.add(line); // TODO truncate to MAX_LINE
+            }
         }
         Ok(())
     }
 
+    /// Write the entries to `writer` in the file format of another program
+    /// (without the entries matching `Config::history_exclude`).
+    pub fn export<W: Write>(&self, format: HistoryFormat, mut writer: W) -> Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        for entry in self.entries.iter().filter(|e| !self.is_excluded(e)) {
+            match format {
+                HistoryFormat::Readline => try!(writeln!(writer, "{}", entry)),
+                HistoryFormat::Bash => try!(writeln!(writer, "#{}\n{}", time, entry)),
+                HistoryFormat::Zsh => {
+                    try!(write!(writer, ": {}:0;", time));
+                    try!(writer.write_all(&metafy(&entry.replace('\n', "\\\n"))));
+                    try!(writer.write_all(b"\n"))
+                }
+                HistoryFormat::JsonLines => {
+                    let record = object(vec![("command", Value::from(entry.as_str())),
+                                             ("time", Value::from(time))]);
+                    try!(writeln!(writer, "{}", record))
+                }
+            }
+        }
+        try!(writer.flush());
+        Ok(())
+    }
+
+    /// Add the entries read from `reader`, in the file format of another program,
+    /// like the ones typed (see `add`). Return the number of entries added.
+    ///
+    /// # Failure
+    /// Will return `Err`, without adding any entry, if a line is not valid UTF-8
+    /// (once unmetafied for `Zsh`), or if a line of a `JsonLines` file
+    /// is not an object with a `command`.
+    pub fn import<R: Read>(&mut self, format: HistoryFormat, reader: R) -> Result<usize> {
+        let mut entries = Vec::new();
+        // entry whose lines are still being read
+        let mut pending: Option<String> = None;
+        for (i, line) in BufReader::new(reader).split(b'\n').enumerate() {
+            let mut line = try!(line);
+            if format == HistoryFormat::Zsh {
+                line = unmetafy(&line);
+            }
+            let line = match String::from_utf8(line) {
+                Ok(line) => line,
+                Err(_) => {
+                    let msg = format!("line {}: invalid UTF-8", i + 1);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg).into());
+                }
+            };
+            match format {
+                HistoryFormat::Readline => entries.push(line),
+                HistoryFormat::Bash => {
+                    if is_bash_time(&line) {
+                        entries.extend(pending.take());
+                        pending = Some(String::new());
+                    } else if let Some(ref mut entry) = pending {
+                        if !entry.is_empty() {
+                            entry.push('\n');
+                        }
+                        entry.push_str(&line);
+                    } else {
+                        // written without HISTTIMEFORMAT
+                        entries.push(line);
+                    }
+                }
+                HistoryFormat::Zsh => {
+                    let mut entry = match pending.take() {
+                        Some(entry) => entry + "\n" + &line,
+                        None => strip_zsh_time(&line).to_owned(),
+                    };
+                    if entry.ends_with('\\') {
+                        entry.pop();
+                        pending = Some(entry);
+                    } else {
+                        entries.push(entry);
+                    }
+                }
+                HistoryFormat::JsonLines => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let command = json::parse(&line)
+                        .and_then(|record| record.get("command").and_then(Value::as_str)
+                            .map(String::from));
+                    match command {
+                        Some(command) => entries.push(command),
+                        None => {
+                            let msg = format!("line {}: no JSON history record", i + 1);
+                            return Err(io::Error::new(io::ErrorKind::InvalidData, msg).into());
+                        }
+                    }
+                }
+            }
+        }
+        entries.extend(pending);
+        Ok(entries.into_iter().filter(|entry| self.add(entry.as_str())).count())
+    }
+
     /// Clear history
     pub fn clear(&mut self) {
         self.entries.clear()
@@ -163,9 +526,92 @@ impl History {
         }
     }
 
+    /// Rank the matches of the searches with `scorer`
+    /// instead of the strict reverse order (by default).
+    pub fn set_scorer(&mut self, scorer: Option<Box<HistoryScorer>>) {
+        self.scorer = scorer;
+    }
+
+    /// Tell if the matches of the searches are ranked by a scorer.
+    pub fn is_ranked(&self) -> bool {
+        self.scorer.is_some()
+    }
+
+    /// Return the index of the last occurrence of the distinct entries matching
+    /// `filter`, the best scored first (the most recent first without scorer
+    /// or when the scores are equal).
+    pub fn ranked_matches(&self, filter: &Fn(&str) -> bool) -> Vec<usize> {
+        // occurrences and index of the last one, by entry
+        let mut stats: HashMap<&str, (usize, usize)> = HashMap::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            if filter(entry) {
+                let stat = stats.entry(entry).or_insert((0, index));
+                stat.0 += 1;
+                stat.1 = index;
+            }
+        }
+        let last = self.entries.len().saturating_sub(1);
+        let mut ranked: Vec<(f64, usize)> = stats.values()
+            .map(|&(count, index)| {
+                let score = match self.scorer {
+                    Some(ref scorer) => scorer.score(count, last - index),
+                    None => 0.,
+                };
+                (score, index)
+            })
+            .collect();
+        ranked.sort_by(|a, b| match b.0.partial_cmp(&a.0) {
+            Some(Ordering::Equal) | None => b.1.cmp(&a.1),
+            Some(ordering) => ordering,
+        });
+        ranked.into_iter().map(|(_, index)| index).collect()
+    }
+
     /// Return a forward iterator.
     pub fn iter(&self) -> Iter {
-        Iter(self.entries.iter())
+        Iter {
+            history: self,
+            range: 0..self.len(),
+        }
+    }
+}
+
+impl HistoryBackend for History {
+    fn len(&self) -> usize {
+        History::len(self)
+    }
+    fn get(&self, index: usize) -> Option<&String> {
+        History::get(self, index)
+    }
+    fn add(&mut self, line: &str) -> bool {
+        History::add(self, line)
+    }
+    fn set(&mut self, index: usize, line: &str) -> bool {
+        History::set(self, index, line)
+    }
+    fn clear(&mut self) {
+        History::clear(self)
+    }
+    fn load(&mut self, path: &Path) -> Result<()> {
+        History::load(self, path)
+    }
+    fn save(&self, path: &Path) -> Result<()> {
+        History::save(self, path)
+    }
+    fn search(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+        History::search(self, term, start, dir)
+    }
+    fn is_ranked(&self) -> bool {
+        History::is_ranked(self)
+    }
+    fn ranked_matches(&self, filter: &Fn(&str) -> bool) -> Vec<usize> {
+        History::ranked_matches(self, filter)
+    }
+    fn set_scorer(&mut self, scorer: Option<Box<HistoryScorer>>) {
+        History::set_scorer(self, scorer)
+    }
+    fn apply_config(&mut self, config: &Config) {
+        History::apply_config(self, config)
     }
 }
 
@@ -187,24 +633,137 @@ impl<'a> IntoIterator for &'a History {
 }
 
 /// History iterator.
-pub struct Iter<'a>(vec_deque::Iter<'a, String>);
+pub struct Iter<'a> {
+    history: &'a HistoryBackend,
+    range: Range<usize>,
+}
 
 impl<'a> Iterator for Iter<'a> {
     type Item = &'a String;
 
     fn next(&mut self) -> Option<&'a String> {
-        self.0.next()
+        self.range.next().and_then(|index| self.history.get(index))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.0.size_hint()
+        self.range.size_hint()
     }
 }
 
 impl<'a> DoubleEndedIterator for Iter<'a> {
     fn next_back(&mut self) -> Option<&'a String> {
-        self.0.next_back()
+        self.range.next_back().and_then(|index| self.history.get(index))
+    }
+}
+
+/// Remove the older duplicates of `entries`, keeping the order of the most recent ones.
+fn dedup_keep_newest(entries: Vec<&String>) -> Vec<&String> {
+    let mut seen = HashSet::new();
+    let mut kept: Vec<&String> = entries.into_iter().rev().filter(|e| seen.insert(*e)).collect();
+    kept.reverse();
+    kept
+}
+
+/// Tell if the whole `text` matches the glob `pattern`
+/// (`*` matching any chars, `?` any single char).
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` and of the text it was matched from
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // let the last `*` match one more char
+            star = Some((star_p, star_t + 1));
+            p = star_p + 1;
+            t = star_t + 1;
+        } else {
+            return false;
+        }
     }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Tell if `line` is the time of the next entry in a bash history file.
+fn is_bash_time(line: &str) -> bool {
+    line.len() > 1 && line.starts_with('#') && line[1..].chars().all(|c| c.is_digit(10))
+}
+
+/// Remove the `: <seconds>:<duration>;` prefix of a line of a zsh extended history.
+fn strip_zsh_time(line: &str) -> &str {
+    if !line.starts_with(": ") {
+        return line;
+    }
+    match line.find(';') {
+        Some(semi) if line[2..semi].chars().all(|c| c.is_digit(10) || c == ':') => {
+            &line[semi + 1..]
+        }
+        _ => line,
+    }
+}
+
+/// First byte of the pairs escaping the bytes used by zsh internally
+/// (the following one being the escaped byte XOR 32).
+const ZSH_META: u8 = 0x83;
+
+/// Escape the bytes of `text` that zsh uses internally, like zsh writes its history.
+fn metafy(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for &b in text.as_bytes() {
+        if b == 0 || (b >= ZSH_META && b <= 0xa2) {
+            bytes.push(ZSH_META);
+            bytes.push(b ^ 32);
+        } else {
+            bytes.push(b);
+        }
+    }
+    bytes
+}
+
+/// Restore the bytes escaped by zsh in a line of its history.
+fn unmetafy(line: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(line.len());
+    let mut escaped = false;
+    for &b in line {
+        if escaped {
+            bytes.push(b ^ 32);
+            escaped = false;
+        } else if b == ZSH_META {
+            escaped = true;
+        } else {
+            bytes.push(b);
+        }
+    }
+    bytes
+}
+
+/// Escape the backslashes and newlines of `entry` so that it fits on one line.
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            entry.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => entry.push('\n'),
+            Some(c) => entry.push(c),
+            None => entry.push('\\'),
+        }
+    }
+    entry
 }
 
 #[cfg(windows)]
@@ -234,11 +793,73 @@ fn fix_perm(file: &File) {
     }
 }
 
+/// History keeping its most recent entries in memory
+/// and all of them in a vector (like a database), for the tests.
+#[cfg(test)]
+pub struct VecStore {
+    pub stored: Vec<String>,
+    pub history: History,
+}
+
+#[cfg(test)]
+impl VecStore {
+    pub fn new(entries: &[&str]) -> VecStore {
+        let mut store = VecStore {
+            stored: Vec::new(),
+            history: History::new(),
+        };
+        for entry in entries {
+            store.add(entry);
+        }
+        store
+    }
+}
+
+#[cfg(test)]
+impl HistoryBackend for VecStore {
+    fn len(&self) -> usize {
+        self.history.len()
+    }
+    fn get(&self, index: usize) -> Option<&String> {
+        self.history.get(index)
+    }
+    fn add(&mut self, line: &str) -> bool {
+        if !self.history.add(line) {
+            return false;
+        }
+        self.stored.push(String::from(line));
+        true
+    }
+    fn clear(&mut self) {
+        self.stored.clear();
+        self.history.clear()
+    }
+    fn load(&mut self, _: &Path) -> Result<()> {
+        Ok(())
+    }
+    fn save(&self, _: &Path) -> Result<()> {
+        Ok(())
+    }
+    fn is_truncated(&self) -> bool {
+        true
+    }
+    fn search_backward(&self, term: &str, limit: usize) -> Result<Vec<String>> {
+        Ok(self.stored.iter().rev().filter(|e| e.contains(term)).take(limit).cloned().collect())
+    }
+    fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+        let entries = self.stored.iter().rev().filter(|e| e.starts_with(prefix));
+        Ok(entries.take(limit).cloned().collect())
+    }
+    fn apply_config(&mut self, config: &Config) {
+        self.history.apply_config(config)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate tempdir;
     use std::path::Path;
-    use super::{Direction, History};
+    use super::{Direction, Frecency, History, HistoryBackend, HistoryFormat, HistoryScorer};
     use config::Config;
 
     fn init() -> History {
@@ -260,7 +881,7 @@ mod tests {
         let config = Config::builder()
             .history_ignore_space(true)
             .build();
-        let mut history = History::with_config(config);
+        let mut history = History::with_config(config.clone());
         assert_eq!(config.max_history_size(), history.max_len);
         assert!(history.add("line1"));
         assert!(history.add("line2"));
@@ -269,6 +890,39 @@ mod tests {
         assert!(!history.add(" line3"));
     }
 
+    #[test]
+    fn apply_config() {
+        let mut history = init();
+        let config = Config::builder()
+            .max_history_size(2)
+            .history_ignore_dups(false)
+            .build();
+        history.apply_config(&config);
+        assert_eq!(2, history.entries.len());
+        assert!(history.add("line3"));
+        assert!(history.add(" line4"));
+    }
+
+    #[test]
+    fn ignore_space_and_dups() {
+        // each setting is applied to its own flag
+        let config = Config::builder()
+            .history_ignore_space(true)
+            .history_ignore_dups(false)
+            .build();
+        let mut history = History::with_config(config);
+        assert!(!history.add(" line"));
+        assert!(history.add("line"));
+        assert!(history.add("line"));
+        let config = Config::builder()
+            .history_ignore_space(false)
+            .history_ignore_dups(true)
+            .build();
+        history.apply_config(&config);
+        assert!(history.add(" line"));
+        assert!(!history.add(" line"));
+    }
+
     #[test]
     fn set_max_len() {
         let mut history = init();
@@ -288,6 +942,127 @@ mod tests {
         td.close().unwrap();
     }
 
+    #[test]
+    fn save_multi_line() {
+        let mut history = init();
+        assert!(history.add("for i in 1 2\ndo echo \\n$i\ndone\\"));
+        let td = tempdir::TempDir::new_in(&Path::new("."), "histo").unwrap();
+        let history_path = td.path().join(".history");
+
+        history.save(&history_path).unwrap();
+        let mut loaded = History::new();
+        loaded.load(&history_path).unwrap();
+        assert_eq!(history.entries, loaded.entries);
+        td.close().unwrap();
+    }
+
+    #[test]
+    fn export_import() {
+        let mut history = init();
+        assert!(history.add("for i in 1 2\ndo echo $i\ndone"));
+        for &format in &[HistoryFormat::Bash, HistoryFormat::Zsh, HistoryFormat::JsonLines] {
+            let mut file = Vec::new();
+            history.export(format, &mut file).unwrap();
+            let mut imported = History::new();
+            assert_eq!(4, imported.import(format, &file[..]).unwrap());
+            assert_eq!(history.entries, imported.entries);
+        }
+        let mut file = Vec::new();
+        history.export(HistoryFormat::Readline, &mut file).unwrap();
+        assert_eq!(&b"line1\nline2\nline3\nfor i in 1 2\ndo echo $i\ndone\n"[..], &file[..]);
+
+        let mut imported = History::new();
+        let bash = "ls\n#1700000000\ncd /\n#1700000001\nif x\nthen y; fi\n";
+        assert_eq!(3, imported.import(HistoryFormat::Bash, bash.as_bytes()).unwrap());
+        assert_eq!("if x\nthen y; fi", imported.last().unwrap());
+        let zsh = ": 1700000000:0;ls\n: 1700000001:3;echo a\\\nb\nplain\n";
+        assert_eq!(3, imported.import(HistoryFormat::Zsh, zsh.as_bytes()).unwrap());
+        assert_eq!("echo a\nb", imported[4]);
+        assert_eq!("plain", imported[5]);
+        // "→" (e2 86 92) as zsh writes it
+        let zsh = b": 1700000000:0;echo \xe2\x83\xa6\x83\xb2\n";
+        assert_eq!(1, imported.import(HistoryFormat::Zsh, &zsh[..]).unwrap());
+        assert_eq!("echo →", imported.last().unwrap());
+        let mut file = Vec::new();
+        imported.export(HistoryFormat::Zsh, &mut file).unwrap();
+        assert!(file.ends_with(b";echo \xe2\x83\xa6\x83\xb2\n"));
+        assert!(imported.import(HistoryFormat::Readline, &b"\xff\n"[..]).is_err());
+        let json = "{\"command\":\"make\",\"time\":1}\n\n[]\n";
+        assert!(imported.import(HistoryFormat::JsonLines, json.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn exclude() {
+        let mut history = init();
+        assert!(history.add("rm -rf target"));
+        // patterns built at runtime, as if read from a configuration file
+        let patterns: Vec<String> = " *\nrm -rf*\n*password*".lines().map(String::from).collect();
+        let config = Config::builder().history_exclude(&patterns).build();
+        drop(patterns);
+        history.apply_config(&config);
+        assert_eq!("rm -rf*", config.history_exclude()[1]);
+        assert!(!history.add("export PASSWORD=x; set password=x"));
+        assert!(history.last_excluded());
+        assert!(!history.add(" ls"));
+        assert!(history.last_excluded());
+        assert!(history.add("rm -r target"));
+        assert!(!history.last_excluded());
+
+        let td = tempdir::TempDir::new_in(&Path::new("."), "histo").unwrap();
+        let history_path = td.path().join(".history");
+        history.save(&history_path).unwrap();
+        let mut loaded = History::new();
+        loaded.load(&history_path).unwrap();
+        assert_eq!(vec!["line1", "line2", "line3", "rm -r target"],
+                   loaded.iter().collect::<Vec<_>>());
+        td.close().unwrap();
+    }
+
+    #[test]
+    fn dedup_on_save() {
+        let mut history = init();
+        assert!(history.add("line1"));
+        assert!(history.add("line3"));
+        assert!(history.add("line4"));
+        history.apply_config(&Config::builder().history_dedup_on_save(true).build());
+
+        let td = tempdir::TempDir::new_in(&Path::new("."), "histo").unwrap();
+        let history_path = td.path().join(".history");
+        history.save(&history_path).unwrap();
+        assert_eq!(2, history.pruned());
+        assert_eq!(6, history.len());
+        let mut loaded = History::new();
+        loaded.load(&history_path).unwrap();
+        assert_eq!(vec!["line2", "line1", "line3", "line4"],
+                   loaded.iter().collect::<Vec<_>>());
+        td.close().unwrap();
+    }
+
+    #[test]
+    fn glob_match() {
+        assert!(super::glob_match("", ""));
+        assert!(super::glob_match("*", "anything"));
+        assert!(super::glob_match("l?", "ls"));
+        assert!(!super::glob_match("l?", "l"));
+        assert!(super::glob_match("*pass*word", "my password"));
+        assert!(!super::glob_match("*pass*word", "my password!"));
+        assert!(super::glob_match("a*b*c", "aXbYbZc"));
+        assert!(!super::glob_match("rm*", " rm"));
+    }
+
+    #[test]
+    fn search_prefix_and_backward() {
+        let mut history = init();
+        for entry in &["cd", "cat", "cd", "cargo"] {
+            history.add(*entry);
+        }
+        let history: &HistoryBackend = &history;
+        assert_eq!(vec!["cargo", "cd", "cat"], history.search_prefix("c", 5).unwrap());
+        assert_eq!(vec!["cargo", "cd"], history.search_prefix("c", 2).unwrap());
+        assert_eq!(vec!["cargo", "cat"], history.search_backward("a", 5).unwrap());
+        assert!(history.search_backward("none", 5).unwrap().is_empty());
+    }
+
     #[test]
     fn search() {
         let history = init();
@@ -311,4 +1086,26 @@ mod tests {
         assert_eq!(Some(1), history.search("line", 1, Direction::Reverse));
         assert_eq!(Some(0), history.search("line1", 1, Direction::Reverse));
     }
+
+    #[test]
+    fn ranked_matches() {
+        let mut history = init();
+        assert!(history.add("line1"));
+        assert!(history.add("line4"));
+        let all = |_: &str| true;
+        assert_eq!(vec![4, 3, 2, 1], history.ranked_matches(&all));
+
+        history.set_scorer(Some(Box::new(Frecency::new(10))));
+        assert!(history.is_ranked());
+        assert_eq!(vec![3, 4, 2, 1], history.ranked_matches(&all));
+        assert_eq!(vec![2], history.ranked_matches(&|e: &str| e.ends_with('3')));
+    }
+
+    #[test]
+    fn frecency() {
+        let frecency = Frecency::new(10);
+        assert_eq!(2., frecency.score(2, 0));
+        assert_eq!(1., frecency.score(2, 10));
+        assert!(frecency.score(3, 10) > frecency.score(1, 0));
+    }
 }
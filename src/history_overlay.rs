@@ -0,0 +1,223 @@
+//! Copy-on-write view of the history entries edited while browsing
+use std::collections::HashMap;
+
+use history::HistoryBackend;
+use line_buffer::LineBuffer;
+
+/// Edits made to recalled history entries during one `readline` call.
+/// The line being composed is stored at index `history.len()`.
+///
+/// The underlying `History` is never modified, so original entries are
+/// restored as soon as the overlay is dropped (line accepted or aborted).
+///
+/// Each entry has its own undo stack, kept while other entries are recalled:
+/// undoing steps back through the edits of the displayed entry only, down to
+/// its recalled text.
+#[derive(Debug, Default)]
+pub struct HistoryOverlay {
+    edits: HashMap<usize, (String, usize)>, // (buffer, cursor position)
+    undos: HashMap<usize, Vec<(String, usize)>>, // Versions of each entry before its changes
+    checkpoint: Option<(usize, String, usize)>, // Entry displayed before the last command
+    inserting: bool, // The last undo step is a run of inserted chars
+}
+
+impl HistoryOverlay {
+    pub fn new() -> HistoryOverlay {
+        HistoryOverlay::default()
+    }
+
+    /// Remember the content of `line` as the current version of entry `index`.
+    pub fn save(&mut self, history: &HistoryBackend, index: usize, line: &LineBuffer) {
+        match history.get(index) {
+            Some(entry) if entry == line.as_str() => {
+                self.edits.remove(&index);
+            }
+            _ => {
+                self.edits.insert(index, (String::from(line.as_str()), line.pos()));
+            }
+        }
+    }
+
+    /// Load the current version of entry `index` into `line`.
+    pub fn load(&self, history: &HistoryBackend, index: usize, line: &mut LineBuffer) {
+        if let Some(&(ref buf, pos)) = self.edits.get(&index) {
+            line.update(buf, pos);
+        } else if let Some(entry) = history.get(index) {
+            line.update(entry, entry.len());
+        } else {
+            line.update("", 0);
+        }
+    }
+
+    /// Return the edited version of entry `index`, if any.
+    #[cfg(test)]
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.edits.get(&index).map(|&(ref buf, _)| buf.as_str())
+    }
+
+    /// Discard the edits made to entry `index`.
+    pub fn revert(&mut self, index: usize) {
+        self.edits.remove(&index);
+    }
+
+    /// Remember `line` as the version of entry `index` before the next command.
+    /// If the previous command changed the entry, its version before is pushed
+    /// on the undo stack of the entry, unless both the previous change and this one
+    /// are `inserted` chars (typed chars are undone together).
+    /// The recall of another entry is not a change.
+    pub fn checkpoint(&mut self, index: usize, line: &LineBuffer, inserted: bool) {
+        if let Some((prev_index, prev, prev_pos)) = self.checkpoint.take() {
+            if prev_index == index && prev != line.as_str() {
+                if !(inserted && self.inserting) {
+                    self.undos.entry(index).or_insert_with(Vec::new).push((prev, prev_pos));
+                }
+                self.inserting = inserted;
+            } else if prev_index != index {
+                self.inserting = false;
+            }
+        }
+        self.checkpoint = Some((index, String::from(line.as_str()), line.pos()));
+    }
+
+    /// Restore into `line` the version of entry `index` before its last change.
+    /// Return `false` if the entry has not been changed.
+    pub fn undo(&mut self, index: usize, line: &mut LineBuffer) -> bool {
+        let version = self.undos.get_mut(&index).and_then(|undos| undos.pop());
+        match version {
+            Some((buf, pos)) => {
+                line.update(&buf, pos);
+                // the restored version is not a change
+                self.checkpoint = Some((index, buf, pos));
+                self.inserting = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Write the edited entries back into `history`.
+    pub fn commit(&self, history: &mut HistoryBackend) {
+        for (&index, &(ref buf, _)) in &self.edits {
+            history.set(index, buf.as_str());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "proptests")]
+    use proptest::prelude::*;
+    use history::History;
+    use line_buffer::{LineBuffer, MAX_LINE};
+    #[cfg(feature = "proptests")]
+    use line_buffer::test::edit;
+    use super::HistoryOverlay;
+
+    #[test]
+    fn save_and_load() {
+        let mut history = History::new();
+        history.add("line0");
+        let mut overlay = HistoryOverlay::new();
+        let mut line = LineBuffer::with_capacity(MAX_LINE);
+        line.update("line0", 5);
+
+        overlay.save(&history, 0, &line);
+        assert_eq!(None, overlay.get(0));
+
+        line.insert('!');
+        overlay.save(&history, 0, &line);
+        assert_eq!(Some("line0!"), overlay.get(0));
+        assert_eq!("line0", history.get(0).unwrap());
+
+        line.update("", 0);
+        overlay.load(&history, 0, &mut line);
+        assert_eq!("line0!", line.as_str());
+        assert_eq!(6, line.pos());
+
+        overlay.load(&history, 1, &mut line);
+        assert_eq!("", line.as_str());
+    }
+
+    #[test]
+    fn undo() {
+        let mut history = History::new();
+        history.add("line0");
+        let mut overlay = HistoryOverlay::new();
+        let mut line = LineBuffer::with_capacity(MAX_LINE);
+        overlay.checkpoint(1, &line, false);
+        line.insert('a');
+        overlay.checkpoint(1, &line, true);
+        line.insert('b');
+        overlay.checkpoint(1, &line, true);
+        line.move_home();
+        overlay.checkpoint(1, &line, false);
+
+        // recalled entry
+        overlay.save(&history, 1, &line);
+        overlay.load(&history, 0, &mut line);
+        overlay.checkpoint(0, &line, false);
+        assert!(!overlay.undo(0, &mut line));
+        line.backspace();
+        overlay.checkpoint(0, &line, false);
+        line.backspace();
+        overlay.checkpoint(0, &line, false);
+        assert!(overlay.undo(0, &mut line));
+        assert_eq!("line", line.as_str());
+        overlay.checkpoint(0, &line, false);
+        assert!(overlay.undo(0, &mut line));
+        assert_eq!("line0", line.as_str());
+        assert_eq!(5, line.pos());
+        assert!(!overlay.undo(0, &mut line));
+
+        // the edits of the new line are kept, the typed chars undone together
+        overlay.save(&history, 0, &line);
+        overlay.load(&history, 1, &mut line);
+        overlay.checkpoint(1, &line, false);
+        assert_eq!("ab", line.as_str());
+        assert!(overlay.undo(1, &mut line));
+        assert_eq!("", line.as_str());
+    }
+
+    #[cfg(feature = "proptests")]
+    proptest! {
+        #[test]
+        fn undo_all_edits(line in "[a-z é]*",
+                          edits in prop::collection::vec(any::<(u8, char)>(), 0..16)) {
+            let mut overlay = HistoryOverlay::new();
+            let mut s = LineBuffer::init(&line, line.len());
+            overlay.checkpoint(0, &s, false);
+            for (op, c) in edits {
+                edit(&mut s, op, c);
+                overlay.checkpoint(0, &s, false);
+            }
+            while overlay.undo(0, &mut s) {
+                overlay.checkpoint(0, &s, false);
+            }
+            prop_assert_eq!(line.as_str(), s.as_str());
+        }
+    }
+
+    #[test]
+    fn revert_and_commit() {
+        let mut history = History::new();
+        history.add("line0");
+        history.add("line1");
+        let mut overlay = HistoryOverlay::new();
+        let mut line = LineBuffer::with_capacity(MAX_LINE);
+        line.update("edit0", 5);
+        overlay.save(&history, 0, &line);
+        line.update("edit1", 5);
+        overlay.save(&history, 1, &line);
+        line.update("new", 3);
+        overlay.save(&history, 2, &line);
+
+        overlay.revert(1);
+        overlay.load(&history, 1, &mut line);
+        assert_eq!("line1", line.as_str());
+
+        overlay.commit(&mut history);
+        assert_eq!(2, history.len());
+        assert_eq!("edit0", history.get(0).unwrap());
+        assert_eq!("line1", history.get(1).unwrap());
+    }
+}
@@ -0,0 +1,29 @@
+//! Hook called while no key is pressed
+//! (to refresh a clock in the prompt or the status of background jobs)
+
+/// Update of the display requested by an idle hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Idle {
+    /// Nothing to redisplay.
+    Keep,
+    /// Redisplay the line, with its highlighting and hint computed again.
+    Refresh,
+    /// Replace the status rows below the line (see `Editor::set_status_bar`) and redisplay.
+    Status(Vec<String>),
+}
+
+/// Hook called when no key is pressed for `Config::idle_timeout`.
+pub trait IdleHook {
+    /// Called with the line being edited each time the timeout elapses without any key.
+    /// Only terminals which can wait for a key are idle (not a script without delays,
+    /// nor a terminal whose reader cannot wait for them, see `RawReader::can_wait`).
+    fn idle(&self, line: &str, pos: usize) -> Idle;
+}
+
+impl<F> IdleHook for F
+    where F: Fn(&str, usize) -> Idle
+{
+    fn idle(&self, line: &str, pos: usize) -> Idle {
+        self(line, pos)
+    }
+}
@@ -0,0 +1,295 @@
+//! Just enough JSON to talk to a language server and to export the history
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::Chars;
+use std::iter::Peekable;
+
+macro_rules! try_opt {
+    ($e:expr) => (match $e { Some(v) => v, None => return None })
+}
+
+/// Maximum nesting of the arrays and objects: the deeper documents are rejected
+/// (instead of overflowing the stack).
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// Member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref members) => members.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Number(n) if n >= 0.0 => Some(n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match *self {
+            Value::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Build an object from its members.
+pub fn object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(members.into_iter().map(|(k, v)| (String::from(k), v)).collect())
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Value {
+        Value::String(String::from(s))
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Value {
+        Value::Number(n as f64)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(ref s) => write_string(f, s),
+            Value::Array(ref items) => {
+                try!(write!(f, "["));
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(f, ","));
+                    }
+                    try!(write!(f, "{}", item));
+                }
+                write!(f, "]")
+            }
+            Value::Object(ref members) => {
+                try!(write!(f, "{{"));
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(f, ","));
+                    }
+                    try!(write_string(f, key));
+                    try!(write!(f, ":{}", value));
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    try!(write!(f, "\""));
+    for c in s.chars() {
+        match c {
+            '"' => try!(write!(f, "\\\"")),
+            '\\' => try!(write!(f, "\\\\")),
+            '\n' => try!(write!(f, "\\n")),
+            '\r' => try!(write!(f, "\\r")),
+            '\t' => try!(write!(f, "\\t")),
+            c if (c as u32) < 0x20 => try!(write!(f, "\\u{:04x}", c as u32)),
+            c => try!(write!(f, "{}", c)),
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Parse a JSON document (`None` if it is malformed).
+pub fn parse(text: &str) -> Option<Value> {
+    let mut chars = text.chars().peekable();
+    let value = parse_value(&mut chars, 0);
+    skip_whitespaces(&mut chars);
+    if chars.next().is_some() { None } else { value }
+}
+
+fn skip_whitespaces(chars: &mut Peekable<Chars>) {
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, word: &str) -> Option<()> {
+    for expected in word.chars() {
+        if chars.next() != Some(expected) {
+            return None;
+        }
+    }
+    Some(())
+}
+
+/// Parse a value nested in `depth` arrays or objects.
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Option<Value> {
+    skip_whitespaces(chars);
+    let first = match chars.peek() {
+        Some(&c) => c,
+        None => return None,
+    };
+    match first {
+        'n' => expect(chars, "null").map(|_| Value::Null),
+        't' => expect(chars, "true").map(|_| Value::Bool(true)),
+        'f' => expect(chars, "false").map(|_| Value::Bool(false)),
+        '"' => parse_string(chars).map(Value::String),
+        '[' | '{' if depth == MAX_DEPTH => None,
+        '[' => {
+            chars.next();
+            let mut items = Vec::new();
+            skip_whitespaces(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Some(Value::Array(items));
+            }
+            loop {
+                items.push(try_opt!(parse_value(chars, depth + 1)));
+                skip_whitespaces(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Some(Value::Array(items)),
+                    _ => return None,
+                }
+            }
+        }
+        '{' => {
+            chars.next();
+            let mut members = BTreeMap::new();
+            skip_whitespaces(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Some(Value::Object(members));
+            }
+            loop {
+                skip_whitespaces(chars);
+                let key = try_opt!(parse_string(chars));
+                skip_whitespaces(chars);
+                if chars.next() != Some(':') {
+                    return None;
+                }
+                members.insert(key, try_opt!(parse_value(chars, depth + 1)));
+                skip_whitespaces(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some('}') => return Some(Value::Object(members)),
+                    _ => return None,
+                }
+            }
+        }
+        _ => {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_digit(10) || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            number.parse().ok().map(Value::Number)
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    if chars.next() != Some('"') {
+        return None;
+    }
+    let mut s = String::new();
+    loop {
+        match try_opt!(chars.next()) {
+            '"' => return Some(s),
+            '\\' => {
+                match try_opt!(chars.next()) {
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'b' => s.push('\x08'),
+                    'f' => s.push('\x0c'),
+                    'u' => {
+                        let mut unit = try_opt!(parse_hex4(chars));
+                        if unit >= 0xD800 && unit < 0xDC00 {
+                            // surrogate pair
+                            if chars.next() != Some('\\') || chars.next() != Some('u') {
+                                return None;
+                            }
+                            let low = try_opt!(parse_hex4(chars));
+                            unit = 0x10000 + ((unit - 0xD800) << 10) + (low.wrapping_sub(0xDC00));
+                        }
+                        s.push(::std::char::from_u32(unit).unwrap_or('\u{fffd}'));
+                    }
+                    c => s.push(c), // '"', '\\', '/'
+                }
+            }
+            c => s.push(c),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let mut unit = 0;
+    for _ in 0..4 {
+        unit = unit * 16 + try_opt!(chars.next().and_then(|c| c.to_digit(16)));
+    }
+    Some(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::{MAX_DEPTH, Value, object, parse};
+
+    #[test]
+    fn parse_values() {
+        let value = parse(r#" {"a": [1, -2.5e1, true, null], "b": "x\"é😀"} "#)
+            .unwrap();
+        assert_eq!(Some(&Value::Array(vec![Value::Number(1.0),
+                                           Value::Number(-25.0),
+                                           Value::Bool(true),
+                                           Value::Null])),
+                   value.get("a"));
+        assert_eq!(Some("x\"é😀"), value.get("b").and_then(Value::as_str));
+        assert_eq!(None, parse("[1,"));
+        assert_eq!(None, parse("{} x"));
+    }
+
+    #[test]
+    fn max_depth() {
+        fn nested(depth: usize) -> String {
+            let open: String = iter::repeat('[').take(depth).collect();
+            let close: String = iter::repeat(']').take(depth).collect();
+            open + &close
+        }
+        assert!(parse(&nested(MAX_DEPTH)).is_some());
+        assert_eq!(None, parse(&nested(MAX_DEPTH + 1)));
+        assert_eq!(None, parse(&nested(100_000)));
+    }
+
+    #[test]
+    fn display() {
+        let value = object(vec![("id", Value::from(1)), ("text", Value::from("a\"\nb"))]);
+        assert_eq!(r#"{"id":1,"text":"a\"\nb"}"#, value.to_string());
+        assert_eq!(Some(value.clone()), parse(&value.to_string()));
+    }
+}
@@ -0,0 +1,114 @@
+//! Escape sequences decoded as keys, registered by the application
+//! (for the terminals whose sequences are not known, see `Editor::bind_escape_sequence`)
+use std::collections::HashMap;
+use std::mem;
+use std::str::Chars;
+
+use consts::KeyPress;
+
+/// Node of the trie: the key decoded when the sequence ends here, if any,
+/// and the nodes of the longer sequences.
+#[derive(Debug, Default)]
+pub struct Node {
+    key: Option<KeyPress>,
+    children: HashMap<char, Node>,
+}
+
+impl Node {
+    /// Key of the sequence ending at this node.
+    pub fn key(&self) -> Option<KeyPress> {
+        self.key
+    }
+
+    /// Node of the sequence continued with `c`.
+    pub fn child(&self, c: char) -> Option<&Node> {
+        self.children.get(&c)
+    }
+
+    /// Tell if no longer sequence starts with this one.
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn remove(&mut self, mut chars: Chars) -> Option<KeyPress> {
+        let c = match chars.next() {
+            Some(c) => c,
+            None => return self.key.take(),
+        };
+        let (key, empty) = match self.children.get_mut(&c) {
+            Some(child) => {
+                let key = child.remove(chars);
+                (key, child.key.is_none() && child.is_leaf())
+            }
+            None => return None,
+        };
+        if empty {
+            self.children.remove(&c);
+        }
+        key
+    }
+}
+
+/// Trie of the escape sequences decoded as keys.
+#[derive(Debug, Default)]
+pub struct KeyTable {
+    root: Node,
+}
+
+impl KeyTable {
+    /// Decode `seq` as `key`.
+    /// Return the key it was decoded as, if any.
+    pub fn insert(&mut self, seq: &str, key: KeyPress) -> Option<KeyPress> {
+        let mut node = &mut self.root;
+        for c in seq.chars() {
+            node = node.children.entry(c).or_insert_with(Node::default);
+        }
+        mem::replace(&mut node.key, Some(key))
+    }
+
+    /// Stop decoding `seq`.
+    /// Return the key it was decoded as, if any.
+    pub fn remove(&mut self, seq: &str) -> Option<KeyPress> {
+        self.root.remove(seq.chars())
+    }
+
+    /// Node of the empty sequence.
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use consts::{Key, KeyPress};
+    use super::KeyTable;
+
+    fn decode(table: &KeyTable, seq: &str) -> Option<KeyPress> {
+        let mut node = table.root();
+        for c in seq.chars() {
+            node = match node.child(c) {
+                Some(child) => child,
+                None => return None,
+            };
+        }
+        node.key()
+    }
+
+    #[test]
+    fn insert_remove() {
+        let mut table = KeyTable::default();
+        assert!(table.root().is_leaf());
+        assert_eq!(None, table.insert("\x1b[1", key!(Key::Home)));
+        assert_eq!(None, table.insert("\x1b[15~", key!(Key::Insert)));
+        assert_eq!(Some(key!(Key::Insert)), table.insert("\x1b[15~", key!(Key::PageUp)));
+        assert_eq!(Some(key!(Key::Home)), decode(&table, "\x1b[1"));
+        assert_eq!(Some(key!(Key::PageUp)), decode(&table, "\x1b[15~"));
+        assert_eq!(None, decode(&table, "\x1b[15"));
+
+        assert_eq!(None, table.remove("\x1b[15"));
+        assert_eq!(Some(key!(Key::Home)), table.remove("\x1b[1"));
+        assert!(!table.root().child('\x1b').unwrap().child('[').unwrap().is_leaf());
+        assert_eq!(Some(key!(Key::PageUp)), table.remove("\x1b[15~"));
+        assert!(table.root().is_leaf());
+    }
+}
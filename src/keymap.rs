@@ -0,0 +1,1072 @@
+//! Bindings from keys to commands
+use std::collections::HashMap;
+use std::mem;
+
+use consts::{Key, KeyPress};
+use line_buffer::LineBuffer;
+use text_object::TextObject;
+
+/// Commands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cmd {
+    /// abort (cancel the pending key prefix, completion, register or region)
+    Abort,
+    /// Insert the whole hint displayed after the line
+    AcceptHint,
+    /// Insert the first word of the hint displayed after the line
+    AcceptHintWord,
+    /// accept-line
+    AcceptLine,
+    /// backward-char
+    BackwardChar,
+    /// backward-delete-char
+    BackwardDeleteChar,
+    /// backward-kill-word
+    BackwardKillWord,
+    /// backward-word
+    BackwardWord,
+    /// beginning-of-history
+    BeginningOfHistory,
+    /// bracketed-paste-begin: insert the pasted text as a whole (unix only)
+    BracketedPaste,
+    /// beginning-of-line
+    BeginningOfLine,
+    /// capitalize-word
+    CapitalizeWord,
+    /// clear-screen
+    ClearScreen,
+    /// Select a command by name in a searchable list and execute it
+    CommandPalette,
+    /// complete
+    Complete,
+    /// copy-region-as-kill
+    CopyRegionAsKill,
+    /// dabbrev-expand
+    DabbrevExpand,
+    /// delete-char
+    DeleteChar,
+    /// Display the full description of the current line in the pager
+    Describe,
+    /// downcase-word
+    DowncaseWord,
+    /// delete-char or end-of-file when the line is empty
+    EndOfFile,
+    /// end-of-history
+    EndOfHistory,
+    /// end-of-line
+    EndOfLine,
+    /// exchange-point-and-mark
+    ExchangePointAndMark,
+    /// Accept the line even if it is not balanced
+    ForceAcceptLine,
+    /// forward-char
+    ForwardChar,
+    /// forward-search-history
+    ForwardSearchHistory,
+    /// forward-word
+    ForwardWord,
+    /// Select a history entry in the list of fuzzy matches
+    FuzzySearchHistory,
+    /// insert-comment
+    InsertComment,
+    /// insert-completions
+    InsertCompletions,
+    /// Interrupt the line edition
+    Interrupt,
+    /// kill-line
+    KillLine,
+    /// kill-region
+    KillRegion,
+    /// Kill the text object around the cursor (like vi's `d` + text object)
+    KillTextObject(TextObject),
+    /// kill-whole-line (of a multi-line buffer)
+    KillWholeLine,
+    /// kill-word
+    KillWord,
+    /// next-history
+    NextHistory,
+    /// non-incremental-forward-search-history
+    NonIncrementalForwardSearchHistory,
+    /// non-incremental-reverse-search-history
+    NonIncrementalReverseSearchHistory,
+    /// Do nothing
+    Noop,
+    /// possible-completions
+    PossibleCompletions,
+    /// previous-history
+    PreviousHistory,
+    /// quoted-insert
+    QuotedInsert,
+    /// re-read-init-file
+    ReReadInitFile,
+    /// Repeat the last change (vi's `.`)
+    RepeatLastChange,
+    /// reverse-search-history
+    ReverseSearchHistory,
+    /// revert-line
+    RevertLine,
+    /// Select the register used by the next kill or yank
+    SelectRegister,
+    /// self-insert
+    SelfInsert(char),
+    /// set-mark
+    SetMark,
+    /// Suspend the process (unix only)
+    Suspend,
+    /// transpose-chars
+    TransposeChars,
+    /// transpose-words
+    TransposeWords,
+    /// undo
+    Undo,
+    /// unix-line-discard
+    UnixLineDiscard,
+    /// unix-word-rubout
+    UnixWordRubout,
+    /// upcase-word
+    UpcaseWord,
+    /// Execute the command added by the application under this name
+    /// (see `Editor::add_command`)
+    User(String),
+    /// yank
+    Yank,
+    /// yank-last-arg
+    YankLastArg,
+    /// yank-pop
+    YankPop,
+}
+
+impl Cmd {
+    /// Name of the command, as listed by the command palette (if it has one).
+    pub fn name(&self) -> Option<&'static str> {
+        COMMANDS.iter().find(|&&(_, ref cmd)| cmd == self).map(|&(name, _)| name)
+    }
+
+    /// Tell if the command modifies the line
+    /// (and so is recorded to be repeated by `RepeatLastChange`).
+    pub fn is_change(&self) -> bool {
+        match *self {
+            Cmd::BackwardDeleteChar |
+            Cmd::BackwardKillWord |
+            Cmd::CapitalizeWord |
+            Cmd::DeleteChar |
+            Cmd::DowncaseWord |
+            Cmd::KillLine |
+            Cmd::KillRegion |
+            Cmd::KillTextObject(_) |
+            Cmd::KillWholeLine |
+            Cmd::KillWord |
+            Cmd::SelfInsert(_) |
+            Cmd::TransposeChars |
+            Cmd::TransposeWords |
+            Cmd::UnixLineDiscard |
+            Cmd::UnixWordRubout |
+            Cmd::UpcaseWord |
+            Cmd::Yank => true,
+            _ => false,
+        }
+    }
+
+    /// Tell if the command kills or yanks text
+    /// (and so must not reset the kill-ring state).
+    pub fn is_kill_or_yank(&self) -> bool {
+        match *self {
+            Cmd::BackwardKillWord |
+            Cmd::CopyRegionAsKill |
+            Cmd::KillLine |
+            Cmd::KillRegion |
+            Cmd::KillTextObject(_) |
+            Cmd::KillWholeLine |
+            Cmd::KillWord |
+            Cmd::UnixLineDiscard |
+            Cmd::UnixWordRubout |
+            Cmd::Yank |
+            Cmd::YankPop => true,
+            _ => false,
+        }
+    }
+}
+
+/// Named commands listed by the command palette
+/// (commands with an argument or bound to a prefix are excluded).
+pub static COMMANDS: &'static [(&'static str, Cmd)] =
+    &[("abort", Cmd::Abort),
+      ("accept-hint", Cmd::AcceptHint),
+      ("accept-hint-word", Cmd::AcceptHintWord),
+      ("accept-line", Cmd::AcceptLine),
+      ("backward-char", Cmd::BackwardChar),
+      ("backward-delete-char", Cmd::BackwardDeleteChar),
+      ("backward-kill-word", Cmd::BackwardKillWord),
+      ("backward-word", Cmd::BackwardWord),
+      ("beginning-of-history", Cmd::BeginningOfHistory),
+      ("beginning-of-line", Cmd::BeginningOfLine),
+      ("capitalize-word", Cmd::CapitalizeWord),
+      ("clear-screen", Cmd::ClearScreen),
+      ("complete", Cmd::Complete),
+      ("copy-region-as-kill", Cmd::CopyRegionAsKill),
+      ("dabbrev-expand", Cmd::DabbrevExpand),
+      ("delete-char", Cmd::DeleteChar),
+      ("describe", Cmd::Describe),
+      ("downcase-word", Cmd::DowncaseWord),
+      ("end-of-file", Cmd::EndOfFile),
+      ("end-of-history", Cmd::EndOfHistory),
+      ("end-of-line", Cmd::EndOfLine),
+      ("exchange-point-and-mark", Cmd::ExchangePointAndMark),
+      ("force-accept-line", Cmd::ForceAcceptLine),
+      ("forward-char", Cmd::ForwardChar),
+      ("forward-search-history", Cmd::ForwardSearchHistory),
+      ("forward-word", Cmd::ForwardWord),
+      ("fuzzy-search-history", Cmd::FuzzySearchHistory),
+      ("insert-comment", Cmd::InsertComment),
+      ("insert-completions", Cmd::InsertCompletions),
+      ("interrupt", Cmd::Interrupt),
+      ("kill-line", Cmd::KillLine),
+      ("kill-region", Cmd::KillRegion),
+      ("kill-whole-line", Cmd::KillWholeLine),
+      ("kill-word", Cmd::KillWord),
+      ("next-history", Cmd::NextHistory),
+      ("non-incremental-forward-search-history", Cmd::NonIncrementalForwardSearchHistory),
+      ("non-incremental-reverse-search-history", Cmd::NonIncrementalReverseSearchHistory),
+      ("possible-completions", Cmd::PossibleCompletions),
+      ("previous-history", Cmd::PreviousHistory),
+      ("quoted-insert", Cmd::QuotedInsert),
+      ("re-read-init-file", Cmd::ReReadInitFile),
+      ("repeat-last-change", Cmd::RepeatLastChange),
+      ("reverse-search-history", Cmd::ReverseSearchHistory),
+      ("revert-line", Cmd::RevertLine),
+      ("select-register", Cmd::SelectRegister),
+      ("set-mark", Cmd::SetMark),
+      ("suspend", Cmd::Suspend),
+      ("transpose-chars", Cmd::TransposeChars),
+      ("transpose-words", Cmd::TransposeWords),
+      ("undo", Cmd::Undo),
+      ("unix-line-discard", Cmd::UnixLineDiscard),
+      ("unix-word-rubout", Cmd::UnixWordRubout),
+      ("upcase-word", Cmd::UpcaseWord),
+      ("yank", Cmd::Yank),
+      ("yank-last-arg", Cmd::YankLastArg),
+      ("yank-pop", Cmd::YankPop)];
+
+/// Custom behaviour bound to a key, with a mutable access to the edited line
+/// (to rewrite its content, move the cursor, insert text around the cursor, ...).
+/// It is also the command executed by `Cmd::User` (see `Editor::add_command`),
+/// which can be tested on a `LineBuffer::init` buffer (see `LineBuffer::assert_invariants`).
+pub trait KeyHandler {
+    /// Handle `key` by modifying `line`.
+    /// Return `false` to fall back to the command bound to `key`.
+    fn handle(&self, key: KeyPress, line: &mut LineBuffer) -> bool;
+}
+
+impl<F> KeyHandler for F
+    where F: Fn(KeyPress, &mut LineBuffer) -> bool
+{
+    fn handle(&self, key: KeyPress, line: &mut LineBuffer) -> bool {
+        self(key, line)
+    }
+}
+
+/// Key binding listed by `Editor::bindings`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// Keys typed one after the other (Ctrl-X first for the Ctrl-X sequences).
+    pub keys: Vec<KeyPress>,
+    pub cmd: Cmd,
+    /// Default command overridden by this custom binding, if any.
+    /// For a sequence of keys, the command of its first key,
+    /// which then only runs when no key follows it in time.
+    pub shadowed: Option<Cmd>,
+}
+
+impl Binding {
+    /// Readable name of the keys (like "Ctrl-X Ctrl-R" or "g g").
+    pub fn name(&self) -> String {
+        let names: Vec<String> = self.keys.iter().map(|key| key_name(*key)).collect();
+        names.join(" ")
+    }
+}
+
+/// State of a sequence of keys typed one after the other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sequence {
+    /// No binding starts with these keys
+    Unbound,
+    /// Some bindings start with these keys: the next key is awaited.
+    /// The command bound to the keys themselves (if any) is used
+    /// when no key follows before the timeout.
+    Prefix(Option<Cmd>),
+    /// The keys are bound to this command (and don't start any other binding)
+    Bound(Cmd),
+}
+
+/// Prefix tree of the key sequences
+#[derive(Default)]
+struct KeyTrie {
+    cmd: Option<Cmd>,
+    children: HashMap<KeyPress, KeyTrie>,
+}
+
+impl KeyTrie {
+    fn find(&self, keys: &[KeyPress]) -> Option<&KeyTrie> {
+        match keys.split_first() {
+            Some((key, rest)) => self.children.get(key).and_then(|child| child.find(rest)),
+            None => Some(self),
+        }
+    }
+
+    fn remove(&mut self, keys: &[KeyPress]) -> Option<Cmd> {
+        match keys.split_first() {
+            Some((key, rest)) => {
+                let (cmd, empty) = match self.children.get_mut(key) {
+                    Some(child) => {
+                        let cmd = child.remove(rest);
+                        (cmd, child.cmd.is_none() && child.children.is_empty())
+                    }
+                    None => return None,
+                };
+                if empty {
+                    self.children.remove(key);
+                }
+                cmd
+            }
+            None => self.cmd.take(),
+        }
+    }
+
+    /// Append the bound sequences starting with `prefix` to `sequences`.
+    fn sequences(&self, prefix: &[KeyPress], sequences: &mut Vec<(Vec<KeyPress>, Cmd)>) {
+        for (key, child) in &self.children {
+            let mut keys = prefix.to_vec();
+            keys.push(*key);
+            if let Some(ref cmd) = child.cmd {
+                sequences.push((keys.clone(), cmd.clone()));
+            }
+            child.sequences(&keys, sequences);
+        }
+    }
+}
+
+/// Key bindings: default Emacs bindings overridden by custom ones.
+#[derive(Default)]
+pub struct Keymap {
+    custom_bindings: HashMap<KeyPress, Cmd>,
+    ctrl_x_bindings: HashMap<KeyPress, Cmd>,
+    hint_bindings: HashMap<KeyPress, Cmd>,
+    handlers: HashMap<KeyPress, Box<KeyHandler>>,
+    commands: HashMap<String, Box<KeyHandler>>,
+    sequences: KeyTrie, // Bindings of several keys
+    raw_bindings: HashMap<String, Cmd>, // Bindings of undecoded escape sequences
+    last_change: Vec<Cmd>, // Last editing command followed by the inserted text
+    inserting: bool, // Inserted chars are appended to `last_change`
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        Keymap {
+            custom_bindings: HashMap::new(),
+            ctrl_x_bindings: HashMap::new(),
+            hint_bindings: HashMap::new(),
+            handlers: HashMap::new(),
+            commands: HashMap::new(),
+            sequences: KeyTrie::default(),
+            raw_bindings: HashMap::new(),
+            last_change: Vec::new(),
+            inserting: false,
+        }
+    }
+
+    /// Record `cmd` as (part of) the last change if it modifies the line:
+    /// a change is an editing command followed by the chars inserted just after it.
+    pub fn record(&mut self, cmd: &Cmd) {
+        match *cmd {
+            Cmd::SelfInsert(_) if self.inserting => self.last_change.push(cmd.clone()),
+            ref cmd if cmd.is_change() => {
+                self.last_change.clear();
+                self.last_change.push(cmd.clone());
+                self.inserting = true;
+            }
+            Cmd::RepeatLastChange => {}
+            _ => self.inserting = false,
+        }
+    }
+
+    /// Commands of the last change.
+    pub fn last_change(&self) -> &[Cmd] {
+        &self.last_change
+    }
+
+    /// Bind the sequence of `keys` to `cmd`, returning the previous binding of the sequence.
+    /// A single key is bound like with `bind`.
+    pub fn bind_keys(&mut self, keys: &[KeyPress], cmd: Cmd) -> Option<Cmd> {
+        match keys.len() {
+            0 => None,
+            1 => self.bind(keys[0], cmd),
+            _ => {
+                let mut node = &mut self.sequences;
+                for key in keys {
+                    node = node.children.entry(*key).or_insert_with(KeyTrie::default);
+                }
+                mem::replace(&mut node.cmd, Some(cmd))
+            }
+        }
+    }
+
+    /// Remove the binding of the sequence of `keys`.
+    pub fn unbind_keys(&mut self, keys: &[KeyPress]) -> Option<Cmd> {
+        match keys.len() {
+            0 => None,
+            1 => self.unbind(keys[0]),
+            _ => self.sequences.remove(keys),
+        }
+    }
+
+    /// Tell if `key` starts a sequence bound with `bind_keys`.
+    pub fn is_sequence_prefix(&self, key: KeyPress) -> bool {
+        self.sequences.children.contains_key(&key)
+    }
+
+    /// State of the sequence of `keys` (bound with `bind_keys`).
+    pub fn sequence(&self, keys: &[KeyPress]) -> Sequence {
+        match self.sequences.find(keys) {
+            Some(node) if node.children.is_empty() => {
+                match node.cmd {
+                    Some(ref cmd) => Sequence::Bound(cmd.clone()),
+                    None => Sequence::Unbound,
+                }
+            }
+            Some(node) => Sequence::Prefix(node.cmd.clone()),
+            None => Sequence::Unbound,
+        }
+    }
+
+    /// Commands of the `keys` of a broken sequence, typed one by one.
+    pub fn unsequenced(&self, keys: &[KeyPress]) -> Vec<Cmd> {
+        let mut cmds = Vec::new();
+        let mut keys = keys.iter();
+        while let Some(&key) = keys.next() {
+            if self.is_ctrl_x_prefix(key) {
+                if let Some(&key) = keys.next() {
+                    cmds.push(self.get_ctrl_x(key));
+                }
+            } else {
+                cmds.push(self.get(key));
+            }
+        }
+        cmds
+    }
+
+    /// Tell if `key` starts a Ctrl-X sequence (i.e. Ctrl-X is not bound to a command).
+    pub fn is_ctrl_x_prefix(&self, key: KeyPress) -> bool {
+        key == ctrl!('X') && !self.custom_bindings.contains_key(&key)
+    }
+
+    /// Bind the sequence Ctrl-X `key` to `cmd`, returning the previous custom binding.
+    pub fn bind_ctrl_x(&mut self, key: KeyPress, cmd: Cmd) -> Option<Cmd> {
+        self.ctrl_x_bindings.insert(key, cmd)
+    }
+
+    /// Remove the custom binding of the sequence Ctrl-X `key`.
+    pub fn unbind_ctrl_x(&mut self, key: KeyPress) -> Option<Cmd> {
+        self.ctrl_x_bindings.remove(&key)
+    }
+
+    /// Return the command bound to the sequence Ctrl-X `key`.
+    pub fn get_ctrl_x(&self, key: KeyPress) -> Cmd {
+        match self.ctrl_x_bindings.get(&key) {
+            Some(cmd) => cmd.clone(),
+            None => default_ctrl_x_binding(key),
+        }
+    }
+
+    /// Bind `key` to `cmd` when a hint is displayed and the cursor is at the end of the line,
+    /// returning the previous custom binding.
+    /// `Cmd::Noop` keeps the regular binding of `key`.
+    pub fn bind_hint(&mut self, key: KeyPress, cmd: Cmd) -> Option<Cmd> {
+        self.hint_bindings.insert(key, cmd)
+    }
+
+    /// Remove the custom binding of `key` when a hint is displayed.
+    pub fn unbind_hint(&mut self, key: KeyPress) -> Option<Cmd> {
+        self.hint_bindings.remove(&key)
+    }
+
+    /// Return the command bound to `key` when a hint is displayed,
+    /// or `Cmd::Noop` if the regular binding applies.
+    pub fn get_hint(&self, key: KeyPress) -> Cmd {
+        match (self.hint_bindings.get(&key), meta_fallback(key)) {
+            (Some(cmd), _) => cmd.clone(),
+            (None, Some(upper)) => self.get_hint(upper),
+            (None, None) => default_hint_binding(key),
+        }
+    }
+
+    /// Bind `key` to `handler`, returning the previous handler.
+    pub fn bind_handler(&mut self,
+                        key: KeyPress,
+                        handler: Box<KeyHandler>)
+                        -> Option<Box<KeyHandler>> {
+        self.handlers.insert(key, handler)
+    }
+
+    /// Remove the handler of `key`.
+    pub fn unbind_handler(&mut self, key: KeyPress) -> Option<Box<KeyHandler>> {
+        self.handlers.remove(&key)
+    }
+
+    /// Return the handler bound to `key`, if any.
+    pub fn handler(&self, key: KeyPress) -> Option<&KeyHandler> {
+        match (self.handlers.get(&key), meta_fallback(key)) {
+            (Some(handler), _) => Some(&**handler),
+            (None, Some(upper)) => self.handler(upper),
+            (None, None) => None,
+        }
+    }
+
+    /// Register `command` under `name`, returning the previous command of this name.
+    pub fn add_command(&mut self,
+                       name: &str,
+                       command: Box<KeyHandler>)
+                       -> Option<Box<KeyHandler>> {
+        self.commands.insert(String::from(name), command)
+    }
+
+    /// Remove the command registered under `name`.
+    pub fn remove_command(&mut self, name: &str) -> Option<Box<KeyHandler>> {
+        self.commands.remove(name)
+    }
+
+    /// Return the command registered under `name`, if any.
+    pub fn command(&self, name: &str) -> Option<&KeyHandler> {
+        self.commands.get(name).map(|c| &**c)
+    }
+
+    /// Bind the raw escape sequence `seq` of a key decoded as `Key::Unknown` to `cmd`,
+    /// returning the previous binding.
+    pub fn bind_raw(&mut self, seq: &str, cmd: Cmd) -> Option<Cmd> {
+        self.raw_bindings.insert(String::from(seq), cmd)
+    }
+
+    /// Remove the binding of the raw escape sequence `seq`.
+    pub fn unbind_raw(&mut self, seq: &str) -> Option<Cmd> {
+        self.raw_bindings.remove(seq)
+    }
+
+    /// Return the command bound to the raw escape sequence `seq`
+    /// (`Cmd::Noop` if there is none).
+    pub fn get_raw(&self, seq: &str) -> Cmd {
+        match self.raw_bindings.get(seq) {
+            Some(cmd) => cmd.clone(),
+            None => Cmd::Noop,
+        }
+    }
+
+    /// Bind `key` to `cmd`, returning the previous custom binding.
+    pub fn bind(&mut self, key: KeyPress, cmd: Cmd) -> Option<Cmd> {
+        self.custom_bindings.insert(key, cmd)
+    }
+
+    /// Remove the custom binding of `key`.
+    pub fn unbind(&mut self, key: KeyPress) -> Option<Cmd> {
+        self.custom_bindings.remove(&key)
+    }
+
+    /// Return the command bound to `key`.
+    /// An unbound lower-case Meta letter gets the binding of the upper-case one
+    /// (Meta-b the one of Meta-B).
+    pub fn get(&self, key: KeyPress) -> Cmd {
+        match (self.custom_bindings.get(&key), meta_fallback(key)) {
+            (Some(cmd), _) => cmd.clone(),
+            (None, Some(upper)) => self.get(upper),
+            (None, None) => default_binding(key),
+        }
+    }
+
+    /// Names of the keys (and Ctrl-X sequences) currently bound to `cmd`.
+    pub fn bindings(&self, cmd: &Cmd) -> Vec<String> {
+        self.list().iter().filter(|b| b.cmd == *cmd).map(Binding::name).collect()
+    }
+
+    /// Current bindings of the single keys, of the Ctrl-X sequences
+    /// and of the sequences of keys, in this order
+    /// (without the default self-inserted chars nor the bindings used with a hint).
+    pub fn list(&self) -> Vec<Binding> {
+        let mut list: Vec<Binding> = Vec::new();
+        for key in candidate_keys(false, &self.custom_bindings) {
+            let cmd = self.get(key);
+            let custom = self.custom_bindings.contains_key(&key);
+            if self.is_ctrl_x_prefix(key) || cmd == Cmd::Noop ||
+               (!custom && (is_self_insert(&cmd) || meta_fallback(key).is_some())) {
+                continue;
+            }
+            let default = default_binding(key);
+            list.push(Binding {
+                keys: vec![key],
+                shadowed: if custom { overridden(default, &cmd) } else { None },
+                cmd: cmd,
+            });
+        }
+        if self.is_ctrl_x_prefix(ctrl!('X')) {
+            for key in candidate_keys(true, &self.ctrl_x_bindings) {
+                let cmd = self.get_ctrl_x(key);
+                if cmd == Cmd::Noop {
+                    continue;
+                }
+                let shadowed = if self.ctrl_x_bindings.contains_key(&key) {
+                    overridden(default_ctrl_x_binding(key), &cmd)
+                } else {
+                    None
+                };
+                list.push(Binding {
+                    keys: vec![ctrl!('X'), key],
+                    cmd: cmd,
+                    shadowed: shadowed,
+                });
+            }
+        }
+        let mut sequences = Vec::new();
+        self.sequences.sequences(&[], &mut sequences);
+        let mut sequences: Vec<Binding> = sequences.into_iter()
+            .map(|(keys, cmd)| {
+                let first = self.get(keys[0]);
+                Binding {
+                    keys: keys,
+                    cmd: cmd,
+                    shadowed: if first == Cmd::Noop { None } else { Some(first) },
+                }
+            })
+            .collect();
+        sequences.sort_by_key(Binding::name);
+        list.extend(sequences);
+        // keys with the same name (like Meta-BackSpace) are listed once
+        let mut names = Vec::new();
+        list.retain(|binding| {
+            let name = binding.name();
+            if names.contains(&name) {
+                false
+            } else {
+                names.push(name);
+                true
+            }
+        });
+        list
+    }
+}
+
+/// Keys listed with their bindings: the Ctrl keys, then the special keys and the Meta chars
+/// (or the plain chars after Ctrl-X), then the other keys of the `custom` bindings.
+fn candidate_keys(ctrl_x: bool, custom: &HashMap<KeyPress, Cmd>) -> Vec<KeyPress> {
+    let mut keys = Vec::new();
+    for c in b'A'..b'Z' + 1 {
+        keys.push(ctrl!(Key::Char(c as char)));
+    }
+    if ctrl_x {
+        for c in b'!'..b'~' + 1 {
+            keys.push(key!(Key::Char(c as char)));
+        }
+    } else {
+        keys.extend_from_slice(&[key!(Key::Backspace),
+                                 key!(Key::Delete),
+                                 key!(Key::Down),
+                                 key!(Key::End),
+                                 key!(Key::Enter),
+                                 key!(Key::Home),
+                                 key!(Key::Insert),
+                                 key!(Key::Left),
+                                 key!(Key::Null),
+                                 key!(Key::PageDown),
+                                 key!(Key::PageUp),
+                                 key!(Key::Right),
+                                 key!(Key::Tab),
+                                 key!(Key::Up),
+                                 alt!(Key::Enter),
+                                 alt!('\x08'),
+                                 alt!('\x7f')]);
+        for c in b'!'..b'~' + 1 {
+            keys.push(alt!(Key::Char(c as char)));
+        }
+    }
+    let mut custom: Vec<KeyPress> = custom.keys().filter(|k| !keys.contains(k)).cloned().collect();
+    custom.sort_by_key(|key| key_name(*key));
+    keys.extend(custom);
+    keys
+}
+
+/// Upper-case Meta letter whose binding applies to the lower-case `key` when it is not bound.
+fn meta_fallback(key: KeyPress) -> Option<KeyPress> {
+    match key {
+        alt!(c) if c >= 'a' && c <= 'z' => {
+            Some(alt!(Key::Char((c as u8 - b'a' + b'A') as char)))
+        }
+        _ => None,
+    }
+}
+
+/// `default` if it is overridden by `cmd`.
+fn overridden(default: Cmd, cmd: &Cmd) -> Option<Cmd> {
+    if default == Cmd::Noop || default == *cmd {
+        None
+    } else {
+        Some(default)
+    }
+}
+
+fn is_self_insert(cmd: &Cmd) -> bool {
+    match *cmd {
+        Cmd::SelfInsert(_) => true,
+        _ => false,
+    }
+}
+
+/// Readable name of `key` (like "Ctrl-A", "Meta-B" or "Home").
+pub fn key_name(key: KeyPress) -> String {
+    let mut name = String::new();
+    if key.ctrl {
+        name.push_str("Ctrl-");
+    }
+    if key.alt {
+        name.push_str("Meta-");
+    }
+    if key.shift {
+        name.push_str("Shift-");
+    }
+    if key.sup {
+        name.push_str("Super-");
+    }
+    match key.key {
+        Key::Backspace |
+        Key::Char('\x08') |
+        Key::Char('\x7f') => name.push_str("BackSpace"),
+        Key::Char(' ') => name.push_str("Space"),
+        Key::Char(c) => name.push(c),
+        Key::F(n) => name.push_str(&format!("F{}", n)),
+        other => name.push_str(&format!("{:?}", other)),
+    }
+    name
+}
+
+/// Parse a key `name` as returned by `key_name`.
+pub fn parse_key_name(name: &str) -> Option<KeyPress> {
+    let mut key = key!(Key::Null);
+    let mut rest = name;
+    loop {
+        if rest.len() > 5 && rest.starts_with("Ctrl-") {
+            key.ctrl = true;
+            rest = &rest[5..];
+        } else if rest.len() > 5 && rest.starts_with("Meta-") {
+            key.alt = true;
+            rest = &rest[5..];
+        } else if rest.len() > 6 && rest.starts_with("Shift-") {
+            key.shift = true;
+            rest = &rest[6..];
+        } else if rest.len() > 6 && rest.starts_with("Super-") {
+            key.sup = true;
+            rest = &rest[6..];
+        } else {
+            break;
+        }
+    }
+    key.key = match rest {
+        "BackSpace" if key.alt => Key::Char('\x7f'),
+        "BackSpace" => Key::Backspace,
+        "BracketedPaste" => Key::BracketedPaste,
+        "Space" => Key::Char(' '),
+        "Delete" => Key::Delete,
+        "Down" => Key::Down,
+        "End" => Key::End,
+        "Enter" => Key::Enter,
+        "Esc" => Key::Esc,
+        "Home" => Key::Home,
+        "Insert" => Key::Insert,
+        "Left" => Key::Left,
+        "Null" => Key::Null,
+        "PageDown" => Key::PageDown,
+        "PageUp" => Key::PageUp,
+        "Right" => Key::Right,
+        "Tab" => Key::Tab,
+        "Unknown" => Key::Unknown,
+        "Up" => Key::Up,
+        _ if rest.starts_with('F') && rest[1..].parse::<u8>().is_ok() => {
+            Key::F(rest[1..].parse().unwrap())
+        }
+        _ => {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                // Ctrl letters are decoded as upper case characters
+                (Some(c), None) if key.ctrl && c >= 'a' && c <= 'z' => {
+                    Key::Char((c as u8 - b'a' + b'A') as char)
+                }
+                (Some(c), None) => Key::Char(c),
+                _ => return None,
+            }
+        }
+    };
+    Some(key)
+}
+
+/// Default (Emacs) binding of `key`.
+#[allow(match_same_arms)]
+pub fn default_binding(key: KeyPress) -> Cmd {
+    match key {
+        key!(c) => Cmd::SelfInsert(c),
+        ctrl!('A') |
+        key!(Key::Home) => Cmd::BeginningOfLine,
+        ctrl!('B') |
+        key!(Key::Left) => Cmd::BackwardChar,
+        ctrl!('C') => Cmd::Interrupt,
+        ctrl!('D') => Cmd::EndOfFile,
+        ctrl!('E') |
+        key!(Key::End) => Cmd::EndOfLine,
+        ctrl!('F') |
+        key!(Key::Right) => Cmd::ForwardChar,
+        ctrl!('G') => Cmd::Abort,
+        ctrl!('H') |
+        key!(Key::Backspace) => Cmd::BackwardDeleteChar,
+        key!(Key::Tab) => Cmd::Complete,
+        ctrl!('K') => Cmd::KillLine,
+        ctrl!('L') => Cmd::ClearScreen,
+        ctrl!('N') |
+        key!(Key::Down) => Cmd::NextHistory,
+        ctrl!('P') |
+        key!(Key::Up) => Cmd::PreviousHistory,
+        ctrl!('R') => Cmd::ReverseSearchHistory,
+        ctrl!('S') => Cmd::ForwardSearchHistory,
+        ctrl!('T') => Cmd::TransposeChars,
+        ctrl!('U') => Cmd::UnixLineDiscard,
+        // pasted by the Windows console
+        #[cfg(unix)]
+        ctrl!('V') => Cmd::QuotedInsert,
+        key!(Key::BracketedPaste) => Cmd::BracketedPaste,
+        ctrl!('W') => Cmd::UnixWordRubout,
+        ctrl!('Y') => Cmd::Yank,
+        ctrl!('Z') => Cmd::Suspend,
+        ctrl!('_') => Cmd::Undo,
+        key!(Key::Null) => Cmd::SetMark, // Ctrl-Space
+        key!(Key::Enter) |
+        ctrl!('J') => Cmd::AcceptLine,
+        alt!(Key::Enter) => Cmd::ForceAcceptLine,
+        alt!('\x08') |
+        alt!('\x7f') => Cmd::BackwardKillWord,
+        alt!('#') => Cmd::InsertComment,
+        alt!('*') => Cmd::InsertCompletions,
+        alt!('<') => Cmd::BeginningOfHistory,
+        alt!('=') => Cmd::PossibleCompletions,
+        alt!('/') => Cmd::DabbrevExpand,
+        alt!('.') |
+        alt!('_') => Cmd::YankLastArg,
+        alt!('>') => Cmd::EndOfHistory,
+        alt!('B') => Cmd::BackwardWord,
+        alt!('C') => Cmd::CapitalizeWord,
+        alt!('D') => Cmd::KillWord,
+        alt!('F') => Cmd::ForwardWord,
+        alt!('H') => Cmd::Describe,
+        alt!('L') => Cmd::DowncaseWord,
+        alt!('N') => Cmd::NonIncrementalForwardSearchHistory,
+        alt!('P') => Cmd::NonIncrementalReverseSearchHistory,
+        alt!('R') => Cmd::RevertLine,
+        alt!('T') => Cmd::TransposeWords,
+        alt!('U') => Cmd::UpcaseWord,
+        alt!('W') => Cmd::CopyRegionAsKill,
+        alt!('Y') => Cmd::YankPop,
+        key!(Key::Delete) => Cmd::DeleteChar,
+        _ => Cmd::Noop,
+    }
+}
+
+/// Default binding of `key` when a hint is displayed at the end of the line.
+pub fn default_hint_binding(key: KeyPress) -> Cmd {
+    match key {
+        ctrl!('E') |
+        ctrl!('F') |
+        key!(Key::End) |
+        key!(Key::Right) => Cmd::AcceptHint,
+        alt!('F') => Cmd::AcceptHintWord,
+        _ => Cmd::Noop,
+    }
+}
+
+/// Default binding of the sequence Ctrl-X `key`.
+pub fn default_ctrl_x_binding(key: KeyPress) -> Cmd {
+    match key {
+        ctrl!('G') => Cmd::Abort,
+        ctrl!('P') => Cmd::CommandPalette,
+        ctrl!('R') => Cmd::ReReadInitFile,
+        ctrl!('U') => Cmd::Undo,
+        ctrl!('X') => Cmd::ExchangePointAndMark,
+        key!('"') => Cmd::SelectRegister,
+        key!('z') => Cmd::RepeatLastChange,
+        _ => Cmd::Noop,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use consts::{Key, KeyPress};
+    use super::{Cmd, Keymap, Sequence, key_name, parse_key_name};
+
+    #[test]
+    fn default_bindings() {
+        let keymap = Keymap::new();
+        assert_eq!(Cmd::SelfInsert('a'), keymap.get(key!('a')));
+        assert_eq!(Cmd::AcceptLine, keymap.get(key!(Key::Enter)));
+        assert_eq!(Cmd::Noop, keymap.get(key!(Key::Unknown)));
+        assert_eq!(Cmd::ReReadInitFile, keymap.get_ctrl_x(ctrl!('R')));
+        assert_eq!(Cmd::ExchangePointAndMark, keymap.get_ctrl_x(ctrl!('X')));
+        assert_eq!(Cmd::Noop, keymap.get_ctrl_x(ctrl!('Y')));
+        assert_eq!(Cmd::Abort, keymap.get_ctrl_x(ctrl!('G')));
+    }
+
+    #[test]
+    fn hint_bindings() {
+        let mut keymap = Keymap::new();
+        assert_eq!(Cmd::AcceptHint, keymap.get_hint(key!(Key::Right)));
+        assert_eq!(Cmd::AcceptHintWord, keymap.get_hint(alt!('F')));
+        assert_eq!(Cmd::Noop, keymap.get_hint(key!('a')));
+        keymap.bind_hint(key!(Key::Right), Cmd::Noop);
+        keymap.bind_hint(key!(Key::Tab), Cmd::AcceptHint);
+        assert_eq!(Cmd::Noop, keymap.get_hint(key!(Key::Right)));
+        assert_eq!(Cmd::AcceptHint, keymap.get_hint(key!(Key::Tab)));
+    }
+
+    #[test]
+    fn record() {
+        let mut keymap = Keymap::new();
+        keymap.record(&Cmd::SelfInsert('a'));
+        keymap.record(&Cmd::SelfInsert('b'));
+        assert_eq!(&[Cmd::SelfInsert('a'), Cmd::SelfInsert('b')], keymap.last_change());
+        keymap.record(&Cmd::BackwardChar);
+        keymap.record(&Cmd::RepeatLastChange);
+        assert_eq!(2, keymap.last_change().len());
+        keymap.record(&Cmd::KillWord);
+        keymap.record(&Cmd::SelfInsert('c'));
+        assert_eq!(&[Cmd::KillWord, Cmd::SelfInsert('c')], keymap.last_change());
+    }
+
+    #[test]
+    fn bindings() {
+        let mut keymap = Keymap::new();
+        assert_eq!(vec!["Ctrl-A", "Home"], keymap.bindings(&Cmd::BeginningOfLine));
+        assert_eq!(vec!["Meta-BackSpace"], keymap.bindings(&Cmd::BackwardKillWord));
+        assert_eq!(vec!["Ctrl-X z"], keymap.bindings(&Cmd::RepeatLastChange));
+        keymap.bind(key!(Key::Home), Cmd::Noop);
+        keymap.bind(key!(Key::Esc), Cmd::BeginningOfLine);
+        assert_eq!(vec!["Ctrl-A", "Esc"], keymap.bindings(&Cmd::BeginningOfLine));
+        assert_eq!("Meta-<", key_name(alt!('<')));
+    }
+
+    #[test]
+    fn list() {
+        let mut keymap = Keymap::new();
+        let list = keymap.list();
+        assert_eq!("Ctrl-A", list[0].name());
+        assert_eq!(Cmd::BeginningOfLine, list[0].cmd);
+        assert!(list.iter().all(|b| b.shadowed.is_none()));
+        assert!(list.iter().any(|b| b.name() == "Ctrl-X Ctrl-R"));
+        assert_eq!(Some("beginning-of-line"), Cmd::BeginningOfLine.name());
+
+        keymap.bind(ctrl!('A'), Cmd::Noop);
+        keymap.bind(key!(Key::Home), Cmd::EndOfLine);
+        keymap.bind(key!('%'), Cmd::Complete);
+        keymap.bind_ctrl_x(ctrl!('R'), Cmd::ClearScreen);
+        keymap.bind_keys(&[key!('g'), key!('g')], Cmd::BeginningOfHistory);
+        let shadowed: Vec<(String, Option<Cmd>)> = keymap.list()
+            .into_iter()
+            .filter(|b| b.shadowed.is_some())
+            .map(|b| (b.name(), b.shadowed))
+            .collect();
+        assert_eq!(vec![(String::from("Home"), Some(Cmd::BeginningOfLine)),
+                        (String::from("%"), Some(Cmd::SelfInsert('%'))),
+                        (String::from("Ctrl-X Ctrl-R"), Some(Cmd::ReReadInitFile)),
+                        (String::from("g g"), Some(Cmd::SelfInsert('g')))],
+                   shadowed);
+        assert!(keymap.list().iter().all(|b| b.name() != "Ctrl-A"));
+
+        keymap.bind(ctrl!('X'), Cmd::Noop);
+        assert!(keymap.list().iter().all(|b| !b.name().starts_with("Ctrl-X")));
+    }
+
+    #[test]
+    fn sequences() {
+        let mut keymap = Keymap::new();
+        assert_eq!(None, keymap.bind_keys(&[key!('g'), key!('g')], Cmd::BeginningOfHistory));
+        keymap.bind_keys(&[key!('g'), key!('g'), key!('x')], Cmd::Noop);
+        keymap.bind_keys(&[key!('g'), key!('e')], Cmd::EndOfHistory);
+        assert!(keymap.is_sequence_prefix(key!('g')));
+        assert!(!keymap.is_sequence_prefix(key!('e')));
+        assert_eq!(Sequence::Prefix(None), keymap.sequence(&[key!('g')]));
+        assert_eq!(Sequence::Prefix(Some(Cmd::BeginningOfHistory)),
+                   keymap.sequence(&[key!('g'), key!('g')]));
+        assert_eq!(Sequence::Bound(Cmd::EndOfHistory), keymap.sequence(&[key!('g'), key!('e')]));
+        assert_eq!(Sequence::Unbound, keymap.sequence(&[key!('g'), key!('a')]));
+        assert_eq!(vec!["Meta->", "g e"], keymap.bindings(&Cmd::EndOfHistory));
+
+        assert_eq!(Some(Cmd::Noop), keymap.unbind_keys(&[key!('g'), key!('g'), key!('x')]));
+        assert_eq!(Sequence::Bound(Cmd::BeginningOfHistory),
+                   keymap.sequence(&[key!('g'), key!('g')]));
+        keymap.unbind_keys(&[key!('g'), key!('g')]);
+        keymap.unbind_keys(&[key!('g'), key!('e')]);
+        assert!(!keymap.is_sequence_prefix(key!('g')));
+
+        assert_eq!(vec![Cmd::SelfInsert('g'), Cmd::ReReadInitFile, Cmd::SelfInsert('a')],
+                   keymap.unsequenced(&[key!('g'), ctrl!('X'), ctrl!('R'), key!('a')]));
+    }
+
+    #[test]
+    fn raw_bindings() {
+        let mut keymap = Keymap::new();
+        assert_eq!(None, keymap.bind_raw("\x1b[2;5~", Cmd::CapitalizeWord));
+        assert_eq!(Cmd::CapitalizeWord, keymap.get_raw("\x1b[2;5~"));
+        assert_eq!(Cmd::Noop, keymap.get_raw("\x1b[2;3~"));
+        assert_eq!(Some(Cmd::CapitalizeWord), keymap.unbind_raw("\x1b[2;5~"));
+        assert_eq!(Cmd::Noop, keymap.get_raw("\x1b[2;5~"));
+    }
+
+    #[test]
+    fn parse_key_names() {
+        for key in &[key!('a'),
+                     key!(' '),
+                     key!('-'),
+                     key!(Key::Backspace),
+                     key!(Key::PageUp),
+                     key!(Key::F(12)),
+                     ctrl!('A'),
+                     alt!('<'),
+                     alt!('\x7f'),
+                     alt!(Key::Enter)] {
+            assert_eq!(Some(*key), parse_key_name(&key_name(*key)));
+        }
+        assert_eq!(Some(alt!('b')), parse_key_name("Meta-b"));
+        assert_eq!(Some(ctrl!('B')), parse_key_name("Ctrl-b"));
+        assert_eq!(Some(key!('C')), parse_key_name("C"));
+        assert_eq!(Some(key!('F')), parse_key_name("F"));
+        assert_eq!(None, parse_key_name("Ctrl-"));
+        assert_eq!(None, parse_key_name("abc"));
+    }
+
+    #[test]
+    fn meta_case() {
+        let mut keymap = Keymap::new();
+        assert_eq!(Cmd::BackwardWord, keymap.get(alt!('b')));
+        assert_eq!(Cmd::BackwardWord, keymap.get(alt!('B')));
+        assert_eq!(Cmd::Noop, keymap.get(alt!('é')));
+        keymap.bind(alt!('b'), Cmd::BeginningOfLine);
+        assert_eq!(Cmd::BeginningOfLine, keymap.get(alt!('b')));
+        assert_eq!(Cmd::BackwardWord, keymap.get(alt!('B')));
+        keymap.bind(alt!('Z'), Cmd::EndOfLine);
+        assert_eq!(Cmd::EndOfLine, keymap.get(alt!('z')));
+        // listed once, with the case of the binding
+        let names = keymap.bindings(&Cmd::EndOfLine);
+        assert!(names.contains(&String::from("Meta-Z")));
+        assert!(!names.contains(&String::from("Meta-z")));
+        let names = keymap.bindings(&Cmd::BackwardWord);
+        assert!(names.contains(&String::from("Meta-B")));
+        assert!(!names.contains(&String::from("Meta-b")));
+    }
+
+    #[test]
+    fn custom_bindings() {
+        let mut keymap = Keymap::new();
+        assert_eq!(None, keymap.bind(key!(Key::Tab), Cmd::SelfInsert('\t')));
+        assert_eq!(Cmd::SelfInsert('\t'), keymap.get(key!(Key::Tab)));
+        assert_eq!(Some(Cmd::SelfInsert('\t')), keymap.unbind(key!(Key::Tab)));
+        assert_eq!(Cmd::Complete, keymap.get(key!(Key::Tab)));
+    }
+}
@@ -1,4 +1,5 @@
 //! Kill Ring
+use std::collections::HashMap;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Action {
@@ -17,6 +18,8 @@ pub struct KillRing {
     slots: Vec<String>,
     index: usize,
     last_action: Action,
+    registers: HashMap<char, String>, // Named registers ('a' to 'z')
+    register: Option<char>, // Register selected for the next kill or yank
 }
 
 impl KillRing {
@@ -26,16 +29,66 @@ impl KillRing {
             slots: Vec::with_capacity(size),
             index: 0,
             last_action: Action::Other,
+            registers: HashMap::new(),
+            register: None,
         }
     }
 
-    /// Reset `last_action` state.
+    /// Reset `last_action` state (and the selected register).
     pub fn reset(&mut self) {
         self.last_action = Action::Other;
+        self.register = None;
     }
 
-    /// Add `text` to the kill-ring.
+    /// Select the register used by the next kill or yank:
+    /// 'a' to 'z' (replaced by kills), 'A' to 'Z' (appended to by kills),
+    /// or '"' (the kill-ring).
+    /// Return `false` when `name` is not a valid register name.
+    pub fn select_register(&mut self, name: char) -> bool {
+        if name != '"' && !(name >= 'a' && name <= 'z') && !(name >= 'A' && name <= 'Z') {
+            return false;
+        }
+        self.register = Some(name);
+        true
+    }
+
+    /// Return the content of the register `name` ('"' being the last killed text).
+    pub fn register(&self, name: char) -> Option<&str> {
+        if name == '"' {
+            self.slots.get(self.index).map(|s| s.as_str())
+        } else {
+            self.registers.get(&lower(name)).map(|s| s.as_str())
+        }
+    }
+
+    /// Set the content of the register `name`.
+    pub fn set_register(&mut self, name: char, text: &str) {
+        self.registers.insert(lower(name), String::from(text));
+    }
+
+    /// Return the named registers, sorted by name.
+    pub fn registers(&self) -> Vec<(char, &str)> {
+        let mut registers: Vec<(char, &str)> =
+            self.registers.iter().map(|(&name, text)| (name, text.as_str())).collect();
+        registers.sort();
+        registers
+    }
+
+    /// Add `text` to the kill-ring (and to the selected register).
     pub fn kill(&mut self, text: &str, dir: Mode) {
+        if let Some(name) = self.register {
+            if name != '"' {
+                let append = self.last_action == Action::Kill || name.is_uppercase();
+                let entry = self.registers.entry(lower(name)).or_insert_with(String::new);
+                if !append {
+                    entry.clear();
+                }
+                match dir {
+                    Mode::Append => entry.push_str(text),
+                    Mode::Prepend => *entry = String::from(text) + entry,
+                }
+            }
+        }
         match self.last_action {
             Action::Kill => {
                 if self.slots.capacity() == 0 {
@@ -70,9 +123,20 @@ impl KillRing {
         }
     }
 
-    /// Yank previously killed text.
-    /// Return `None` when kill-ring is empty.
+    /// Yank previously killed text (or the content of the selected register).
+    /// Return `None` when kill-ring (or register) is empty.
     pub fn yank(&mut self) -> Option<&String> {
+        if let Some(name) = self.register {
+            if name != '"' {
+                return match self.registers.get(&lower(name)) {
+                    Some(text) => {
+                        self.last_action = Action::Yank(text.len());
+                        Some(text)
+                    }
+                    None => None,
+                };
+            }
+        }
         if self.slots.is_empty() {
             None
         } else {
@@ -102,6 +166,10 @@ impl KillRing {
     }
 }
 
+fn lower(name: char) -> char {
+    name.to_lowercase().next().unwrap_or(name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Action, Mode, KillRing};
@@ -206,4 +274,29 @@ mod tests {
         assert_eq!(Some((5, &"longword2".to_string())), kill_ring.yank_pop());
         assert_eq!(Some((9, &"word1".to_string())), kill_ring.yank_pop());
     }
+
+    #[test]
+    fn registers() {
+        let mut kill_ring = KillRing::new(2);
+        assert!(kill_ring.select_register('a'));
+        kill_ring.kill("word1", Mode::Append);
+        kill_ring.kill(" word2", Mode::Append);
+        kill_ring.reset();
+        assert!(kill_ring.select_register('A'));
+        kill_ring.kill(" word3", Mode::Append);
+        kill_ring.reset();
+        kill_ring.kill("word4", Mode::Append);
+        assert_eq!(Some("word1 word2 word3"), kill_ring.register('a'));
+        assert_eq!(Some("word4"), kill_ring.register('"'));
+        assert!(!kill_ring.select_register('1'));
+
+        kill_ring.reset();
+        kill_ring.select_register('a');
+        assert_eq!(Some(&"word1 word2 word3".to_string()), kill_ring.yank());
+        kill_ring.reset();
+        assert_eq!(Some(&"word4".to_string()), kill_ring.yank());
+
+        kill_ring.set_register('z', "text");
+        assert_eq!(vec![('a', "word1 word2 word3"), ('z', "text")], kill_ring.registers());
+    }
 }
@@ -17,7 +17,7 @@
 #![allow(unknown_lints)]
 
 extern crate libc;
-#[cfg(unix)]
+#[cfg(all(unix, feature = "nix"))]
 extern crate nix;
 extern crate unicode_width;
 extern crate encode_unicode;
@@ -25,34 +25,117 @@ extern crate encode_unicode;
 extern crate winapi;
 #[cfg(windows)]
 extern crate kernel32;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+#[cfg(all(test, feature = "proptests"))]
+extern crate proptest;
+
+/// Evaluate `$e`, counting its duration in `$stage` of the current key
+/// (`perf` feature, `$e` must not return from the function).
+#[cfg(feature = "perf")]
+macro_rules! timed {
+    ($stage:ident, $e:expr) => { ::perf::timed(::perf::Stage::$stage, || $e) }
+}
+#[cfg(not(feature = "perf"))]
+macro_rules! timed {
+    ($stage:ident, $e:expr) => { $e }
+}
 
+pub mod accept;
+mod accessible;
+mod auto_pair;
+#[cfg(feature = "async")]
+pub mod background;
 pub mod completion;
 #[macro_use]
 mod consts;
+#[cfg(test)]
+mod compat;
 pub mod error;
+pub mod filter;
+pub mod highlight;
+pub mod hint;
 pub mod history;
+mod history_overlay;
+pub mod idle;
+// partly used by the language server bridge only
+#[cfg_attr(not(feature = "lsp"), allow(dead_code))]
+mod json;
+mod key_table;
+mod keymap;
 mod kill_ring;
 pub mod line_buffer;
-#[cfg(unix)]
+pub mod message;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod observer;
+pub mod paste;
+#[cfg(feature = "perf")]
+pub mod perf;
+pub mod preprocess;
+pub mod remote;
+mod pager;
+mod palette;
+mod prompt;
+pub mod script;
+pub mod shell_words;
+pub mod template;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_history;
+mod text_object;
 mod char_iter;
 pub mod config;
+mod dabbrev;
+mod decode;
+mod fuzzy_search;
 
 mod tty;
 
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::env;
 use std::fmt;
 use std::io::{self, Write};
+use std::iter;
 use std::mem;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 use std::result;
-use tty::{RawMode, RawReader, Terminal, Term};
+use std::time::Instant;
+use std::usize;
+use tty::{ScriptedReader, SharedKeyTable, SharedPreprocessor, Terminal};
 
 use encode_unicode::CharExt;
-use completion::{Completer, longest_common_prefix};
-use consts::{Key, KeyPress};
-use history::{Direction, History};
-use line_buffer::{LineBuffer, MAX_LINE, WordAction};
+use accept::{AcceptHook, Acceptance};
+use accessible::Echo;
+use completion::{Completer, Descriptor, longest_common_prefix};
+use filter::InsertFilter;
+use highlight::{ColorDepth, Highlighter};
+use hint::Hinter;
+use idle::{Idle, IdleHook};
+use observer::{DebugLogger, Observer};
+use paste::{PasteFilter, PasteNormalizer};
+use preprocess::InputPreprocessor;
+use history::{Direction, History, HistoryBackend, HistoryScorer};
+use history_overlay::HistoryOverlay;
+use line_buffer::{MAX_LINE, WordAction};
+use message::{MessageLine, StatusBar};
 use kill_ring::{Mode, KillRing};
-pub use config::{CompletionType, Config, HistoryDuplicates};
+use script::{Recorder, Script};
+use template::{PromptVariable, PromptVariables};
+pub use config::{BellStyle, ColorMode, CompletionType, Config, ConfigLoader, CursorShape,
+                 EscMode, HistoryDuplicates, LineContinuation, LongPrompt, OutputStreamType,
+                 TabDisplay, TermSupport, UpDown};
+pub use consts::{Key, KeyPress};
+pub use decode::decode_keys;
+pub use keymap::{Binding, Cmd, KeyHandler};
+pub use line_buffer::LineBuffer;
+pub use prompt::Number;
+pub use text_object::TextObject;
+pub use tty::{RawMode, RawReader, Term};
+use keymap::{Keymap, Sequence};
 
 /// The error type for I/O and Linux Syscalls (Errno)
 pub type Result<T> = result::Result<T, error::ReadlineError>;
@@ -60,15 +143,68 @@ pub type Result<T> = result::Result<T, error::ReadlineError>;
 // Represent the state during line editing.
 struct State<'out, 'prompt> {
     out: &'out mut Write,
-    prompt: &'prompt str, // Prompt to display
+    template: &'prompt str, // Prompt given by the application, with its variables
+    prompt: Cow<'prompt, str>, // Prompt to display
     prompt_size: Position, // Prompt Unicode width and height
+    long_prompt: LongPrompt, // Display of a prompt wider than the terminal
     line: LineBuffer, // Edited line buffer
     cursor: Position, // Cursor position (relative to the start of the prompt for `row`)
     cols: usize, // Number of columns in terminal
-    old_rows: usize, // Number of rows used so far (from start of prompt to end of input)
+    old_rows: usize, // Number of rows used so far (from start of prompt to end of input or of rows below)
     history_index: usize, // The history index we are currently editing
-    snapshot: LineBuffer, // Current edited line before history browsing/completion
-    term: Terminal, // terminal
+    snapshot: LineBuffer, // Current edited line before completion/search
+    history_edits: HistoryOverlay, // Edits made to recalled entries (and to the new line)
+    message: Option<String>, // Transient message displayed below the input line
+    description: Option<String>, // Help displayed below the input line
+    menu: Vec<String>, // Rows of the pager displayed below the input line
+    status: Vec<String>, // Status rows displayed at the bottom
+    pending_pairs: usize, // Number of auto-inserted closing chars after the cursor
+    mark: Option<usize>, // Set by set-mark, the region between the mark and the cursor is active
+    variables: Option<&'out PromptVariables>, // Evaluated in the prompt on each refresh
+    search_match: Option<(usize, usize)>, // Text of the line matched by the incremental search
+    highlighter: Option<&'out Highlighter>, // None when colors are disabled
+    hinter: Option<&'out Hinter>,
+    history: Option<&'out RefCell<Box<HistoryBackend>>>, // Consulted by the hinter
+    observer: Option<&'out Observer>,
+    color_depth: ColorDepth, // Number of colors supported by the terminal
+    rendered: Rendered, // Output of the highlighter and hinter for the current line
+    echo: Option<Echo>, // Linear rendering for screen readers (accessible mode)
+    bell_style: BellStyle,
+    flash: bool, // Visible bell: the next refresh displays the prompt in reverse video
+    mask: Option<char>, // Displayed instead of each char of the line (password prompt)
+    tab_display: TabDisplay, // Displayed instead of each tab of the line
+    term: Box<tty::DynTerm + 'out>, // terminal
+    scratch: String, // Output of the last refresh (its buffer is reused by the next one)
+}
+
+/// Output of the highlighter and hinter, only recomputed when
+/// the line or the cursor position changed since the last refresh
+/// (expensive highlighters are not called on each redisplay).
+#[derive(Debug)]
+struct Rendered {
+    dirty: bool, // Force the next computation (hooks changed)
+    line: String, // Line and cursor position used by the last computation
+    pos: usize,
+    highlighted: Option<String>, // None when there is no highlighter
+    hint: Option<String>,
+    highlighted_hint: Option<String>,
+}
+
+impl Rendered {
+    fn new() -> Rendered {
+        Rendered {
+            dirty: true,
+            line: String::with_capacity(MAX_LINE),
+            pos: 0,
+            highlighted: None,
+            hint: None,
+            highlighted_hint: None,
+        }
+    }
+
+    fn is_dirty(&self, line: &LineBuffer) -> bool {
+        self.dirty || self.pos != line.pos() || self.line != line.as_str()
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -79,24 +215,50 @@ struct Position {
 
 impl<'out, 'prompt> State<'out, 'prompt> {
     fn new(out: &'out mut Write,
-           term: Terminal,
+           term: Box<tty::DynTerm + 'out>,
            prompt: &'prompt str,
            history_index: usize)
            -> State<'out, 'prompt> {
         let capacity = MAX_LINE;
-        let cols = term.get_columns();
-        let prompt_size = calculate_position(prompt, Position::default(), cols);
+        term.update_size();
+        // at least one column to avoid divisions by zero with broken terminal sizes
+        let cols = cmp::max(1, term.get_columns());
+        let fitted = fit_prompt(prompt, cols, LongPrompt::Truncate);
+        let prompt_size = calculate_position(&fitted, Position::default(), cols);
         State {
             out: out,
-            prompt: prompt,
+            template: prompt,
+            prompt: Cow::Borrowed(prompt),
             prompt_size: prompt_size,
+            long_prompt: LongPrompt::Truncate,
             line: LineBuffer::with_capacity(capacity),
             cursor: prompt_size,
             cols: cols,
             old_rows: prompt_size.row,
             history_index: history_index,
             snapshot: LineBuffer::with_capacity(capacity),
+            history_edits: HistoryOverlay::new(),
+            message: None,
+            description: None,
+            menu: Vec::new(),
+            status: Vec::new(),
+            pending_pairs: 0,
+            mark: None,
+            variables: None,
+            search_match: None,
+            highlighter: None,
+            hinter: None,
+            history: None,
+            observer: None,
+            color_depth: term.color_depth(),
+            rendered: Rendered::new(),
+            echo: None,
+            bell_style: BellStyle::Audible,
+            flash: false,
+            mask: None,
+            tab_display: TabDisplay::Spaces(4),
             term: term,
+            scratch: String::with_capacity(capacity),
         }
     }
 
@@ -104,6 +266,46 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         mem::swap(&mut self.line, &mut self.snapshot);
     }
 
+    /// Start editing a new line on the current row (after the previous one has been saved).
+    fn restart(&mut self, history_index: usize) {
+        self.line.update("", 0);
+        self.cursor = self.prompt_size;
+        self.old_rows = self.prompt_size.row;
+        self.history_index = history_index;
+        self.history_edits = HistoryOverlay::new();
+        self.pending_pairs = 0;
+        self.mark = None;
+        self.reset_echo();
+    }
+
+    /// Echo the line again from the current row (accessible mode),
+    /// after something else has been written.
+    fn reset_echo(&mut self) {
+        if let Some(ref mut echo) = self.echo {
+            echo.reset();
+        }
+    }
+
+    /// Replace the edited line by the history entry at `index`,
+    /// keeping the edits made to the current one.
+    fn recall_history(&mut self, history: &HistoryBackend, index: usize) {
+        self.history_edits.save(history, self.history_index, &self.line);
+        self.history_index = index;
+        self.history_edits.load(history, index, &mut self.line);
+        self.mark = None;
+    }
+
+    /// Range of the active region (between the mark and the cursor), if any.
+    fn region(&self) -> Option<(usize, usize)> {
+        match self.mark {
+            Some(mark) if mark <= self.line.len() && self.line.is_char_boundary(mark) => {
+                let pos = self.line.pos();
+                Some((cmp::min(mark, pos), cmp::max(mark, pos)))
+            }
+            _ => None,
+        }
+    }
+
     fn backup(&mut self) {
         self.snapshot.backup(&self.line);
     }
@@ -111,25 +313,84 @@ impl<'out, 'prompt> State<'out, 'prompt> {
     /// Rewrite the currently edited line accordingly to the buffer content,
     /// cursor position, and number of columns of the terminal.
     fn refresh_line(&mut self) -> Result<()> {
+        self.expand_prompt();
         let prompt_size = self.prompt_size;
-        self.refresh(self.prompt, prompt_size)
+        // moved out during the refresh rather than copied
+        let prompt = mem::replace(&mut self.prompt, Cow::Borrowed(""));
+        let result = {
+            let fitted = fit_prompt(&prompt, self.cols, self.long_prompt);
+            self.refresh_observed(&fitted, prompt_size)
+        };
+        self.prompt = prompt;
+        result
     }
 
     fn refresh_prompt_and_line(&mut self, prompt: &str) -> Result<()> {
-        let prompt_size = calculate_position(prompt, Position::default(), self.cols);
-        self.refresh(prompt, prompt_size)
+        let prompt = fit_prompt(prompt, self.cols, self.long_prompt);
+        let prompt_size = calculate_position(&prompt, Position::default(), self.cols);
+        self.refresh_observed(&prompt, prompt_size)
+    }
+
+    /// Refresh and report the time it took to the observer.
+    fn refresh_observed(&mut self, prompt: &str, prompt_size: Position) -> Result<()> {
+        let start = Instant::now();
+        let result = timed!(Render,
+                            if self.echo.is_some() {
+                                self.refresh_echo(prompt)
+                            } else {
+                                self.refresh(prompt, prompt_size)
+                            });
+        if let Some(observer) = self.observer {
+            observer.refreshed(start.elapsed());
+        }
+        result
+    }
+
+    /// Echo the changes of the line and announce the rows below it (accessible mode).
+    fn refresh_echo(&mut self, prompt: &str) -> Result<()> {
+        let below: Vec<String> = self.message
+            .iter()
+            .chain(self.description.iter())
+            .chain(self.menu.iter())
+            .chain(self.status.iter())
+            .cloned()
+            .collect();
+        let (line, pos) = {
+            let (line, pos) = self.displayed();
+            (line.into_owned(), pos)
+        };
+        let mut ab = String::new();
+        if let Some(ref mut echo) = self.echo {
+            echo.update(&mut ab, prompt, &line, pos, &below);
+        }
+        write_and_flush(self.out, ab.as_bytes())
     }
 
     #[cfg(unix)]
     fn refresh(&mut self, prompt: &str, prompt_size: Position) -> Result<()> {
+        self.refresh_ansi(prompt, prompt_size)
+    }
+
+    /// Render with ANSI escape sequences.
+    fn refresh_ansi(&mut self, prompt: &str, prompt_size: Position) -> Result<()> {
         use std::fmt::Write;
 
-        // calculate the position of the end of the input line
-        let end_pos = calculate_position(&self.line, prompt_size, self.cols);
-        // calculate the desired position of the cursor
-        let cursor = calculate_position(&self.line[..self.line.pos()], prompt_size, self.cols);
+        timed!(Hooks, self.update_rendered());
+        let (end_pos, cursor) = {
+            let (line, pos) = self.displayed();
+            // calculate the position of the end of the input line (and hint)
+            let mut end_pos = calculate_position(&line, prompt_size, self.cols);
+            if let Some(ref hint) = self.rendered.hint {
+                let hint = self.expand_controls(hint, false);
+                end_pos = calculate_position(&hint, end_pos, self.cols);
+            }
+            // calculate the desired position of the cursor
+            (end_pos, calculate_position(&line[..pos], prompt_size, self.cols))
+        };
 
-        let mut ab = String::new();
+        // the buffer of the previous refresh
+        let mut ab = mem::replace(&mut self.scratch, String::new());
+        ab.clear();
 
         let cursor_row_movement = self.old_rows - self.cursor.row;
         // move the cursor down as required
@@ -144,15 +405,23 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         ab.push_str("\r\x1b[0K");
 
         // display the prompt
-        ab.push_str(prompt);
+        if self.flash {
+            ab.push_str("\x1b[7m");
+            ab.push_str(prompt);
+            ab.push_str("\x1b[27m");
+        } else {
+            ab.push_str(prompt);
+        }
         // display the input line
-        ab.push_str(&self.line);
+        self.render_line(&mut ab);
         // we have to generate our own newline on line wrap
         if end_pos.col == 0 && end_pos.row > 0 {
             ab.push_str("\n");
         }
+        // display the rows reserved below the input line
+        let end_row = end_pos.row + self.render_below(&mut ab);
         // position the cursor
-        let cursor_row_movement = end_pos.row - cursor.row;
+        let cursor_row_movement = end_row - cursor.row;
         // move the cursor up as required
         if cursor_row_movement > 0 {
             write!(ab, "\x1b[{}A", cursor_row_movement).unwrap();
@@ -165,48 +434,339 @@ impl<'out, 'prompt> State<'out, 'prompt> {
         }
 
         self.cursor = cursor;
-        self.old_rows = end_pos.row;
+        self.old_rows = end_row;
 
-        write_and_flush(self.out, ab.as_bytes())
+        let result = write_and_flush(self.out, ab.as_bytes());
+        self.scratch = ab;
+        result
     }
 
     #[cfg(windows)]
     fn refresh(&mut self, prompt: &str, prompt_size: Position) -> Result<()> {
-        // calculate the position of the end of the input line
-        let end_pos = calculate_position(&self.line, prompt_size, self.cols);
-        // calculate the desired position of the cursor
-        let cursor = calculate_position(&self.line[..self.line.pos()], prompt_size, self.cols);
+        // a terminal of the application renders ANSI escape sequences
+        let mut console = match self.term.console() {
+            Some(console) => console.clone(),
+            None => return self.refresh_ansi(prompt, prompt_size),
+        };
+        timed!(Hooks, self.update_rendered());
+        let (end_pos, cursor) = {
+            let (line, pos) = self.displayed();
+            // calculate the position of the end of the input line (and hint)
+            let mut end_pos = calculate_position(&line, prompt_size, self.cols);
+            if let Some(ref hint) = self.rendered.hint {
+                let hint = self.expand_controls(hint, false);
+                end_pos = calculate_position(&hint, end_pos, self.cols);
+            }
+            // calculate the desired position of the cursor
+            (end_pos, calculate_position(&line[..pos], prompt_size, self.cols))
+        };
 
         // position at the start of the prompt, clear to end of previous input
-        let mut info = try!(self.term.get_console_screen_buffer_info());
+        let mut info = try!(console.get_console_screen_buffer_info());
         info.dwCursorPosition.X = 0;
         info.dwCursorPosition.Y -= self.cursor.row as i16;
-        try!(self.term.set_console_cursor_position(info.dwCursorPosition));
+        try!(console.set_console_cursor_position(info.dwCursorPosition));
         let mut _count = 0;
-        try!(self.term
+        try!(console
             .fill_console_output_character((info.dwSize.X * (self.old_rows as i16 + 1)) as u32,
                                            info.dwCursorPosition));
-        let mut ab = String::new();
+        // the buffer of the previous refresh
+        let mut ab = mem::replace(&mut self.scratch, String::new());
+        ab.clear();
         // display the prompt
         ab.push_str(prompt); // TODO handle ansi escape code (SetConsoleTextAttribute)
         // display the input line
-        ab.push_str(&self.line);
-        try!(write_and_flush(self.out, ab.as_bytes()));
+        self.render_line(&mut ab);
+        // display the rows reserved below the input line
+        let end_row = end_pos.row + self.render_below(&mut ab);
+        let result = write_and_flush(self.out, ab.as_bytes());
+        self.scratch = ab;
+        try!(result);
 
         // position the cursor
-        let mut info = try!(self.term.get_console_screen_buffer_info());
+        let mut info = try!(console.get_console_screen_buffer_info());
         info.dwCursorPosition.X = cursor.col as i16;
-        info.dwCursorPosition.Y -= (end_pos.row - cursor.row) as i16;
-        try!(self.term.set_console_cursor_position(info.dwCursorPosition));
+        info.dwCursorPosition.Y -= (end_row - cursor.row) as i16;
+        try!(console.set_console_cursor_position(info.dwCursorPosition));
 
         self.cursor = cursor;
-        self.old_rows = end_pos.row;
+        self.old_rows = end_row;
 
         Ok(())
     }
 
+    /// Call the highlighter and the hinter if the line or the cursor changed.
+    fn update_rendered(&mut self) {
+        if !self.rendered.is_dirty(&self.line) {
+            return;
+        }
+        let pos = self.line.pos();
+        let depth = self.color_depth;
+        let hint = match (self.hinter, self.history) {
+            (Some(hinter), Some(history)) => hinter.hint(&self.line, pos, &**history.borrow()),
+            _ => None,
+        };
+        let rendered = &mut self.rendered;
+        // the buffers of the previous output are reused
+        let previous = (rendered.highlighted.take(), rendered.highlighted_hint.take());
+        if let Some(highlighter) = self.highlighter {
+            let highlighted = highlighter.highlight(&self.line, pos);
+            rendered.highlighted =
+                Some(reuse(previous.0, &highlight::downgrade(&highlighted, depth)));
+            if let Some(ref hint) = hint {
+                let highlighted = highlighter.highlight_hint(hint);
+                rendered.highlighted_hint =
+                    Some(reuse(previous.1, &highlight::downgrade(&highlighted, depth)));
+            }
+        }
+        rendered.hint = hint;
+        rendered.line.clear();
+        rendered.line.push_str(&self.line);
+        rendered.pos = pos;
+        rendered.dirty = false;
+    }
+
+    /// The line as displayed and the cursor position in it:
+    /// each char is replaced by the mask of a password prompt,
+    /// each tab as configured by `Config::tab_display`,
+    /// and the other control chars in caret notation.
+    fn displayed(&self) -> (Cow<str>, usize) {
+        match self.mask {
+            Some(mask) => {
+                let masked: String = self.line.chars().map(|_| mask).collect();
+                let pos = self.line[..self.line.pos()].chars().count() * mask.len_utf8();
+                (Cow::Owned(masked), pos)
+            }
+            None => {
+                let line = self.expand_controls(&self.line, false);
+                let pos = match line {
+                    Cow::Borrowed(_) => self.line.pos(),
+                    Cow::Owned(_) => {
+                        self.expand_controls(&self.line[..self.line.pos()], false).len()
+                    }
+                };
+                (line, pos)
+            }
+        }
+    }
+
+    /// `s` (highlighted) with its tabs replaced as configured by `Config::tab_display`.
+    fn expand_tabs<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        if !s.contains('\t') {
+            return Cow::Borrowed(s);
+        }
+        let tab = match self.tab_display {
+            TabDisplay::Spaces(n) => iter::repeat(' ').take(n).collect(),
+            TabDisplay::Caret => String::from("^I"),
+        };
+        Cow::Owned(s.replace('\t', &tab))
+    }
+
+    /// `s` with its tabs replaced as configured by `Config::tab_display`,
+    /// and its other control chars (but newlines) in caret notation,
+    /// in reverse video when `styled` (unix only).
+    fn expand_controls<'s>(&self, s: &'s str, styled: bool) -> Cow<'s, str> {
+        if !has_controls(s) && !s.contains('\t') {
+            return Cow::Borrowed(s);
+        }
+        let styled = styled && cfg!(unix);
+        let mut expanded = String::with_capacity(s.len() + 8);
+        for c in s.chars() {
+            if c == '\t' {
+                match self.tab_display {
+                    TabDisplay::Spaces(n) => expanded.extend(iter::repeat(' ').take(n)),
+                    TabDisplay::Caret => expanded.push_str("^I"),
+                }
+            } else if c.is_control() && c != '\n' {
+                if styled {
+                    expanded.push_str("\x1b[7m");
+                }
+                push_caret(&mut expanded, c);
+                if styled {
+                    expanded.push_str("\x1b[27m");
+                }
+            } else {
+                expanded.push(c);
+            }
+        }
+        Cow::Owned(expanded)
+    }
+
+    /// Append the (highlighted) input line and hint to `ab`.
+    /// The active region is displayed with `Highlighter::highlight_region`
+    /// (instead of the highlighting),
+    /// like the control chars (a line containing some is not highlighted,
+    /// its escape chars being mistaken for the styles).
+    fn render_line(&self, ab: &mut String) {
+        if self.mask.is_some() {
+            ab.push_str(&self.displayed().0);
+            return;
+        }
+        match (self.search_match, self.region(), &self.rendered.highlighted) {
+            (Some((start, end)), _, _) if start < end && end <= self.line.len() => {
+                ab.push_str(&self.expand_controls(&self.line[..start], true));
+                ab.push_str(&self.highlight_search_match(&self.line[start..end]));
+                ab.push_str(&self.expand_controls(&self.line[end..], true));
+            }
+            (_, Some((start, end)), _) if start < end => {
+                ab.push_str(&self.expand_controls(&self.line[..start], true));
+                ab.push_str(&self.highlight_region(&self.line[start..end]));
+                ab.push_str(&self.expand_controls(&self.line[end..], true));
+            }
+            (_, _, &Some(ref highlighted)) if !has_controls(&self.line) => {
+                ab.push_str(&self.expand_tabs(highlighted))
+            }
+            _ => ab.push_str(&self.expand_controls(&self.line, true)),
+        }
+        match (&self.rendered.highlighted_hint, &self.rendered.hint) {
+            (&Some(ref highlighted), &Some(ref hint)) if !has_controls(hint) => {
+                ab.push_str(&self.expand_tabs(highlighted))
+            }
+            (_, &Some(ref hint)) => ab.push_str(&self.expand_controls(hint, true)),
+            _ => {}
+        }
+    }
+
+    /// Text matched by the incremental search, as styled by the highlighter
+    /// (in bold without one).
+    fn highlight_search_match(&self, matched: &str) -> String {
+        let matched = self.expand_controls(matched, false);
+        let highlighter: &Highlighter = self.highlighter.unwrap_or(&());
+        let highlighted = highlighter.highlight_search_match(&matched);
+        highlight::downgrade(&highlighted, self.color_depth).into_owned()
+    }
+
+    fn highlight_region(&self, region: &str) -> String {
+        let region = self.expand_controls(region, false);
+        let highlighter: &Highlighter = self.highlighter.unwrap_or(&());
+        let highlighted = highlighter.highlight_region(&region);
+        highlight::downgrade(&highlighted, self.color_depth).into_owned()
+    }
+
+    /// Append the rows displayed below the input line to `ab`.
+    /// Return the number of rows used.
+    fn render_below(&self, ab: &mut String) -> usize {
+        let mut rows = 0;
+        for row in self.message.iter().chain(self.description.iter()) {
+            ab.push('\n');
+            ab.push_str(truncate_to_width(row, self.cols));
+            rows += 1;
+        }
+        for row in self.menu.iter().chain(self.status.iter()) {
+            ab.push('\n');
+            ab.push_str(truncate_to_width(row, self.cols));
+            rows += 1;
+        }
+        rows
+    }
+
+    /// Number of rows displayed below the input line.
+    fn rows_below(&self) -> usize {
+        self.message.iter().count() + self.description.iter().count() + self.menu.len() +
+        self.status.len()
+    }
+
+    /// Erase the hint and the rows displayed below the input line
+    /// (before the line is accepted or the cursor leaves it).
+    fn clear_below(&mut self) -> Result<()> {
+        if self.rows_below() == 0 && self.rendered.hint.is_none() {
+            return Ok(());
+        }
+        // the hint is computed again by the next refresh
+        let hinter = self.hinter.take();
+        self.rendered.dirty = true;
+        self.message = None;
+        self.description = None;
+        self.menu.clear();
+        self.status.clear();
+        let result = self.refresh_line();
+        self.hinter = hinter;
+        self.rendered.dirty = true;
+        result
+    }
+
+    /// Beep, used for completion when there is nothing to complete or when all
+    /// the choices were already shown (see `Config::bell_style`).
+    fn beep(&mut self) -> Result<()> {
+        match self.bell_style {
+            // screen readers do not show a flash
+            BellStyle::Audible => write_and_flush(&mut io::stderr(), b"\x07"),
+            BellStyle::Visible if self.echo.is_some() => {
+                write_and_flush(&mut io::stderr(), b"\x07")
+            }
+            BellStyle::Visible => {
+                // the prompt is displayed in reverse video until the next refresh
+                self.flash = true;
+                let result = self.refresh_line();
+                self.flash = false;
+                result
+            }
+            BellStyle::None => Ok(()),
+        }
+    }
+
+    /// Update the description displayed below the input line.
+    fn set_description(&mut self, description: Option<String>) -> Result<()> {
+        if self.description == description {
+            return Ok(());
+        }
+        self.description = description;
+        self.refresh_line()
+    }
+
+    /// Redisplay the line after another process used the terminal
+    /// (it may have moved the cursor or resized the terminal):
+    /// on a new row, unless the cursor is at the start of one.
+    fn redisplay(&mut self) -> Result<()> {
+        self.term.update_size();
+        self.update_columns();
+        if self.term.cursor_column(self.out) != Some(0) {
+            try!(write_and_flush(self.out, b"\r\n"));
+        }
+        self.cursor = Position::default();
+        self.old_rows = 0;
+        self.reset_echo();
+        self.rendered.dirty = true;
+        self.refresh_line()
+    }
+
     fn update_columns(&mut self) {
-        self.cols = self.term.get_columns();
+        self.cols = cmp::max(1, self.term.get_columns());
+        self.update_prompt_size();
+    }
+
+    /// Display a prompt wider than the terminal as `long_prompt`
+    /// (before the line is displayed).
+    fn set_long_prompt(&mut self, long_prompt: LongPrompt) {
+        self.long_prompt = long_prompt;
+        self.update_prompt_size();
+        self.cursor = self.prompt_size;
+        self.old_rows = self.prompt_size.row;
+    }
+
+    fn update_prompt_size(&mut self) {
+        let prompt = fit_prompt(&self.prompt, self.cols, self.long_prompt);
+        self.prompt_size = calculate_position(&prompt, Position::default(), self.cols);
+    }
+
+    /// Replace the prompt (before the line is displayed).
+    fn set_prompt(&mut self, prompt: &'prompt str) {
+        self.template = prompt;
+        self.prompt = Cow::Borrowed(prompt);
+        self.expand_prompt();
+        self.update_prompt_size();
+    }
+
+    /// Evaluate the variables of the prompt again (see `Editor::set_prompt_variable`),
+    /// its size being updated when their values changed.
+    fn expand_prompt(&mut self) {
+        if let Some(variables) = self.variables {
+            let expanded = variables.expand(self.template);
+            if expanded != self.prompt {
+                self.prompt = Cow::Owned(expanded);
+                self.update_prompt_size();
+            }
+        }
     }
 }
 
@@ -221,20 +781,32 @@ impl<'out, 'prompt> fmt::Debug for State<'out, 'prompt> {
             .field("old_rows", &self.old_rows)
             .field("history_index", &self.history_index)
             .field("snapshot", &self.snapshot)
+            .field("history_edits", &self.history_edits)
+            .field("description", &self.description)
+            .field("menu", &self.menu)
+            .field("status", &self.status)
+            .field("rendered", &self.rendered)
+            .field("pending_pairs", &self.pending_pairs)
+            .field("mark", &self.mark)
             .finish()
     }
 }
 
-fn write_and_flush(w: &mut Write, buf: &[u8]) -> Result<()> {
-    try!(w.write_all(buf));
-    try!(w.flush());
-    Ok(())
+/// `text` copied into `buffer` (allocated only if there is none).
+fn reuse(buffer: Option<String>, text: &str) -> String {
+    match buffer {
+        Some(mut buffer) => {
+            buffer.clear();
+            buffer.push_str(text);
+            buffer
+        }
+        None => String::from(text),
+    }
 }
 
-/// Beep, used for completion when there is nothing to complete or when all
-/// the choices were already shown.
-fn beep() -> Result<()> {
-    write_and_flush(&mut io::stderr(), b"\x07") // TODO bell-style
+fn write_and_flush(w: &mut Write, buf: &[u8]) -> Result<()> {
+    try!(timed!(Flush, w.write_all(buf).and_then(|()| w.flush())));
+    Ok(())
 }
 
 /// Calculate the number of columns and rows used to display `s` on a `cols` width terminal
@@ -279,7 +851,8 @@ fn calculate_position(s: &str, orig: Position, cols: usize) -> Position {
             pos.col += cw;
             if pos.col > cols {
                 pos.row += 1;
-                pos.col = cw;
+                // a wide char may not even fit in an empty row of a tiny terminal
+                pos.col = cmp::min(cw, cols);
             }
         }
     }
@@ -290,12 +863,149 @@ fn calculate_position(s: &str, orig: Position, cols: usize) -> Position {
     pos
 }
 
+/// Shorten a single line `prompt` wider than the terminal to half of its width,
+/// ending with an ellipsis (and without its styles), so that the input stays visible.
+/// With `LongPrompt::Wrap`, the prompt is kept whole.
+fn fit_prompt(prompt: &str, cols: usize, long_prompt: LongPrompt) -> Cow<str> {
+    let width = calculate_position(prompt, Position::default(), usize::MAX).col;
+    if width < cols || prompt.contains('\n') || long_prompt == LongPrompt::Wrap {
+        return Cow::Borrowed(prompt);
+    }
+    let max = cols / 2;
+    let mut fitted = String::new();
+    if max == 0 {
+        return Cow::Owned(fitted);
+    }
+    let mut width = 0;
+    let mut chars = prompt.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip the escape sequence
+            if chars.next() == Some('[') {
+                while let Some(c) = chars.next() {
+                    if c >= '@' && c <= '~' {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        let cw = unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+        if width + cw >= max {
+            break;
+        }
+        width += cw;
+        fitted.push(c);
+    }
+    fitted.push('\u{2026}');
+    Cow::Owned(fitted)
+}
+
+const BRACKETED_PASTE_ON: &'static [u8] = b"\x1b[?2004h";
+const BRACKETED_PASTE_OFF: &'static [u8] = b"\x1b[?2004l";
+/// End marker of a text pasted in bracketed paste mode.
+#[cfg(unix)]
+const PASTE_END: &'static str = "\x1b[201~";
+
+/// Read the text pasted in bracketed paste mode up to its end marker.
+#[cfg(unix)]
+fn read_pasted_text<R: RawReader>(rdr: &mut R) -> Result<String> {
+    let mut text = String::new();
+    while !text.ends_with(PASTE_END) {
+        text.push(try!(rdr.next_char()));
+    }
+    let len = text.len() - PASTE_END.len();
+    text.truncate(len);
+    Ok(text)
+}
+
+/// `s` without its escape sequences: CSI (like the colors),
+/// OSC (like the window titles and the hyperlinks) and two-char ones.
+fn strip_ansi(s: &str) -> Cow<str> {
+    if !s.contains('\x1b') {
+        return Cow::Borrowed(s);
+    }
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                // parameter and intermediate bytes up to the final byte
+                while let Some(c) = chars.next() {
+                    if c >= '@' && c <= '~' {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                // up to BEL or ST (ESC \)
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    } else if c == '\x1b' {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Cow::Owned(stripped)
+}
+
+/// Tell if `s` contains control chars displayed in caret notation
+/// (all but tabs and newlines).
+fn has_controls(s: &str) -> bool {
+    s.chars().any(|c| c.is_control() && c != '\t' && c != '\n')
+}
+
+/// Append the caret notation of the control char `c` to `out`:
+/// `^C`, `^[` or `^?`, and `M-^C` for the C1 controls.
+fn push_caret(out: &mut String, c: char) {
+    let mut code = c as u32;
+    if code >= 0x80 {
+        out.push_str("M-");
+        code -= 0x80;
+    }
+    out.push('^');
+    out.push(if code == 0x7f {
+        '?'
+    } else {
+        (code as u8 + 0x40) as char
+    });
+}
+
+/// Return the first line of `s` truncated so that it fits in `cols` - 1 columns
+/// (to avoid an automatic wrap).
+fn truncate_to_width(s: &str, cols: usize) -> &str {
+    let mut width = 0;
+    for (i, c) in s.char_indices() {
+        if c == '\n' {
+            return &s[..i];
+        }
+        width += unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+        if width >= cols {
+            return &s[..i];
+        }
+    }
+    s
+}
+
 /// Insert the character `ch` at cursor current position.
 fn edit_insert(s: &mut State, ch: char) -> Result<()> {
     if let Some(push) = s.line.insert(ch) {
         if push {
-            if s.cursor.col + unicode_width::UnicodeWidthChar::width(ch).unwrap_or(0) < s.cols {
-                // Avoid a full update of the line in the trivial case.
+            let width = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(0);
+            if s.cursor.col + width < s.cols && !ch.is_control() && s.highlighter.is_none() &&
+               s.hinter.is_none() && s.echo.is_none() && s.mask.is_none() {
+                // Avoid a full update of the line in the trivial case
+                // (the highlighting and the hint may depend on the inserted char,
+                // a newline starts a new row).
                 let cursor = calculate_position(&s.line[..s.line.pos()], s.prompt_size, s.cols);
                 s.cursor = cursor;
                 write_and_flush(s.out, ch.to_utf8().as_bytes())
@@ -305,6 +1015,23 @@ fn edit_insert(s: &mut State, ch: char) -> Result<()> {
         } else {
             s.refresh_line()
         }
+    } else {
+        // the line is too long (see `Config::max_line_len`)
+        s.beep()
+    }
+}
+
+/// Insert the pasted `text` at the cursor with a single refresh:
+/// as much of it as fits in the line (see `Config::max_line_len`), the rest being rejected.
+fn edit_insert_pasted(s: &mut State, text: &str, max_len: usize) -> Result<()> {
+    let room = max_len.saturating_sub(s.line.len());
+    let end = line_buffer::floor_char_boundary(text, room);
+    if end > 0 {
+        s.line.yank(&text[..end]);
+        try!(s.refresh_line());
+    }
+    if end < text.len() {
+        s.beep()
     } else {
         Ok(())
     }
@@ -314,6 +1041,9 @@ fn edit_insert(s: &mut State, ch: char) -> Result<()> {
 fn edit_yank(s: &mut State, text: &str) -> Result<()> {
     if s.line.yank(text).is_some() {
         s.refresh_line()
+    } else if !text.is_empty() {
+        // the whole text is rejected, rather than truncated
+        s.beep()
     } else {
         Ok(())
     }
@@ -361,6 +1091,42 @@ fn edit_move_end(s: &mut State) -> Result<()> {
     }
 }
 
+/// Move cursor to the start of the current line of a multi-line buffer.
+fn edit_move_line_start(s: &mut State) -> Result<()> {
+    if s.line.move_line_start() {
+        s.refresh_line()
+    } else {
+        Ok(())
+    }
+}
+
+/// Move cursor to the end of the current line of a multi-line buffer.
+fn edit_move_line_end(s: &mut State) -> Result<()> {
+    if s.line.move_line_end() {
+        s.refresh_line()
+    } else {
+        Ok(())
+    }
+}
+
+/// Move cursor to the previous (or next) line of a multi-line buffer.
+/// Return `false` when the history must be navigated instead.
+fn edit_move_line(s: &mut State, up_down: UpDown, up: bool) -> Result<bool> {
+    if up_down == UpDown::History {
+        return Ok(false);
+    }
+    let moved = if up {
+        s.line.move_line_up()
+    } else {
+        s.line.move_line_down()
+    };
+    if moved {
+        try!(s.refresh_line());
+    }
+    // with `UpDown::Lines`, the history is only navigated from single-line buffers
+    Ok(moved || up_down == UpDown::Lines && s.line.contains('\n'))
+}
+
 /// Delete the character at the right of the cursor without altering the cursor
 /// position. Basically this is what happens with the "Delete" keyboard key.
 fn edit_delete(s: &mut State) -> Result<()> {
@@ -438,6 +1204,18 @@ fn edit_move_to_next_word(s: &mut State) -> Result<()> {
     }
 }
 
+/// Kill the text object around the cursor.
+fn edit_kill_text_object(s: &mut State, obj: TextObject) -> Result<Option<String>> {
+    match obj.range(&s.line, s.line.pos()) {
+        Some((start, end)) if start < end => {
+            let text = s.line.delete_range(start, end);
+            try!(s.refresh_line());
+            Ok(Some(text))
+        }
+        _ => Ok(None),
+    }
+}
+
 /// Kill from the cursor to the end of the current word, or, if between words, to the end of the next word.
 fn edit_delete_word(s: &mut State) -> Result<Option<String>> {
     if let Some(text) = s.line.delete_word() {
@@ -466,100 +1244,237 @@ fn edit_transpose_words(s: &mut State) -> Result<()> {
 
 /// Substitute the currently edited line with the next or previous history
 /// entry.
-fn edit_history_next(s: &mut State, history: &History, prev: bool) -> Result<()> {
+fn edit_history_next(s: &mut State, history: &HistoryBackend, prev: bool) -> Result<()> {
     if history.is_empty() {
         return Ok(());
     }
-    if s.history_index == history.len() {
-        if prev {
-            // Save the current edited line before to overwrite it
-            s.snapshot();
-        } else {
-            return Ok(());
-        }
-    } else if s.history_index == 0 && prev {
-        return Ok(());
-    }
-    if prev {
-        s.history_index -= 1;
-    } else {
-        s.history_index += 1;
-    }
-    if s.history_index < history.len() {
-        let buf = history.get(s.history_index).unwrap();
-        s.line.update(buf, buf.len());
+    let index = if prev && s.history_index > 0 {
+        s.history_index - 1
+    } else if !prev && s.history_index < history.len() {
+        s.history_index + 1
     } else {
-        // Restore current edited line
-        s.snapshot();
-    }
+        return Ok(());
+    };
+    s.recall_history(history, index);
     s.refresh_line()
 }
 
 /// Substitute the currently edited line with the first/last history entry.
-fn edit_history(s: &mut State, history: &History, first: bool) -> Result<()> {
+fn edit_history(s: &mut State, history: &HistoryBackend, first: bool) -> Result<()> {
     if history.is_empty() {
         return Ok(());
     }
-    if s.history_index == history.len() {
-        if first {
-            // Save the current edited line before to overwrite it
-            s.snapshot();
-        } else {
-            return Ok(());
-        }
-    } else if s.history_index == 0 && first {
+    let index = if first { 0 } else { history.len() };
+    if s.history_index == index {
         return Ok(());
     }
-    if first {
-        s.history_index = 0;
-        let buf = history.get(s.history_index).unwrap();
-        s.line.update(buf, buf.len());
-    } else {
-        s.history_index = history.len();
-        // Restore current edited line
-        s.snapshot();
-    }
+    s.recall_history(history, index);
     s.refresh_line()
 }
 
-/// Completes the line/word
-fn complete_line<R: RawReader>(rdr: &mut R,
-                               s: &mut State,
-                               completer: &Completer,
-                               config: &Config)
-                               -> Result<Option<KeyPress>> {
-    // get a list of completions
-    let (start, candidates) = try!(completer.complete(&s.line, s.line.pos()));
-    // if no completions, we are done
-    if candidates.is_empty() {
-        try!(beep());
-        Ok(None)
-    } else if CompletionType::Circular == config.completion_type() {
-        // Save the current edited line before to overwrite it
-        s.backup();
-        let mut key;
-        let mut i = 0;
-        loop {
-            // Show completion or original buffer
-            if i < candidates.len() {
-                completer.update(&mut s.line, start, &candidates[i]);
-                try!(s.refresh_line());
-            } else {
-                // Restore current edited line
-                s.snapshot();
-                try!(s.refresh_line());
-                s.snapshot();
+/// Insert `comment` at the beginning of the line.
+fn edit_insert_comment(s: &mut State, comment: &str) -> Result<()> {
+    s.line.move_home();
+    s.line.yank(comment);
+    s.line.move_end();
+    s.refresh_line()
+}
+
+/// Discard the edits made to the current line (new line or recalled history entry).
+fn edit_revert_line(s: &mut State, history: &HistoryBackend) -> Result<()> {
+    let index = s.history_index;
+    s.history_edits.revert(index);
+    s.history_edits.load(history, index, &mut s.line);
+    s.refresh_line()
+}
+
+/// Delay between two checks of the background computations.
+#[cfg(any(feature = "async", feature = "lsp"))]
+const BACKGROUND_TICK_MS: u64 = 80;
+
+/// Compute the completions, in a background thread if the completer supports it:
+/// a spinner is then displayed until they are ready,
+/// unless a key is pressed first (`None` is returned).
+#[cfg(feature = "async")]
+fn fetch_candidates<R: RawReader>(rdr: &mut R,
+                                  s: &mut State,
+                                  completer: &Completer)
+                                  -> Result<Option<(usize, Vec<String>)>> {
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Duration;
+    const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+    let pending = match completer.spawn(&s.line, s.line.pos()) {
+        Some(pending) => pending,
+        None => return timed!(Hooks, completer.complete(&s.line, s.line.pos())).map(Some),
+    };
+    let mut frame = 0;
+    let result;
+    loop {
+        match pending.wait(Duration::from_millis(BACKGROUND_TICK_MS)) {
+            Ok(completions) => {
+                result = completions.map(Some);
+                break;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                // the completer panicked
+                result = Ok(Some((s.line.pos(), Vec::new())));
+                break;
             }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        if try!(rdr.poll(0)) {
+            // the key is read by the caller, the completion is cancelled when dropped
+            result = Ok(None);
+            break;
+        }
+        s.menu.clear();
+        s.menu.push(format!("{} Completing...", SPINNER[frame % SPINNER.len()]));
+        try!(s.refresh_line());
+        frame += 1;
+    }
+    if frame > 0 {
+        s.menu.clear();
+        try!(s.refresh_line());
+    }
+    result
+}
 
-            key = try!(rdr.next_key(config.keyseq_timeout()));
-            match key {
+#[cfg(not(feature = "async"))]
+fn fetch_candidates<R: RawReader>(_: &mut R,
+                                  s: &mut State,
+                                  completer: &Completer)
+                                  -> Result<Option<(usize, Vec<String>)>> {
+    timed!(Hooks, completer.complete(&s.line, s.line.pos())).map(Some)
+}
+
+/// Refresh the line once the hint computed in the background is ready,
+/// unless a key is pressed first.
+#[cfg(any(feature = "async", feature = "lsp"))]
+fn wait_for_hint<R: RawReader>(rdr: &mut R, s: &mut State) -> Result<()> {
+    let hinter = match s.hinter {
+        Some(hinter) if hinter.is_pending() => hinter,
+        _ => return Ok(()),
+    };
+    while hinter.is_pending() {
+        if try!(rdr.poll(BACKGROUND_TICK_MS as i32)) {
+            return Ok(());
+        }
+    }
+    s.rendered.dirty = true;
+    s.refresh_line()
+}
+
+#[cfg(not(any(feature = "async", feature = "lsp")))]
+fn wait_for_hint<R: RawReader>(_: &mut R, _: &mut State) -> Result<()> {
+    Ok(())
+}
+
+/// Read the printable chars already buffered after `c` (pasted without the bracketed paste
+/// mode), to insert them at once. Return them, and the key which ended them, if any.
+fn read_pasted<R: RawReader>(rdr: &mut R,
+                             keymap: &Keymap,
+                             c: char,
+                             config: &Config)
+                             -> Result<(String, Option<KeyPress>)> {
+    let mut text = String::new();
+    text.push(c);
+    while try!(rdr.poll(0)) {
+        let key = try!(rdr.next_key(config.esc_timeout()));
+        match key {
+            key!(Key::Char(c)) if !c.is_control() && keymap.handler(key).is_none() &&
+                                  !keymap.is_sequence_prefix(key) &&
+                                  keymap.get(key) == Cmd::SelfInsert(c) => text.push(c),
+            _ => return Ok((text, Some(key))),
+        }
+    }
+    Ok((text, None))
+}
+
+/// Call the idle hook each time `timeout_ms` elapses without any key,
+/// until a key is pressed or the terminal is resized (`true` is then returned).
+/// The hook is not called when the reader cannot wait for the keys.
+fn wait_idle<R: RawReader>(rdr: &mut R,
+                           s: &mut State,
+                           hook: &IdleHook,
+                           timeout_ms: i32)
+                           -> Result<bool> {
+    if !rdr.can_wait() {
+        return Ok(false);
+    }
+    loop {
+        match rdr.poll(cmp::max(1, timeout_ms)) {
+            Ok(false) => {}
+            Ok(true) => return Ok(false),
+            // interrupted by a signal
+            Err(_) if s.term.sigwinch() => return Ok(true),
+            Err(_) if s.term.continued() => {
+                try!(s.redisplay());
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+        if s.term.sigwinch() {
+            return Ok(true);
+        }
+        match hook.idle(&s.line, s.line.pos()) {
+            Idle::Keep => {}
+            Idle::Refresh => {
+                s.rendered.dirty = true;
+                try!(s.refresh_line());
+            }
+            Idle::Status(rows) => {
+                s.status = rows;
+                try!(s.refresh_line());
+            }
+        }
+    }
+}
+
+/// Completes the line/word
+fn complete_line<R: RawReader>(rdr: &mut R,
+                               s: &mut State,
+                               completer: &Completer,
+                               config: &Config)
+                               -> Result<Option<KeyPress>> {
+    // get a list of completions
+    let (start, candidates) = match try!(fetch_candidates(rdr, s, completer)) {
+        Some(completions) => completions,
+        None => return Ok(None), // cancelled by the next key
+    };
+    let end = completer.word_end(&s.line, s.line.pos());
+    // if no completions, we are done
+    if candidates.is_empty() {
+        try!(s.beep());
+        Ok(None)
+    } else if CompletionType::Circular == config.completion_type() {
+        // Save the current edited line before to overwrite it
+        s.backup();
+        let mut key;
+        let mut i = 0;
+        loop {
+            // Show completion or original buffer
+            if i < candidates.len() {
+                s.line.backup(&s.snapshot);
+                update_word(&mut s.line, completer, start, end, &candidates[i]);
+                try!(s.refresh_line());
+            } else {
+                // Restore current edited line
+                s.snapshot();
+                try!(s.refresh_line());
+                s.snapshot();
+            }
+
+            key = try!(rdr.next_key(config.esc_timeout()));
+            match key {
                 key!(Key::Tab) => {
                     i = (i + 1) % (candidates.len() + 1); // Circular
                     if i == candidates.len() {
-                        try!(beep());
+                        try!(s.beep());
                     }
                 }
-                key!(Key::Esc) => {
+                key!(Key::Esc) |
+                ctrl!('G') => {
                     // Re-show original buffer
                     s.snapshot();
                     if i < candidates.len() {
@@ -579,60 +1494,231 @@ fn complete_line<R: RawReader>(rdr: &mut R,
     } else if CompletionType::List == config.completion_type() {
         // beep if ambiguous
         if candidates.len() > 1 {
-            try!(beep());
+            try!(s.beep());
         }
         if let Some(lcp) = longest_common_prefix(&candidates) {
             // if we can extend the item, extend it and return to main loop
             if lcp.len() > s.line.pos() - start {
+                // the end of the word after the cursor is kept
                 completer.update(&mut s.line, start, lcp);
                 try!(s.refresh_line());
                 return Ok(None);
             }
         }
         // we can't complete any further, wait for second tab
-        let mut key = try!(rdr.next_key(config.keyseq_timeout()));
+        let key = try!(rdr.next_key(config.esc_timeout()));
         // if any character other than tab, pass it to the main loop
         if key != key!(Key::Tab) {
             return Ok(Some(key));
         }
-        // move cursor to EOL to avoid overwriting the command line
-        let save_pos = s.line.pos();
-        try!(edit_move_end(s));
-        s.line.set_pos(save_pos);
         // we got a second tab, maybe show list of possible completions
-        let mut show_completions = true;
-        if candidates.len() > config.completion_prompt_limit() {
-            let msg = format!("\nDisplay all {} possibilities? (y or n)", candidates.len());
-            try!(write_and_flush(s.out, msg.as_bytes()));
-            s.old_rows += 1;
-            while key != key!('y') && key != key!('Y') &&
-                  key != key!('n') && key != key!('N') &&
-                  key != key!(Key::Backspace) {
-                key = try!(rdr.next_key(config.keyseq_timeout()));
-            }
-            show_completions = match key {
-                key!('y') |
-                key!('Y') => true,
-                _ => false,
-            };
+        show_completions(rdr, s, config, &candidates)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Replace the word spanning [`start`..`end`] (around the cursor) by `elected`.
+fn update_word(line: &mut LineBuffer,
+               completer: &Completer,
+               start: usize,
+               end: usize,
+               elected: &str) {
+    let pos = line.pos();
+    if end > pos {
+        line.replace(pos, end, "");
+    }
+    completer.update(line, start, elected);
+}
+
+/// Read the keys following `key`, the first key of a sequence bound in the keymap,
+/// until they form a bound sequence.
+/// The pending keys are shown in the status area.
+/// When the sequence is broken or no key follows before the timeout,
+/// the commands of the keys typed one by one are returned instead.
+fn read_sequence<R: RawReader>(rdr: &mut R,
+                               s: &mut State,
+                               keymap: &Keymap,
+                               key: KeyPress,
+                               config: &Config)
+                               -> Result<Vec<Cmd>> {
+    let timeout_ms = config.keyseq_timeout();
+    let mut keys = vec![key];
+    let mut cmds = None;
+    while cmds.is_none() {
+        let pending: Vec<String> = keys.iter().map(|k| keymap::key_name(*k)).collect();
+        s.status.push(format!("{}-", pending.join(" ")));
+        try!(s.refresh_line());
+        s.status.pop();
+        if timeout_ms >= 0 && !try!(rdr.poll(timeout_ms)) {
+            // no key typed in time
+            cmds = Some(match keymap.sequence(&keys) {
+                Sequence::Prefix(Some(cmd)) => vec![cmd],
+                _ => keymap.unsequenced(&keys),
+            });
+            break;
         }
-        if show_completions {
-            page_completions(rdr, s, config, &candidates)
+        let next = try!(rdr.next_key(config.esc_timeout()));
+        keys.push(next);
+        cmds = match keymap.sequence(&keys) {
+            Sequence::Bound(cmd) => Some(vec![cmd]),
+            Sequence::Prefix(_) => None,
+            // the pending keys are dropped
+            Sequence::Unbound if keymap.get(next) == Cmd::Abort => Some(vec![Cmd::Abort]),
+            Sequence::Unbound => Some(keymap.unsequenced(&keys)),
+        };
+    }
+    try!(s.refresh_line());
+    Ok(cmds.unwrap())
+}
+
+/// Expand the word before the cursor with the words of the line and of the history
+/// starting with it. Each new press of `trigger` proposes the next expansion
+/// (`None` when the command is replayed).
+fn dabbrev_expand<R: RawReader>(rdr: &mut R,
+                                s: &mut State,
+                                history: &HistoryBackend,
+                                trigger: Option<KeyPress>,
+                                config: &Config)
+                                -> Result<Option<KeyPress>> {
+    let pos = s.line.pos();
+    let start = dabbrev::word_start(&s.line, pos);
+    let expansions = if start < pos {
+        dabbrev::expansions(&s.line, start, pos, &s.line[start..pos], history)
+    } else {
+        Vec::new()
+    };
+    if expansions.is_empty() {
+        try!(s.beep());
+        return Ok(None);
+    }
+    s.backup();
+    let mut i = 0;
+    loop {
+        if i < expansions.len() {
+            s.line.backup(&s.snapshot);
+            s.line.replace(start, pos, &expansions[i]);
         } else {
-            try!(s.refresh_line());
-            Ok(None)
+            // back to the abbreviation
+            s.line.backup(&s.snapshot);
+        }
+        try!(s.refresh_line());
+        let key = try!(rdr.next_key(config.esc_timeout()));
+        if Some(key) != trigger {
+            return Ok(Some(key));
+        }
+        i = (i + 1) % (expansions.len() + 1);
+        if i == expansions.len() {
+            try!(s.beep());
+        }
+    }
+}
+
+/// Insert the last word of the previous history entry.
+/// Each new press of `trigger` replaces it with the last word of the entry before
+/// (`None` when the command is replayed).
+fn yank_last_arg<R: RawReader>(rdr: &mut R,
+                               s: &mut State,
+                               history: &HistoryBackend,
+                               trigger: Option<KeyPress>,
+                               config: &Config)
+                               -> Result<Option<KeyPress>> {
+    // the words are inserted as typed, quotes included
+    let args: Vec<&str> = history.iter()
+        .rev()
+        .filter_map(|entry| shell_words::split(entry).pop().map(|w| &entry[w.start..w.end]))
+        .collect();
+    if args.is_empty() {
+        try!(s.beep());
+        return Ok(None);
+    }
+    s.backup();
+    let pos = s.line.pos();
+    let mut i = 0;
+    loop {
+        s.line.backup(&s.snapshot);
+        s.line.replace(pos, pos, args[i]);
+        try!(s.refresh_line());
+        let key = try!(rdr.next_key(config.esc_timeout()));
+        if Some(key) != trigger {
+            return Ok(Some(key));
+        }
+        if i + 1 < args.len() {
+            i += 1;
+        } else {
+            try!(s.beep());
+        }
+    }
+}
+
+/// Show the list of `candidates` below the line, asking first if there are many.
+fn show_completions<R: RawReader>(rdr: &mut R,
+                                  s: &mut State,
+                                  config: &Config,
+                                  candidates: &[String])
+                                  -> Result<Option<KeyPress>> {
+    // move cursor to EOL to avoid overwriting the command line
+    try!(s.set_description(None));
+    let save_pos = s.line.pos();
+    try!(edit_move_end(s));
+    s.line.set_pos(save_pos);
+    let mut show_completions = true;
+    if candidates.len() > config.completion_prompt_limit() {
+        let msg = format!("\nDisplay all {} possibilities? (y or n)", candidates.len());
+        try!(write_and_flush(s.out, msg.as_bytes()));
+        s.old_rows += 1;
+        s.reset_echo();
+        let mut key = key!(Key::Null);
+        while key != key!('y') && key != key!('Y') &&
+              key != key!('n') && key != key!('N') &&
+              key != key!(Key::Backspace) {
+            key = try!(rdr.next_key(config.esc_timeout()));
         }
+        show_completions = match key {
+            key!('y') |
+            key!('Y') => true,
+            _ => false,
+        };
+    }
+    if show_completions {
+        page_completions(rdr, s, config, candidates)
     } else {
+        try!(s.refresh_line());
         Ok(None)
     }
 }
 
+/// Insert all the completion candidates of the word before the cursor.
+fn insert_completions(s: &mut State, completer: &Completer) -> Result<()> {
+    let (start, candidates) = try!(timed!(Hooks, completer.complete(&s.line, s.line.pos())));
+    if candidates.is_empty() {
+        return s.beep();
+    }
+    let end = completer.word_end(&s.line, s.line.pos());
+    update_word(&mut s.line, completer, start, end, &candidates.join(" "));
+    s.refresh_line()
+}
+
+/// List the completion candidates of the word before the cursor
+/// without modifying the line.
+fn possible_completions<R: RawReader>(rdr: &mut R,
+                                      s: &mut State,
+                                      completer: &Completer,
+                                      config: &Config)
+                                      -> Result<()> {
+    let (_, candidates) = try!(timed!(Hooks, completer.complete(&s.line, s.line.pos())));
+    if candidates.is_empty() {
+        return s.beep();
+    }
+    try!(show_completions(rdr, s, config, &candidates));
+    Ok(())
+}
+
 fn page_completions<R: RawReader>(rdr: &mut R,
                                   s: &mut State,
                                   config: &Config,
                                   candidates: &[String])
                                   -> Result<Option<KeyPress>> {
-    use std::cmp;
     use unicode_width::UnicodeWidthStr;
 
     let min_col_pad = 2;
@@ -641,39 +1727,13 @@ fn page_completions<R: RawReader>(rdr: &mut R,
                                  .map(|s| UnicodeWidthStr::width(s.as_str()))
                                  .max()
                                  .unwrap() + min_col_pad);
-    let num_cols = s.cols / max_width;
+    // one candidate per line for screen readers
+    let num_cols = if s.echo.is_some() { 1 } else { s.cols / max_width };
 
-    let mut pause_row = s.term.get_rows() - 1;
     let num_rows = (candidates.len() + num_cols - 1) / num_cols;
-    let mut ab = String::new();
+    let mut rows = Vec::with_capacity(num_rows);
     for row in 0..num_rows {
-        if row == pause_row {
-            try!(write_and_flush(s.out, b"\n--More--"));
-            let mut key = key!(Key::Null);
-            while key != key!('y') && key != key!('Y') &&
-                  key != key!('n') && key != key!('N') &&
-                  key != key!('q') &&
-                  key != key!('Q') &&
-                  key != key!(' ') &&
-                  key != key!(Key::Backspace) && key != key!(Key::Enter) {
-                key = try!(rdr.next_key(config.keyseq_timeout()));
-            }
-            match key {
-                key!('y') |
-                key!('Y') |
-                key!(' ') => {
-                    pause_row += s.term.get_rows() - 1;
-                }
-                key!(Key::Enter) => {
-                    pause_row += 1;
-                }
-                _ => break,
-            }
-            try!(write_and_flush(s.out, b"\n"));
-        } else {
-            try!(write_and_flush(s.out, b"\n"));
-        }
-        ab.clear();
+        let mut ab = String::new();
         for col in 0..num_cols {
             let i = (col * num_rows) + row;
             if i < candidates.len() {
@@ -687,43 +1747,90 @@ fn page_completions<R: RawReader>(rdr: &mut R,
                 }
             }
         }
-        try!(write_and_flush(s.out, ab.as_bytes()));
+        rows.push(ab);
+    }
+    if num_rows >= s.term.get_rows() && s.echo.is_none() {
+        // too long: don't dump the list in the scrollback
+        try!(pager::page(rdr, s, &rows, config.esc_timeout()));
+        return Ok(None);
+    }
+    for row in &rows {
+        try!(write_and_flush(s.out, b"\n"));
+        try!(write_and_flush(s.out, row.as_bytes()));
     }
     try!(write_and_flush(s.out, b"\n"));
+    s.reset_echo();
     try!(s.refresh_line());
     Ok(None)
 }
 
-/// Incremental search
-fn reverse_incremental_search<R: RawReader>(rdr: &mut R,
-                                            s: &mut State,
-                                            history: &History,
-                                            config: &Config)
-                                            -> Result<Option<KeyPress>> {
+/// Display the full description of the current line in the pager.
+fn describe_in_pager<R: RawReader>(rdr: &mut R,
+                                   s: &mut State,
+                                   descriptor: &Descriptor,
+                                   config: &Config)
+                                   -> Result<()> {
+    match descriptor.describe(&s.line, s.line.pos()) {
+        Some(description) => {
+            try!(s.set_description(None));
+            let lines: Vec<String> = description.lines().map(String::from).collect();
+            pager::page(rdr, s, &lines, config.esc_timeout())
+        }
+        None => s.beep(),
+    }
+}
+
+/// Incremental search, starting backward from the most recent entry
+/// or forward from the oldest one
+/// (through the ranked matches when the history has a scorer,
+/// or all its matches, most recent first, when it only keeps its recent entries in memory)
+fn incremental_search<R: RawReader>(rdr: &mut R,
+                                    s: &mut State,
+                                    history: &HistoryBackend,
+                                    mut direction: Direction,
+                                    config: &Config)
+                                    -> Result<Option<KeyPress>> {
     if history.is_empty() {
         return Ok(None);
     }
-    // Save the current edited line (and cursor position) before to overwrite it
-    s.snapshot();
+    // Save the current edited line (and cursor position) before to overwrite it,
+    // it stays displayed until a match is found
+    s.backup();
 
     let mut search_buf = String::new();
-    let mut history_idx = history.len() - 1;
-    let mut direction = Direction::Reverse;
+    let mut history_idx = if direction == Direction::Reverse {
+        history.len() - 1
+    } else {
+        0
+    };
     let mut success = true;
+    let ranked = history.is_ranked();
+    let stored = !ranked && history.is_truncated();
+    // position in the ranked (or stored) matches
+    let mut rank = 0;
+
+    // entry of the displayed match
+    let mut matched = None;
 
     let mut key;
-    // Display the reverse-i-search prompt and process chars
+    // Display the i-search prompt (in the current direction, with the number
+    // of the matched entry) and process chars
     loop {
-        let prompt = if success {
-            format!("(reverse-i-search)`{}': ", search_buf)
-        } else {
-            format!("(failed reverse-i-search)`{}': ", search_buf)
+        let position = match matched {
+            Some(idx) => format!(" {}/{}", idx + 1, history.len()),
+            None => String::new(),
         };
+        let prompt = format!("({}{}i-search{})`{}': ",
+                             if success { "" } else { "failed " },
+                             if direction == Direction::Reverse { "reverse-" } else { "" },
+                             position,
+                             search_buf);
         try!(s.refresh_prompt_and_line(&prompt));
 
-        key = try!(rdr.next_key(config.keyseq_timeout()));
+        key = try!(rdr.next_key(config.esc_timeout()));
         if let key!(c) = key {
             search_buf.push(c);
+            rank = 0;
         } else {
             match key {
                 ctrl!('H') |
@@ -733,7 +1840,9 @@ fn reverse_incremental_search<R: RawReader>(rdr: &mut R,
                 }
                 ctrl!('R') => {
                     direction = Direction::Reverse;
-                    if history_idx > 0 {
+                    if ranked || stored {
+                        rank += 1;
+                    } else if history_idx > 0 {
                         history_idx -= 1;
                     } else {
                         success = false;
@@ -742,211 +1851,721 @@ fn reverse_incremental_search<R: RawReader>(rdr: &mut R,
                 }
                 ctrl!('S') => {
                     direction = Direction::Forward;
-                    if history_idx < history.len() - 1 {
+                    if (ranked || stored) && rank > 0 {
+                        rank -= 1;
+                    } else if !ranked && !stored && history_idx < history.len() - 1 {
                         history_idx += 1;
                     } else {
                         success = false;
                         continue;
                     }
                 }
+                key!(Key::Tab) => {
+                    // the match is edited from its end, to complete its last word
+                    s.line.move_end();
+                    break;
+                }
                 ctrl!('G') => {
-                    // Restore current edited line (before search)
-                    s.snapshot();
+                    // Restore current edited line (before search), with its cursor
+                    s.line.backup(&s.snapshot);
+                    s.search_match = None;
                     try!(s.refresh_line());
                     return Ok(None);
                 }
                 _ => break,
             }
         }
-        success = match history.search(&search_buf, history_idx, direction) {
-            Some(idx) => {
-                history_idx = idx;
-                let entry = history.get(idx).unwrap();
-                let pos = entry.find(&search_buf).unwrap();
-                s.line.update(entry, pos);
+        let found = if stored {
+            if search_buf.is_empty() {
+                None
+            } else {
+                // the older entries are not kept in memory
+                let mut matches = try!(history.search_backward(&search_buf, rank + 1));
+                if rank < matches.len() {
+                    Some((None, matches.swap_remove(rank)))
+                } else {
+                    // stay on the last match
+                    rank = rank.saturating_sub(1);
+                    None
+                }
+            }
+        } else {
+            let found = if ranked {
+                if search_buf.is_empty() {
+                    None
+                } else {
+                    let matches =
+                        history.ranked_matches(&|entry: &str| entry.contains(&search_buf));
+                    if rank >= matches.len() && rank > 0 {
+                        // stay on the last match
+                        rank -= 1;
+                        None
+                    } else {
+                        matches.get(rank).cloned()
+                    }
+                }
+            } else {
+                history.search(&search_buf, history_idx, direction)
+            };
+            found.map(|idx| (Some(idx), history[idx].clone()))
+        };
+        matched = None;
+        success = match found {
+            Some((idx, entry)) => {
+                if let Some(idx) = idx {
+                    history_idx = idx;
+                }
+                matched = idx;
+                match entry.find(&search_buf) {
+                    Some(pos) => {
+                        s.line.update(&entry, pos);
+                        s.search_match = Some((pos, pos + search_buf.len()));
+                    }
+                    None => {
+                        // matched differently by the history (ignoring the case...):
+                        // the match is not highlighted
+                        s.line.update(&entry, entry.len());
+                        s.search_match = None;
+                    }
+                }
                 true
             }
             _ => false,
         };
+        if !success {
+            // the line still shows the previous match, of a shorter string
+            s.search_match = None;
+        }
     }
+    s.search_match = None;
     Ok(Some(key))
 }
 
+/// Non-incremental search: the string typed below the line (or the previous one,
+/// if none is typed) is searched from the current entry, and the nearest match recalled.
+fn non_incremental_search<R: RawReader>(rdr: &mut R,
+                                        s: &mut State,
+                                        history: &HistoryBackend,
+                                        direction: Direction,
+                                        search_string: &mut String,
+                                        config: &Config)
+                                        -> Result<()> {
+    let label = if direction == Direction::Reverse {
+        "Search backward"
+    } else {
+        "Search forward"
+    };
+    let mut search_buf = String::new();
+    loop {
+        s.menu.clear();
+        s.menu.push(format!("{}: {}", label, search_buf));
+        try!(s.refresh_line());
+        match try!(rdr.next_key(config.esc_timeout())) {
+            key!(Key::Enter) |
+            ctrl!('J') => break,
+            key!(Key::Backspace) |
+            ctrl!('H') => {
+                search_buf.pop();
+            }
+            key!(c) => search_buf.push(c),
+            key!(Key::Esc) |
+            ctrl!('G') |
+            ctrl!('C') => {
+                s.menu.clear();
+                return s.refresh_line();
+            }
+            _ => try!(s.beep()),
+        }
+    }
+    s.menu.clear();
+    if !search_buf.is_empty() {
+        *search_string = search_buf;
+    }
+    let found = match direction {
+        Direction::Reverse if s.history_index > 0 => {
+            history.search(search_string, s.history_index - 1, direction)
+        }
+        Direction::Forward => history.search(search_string, s.history_index + 1, direction),
+        _ => None,
+    };
+    match found {
+        Some(index) => {
+            s.recall_history(history, index);
+            s.refresh_line()
+        }
+        None => {
+            try!(s.refresh_line());
+            s.beep()
+        }
+    }
+}
+
 /// Handles reading and editting the readline buffer.
 /// It will also handle special inputs in an appropriate fashion
 /// (e.g., C-c will exit readline)
+fn readline_edit<C: Completer, T: Term>(prompt: &str,
+                                       editor: &mut Editor<C, T>,
+                                       overrides: &Overrides,
+                                       original_mode: &RawMode)
+                                       -> Result<String> {
+    if overrides.mask.is_some() || overrides.no_history {
+        // an empty history: nothing to recall or search
+        let history: RefCell<Box<HistoryBackend>> = RefCell::new(Box::new(History::new()));
+        if overrides.mask.is_some() {
+            // nothing killed from a password can be yanked in a later line
+            let kill_ring = mem::replace(&mut editor.kill_ring, KillRing::new(60));
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                edit_line(prompt, editor, &history, overrides, original_mode)
+            }));
+            editor.kill_ring = kill_ring;
+            return match result {
+                Ok(result) => result,
+                Err(payload) => panic::resume_unwind(payload),
+            };
+        }
+        return edit_line(prompt, editor, &history, overrides, original_mode);
+    }
+    // the history is shared with the hinter while the line is edited
+    let history = RefCell::new(mem::replace(&mut editor.history, Box::new(History::new())));
+    // and put back even if a hook panics
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        edit_line(prompt, editor, &history, overrides, original_mode)
+    }));
+    editor.history = history.into_inner();
+    match result {
+        Ok(result) => result,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
 #[allow(let_unit_value)]
-fn readline_edit<C: Completer>(prompt: &str,
-                               editor: &mut Editor<C>,
-                               original_mode: tty::Mode)
-                               -> Result<String> {
-    let completer = editor.completer.as_ref().map(|c| c as &Completer);
+fn edit_line<C: Completer, T: Term>(prompt: &str,
+                                   editor: &mut Editor<C, T>,
+                                   history: &RefCell<Box<HistoryBackend>>,
+                                   overrides: &Overrides,
+                                   original_mode: &RawMode)
+                                   -> Result<String> {
+    // a password is not completed, described, hinted or highlighted
+    let masked = overrides.mask.is_some();
+    let completer = match overrides.completer {
+        Some(completer) => Some(completer),
+        None if masked => None,
+        None => editor.completer.as_ref().map(|c| c as &Completer),
+    };
+    let descriptor = if masked {
+        None
+    } else {
+        editor.descriptor.as_ref().map(|d| &**d)
+    };
+    // the hints are not read by screen readers
+    let hinter = if editor.is_accessible() || masked {
+        None
+    } else {
+        editor.hinter.as_ref().map(|h| &**h)
+    };
+    // the keys of a password are neither observed nor recorded (see below),
+    // nor is it passed to the accept hook
+    let observer = if masked {
+        None
+    } else {
+        editor.observer.as_ref().map(|o| &**o)
+    };
+    let accept_hook = if masked {
+        None
+    } else {
+        editor.accept_hook.as_ref().map(|h| &**h)
+    };
+    let highlighter = if editor.colors_enabled() && !masked {
+        editor.highlighter.as_ref().map(|h| &**h)
+    } else {
+        None
+    };
 
-    let mut stdout = io::stdout();
+    let mut out = editor.term.create_writer();
 
     editor.kill_ring.reset();
-    let mut s = State::new(&mut stdout,
-                           editor.term.clone(),
+    let mut s = State::new(&mut out,
+                           Box::new(editor.term.clone()),
                            prompt,
-                           editor.history.len());
+                           history.borrow().len());
+    s.highlighter = highlighter;
+    s.hinter = hinter;
+    s.line.set_max_len(editor.config.max_line_len());
+    if !editor.prompt_variables.is_empty() {
+        s.variables = Some(&editor.prompt_variables);
+        s.expand_prompt();
+    }
+    if editor.is_accessible() {
+        s.echo = Some(Echo::new());
+    }
+    s.bell_style = editor.config.bell_style();
+    s.set_long_prompt(editor.config.long_prompt());
+    s.tab_display = editor.config.tab_display();
+    s.mask = overrides.mask;
+    s.history = Some(history);
+    s.observer = observer;
+    s.status = editor.status_bar.rows();
+    // the rows replaced before the edition started are displayed now
+    let _ = editor.status_bar.take_changed();
+    if editor.config.cursor_shape() != CursorShape::Default {
+        try!(s.term.set_cursor_shape(s.out, editor.config.cursor_shape()));
+    }
     try!(s.refresh_line());
 
-    let mut rdr = try!(s.term.create_reader());
+    let recorder = if masked {
+        None
+    } else {
+        editor.recorder.as_mut()
+    };
+    let mut rdr = ScriptedReader::new(try!(editor.term.create_reader()),
+                                      editor.script.as_mut(),
+                                      recorder,
+                                      observer);
+    // commands executed before reading the next key (repeated change, palette selection)
+    let mut replay = VecDeque::new();
+    // rows of a line continued with a trailing backslash
+    let mut continued = String::new();
+    // the previous command inserted a char (the typed chars are undone together)
+    let mut inserted = false;
+    // key read after the pasted chars inserted at once, not processed yet
+    let mut pending = None;
 
     loop {
-        let rk = rdr.next_key(editor.config.keyseq_timeout());
-        if rk.is_err() && s.term.sigwinch() {
-            s.update_columns();
+        // undo step of the change made by the previous command
+        s.history_edits.checkpoint(s.history_index, &s.line, inserted);
+        if let Some(descriptor) = descriptor {
+            // previous description is cleared on each keystroke
+            let description = timed!(Hooks, descriptor.describe(&s.line, s.line.pos()));
+            try!(s.set_description(description));
+        }
+        if let Some(message) = editor.message_line.take() {
+            // shown by a hook (or before the edition started)
+            s.message = Some(message);
             try!(s.refresh_line());
-            continue;
         }
-        let mut key = try!(rk);
-        if let key!(c) = key {
-            editor.kill_ring.reset();
-            try!(edit_insert(&mut s, c));
-            continue;
+        if let Some(rows) = editor.status_bar.take_changed() {
+            // replaced by a hook
+            s.status = rows;
+            try!(s.refresh_line());
+        }
+        // a hook handed the terminal over, or the process was continued in the foreground
+        let suspended = tty::take_suspended();
+        if s.term.continued() || suspended {
+            try!(s.redisplay());
         }
 
-        // autocomplete
-        if key == key!(Key::Tab) && completer.is_some() {
-            let next = try!(complete_line(&mut rdr, &mut s, completer.unwrap(), &editor.config));
-            if next.is_some() {
-                editor.kill_ring.reset();
-                key = next.unwrap();
-                if let key!(c) = key {
-                    try!(edit_insert(&mut s, c));
-                    continue;
+        let (key, mut cmd) = if let Some(cmd) = replay.pop_front() {
+            // the replayed commands are not bound to any key
+            (None, cmd)
+        } else {
+            // polled before any wait: the key was read with the previous ones
+            let burst = editor.config.confirm_paste() && (pending.is_some() || try!(rdr.poll(0)));
+            if pending.is_none() {
+                try!(wait_for_hint(&mut rdr, &mut s));
+            }
+            let resized = match editor.idle_hook {
+                Some(ref hook) if pending.is_none() => {
+                    try!(wait_idle(&mut rdr, &mut s, &**hook, editor.config.idle_timeout()))
                 }
+                _ => false,
+            };
+            let rk = if resized {
+                Err(error::ReadlineError::WindowResized)
+            } else if let Some(key) = pending.take() {
+                Ok(key)
             } else {
+                rdr.next_key(editor.config.esc_timeout())
+            };
+            if resized || (rk.is_err() && s.term.sigwinch()) {
+                if editor.config.report_resize() {
+                    try!(s.clear_below());
+                    return Err(error::ReadlineError::WindowResized);
+                }
+                s.update_columns();
+                try!(s.refresh_line());
                 continue;
             }
-        } else if key == ctrl!('R') {
-            // Search history backward
-            let next =
-                try!(reverse_incremental_search(&mut rdr, &mut s, &editor.history, &editor.config));
-            if next.is_some() {
-                key = next.unwrap();
-            } else {
+            if rk.is_err() && s.term.continued() {
+                // the read was interrupted by SIGCONT (continued in the foreground)
+                try!(s.redisplay());
                 continue;
             }
-        } else if key == key!(Key::Unknown) {
-            continue;
-        }
-
-        match key {
-            ctrl!('A') |
-            key!(Key::Home) => {
-                editor.kill_ring.reset();
-                // Move to the beginning of line.
-                try!(edit_move_home(&mut s))
-            }
-            ctrl!('B') |
-            key!(Key::Left) => {
-                editor.kill_ring.reset();
-                // Move back a character.
-                try!(edit_move_left(&mut s))
-            }
-            ctrl!('C') => {
-                editor.kill_ring.reset();
-                return Err(error::ReadlineError::Interrupted);
+            let key = try!(rk);
+            if s.term.sigwinch() {
+                // resized while the key was decoded (only a blocking read is interrupted):
+                // render in the new size before the key changes the line
+                s.update_columns();
+                try!(s.refresh_line());
             }
-            ctrl!('D') => {
-                editor.kill_ring.reset();
-                if s.line.is_empty() {
-                    return Err(error::ReadlineError::Eof);
-                } else {
-                    // Delete (forward) one character at point.
-                    try!(edit_delete(&mut s))
-                }
+            if s.term.continued() {
+                try!(s.redisplay());
             }
-            ctrl!('E') |
-            key!(Key::End) => {
-                editor.kill_ring.reset();
-                // Move to the end of line.
-                try!(edit_move_end(&mut s))
+            if s.message.is_some() {
+                s.message = None;
+                try!(s.refresh_line());
             }
-            ctrl!('F') |
-            key!(Key::Right) => {
-                editor.kill_ring.reset();
-                // Move forward a character.
-                try!(edit_move_right(&mut s))
+            if descriptor.is_none() && s.description.is_some() {
+                // the message of a rejected line is cleared by the next key
+                try!(s.set_description(None));
             }
-            ctrl!('H') |
-            key!(Key::Backspace) => {
-                editor.kill_ring.reset();
-                // Delete one character backward.
-                try!(edit_backspace(&mut s))
+            if let Some(handler) = editor.keymap.handler(key) {
+                if handler.handle(key, &mut s.line) {
+                    editor.kill_ring.reset();
+                    try!(s.refresh_line());
+                    continue;
+                }
             }
-            ctrl!('K') => {
-                // Kill the text from point to the end of the line.
-                if let Some(text) = try!(edit_kill_line(&mut s)) {
-                    editor.kill_ring.kill(&text, Mode::Append)
+            let cmd = if editor.keymap.is_sequence_prefix(key) {
+                let mut cmds = try!(read_sequence(&mut rdr,
+                                                  &mut s,
+                                                  &editor.keymap,
+                                                  key,
+                                                  &editor.config));
+                let cmd = cmds.remove(0);
+                replay.extend(cmds);
+                cmd
+            } else if editor.keymap.is_ctrl_x_prefix(key) {
+                let key = try!(rdr.next_key(editor.config.esc_timeout()));
+                editor.keymap.get_ctrl_x(key)
+            } else if s.rendered.hint.is_some() && s.line.pos() == s.line.len() &&
+                      editor.keymap.get_hint(key) != Cmd::Noop {
+                // a hint is displayed just after the cursor
+                editor.keymap.get_hint(key)
+            } else if key == key!(Key::Unknown) && !rdr.last_sequence().is_empty() {
+                // kept so that the application can identify (and bind) the undecoded key
+                editor.unknown_sequence = Some(String::from(rdr.last_sequence()));
+                match editor.keymap.get_raw(rdr.last_sequence()) {
+                    Cmd::Noop => editor.keymap.get(key),
+                    cmd => cmd,
                 }
+            } else {
+                editor.keymap.get(key)
+            };
+            let cmd = match cmd {
+                // pasted, not typed: the line is accepted only by a typed Enter
+                Cmd::AcceptLine | Cmd::ForceAcceptLine if burst => Cmd::SelfInsert('\n'),
+                cmd => cmd,
+            };
+            (Some(key), cmd)
+        };
+        inserted = match cmd {
+            Cmd::SelfInsert(_) => true,
+            _ => false,
+        };
+
+        if cmd == Cmd::Complete && completer.is_some() {
+            // autocomplete
+            let next = try!(complete_line(&mut rdr, &mut s, completer.unwrap(), &editor.config));
+            match next {
+                Some(key) => cmd = editor.keymap.get(key),
+                None => continue,
             }
-            ctrl!('L') => {
-                // Clear the screen leaving the current line at the top of the screen.
-                try!(s.term.clear_screen(&mut s.out));
-                try!(s.refresh_line())
+        } else if cmd == Cmd::DabbrevExpand {
+            // expand the word before the cursor, cycling on repeated presses
+            let next =
+                try!(dabbrev_expand(&mut rdr, &mut s, &**history.borrow(), key, &editor.config));
+            match next {
+                Some(key) => cmd = editor.keymap.get(key),
+                None => continue,
             }
-            ctrl!('N') |
-            key!(Key::Down) => {
-                editor.kill_ring.reset();
-                // Fetch the next command from the history list.
-                try!(edit_history_next(&mut s, &editor.history, false))
+        } else if cmd == Cmd::YankLastArg {
+            // insert the last argument of the previous commands, cycling on repeated presses
+            let next =
+                try!(yank_last_arg(&mut rdr, &mut s, &**history.borrow(), key, &editor.config));
+            match next {
+                Some(key) => cmd = editor.keymap.get(key),
+                None => continue,
             }
-            ctrl!('P') |
-            key!(Key::Up) => {
-                editor.kill_ring.reset();
-                // Fetch the previous command from the history list.
-                try!(edit_history_next(&mut s, &editor.history, true))
+        } else if (cmd == Cmd::ReverseSearchHistory || cmd == Cmd::ForwardSearchHistory) &&
+                  editor.config.fuzzy_search() {
+            cmd = Cmd::FuzzySearchHistory;
+        } else if cmd == Cmd::ReverseSearchHistory || cmd == Cmd::ForwardSearchHistory {
+            // Search history backward (or forward), switching with Ctrl-R and Ctrl-S
+            let direction = if cmd == Cmd::ReverseSearchHistory {
+                Direction::Reverse
+            } else {
+                Direction::Forward
+            };
+            let shapes = (editor.config.cursor_shape(), editor.config.search_cursor_shape());
+            if shapes.0 != shapes.1 {
+                try!(s.term.set_cursor_shape(s.out, shapes.1));
+            }
+            let next = try!(incremental_search(&mut rdr,
+                                               &mut s,
+                                               &**history.borrow(),
+                                               direction,
+                                               &editor.config));
+            if shapes.0 != shapes.1 {
+                try!(s.term.set_cursor_shape(s.out, shapes.0));
+            }
+            match next {
+                Some(key) => cmd = editor.keymap.get(key),
+                None => continue,
+            }
+            if cmd == Cmd::Complete {
+                // the completion is opened on the match, as on an edited line
+                replay.push_front(cmd);
+                continue;
+            }
+        }
+
+        if let Some(observer) = observer {
+            observer.command_dispatched(key, &cmd);
+        }
+        if replay.is_empty() {
+            editor.keymap.record(&cmd);
+        }
+        if !cmd.is_kill_or_yank() {
+            editor.kill_ring.reset();
+        }
+        match cmd {
+            Cmd::SelfInsert(_) |
+            Cmd::BackwardDeleteChar => {}
+            _ => s.pending_pairs = 0,
+        }
+        // a change deactivates the mark (the region is taken before for kill-region)
+        let region = s.region();
+        if cmd.is_change() {
+            s.mark = None;
+        }
+        match cmd {
+            Cmd::User(ref name) => {
+                // execute the command added by the application
+                let key = key.unwrap_or(key!(Key::Null));
+                match editor.keymap.command(name) {
+                    Some(command) if command.handle(key, &mut s.line) => try!(s.refresh_line()),
+                    _ => try!(s.beep()),
+                }
+            }
+            Cmd::SelfInsert(c) => {
+                // filtered before the line is modified
+                let c = match editor.insert_filter {
+                    Some(ref filter) => filter.filter(&s.line, s.line.pos(), c),
+                    None => Some(c),
+                };
+                match c {
+                    Some(c) if editor.config.auto_pair() &&
+                               auto_pair::insert(&mut s.line,
+                                                 editor.config.auto_pairs(),
+                                                 &mut s.pending_pairs,
+                                                 c) => try!(s.refresh_line()),
+                    // typed, not replayed (see `Cmd::RepeatLastChange`)
+                    Some(c) if key.is_some() && editor.insert_filter.is_none() &&
+                               !editor.config.auto_pair() && try!(rdr.poll(0)) => {
+                        // chars already buffered (a paste): inserted with a single refresh
+                        let (text, next) =
+                            try!(read_pasted(&mut rdr, &editor.keymap, c, &editor.config));
+                        for c in text.chars().skip(1) {
+                            editor.keymap.record(&Cmd::SelfInsert(c));
+                        }
+                        pending = next;
+                        try!(edit_insert_pasted(&mut s, &text, editor.config.max_line_len()))
+                    }
+                    Some(c) => try!(edit_insert(&mut s, c)),
+                    None => try!(s.beep()),
+                }
+            }
+            Cmd::BeginningOfLine => {
+                // Move to the beginning of line.
+                if editor.config.line_wise_home_end() {
+                    try!(edit_move_line_start(&mut s))
+                } else {
+                    try!(edit_move_home(&mut s))
+                }
+            }
+            Cmd::BackwardChar => {
+                // Move back a character.
+                try!(edit_move_left(&mut s))
+            }
+            Cmd::Abort => {
+                // back to plain editing (the selected register is reset with the kill ring)
+                s.mark = None;
+                s.menu.clear();
+                try!(s.refresh_line());
+                try!(s.beep())
+            }
+            Cmd::Interrupt => {
+                try!(s.clear_below());
+                if editor.config.interrupt_with_line() && !s.line.is_empty() {
+                    return Err(error::ReadlineError::InterruptedWithLine(s.line.as_str()
+                        .to_owned()));
+                }
+                return Err(error::ReadlineError::Interrupted);
+            }
+            Cmd::EndOfFile => {
+                if s.line.is_empty() {
+                    try!(s.clear_below());
+                    return Err(error::ReadlineError::Eof);
+                } else {
+                    // Delete (forward) one character at point.
+                    try!(edit_delete(&mut s))
+                }
+            }
+            Cmd::EndOfLine => {
+                // Move to the end of line.
+                if editor.config.line_wise_home_end() {
+                    try!(edit_move_line_end(&mut s))
+                } else {
+                    try!(edit_move_end(&mut s))
+                }
+            }
+            Cmd::ForwardChar => {
+                // Move forward a character.
+                try!(edit_move_right(&mut s))
+            }
+            Cmd::BackwardDeleteChar => {
+                // Delete one character backward (or an empty auto-inserted pair).
+                if editor.config.auto_pair() &&
+                   auto_pair::backspace(&mut s.line,
+                                        editor.config.auto_pairs(),
+                                        &mut s.pending_pairs) {
+                    try!(s.refresh_line())
+                } else {
+                    try!(edit_backspace(&mut s))
+                }
+            }
+            Cmd::KillWholeLine => {
+                // Kill the current line of a multi-line buffer.
+                if let Some(text) = s.line.kill_whole_line() {
+                    try!(s.refresh_line());
+                    editor.kill_ring.kill(&text, Mode::Append)
+                }
+            }
+            Cmd::KillLine => {
+                // Kill the text from point to the end of the line.
+                if let Some(text) = try!(edit_kill_line(&mut s)) {
+                    editor.kill_ring.kill(&text, Mode::Append)
+                }
+            }
+            Cmd::ClearScreen => {
+                // Clear the screen leaving the current line at the top of the screen.
+                try!(s.term.clear_screen(&mut s.out));
+                s.reset_echo();
+                try!(s.refresh_line())
+            }
+            Cmd::NextHistory => {
+                // Move to the next line of a multi-line buffer,
+                // or fetch the next command from the history list.
+                if !try!(edit_move_line(&mut s, editor.config.up_down(), false)) {
+                    try!(edit_history_next(&mut s, &**history.borrow(), false))
+                }
+            }
+            Cmd::PreviousHistory => {
+                // Move to the previous line of a multi-line buffer,
+                // or fetch the previous command from the history list.
+                if !try!(edit_move_line(&mut s, editor.config.up_down(), true)) {
+                    try!(edit_history_next(&mut s, &**history.borrow(), true))
+                }
             }
-            ctrl!('T') => {
-                editor.kill_ring.reset();
+            Cmd::TransposeChars => {
                 // Exchange the char before cursor with the character at cursor.
                 try!(edit_transpose_chars(&mut s))
             }
-            ctrl!('U') => {
+            Cmd::UnixLineDiscard => {
                 // Kill backward from point to the beginning of the line.
                 if let Some(text) = try!(edit_discard_line(&mut s)) {
                     editor.kill_ring.kill(&text, Mode::Prepend)
                 }
             }
             #[cfg(unix)]
-            ctrl!('V') => {
+            Cmd::BracketedPaste => {
+                let mut text = try!(read_pasted_text(&mut rdr));
+                if editor.config.strip_pasted_ansi() {
+                    text = strip_ansi(&text).into_owned();
+                }
+                if let Some(ref filter) = editor.paste_filter {
+                    text = filter.filter(&text);
+                }
+                try!(edit_yank(&mut s, &text))
+            }
+            Cmd::QuotedInsert => {
                 // Quoted insert
-                editor.kill_ring.reset();
                 let c = try!(rdr.next_char());
                 try!(edit_insert(&mut s, c)) // FIXME
             }
-            ctrl!('W') => {
+            Cmd::UnixWordRubout => {
                 // Kill the word behind point, using white space as a word boundary
                 if let Some(text) = try!(edit_delete_prev_word(&mut s, char::is_whitespace)) {
                     editor.kill_ring.kill(&text, Mode::Prepend)
                 }
             }
-            ctrl!('Y') => {
+            Cmd::Yank => {
                 // retrieve (yank) last item killed
                 if let Some(text) = editor.kill_ring.yank() {
                     try!(edit_yank(&mut s, text))
                 }
             }
             #[cfg(unix)]
-            ctrl!('Z') => {
+            Cmd::Suspend if editor.config.report_suspend() => {
+                try!(s.clear_below());
+                return Err(error::ReadlineError::Suspended);
+            }
+            #[cfg(unix)]
+            Cmd::Suspend if !editor.term.can_suspend() => try!(s.beep()),
+            #[cfg(unix)]
+            Cmd::Suspend => {
                 try!(original_mode.disable_raw_mode());
                 try!(tty::suspend());
-                try!(s.term.enable_raw_mode()); // TODO original_mode may have changed
+                try!(editor.term.enable_raw_mode()); // TODO original_mode may have changed
+                let _ = s.term.continued();
+                try!(s.redisplay())
+            }
+            Cmd::Undo => {
+                // restore the displayed entry before its last change
+                if s.history_edits.undo(s.history_index, &mut s.line) {
+                    try!(s.refresh_line())
+                } else {
+                    try!(s.beep())
+                }
+            }
+            Cmd::AcceptLine if editor.config.line_continuation() != LineContinuation::Disabled &&
+                               is_continued(&s.line) => {
+                // continue the line on a new row
+                try!(s.clear_below());
+                try!(edit_move_end(&mut s));
+                try!(write_and_flush(s.out, b"\n"));
+                let keep = editor.config.line_continuation() == LineContinuation::Keep;
+                let end = if keep { s.line.len() } else { s.line.len() - 1 };
+                continued.push_str(&s.line[..end]);
+                continued.push('\n');
+                s.set_prompt(editor.config.continuation_prompt());
+                s.restart(history.borrow().len());
+                s.status = editor.status_bar.rows();
                 try!(s.refresh_line())
             }
-            // TODO CTRL-_ // undo
-            key!(Key::Enter) |
-            ctrl!('J') => {
+            Cmd::AcceptLine if editor.config.check_balance() &&
+                               !auto_pair::is_balanced(&[&continued, s.line.as_str()].concat(),
+                                                       editor.config.auto_pairs()) => {
+                // Refuse to accept an unbalanced line.
+                try!(s.beep())
+            }
+            Cmd::AcceptLine |
+            Cmd::ForceAcceptLine => {
+                if let Some(hook) = accept_hook {
+                    // with the rows of a continued line
+                    let joined = [&continued, s.line.as_str()].concat();
+                    match hook.before_accept(&joined) {
+                        Acceptance::Accept => {}
+                        Acceptance::Replace(line) => {
+                            // only the last row is still edited
+                            let start = line.rfind('\n').map_or(0, |i| i + 1);
+                            continued = String::from(&line[..start]);
+                            s.line.update(&line[start..], line.len() - start);
+                            try!(s.refresh_line())
+                        }
+                        Acceptance::Reject(message) => {
+                            // keep editing
+                            try!(s.set_description(message));
+                            continue;
+                        }
+                    }
+                }
                 // Accept the line regardless of where the cursor is.
-                editor.kill_ring.reset();
+                try!(s.clear_below());
                 try!(edit_move_end(&mut s));
                 break;
             }
-            alt!('\x08') |
-            alt!('\x7f') => {
+            Cmd::BackwardKillWord => {
                 // kill one word backward
                 // Kill from the cursor to the start of the current word, or, if between words, to the start of the previous word.
                 if let Some(text) = try!(edit_delete_prev_word(&mut s,
@@ -954,90 +2573,291 @@ fn readline_edit<C: Completer>(prompt: &str,
                     editor.kill_ring.kill(&text, Mode::Prepend)
                 }
             }
-            alt!('<') => {
+            Cmd::InsertComment => {
+                // comment out the line and accept it
+                try!(edit_insert_comment(&mut s, editor.config.comment_begin()));
+                try!(s.clear_below());
+                break;
+            }
+            Cmd::InsertCompletions => {
+                // insert all the completions of the word before point
+                if let Some(completer) = completer {
+                    try!(insert_completions(&mut s, completer))
+                }
+            }
+            Cmd::NonIncrementalForwardSearchHistory |
+            Cmd::NonIncrementalReverseSearchHistory => {
+                // search history for the string typed below the line
+                let direction = if cmd == Cmd::NonIncrementalReverseSearchHistory {
+                    Direction::Reverse
+                } else {
+                    Direction::Forward
+                };
+                try!(non_incremental_search(&mut rdr,
+                                            &mut s,
+                                            &**history.borrow(),
+                                            direction,
+                                            &mut editor.search_string,
+                                            &editor.config))
+            }
+            Cmd::BeginningOfHistory => {
                 // move to first entry in history
-                editor.kill_ring.reset();
-                try!(edit_history(&mut s, &editor.history, true))
+                try!(edit_history(&mut s, &**history.borrow(), true))
+            }
+            Cmd::PossibleCompletions => {
+                // list the completions of the word before point
+                if let Some(completer) = completer {
+                    try!(possible_completions(&mut rdr, &mut s, completer, &editor.config))
+                }
             }
-            alt!('>') => {
+            Cmd::EndOfHistory => {
                 // move to last entry in history
-                editor.kill_ring.reset();
-                try!(edit_history(&mut s, &editor.history, false))
+                try!(edit_history(&mut s, &**history.borrow(), false))
             }
-            alt!('B') => {
+            Cmd::BackwardWord => {
                 // move backwards one word
-                editor.kill_ring.reset();
                 try!(edit_move_to_prev_word(&mut s))
             }
-            alt!('C') => {
+            Cmd::CapitalizeWord => {
                 // capitalize word after point
-                editor.kill_ring.reset();
                 try!(edit_word(&mut s, WordAction::CAPITALIZE))
             }
-            alt!('D') => {
+            Cmd::KillWord => {
                 // kill one word forward
                 if let Some(text) = try!(edit_delete_word(&mut s)) {
                     editor.kill_ring.kill(&text, Mode::Append)
                 }
             }
-            alt!('F') => {
+            Cmd::KillTextObject(obj) => {
+                // kill the word, quoted or bracketed text around the cursor
+                if let Some(text) = try!(edit_kill_text_object(&mut s, obj)) {
+                    editor.kill_ring.kill(&text, Mode::Append)
+                }
+            }
+            Cmd::ForwardWord => {
                 // move forwards one word
-                editor.kill_ring.reset();
                 try!(edit_move_to_next_word(&mut s))
             }
-            alt!('L') => {
+            Cmd::AcceptHint |
+            Cmd::AcceptHintWord => {
+                // insert the hint (or its first word) at the end of the line
+                match s.rendered.hint.clone() {
+                    Some(hint) => {
+                        let len = if cmd == Cmd::AcceptHint {
+                            hint.len()
+                        } else {
+                            hint::first_word_len(&hint)
+                        };
+                        s.line.move_end();
+                        try!(edit_yank(&mut s, &hint[..len]))
+                    }
+                    None => try!(s.beep()),
+                }
+            }
+            Cmd::Describe => {
+                // display the full description in the pager
+                if let Some(descriptor) = descriptor {
+                    try!(describe_in_pager(&mut rdr, &mut s, descriptor, &editor.config))
+                }
+            }
+            Cmd::DowncaseWord => {
                 // lowercase word after point
-                editor.kill_ring.reset();
                 try!(edit_word(&mut s, WordAction::LOWERCASE))
             }
-            alt!('T') => {
+            Cmd::RevertLine => {
+                // revert all changes made to this line
+                try!(edit_revert_line(&mut s, &**history.borrow()))
+            }
+            Cmd::TransposeWords => {
                 // transpose words
-                editor.kill_ring.reset();
                 try!(edit_transpose_words(&mut s))
             }
-            alt!('U') => {
+            Cmd::UpcaseWord => {
                 // uppercase word after point
-                editor.kill_ring.reset();
                 try!(edit_word(&mut s, WordAction::UPPERCASE))
             }
-            alt!('Y') => {
+            Cmd::YankPop => {
                 // yank-pop
                 if let Some((yank_size, text)) = editor.kill_ring.yank_pop() {
                     try!(edit_yank_pop(&mut s, yank_size, text))
                 }
             }
-            key!(Key::Delete) => {
-                editor.kill_ring.reset();
-                try!(edit_delete(&mut s))
+            Cmd::ReReadInitFile => {
+                // re-apply the configuration given by the application
+                if let Some(config) = editor.config_loader.as_ref().and_then(|l| l.load()) {
+                    history.borrow_mut().apply_config(&config);
+                    if config.restore_terminal_on_signal() {
+                        tty::install_restore_handlers();
+                    }
+                    editor.config = config;
+                    try!(s.refresh_line())
+                }
+            }
+            Cmd::SelectRegister => {
+                // read the register name
+                match try!(rdr.next_key(editor.config.esc_timeout())) {
+                    key!(c) if editor.kill_ring.select_register(c) => {}
+                    _ => try!(s.beep()),
+                }
+            }
+            Cmd::RepeatLastChange => {
+                // replay the last change
+                replay.extend(editor.keymap.last_change().iter().cloned())
+            }
+            Cmd::CommandPalette => {
+                // execute the selected command as if its key was pressed
+                let timeout = editor.config.esc_timeout();
+                let selected = try!(palette::select(&mut rdr, &mut s, &editor.keymap, timeout));
+                replay.extend(selected);
+            }
+            Cmd::FuzzySearchHistory => {
+                let timeout = editor.config.esc_timeout();
+                let history = history.borrow();
+                let selected = try!(fuzzy_search::select(&mut rdr, &mut s, &**history, timeout));
+                if let Some(index) = selected {
+                    s.recall_history(&**history, index);
+                    try!(s.refresh_line());
+                }
+            }
+            Cmd::DeleteChar => try!(edit_delete(&mut s)),
+            Cmd::SetMark => {
+                // the region is displayed as soon as the cursor moves
+                s.mark = Some(s.line.pos());
+            }
+            Cmd::ExchangePointAndMark => {
+                if let (Some(_), Some(mark)) = (region, s.mark) {
+                    s.mark = Some(s.line.pos());
+                    s.line.set_pos(mark);
+                    try!(s.refresh_line())
+                } else {
+                    try!(s.beep())
+                }
+            }
+            Cmd::KillRegion => {
+                // kill the text between the mark and the cursor
+                if let Some((start, end)) = region {
+                    let text = s.line.delete_range(start, end);
+                    try!(s.refresh_line());
+                    editor.kill_ring.kill(&text, Mode::Append)
+                }
+            }
+            Cmd::CopyRegionAsKill => {
+                // save the region in the kill ring and deactivate the mark
+                if let Some((start, end)) = region {
+                    editor.kill_ring.kill(&s.line[start..end], Mode::Append);
+                    s.mark = None;
+                    try!(s.refresh_line())
+                }
             }
             _ => {
-                editor.kill_ring.reset();
                 // Ignore the character typed.
             }
         }
     }
-    Ok(s.line.into_string())
+    if !editor.config.revert_all_at_newline() {
+        // keep the changes made to the other history lines
+        s.history_edits.revert(s.history_index);
+        s.history_edits.commit(&mut **history.borrow_mut());
+    }
+    if continued.is_empty() {
+        Ok(s.line.into_string())
+    } else {
+        continued.push_str(&s.line);
+        Ok(continued)
+    }
+}
+
+/// Tell if `line` ends with a backslash which is not escaped.
+fn is_continued(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
 }
 
-struct Guard(tty::Mode);
+struct Guard<M: RawMode>(M);
 
 #[allow(unused_must_use)]
-impl Drop for Guard {
+impl<M: RawMode> Drop for Guard<M> {
     fn drop(&mut self) {
-        let Guard(mode) = *self;
-        mode.disable_raw_mode();
+        self.0.disable_raw_mode();
     }
 }
 
 /// Readline method that will enable RAW mode, call the `readline_edit()`
 /// method and disable raw mode
-fn readline_raw<C: Completer>(prompt: &str, editor: &mut Editor<C>) -> Result<String> {
-    let original_mode = try!(editor.term.enable_raw_mode());
-    let guard = Guard(original_mode);
-    let user_input = readline_edit(prompt, editor, original_mode);
+fn readline_raw<C: Completer, T: Term>(prompt: &str,
+                                      editor: &mut Editor<C, T>,
+                                      overrides: &Overrides)
+                                      -> Result<String> {
+    let guard = Guard(try!(editor.term.enable_raw_mode()));
+    if editor.config.alternate_screen() {
+        let mut out = editor.term.create_writer();
+        try!(editor.term.enter_alternate_screen(&mut out));
+    }
+    let paste = cfg!(unix) && editor.config.bracketed_paste();
+    if paste {
+        try!(write_and_flush(&mut editor.term.create_writer(), BRACKETED_PASTE_ON));
+    }
+    let user_input = readline_edit(prompt, editor, overrides, &guard.0);
+    #[cfg(feature = "perf")]
+    editor.collect_timings();
+    let paste = if paste {
+        write_and_flush(&mut editor.term.create_writer(), BRACKETED_PASTE_OFF)
+    } else {
+        Ok(())
+    };
+    let shape = if editor.config.cursor_shape() != CursorShape::Default ||
+                   editor.config.search_cursor_shape() != CursorShape::Default {
+        // restore the shape of the terminal, whatever the outcome of the edition
+        editor.term.set_cursor_shape(&mut editor.term.create_writer(), CursorShape::Default)
+    } else {
+        Ok(())
+    };
     drop(guard); // try!(disable_raw_mode(original_mode));
-    println!("");
-    user_input
+    let newline = writeln!(editor.term.create_writer(), "");
+    // the error of the edition (like the end of input of a closed terminal)
+    // is reported rather than the failure to write to it
+    let line = try!(user_input);
+    try!(paste);
+    try!(shape);
+    try!(newline);
+    Ok(line)
+}
+
+/// Restore the terminal mode saved when the editor entered RAW mode.
+///
+/// Intended to be called from an application's own signal handlers
+/// (it is async-signal-safe) so that the user's shell is not left in RAW mode.
+/// Does nothing when the editor is not currently reading a line.
+#[cfg(unix)]
+pub fn restore_terminal() {
+    tty::restore_terminal()
+}
+
+/// Run `f` in the terminal mode saved when the editor entered RAW mode,
+/// then enter RAW mode again: to spawn `$PAGER` or a subprocess,
+/// or to print a report, from a hook while a line is edited (unix only).
+/// The line is then redisplayed below the output of `f`, in the terminal size queried again
+/// (on the same row when the cursor was left at its start).
+/// Outside of an edition, `f` is simply called.
+///
+/// The editor is borrowed by `readline` while its hooks are called,
+/// so this works on the local terminal, without the editor:
+/// between two lines, prefer `Editor::suspend_raw`, which also works on the terminal
+/// of the application (see `Editor::with_terminal`) and leaves the alternate screen.
+///
+/// ```no_run
+/// # use rustyline::{Editor, KeyPress};
+/// # use rustyline::line_buffer::LineBuffer;
+/// # use std::process::Command;
+/// let mut rl = Editor::<()>::new();
+/// // to be bound to a key with `Cmd::User("man")`
+/// rl.add_command("man", |_: KeyPress, line: &mut LineBuffer| {
+///     let page = line.as_str().split_whitespace().next().unwrap_or("man").to_owned();
+///     rustyline::suspend_raw(|| Command::new("man").arg(page).status()).is_ok()
+/// });
+/// ```
+#[cfg(unix)]
+pub fn suspend_raw<R, F: FnOnce() -> R>(f: F) -> R {
+    tty::suspend_raw(f)
 }
 
 fn readline_direct() -> Result<String> {
@@ -1049,13 +2869,68 @@ fn readline_direct() -> Result<String> {
     }
 }
 
-/// Line editor
-pub struct Editor<C: Completer> {
-    term: Terminal,
-    history: History,
+/// Settings overridden for a single call to `Editor::readline_with`,
+/// so that different kinds of prompts can share an editor.
+#[derive(Default)]
+pub struct Overrides<'a> {
+    /// Do not recall or search the history.
+    pub no_history: bool,
+    /// Completer used instead of the editor's one.
+    pub completer: Option<&'a Completer>,
+    /// Display this char instead of each char of the line (password prompt).
+    /// The history, the kill ring, the hints, the highlighting, the description,
+    /// the editor's completer, the accept hook, the observer and the recorder are not used
+    /// (and the line is not echoed on an unsupported terminal).
+    pub mask: Option<char>,
+}
+
+/// Name of the prompt context active when the editor is created
+/// (see `Editor::switch_context`).
+pub const DEFAULT_CONTEXT: &'static str = "default";
+
+/// History, completer and key bindings of a prompt context
+/// kept aside while another context is active.
+struct Context<C> {
+    history: Box<HistoryBackend>,
+    completer: Option<C>,
+    keymap: Keymap,
+}
+
+/// Number of keys whose timings are kept (`perf` feature).
+#[cfg(feature = "perf")]
+const PERF_KEYS: usize = 10000;
+
+/// Line editor, on the terminal of the platform unless another one is given
+/// (see `Editor::with_terminal`).
+pub struct Editor<C: Completer, T: Term = Terminal> {
+    term: T,
+    history: Box<HistoryBackend>,
     completer: Option<C>,
+    descriptor: Option<Box<Descriptor>>,
+    highlighter: Option<Box<Highlighter>>,
+    hinter: Option<Box<Hinter>>,
     kill_ring: KillRing,
+    keymap: Keymap,
     config: Config,
+    config_loader: Option<Box<ConfigLoader>>,
+    status_bar: StatusBar,
+    message_line: MessageLine,
+    script: Option<Script>,
+    recorder: Option<Recorder>,
+    observer: Option<Box<Observer>>,
+    #[cfg(feature = "perf")]
+    timings: perf::Timings,
+    accept_hook: Option<Box<AcceptHook>>,
+    insert_filter: Option<Box<InsertFilter>>,
+    paste_filter: Option<Box<PasteFilter>>,
+    idle_hook: Option<Box<IdleHook>>,
+    key_table: SharedKeyTable,
+    unknown_sequence: Option<String>,
+    prompt_variables: PromptVariables,
+    search_string: String, // Last string of the non-incremental searches
+    unsupported_terms: Vec<String>,
+    context: String, // Name of the active prompt context
+    contexts: HashMap<String, Context<C>>, // Inactive prompt contexts
 }
 
 impl<C: Completer> Editor<C> {
@@ -1064,51 +2939,327 @@ impl<C: Completer> Editor<C> {
     }
 
     pub fn with_config(config: Config) -> Editor<C> {
-        let term = Terminal::new();
-        Editor {
+        let term = Terminal::new(config.output_stream());
+        let mut editor = Self::with_terminal(config, term);
+        editor.term.set_key_table(editor.key_table.clone());
+        editor
+    }
+
+    /// Editor reading and rendering on `term`, a terminal implemented by the application
+    /// (like the connection of a telnet or SSH server,
+    /// see the `telnet_server` and `net_terminal` examples).
+    /// The editor renders to `term` with ANSI escape sequences.
+    pub fn with_terminal<T: Term>(config: Config, term: T) -> Editor<C, T> {
+        if config.restore_terminal_on_signal() {
+            tty::install_restore_handlers();
+        }
+        let mut editor = Editor {
             term: term,
-            history: History::with_config(config),
+            history: Box::new(History::with_config(config.clone())),
             completer: None,
+            descriptor: None,
+            highlighter: None,
+            hinter: None,
             kill_ring: KillRing::new(60),
+            keymap: Keymap::new(),
             config: config,
-        }
+            config_loader: None,
+            status_bar: StatusBar::new(),
+            message_line: MessageLine::new(),
+            script: None,
+            recorder: None,
+            observer: DebugLogger::from_env().map(|logger| Box::new(logger) as Box<Observer>),
+            #[cfg(feature = "perf")]
+            timings: perf::Timings::new(PERF_KEYS),
+            accept_hook: None,
+            insert_filter: None,
+            paste_filter: Some(Box::new(PasteNormalizer::new())),
+            idle_hook: None,
+            key_table: SharedKeyTable::default(),
+            unknown_sequence: None,
+            prompt_variables: PromptVariables::new(),
+            search_string: String::new(),
+            unsupported_terms: tty::UNSUPPORTED_TERMS.iter().map(|t| String::from(*t)).collect(),
+            context: String::from(DEFAULT_CONTEXT),
+            contexts: HashMap::new(),
+        };
+        let config = editor.config.clone();
+        editor.term.apply_config(&config);
+        editor.update_term_support();
+        editor
+    }
+
+    /// Transform the bytes read from the terminal before the keys are decoded
+    /// (to strip a transport protocol, see `preprocess::TelnetFilter`). Unix only.
+    pub fn set_input_preprocessor(&mut self, preprocessor: Option<Box<InputPreprocessor>>) {
+        self.term.set_input_preprocessor(SharedPreprocessor::new(preprocessor));
     }
+}
 
+impl<C: Completer, T: Term> Editor<C, T> {
     /// This method will read a line from STDIN and will display a `prompt`
     pub fn readline(&mut self, prompt: &str) -> Result<String> {
-        if self.term.is_unsupported() {
-            // Write prompt and flush it to stdout
-            let mut stdout = io::stdout();
-            try!(write_and_flush(&mut stdout, prompt.as_bytes()));
+        self.readline_with(prompt, Overrides::default())
+    }
+
+    /// Read a line like `readline`, with the settings of `overrides`
+    /// for this call only (history, completer, masked input).
+    ///
+    /// ```no_run
+    /// # use rustyline::{Editor, Overrides};
+    /// let mut rl = Editor::<()>::new();
+    /// let password = rl.readline_with("Password: ",
+    ///                                 Overrides { mask: Some('*'), ..Overrides::default() });
+    /// ```
+    pub fn readline_with(&mut self, prompt: &str, overrides: Overrides) -> Result<String> {
+        let masked = overrides.mask.is_some();
+        let mut line = if self.term.is_unsupported() {
+            // Write prompt and flush it to the output stream
+            let mut out = self.term.create_writer();
+            try!(write_and_flush(&mut out, prompt.as_bytes()));
 
-            readline_direct()
+            if masked {
+                let line = tty::without_echo(readline_direct);
+                // the line feed typed is not echoed either
+                try!(write_and_flush(&mut out, b"\n"));
+                try!(line)
+            } else {
+                try!(readline_direct())
+            }
         } else if !self.term.is_stdin_tty() {
             // Not a tty: read from file / pipe.
-            readline_direct()
+            try!(readline_direct())
         } else {
-            readline_raw(prompt, self)
+            try!(readline_raw(prompt, self, &overrides))
+        };
+        // only the lines read without editing, or continued on several rows, can be longer
+        let len = line_buffer::floor_char_boundary(&line, self.config.max_line_len());
+        line.truncate(len);
+        match self.accept_hook {
+            Some(ref hook) if !masked => hook.after_accept(&line),
+            _ => {}
+        }
+        Ok(line)
+    }
+
+    /// Read lines until `terminator` is entered alone on a line (like `EOF` in a heredoc,
+    /// or `;`), the following ones after the continuation prompt
+    /// (see `Config::continuation_prompt`).
+    /// Return the lines joined by newlines, without the terminator.
+    ///
+    /// At the end of the input (Ctrl-D), the lines already read are returned
+    /// (`ReadlineError::Eof` is returned only if there are none).
+    ///
+    /// ```no_run
+    /// # use rustyline::Editor;
+    /// let mut rl = Editor::<()>::new();
+    /// let query = rl.readlines_until("sql> ", ";");
+    /// ```
+    pub fn readlines_until(&mut self, prompt: &str, terminator: &str) -> Result<String> {
+        let mut lines: Vec<String> = Vec::new();
+        loop {
+            let prompt = if lines.is_empty() {
+                prompt
+            } else {
+                self.config.continuation_prompt()
+            };
+            match self.readline(prompt) {
+                Ok(ref line) if line.trim() == terminator => break,
+                Ok(line) => lines.push(line),
+                Err(error::ReadlineError::Eof) if !lines.is_empty() => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Ask a yes/no question: `y` or `n` answers it, Enter chooses `default`
+    /// (shown in upper case after the prompt).
+    ///
+    /// On unsupported terminals, or when stdin is not a tty,
+    /// the answer is read on a whole line (and asked again until it is valid).
+    pub fn prompt_confirm(&mut self, prompt: &str, default: bool) -> Result<bool> {
+        let prompt = format!("{}{} ", prompt, if default { "[Y/n]" } else { "[y/N]" });
+        if self.term.is_unsupported() || !self.term.is_stdin_tty() {
+            loop {
+                try!(write_and_flush(&mut self.term.create_writer(), prompt.as_bytes()));
+                if let Some(answer) = prompt::parse_confirm(&try!(readline_direct()), default) {
+                    return Ok(answer);
+                }
+            }
+        }
+        let timeout = self.config.esc_timeout();
+        self.prompt_raw(&prompt, |rdr, s| prompt::confirm(rdr, s, default, timeout))
+    }
+
+    /// Ask to choose one of `options` (listed below the prompt) with the arrow keys
+    /// or with its number, and return its index. Esc interrupts the prompt.
+    ///
+    /// On unsupported terminals, or when stdin is not a tty, the numbered options are
+    /// printed and the number or the name of the chosen one is read on a whole line.
+    ///
+    /// # Panics
+    /// If `options` is empty.
+    pub fn prompt_select(&mut self, prompt: &str, options: &[&str]) -> Result<usize> {
+        assert!(!options.is_empty());
+        if self.term.is_unsupported() || !self.term.is_stdin_tty() {
+            let mut out = self.term.create_writer();
+            for (i, option) in options.iter().enumerate() {
+                try!(writeln!(out, "{}. {}", i + 1, option));
+            }
+            loop {
+                try!(write_and_flush(&mut out, prompt.as_bytes()));
+                if let Some(index) = prompt::parse_select(&try!(readline_direct()), options) {
+                    return Ok(index);
+                }
+            }
         }
+        let timeout = self.config.esc_timeout();
+        self.prompt_raw(prompt, |rdr, s| prompt::select(rdr, s, options, timeout))
+    }
+
+    /// Ask for a number between `min` and `max` (inclusive): only the chars of numbers
+    /// can be typed, Up/Down increment/decrement it, and the validation error
+    /// is displayed below the line until the number is valid.
+    ///
+    /// On unsupported terminals, or when stdin is not a tty, the number is read
+    /// on a whole line (and asked again until it is valid).
+    pub fn prompt_number<N: Number>(&mut self, prompt: &str, min: N, max: N) -> Result<N> {
+        if self.term.is_unsupported() || !self.term.is_stdin_tty() {
+            loop {
+                try!(write_and_flush(&mut self.term.create_writer(), prompt.as_bytes()));
+                match prompt::parse_number(&try!(readline_direct()), min, max) {
+                    Ok(n) => return Ok(n),
+                    Err(error) => try!(writeln!(self.term.create_writer(), "{}", error)),
+                }
+            }
+        }
+        let timeout = self.config.esc_timeout();
+        self.prompt_raw(prompt, |rdr, s| prompt::number(rdr, s, min, max, timeout))
+    }
+
+    /// Display `prompt` and run `ask` in RAW mode (with the keys decoded like `readline`).
+    fn prompt_raw<R, F>(&mut self, prompt: &str, ask: F) -> Result<R>
+        where F: FnOnce(&mut ScriptedReader<T::Reader>, &mut State) -> Result<R>
+    {
+        let guard = Guard(try!(self.term.enable_raw_mode()));
+        let mut out = self.term.create_writer();
+        let result = {
+            let mut s = State::new(&mut out, Box::new(self.term.clone()), prompt, 0);
+            if self.is_accessible() {
+                s.echo = Some(Echo::new());
+            }
+            s.bell_style = self.config.bell_style();
+            s.set_long_prompt(self.config.long_prompt());
+            s.tab_display = self.config.tab_display();
+            let observer = self.observer.as_ref().map(|o| &**o);
+            let mut rdr = ScriptedReader::new(try!(self.term.create_reader()),
+                                              self.script.as_mut(),
+                                              self.recorder.as_mut(),
+                                              observer);
+            ask(&mut rdr, &mut s)
+        };
+        drop(guard);
+        try!(writeln!(out, ""));
+        result
+    }
+
+    /// Read a single key without line editing (for "press any key" or y/n prompts):
+    /// the terminal is in RAW mode only while the key is read.
+    /// Return `None` when no key is pressed within `timeout_ms`
+    /// (a negative value waits forever).
+    ///
+    /// On unsupported terminals, or when stdin is not a tty,
+    /// a whole line is read and its first char is returned.
+    pub fn read_key(&mut self, timeout_ms: i32) -> Result<Option<KeyPress>> {
+        if self.term.is_unsupported() || !self.term.is_stdin_tty() {
+            let line = try!(readline_direct());
+            return Ok(Some(match line.chars().next() {
+                Some('\n') | None => key!(Key::Enter),
+                Some(c) => consts::char_to_key_press(c),
+            }));
+        }
+        let guard = Guard(try!(self.term.enable_raw_mode()));
+        let observer = self.observer.as_ref().map(|o| &**o);
+        let mut rdr = ScriptedReader::new(try!(self.term.create_reader()),
+                                          self.script.as_mut(),
+                                          self.recorder.as_mut(),
+                                          observer);
+        let key = if timeout_ms < 0 || try!(rdr.poll(timeout_ms)) {
+            Some(try!(rdr.next_key(self.config.esc_timeout())))
+        } else {
+            None
+        };
+        drop(guard);
+        Ok(key)
     }
 
     /// Load the history from the specified file.
     pub fn load_history<P: AsRef<Path> + ?Sized>(&mut self, path: &P) -> Result<()> {
-        self.history.load(path)
+        self.history.load(path.as_ref())
     }
     /// Save the history in the specified file.
     pub fn save_history<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Result<()> {
-        self.history.save(path)
+        self.history.save(path.as_ref())
     }
     /// Add a new entry in the history.
     pub fn add_history_entry<S: AsRef<str> + Into<String>>(&mut self, line: S) -> bool {
-        self.history.add(line)
+        self.history.add(line.as_ref())
     }
     /// Clear history.
     pub fn clear_history(&mut self) {
         self.history.clear()
     }
     /// Return a reference to the history object.
-    pub fn get_history(&mut self) -> &mut History {
-        &mut self.history
+    pub fn get_history(&mut self) -> &mut HistoryBackend {
+        &mut *self.history
+    }
+    /// Replace the history (a `History` by default) by `history`,
+    /// e.g. the history of the application kept in its own database.
+    pub fn set_history(&mut self, mut history: Box<HistoryBackend>) {
+        history.apply_config(&self.config);
+        self.history = history;
+    }
+    /// Rank the matches of the incremental search and of `HistoryHinter`
+    /// with `scorer` (like `history::Frecency`) instead of the reverse order.
+    pub fn set_history_scorer(&mut self, scorer: Option<Box<HistoryScorer>>) {
+        self.history.set_scorer(scorer)
+    }
+
+    /// Switch to the prompt context `name` (created empty on first use),
+    /// e.g. a debugger with a command prompt and an expression prompt.
+    /// The history, the completer and the key bindings of the current context
+    /// are kept aside until it is switched back to.
+    pub fn switch_context(&mut self, name: &str) {
+        if name == self.context {
+            return;
+        }
+        let config = self.config.clone();
+        let context = self.contexts.remove(name).unwrap_or_else(|| {
+            Context {
+                history: Box::new(History::with_config(config)),
+                completer: None,
+                keymap: Keymap::new(),
+            }
+        });
+        let previous = Context {
+            history: mem::replace(&mut self.history, context.history),
+            completer: mem::replace(&mut self.completer, context.completer),
+            keymap: mem::replace(&mut self.keymap, context.keymap),
+        };
+        let previous_name = mem::replace(&mut self.context, String::from(name));
+        self.contexts.insert(previous_name, previous);
+    }
+
+    /// Name of the active prompt context (`DEFAULT_CONTEXT` initially).
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+
+    /// Drop the inactive prompt context `name` (with its history).
+    /// Return `false` when there is no such inactive context.
+    pub fn remove_context(&mut self, name: &str) -> bool {
+        self.contexts.remove(name).is_some()
     }
 
     /// Register a callback function to be called for tab-completion.
@@ -1116,89 +3267,515 @@ impl<C: Completer> Editor<C> {
         self.completer = completer;
     }
 
-    /// ```
-    /// let mut rl = rustyline::Editor::<()>::new();
-    /// for readline in rl.iter("> ") {
-    ///     match readline {
-    ///         Ok(line) => {
-    ///             println!("Line: {}", line);
-    ///         },
-    ///         Err(err) => {
-    ///             println!("Error: {:?}", err);
-    ///             break
-    ///         }
-    ///     }
-    /// }
-    /// ```
-    pub fn iter<'a>(&'a mut self, prompt: &'a str) -> Iter<C> {
-        Iter {
-            editor: self,
-            prompt: prompt,
-        }
+    /// Register a callback function to be called for contextual help
+    /// (displayed below the edited line).
+    pub fn set_descriptor(&mut self, descriptor: Option<Box<Descriptor>>) {
+        self.descriptor = descriptor;
     }
-}
 
-impl<C: Completer> fmt::Debug for Editor<C> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("Editor")
-            .field("term", &self.term)
-            .field("config", &self.config)
-            .finish()
+    /// Register a callback function to be called for syntax highlighting
+    /// (only used when colors are enabled).
+    pub fn set_highlighter(&mut self, highlighter: Option<Box<Highlighter>>) {
+        self.highlighter = highlighter;
     }
-}
 
-pub struct Iter<'a, C: Completer>
-    where C: 'a
-{
-    editor: &'a mut Editor<C>,
-    prompt: &'a str,
-}
+    /// Return the content of the register `name`
+    /// ('a' to 'z', or '"' for the last killed text).
+    pub fn register(&self, name: char) -> Option<&str> {
+        self.kill_ring.register(name)
+    }
 
-impl<'a, C: Completer> Iterator for Iter<'a, C> {
-    type Item = Result<String>;
+    /// Set the content of the register `name` ('a' to 'z'),
+    /// e.g. to restore registers saved by the application.
+    pub fn set_register(&mut self, name: char, text: &str) {
+        self.kill_ring.set_register(name, text)
+    }
 
-    fn next(&mut self) -> Option<Result<String>> {
-        let readline = self.editor.readline(self.prompt);
-        match readline {
-            Ok(l) => {
-                self.editor.add_history_entry(l.as_ref()); // TODO Validate
-                Some(Ok(l))
+    /// Return the named registers (name, content), sorted by name.
+    pub fn registers(&self) -> Vec<(char, &str)> {
+        self.kill_ring.registers()
+    }
+
+    /// Register a callback function to be called for hints
+    /// (displayed at the right of the cursor).
+    pub fn set_hinter(&mut self, hinter: Option<Box<Hinter>>) {
+        self.hinter = hinter;
+    }
+
+    /// Tell if the output is colored, according to `Config::color_mode`:
+    /// in `ColorMode::Enabled`, colors are disabled when stdout is not a terminal
+    /// or when the `NO_COLOR` environment variable is set.
+    pub fn colors_enabled(&self) -> bool {
+        match self.config.color_mode() {
+            ColorMode::Enabled => {
+                self.term.colors_enabled() &&
+                env::var_os("NO_COLOR").map_or(true, |v| v.is_empty())
             }
-            Err(error::ReadlineError::Eof) => None,
-            e @ Err(_) => Some(e),
+            ColorMode::Forced => true,
+            ColorMode::Disabled => false,
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use std::io::Write;
-    use line_buffer::LineBuffer;
-    use history::History;
-    use completion::Completer;
-    use config::Config;
-    use consts::{Key, KeyPress};
-    use {Position, State};
-    use super::{Editor, Result};
-    use tty::{Terminal, Term};
+    /// Tell if the line is rendered for screen readers, according to `Config::accessible`
+    /// or to the `RUSTYLINE_ACCESSIBLE` environment variable (when it is set and not empty).
+    pub fn is_accessible(&self) -> bool {
+        self.config.accessible() ||
+        env::var_os(accessible::ACCESSIBLE_ENV_VAR).map_or(false, |v| !v.is_empty())
+    }
 
-    fn init_state<'out>(out: &'out mut Write,
-                        line: &str,
-                        pos: usize,
-                        cols: usize)
+    /// Set the rows displayed at the bottom of the edited line
+    /// (mode indicator, connection info, key hints, ...).
+    /// They are kept below the line, completion menus and descriptions
+    /// until the line is accepted. An empty `rows` removes the status bar.
+    pub fn set_status_bar(&mut self, rows: Vec<String>) {
+        self.status_bar.set(rows)
+    }
+
+    /// Return a handle to replace the status bar from the hooks,
+    /// while a line is edited (see `set_status_bar`).
+    pub fn status_bar(&self) -> StatusBar {
+        self.status_bar.clone()
+    }
+
+    /// Display `message` below the edited line until the next key is pressed
+    /// (or when the next line is read, if no line is being edited).
+    pub fn show_message(&self, message: &str) {
+        self.message_line.show(message)
+    }
+
+    /// Return a handle to display messages below the edited line from the hooks
+    /// (like the errors of a completer), which cannot print them without
+    /// corrupting the display.
+    pub fn message_line(&self) -> MessageLine {
+        self.message_line.clone()
+    }
+
+    /// Read the keys from `script` instead of the terminal
+    /// (until the end of the script, which ends the input like Ctrl-D).
+    /// `None` restores the terminal input.
+    pub fn set_input_script(&mut self, script: Option<Script>) {
+        self.script = script;
+    }
+
+    /// Record the keys read (from the terminal or a script) with `recorder`,
+    /// so that the session can be replayed with `set_input_script`.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
+    }
+
+    /// Register an observer of the key presses, commands and refreshes
+    /// (replacing the `DebugLogger` activated by the `RUSTYLINE_LOG` environment variable).
+    pub fn set_observer(&mut self, observer: Option<Box<Observer>>) {
+        self.observer = observer;
+    }
+
+    /// Timings of the last keys typed in the lines read (`perf` feature),
+    /// reported at exit in the file named by the `RUSTYLINE_PERF` environment variable.
+    #[cfg(feature = "perf")]
+    pub fn timings(&self) -> &perf::Timings {
+        &self.timings
+    }
+
+    /// Forget the timings of the keys typed until now (`perf` feature).
+    #[cfg(feature = "perf")]
+    pub fn clear_timings(&mut self) {
+        self.timings.clear();
+    }
+
+    /// Keep the timings of the keys of the line just read.
+    #[cfg(feature = "perf")]
+    fn collect_timings(&mut self) {
+        perf::key_done();
+        for timing in perf::take_done() {
+            self.timings.push(timing);
+        }
+    }
+
+    /// Register hooks called when a line is accepted
+    /// (to transform it, veto it or be notified of it).
+    pub fn set_accept_hook(&mut self, hook: Option<Box<AcceptHook>>) {
+        self.accept_hook = hook;
+    }
+
+    /// Register a filter called for each typed char before it is inserted
+    /// (to reject or transform it, see `filter::AsciiOnly`).
+    pub fn set_insert_filter(&mut self, filter: Option<Box<InsertFilter>>) {
+        self.insert_filter = filter;
+    }
+
+    /// Register a filter transforming the text pasted in bracketed paste mode
+    /// before it is inserted (see `paste::PasteNormalizer`, the default one).
+    /// Without filter, the pasted text is inserted as is, carriage returns included.
+    pub fn set_paste_filter(&mut self, filter: Option<Box<PasteFilter>>) {
+        self.paste_filter = filter;
+    }
+
+    /// Register a hook called when no key is pressed for `Config::idle_timeout`
+    /// (to refresh a clock in the prompt or the status of background jobs).
+    pub fn set_idle_hook(&mut self, hook: Option<Box<IdleHook>>) {
+        self.idle_hook = hook;
+    }
+
+    /// Display the value of `variable` instead of `{name}` in the prompts
+    /// (or stop evaluating it with `None`), like `"{user}@{host} {cwd}> "`:
+    /// it is evaluated on each refresh of the line, so a prompt showing the time
+    /// is updated by an idle hook returning `Idle::Refresh`.
+    ///
+    /// ```no_run
+    /// # use rustyline::Editor;
+    /// let mut rl = Editor::<()>::new();
+    /// rl.set_prompt_variable("cwd", Some(Box::new(|| {
+    ///     std::env::current_dir().map(|dir| dir.display().to_string()).unwrap_or(String::new())
+    /// })));
+    /// let line = rl.readline("{cwd}> ");
+    /// ```
+    pub fn set_prompt_variable(&mut self, name: &str, variable: Option<Box<PromptVariable>>) {
+        self.prompt_variables.set(name, variable);
+    }
+
+    /// Bind a key to a command, overriding the default binding.
+    /// Return the previous custom binding, if any.
+    pub fn bind_sequence(&mut self, key: KeyPress, cmd: Cmd) -> Option<Cmd> {
+        self.keymap.bind(key, cmd)
+    }
+
+    /// Remove the custom binding of a key (restoring the default one).
+    pub fn unbind_sequence(&mut self, key: KeyPress) -> Option<Cmd> {
+        self.keymap.unbind(key)
+    }
+
+    /// Bind a key to a command used when a hint is displayed after the cursor
+    /// (by default, Right, End, Ctrl-E and Ctrl-F accept the hint and Meta-F its first word).
+    /// Binding a key to `Cmd::Noop` keeps its regular command.
+    /// Return the previous custom binding, if any.
+    pub fn bind_hint_sequence(&mut self, key: KeyPress, cmd: Cmd) -> Option<Cmd> {
+        self.keymap.bind_hint(key, cmd)
+    }
+
+    /// Remove the custom binding of a key used when a hint is displayed.
+    pub fn unbind_hint_sequence(&mut self, key: KeyPress) -> Option<Cmd> {
+        self.keymap.unbind_hint(key)
+    }
+
+    /// Bind a sequence of keys (like `g g`) to a command.
+    /// When a key starting a sequence is pressed, the next ones are awaited
+    /// for at most `Config::keyseq_timeout`.
+    /// Return the previous binding of the sequence, if any.
+    pub fn bind_keys(&mut self, keys: &[KeyPress], cmd: Cmd) -> Option<Cmd> {
+        self.keymap.bind_keys(keys, cmd)
+    }
+
+    /// Remove the binding of a sequence of keys.
+    pub fn unbind_keys(&mut self, keys: &[KeyPress]) -> Option<Cmd> {
+        self.keymap.unbind_keys(keys)
+    }
+
+    /// Current key bindings, for a "show keys" help screen:
+    /// the single keys, the Ctrl-X sequences and the sequences of keys
+    /// (see `Binding::name` for their names and `Cmd::name` for the command names).
+    pub fn bindings(&self) -> Vec<Binding> {
+        self.keymap.list()
+    }
+
+    /// Custom bindings overriding a default one
+    /// (or delaying it, for a sequence starting with a bound key).
+    pub fn shadowed_bindings(&self) -> Vec<Binding> {
+        self.keymap.list().into_iter().filter(|b| b.shadowed.is_some()).collect()
+    }
+
+    /// Bind the raw escape sequence of a key the terminal sends but which is not decoded
+    /// (see `last_unknown_sequence`) to a command.
+    /// Return the previous binding of the sequence, if any.
+    pub fn bind_raw_sequence(&mut self, seq: &str, cmd: Cmd) -> Option<Cmd> {
+        self.keymap.bind_raw(seq, cmd)
+    }
+
+    /// Remove the binding of a raw escape sequence.
+    pub fn unbind_raw_sequence(&mut self, seq: &str) -> Option<Cmd> {
+        self.keymap.unbind_raw(seq)
+    }
+
+    /// Decode the escape sequence `seq` sent by the terminal as `key` (unix only),
+    /// like `"\x1b[15~"` as `F5`, then bind the key like any other
+    /// (a sequence not starting with Esc is never decoded).
+    /// The longest registered sequence wins over the built-in ones.
+    /// Return the key it was decoded as, if any.
+    ///
+    /// ```
+    /// # use rustyline::{Cmd, Editor, Key, KeyPress};
+    /// let mut rl = Editor::<()>::new();
+    /// let f5 = KeyPress { key: Key::F(5), alt: false, ctrl: false, shift: false, sup: false };
+    /// rl.bind_escape_sequence("\x1b[15~", f5);
+    /// rl.bind_sequence(f5, Cmd::ClearScreen);
+    /// ```
+    pub fn bind_escape_sequence(&mut self, seq: &str, key: KeyPress) -> Option<KeyPress> {
+        self.key_table.borrow_mut().insert(seq, key)
+    }
+
+    /// Stop decoding the escape sequence `seq` registered with `bind_escape_sequence`.
+    pub fn unbind_escape_sequence(&mut self, seq: &str) -> Option<KeyPress> {
+        self.key_table.borrow_mut().remove(seq)
+    }
+
+    /// Raw escape sequence of the last key which could not be decoded (unix only),
+    /// to report which terminal sequence needs support or to bind it.
+    pub fn last_unknown_sequence(&self) -> Option<&str> {
+        self.unknown_sequence.as_ref().map(|s| s.as_str())
+    }
+
+    /// Bind the sequence Ctrl-X `key` to a command.
+    /// Return the previous custom binding, if any.
+    pub fn bind_ctrl_x_sequence(&mut self, key: KeyPress, cmd: Cmd) -> Option<Cmd> {
+        self.keymap.bind_ctrl_x(key, cmd)
+    }
+
+    /// Remove the custom binding of the sequence Ctrl-X `key`.
+    pub fn unbind_ctrl_x_sequence(&mut self, key: KeyPress) -> Option<Cmd> {
+        self.keymap.unbind_ctrl_x(key)
+    }
+
+    /// Re-apply a configuration (completion type, history settings, ...)
+    /// without losing the history, the key bindings or the hooks.
+    pub fn reload_config(&mut self, config: Config) {
+        if config.restore_terminal_on_signal() {
+            tty::install_restore_handlers();
+        }
+        let new_term = config.output_stream() != self.config.output_stream() ||
+                       config.dev_tty() != self.config.dev_tty();
+        if !config.alternate_screen() || new_term {
+            let mut out = self.term.create_writer();
+            let _ = self.term.leave_alternate_screen(&mut out);
+        }
+        self.term.apply_config(&config);
+        self.history.apply_config(&config);
+        for context in self.contexts.values_mut() {
+            context.history.apply_config(&config);
+        }
+        self.config = config;
+        self.update_term_support();
+    }
+
+    /// Run `f` on the main screen of the editor's terminal, in its original mode
+    /// (RAW mode is only enabled while a line is read): to spawn `$PAGER`
+    /// or a subprocess, or to print a report, between two lines.
+    /// The alternate screen buffer (see `Config::alternate_screen`) is left during `f`,
+    /// then entered again.
+    ///
+    /// ```no_run
+    /// # use rustyline::Editor;
+    /// # use std::process::Command;
+    /// let mut rl = Editor::<()>::new();
+    /// let status = rl.suspend_raw(|| Command::new("less").arg("README.md").status());
+    /// ```
+    pub fn suspend_raw<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        if !self.config.alternate_screen() {
+            return f();
+        }
+        let mut out = self.term.create_writer();
+        // like in `drop`, `f` is run anyway if the screen cannot be switched
+        let _ = self.term.leave_alternate_screen(&mut out);
+        let result = f();
+        let _ = self.term.enter_alternate_screen(&mut out);
+        result
+    }
+
+    /// Tell if the line is read without editing it
+    /// (unsupported terminal, see `Config::term_support`).
+    pub fn is_dumb_terminal(&self) -> bool {
+        self.term.is_unsupported()
+    }
+
+    /// Add `term` to the `TERM` values of the terminals which do not support line editing
+    /// (by default, `dumb`, `cons25` and `emacs`).
+    pub fn add_unsupported_term(&mut self, term: &str) {
+        if !self.unsupported_terms.iter().any(|t| t == term) {
+            self.unsupported_terms.push(String::from(term));
+            self.update_term_support();
+        }
+    }
+
+    /// Remove `term` from the `TERM` values of the unsupported terminals.
+    /// Return `false` if it was not in the list.
+    pub fn remove_unsupported_term(&mut self, term: &str) -> bool {
+        let len = self.unsupported_terms.len();
+        self.unsupported_terms.retain(|t| t != term);
+        self.update_term_support();
+        self.unsupported_terms.len() != len
+    }
+
+    fn update_term_support(&mut self) {
+        let unsupported = match self.config.term_support() {
+            TermSupport::Detect => tty::is_unsupported_term(&self.unsupported_terms),
+            TermSupport::Supported => false,
+            TermSupport::Unsupported => true,
+        };
+        self.term.set_unsupported(unsupported);
+    }
+
+    /// Register the source of the configuration re-applied
+    /// by the re-read-init-file command (Ctrl-X Ctrl-R).
+    pub fn set_config_loader(&mut self, config_loader: Option<Box<ConfigLoader>>) {
+        self.config_loader = config_loader;
+    }
+
+    /// Register a command executed when a key bound to `Cmd::User(name)` is pressed
+    /// (with `Key::Null` when it is repeated or selected in the command palette).
+    /// The bell rings when it returns `false`.
+    /// Return the previous command of this name, if any.
+    pub fn add_command<F: KeyHandler + 'static>(&mut self,
+                                                name: &str,
+                                                command: F)
+                                                -> Option<Box<KeyHandler>> {
+        self.keymap.add_command(name, Box::new(command))
+    }
+
+    /// Remove the command registered under `name`.
+    pub fn remove_command(&mut self, name: &str) -> Option<Box<KeyHandler>> {
+        self.keymap.remove_command(name)
+    }
+
+    /// Register a handler called when `key` is pressed, before the command bound to it.
+    /// Return the previous handler, if any.
+    pub fn bind_handler(&mut self,
+                        key: KeyPress,
+                        handler: Box<KeyHandler>)
+                        -> Option<Box<KeyHandler>> {
+        self.keymap.bind_handler(key, handler)
+    }
+
+    /// Remove the handler of `key`.
+    pub fn unbind_handler(&mut self, key: KeyPress) -> Option<Box<KeyHandler>> {
+        self.keymap.unbind_handler(key)
+    }
+
+    /// ```
+    /// let mut rl = rustyline::Editor::<()>::new();
+    /// for readline in rl.iter("> ") {
+    ///     match readline {
+    ///         Ok(line) => {
+    ///             println!("Line: {}", line);
+    ///         },
+    ///         Err(err) => {
+    ///             println!("Error: {:?}", err);
+    ///             break
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn iter<'a>(&'a mut self, prompt: &'a str) -> Iter<C, T> {
+        Iter {
+            editor: self,
+            prompt: prompt,
+        }
+    }
+}
+
+impl<C: Completer, T: Term> Drop for Editor<C, T> {
+    fn drop(&mut self) {
+        let mut out = self.term.create_writer();
+        let _ = self.term.leave_alternate_screen(&mut out);
+        #[cfg(feature = "perf")]
+        self.timings.report_to_env_file();
+    }
+}
+
+impl<C: Completer, T: Term + fmt::Debug> fmt::Debug for Editor<C, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Editor")
+            .field("term", &self.term)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+pub struct Iter<'a, C: Completer, T: Term = Terminal>
+    where C: 'a,
+          T: 'a
+{
+    editor: &'a mut Editor<C, T>,
+    prompt: &'a str,
+}
+
+impl<'a, C: Completer, T: Term> Iterator for Iter<'a, C, T> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Result<String>> {
+        let readline = self.editor.readline(self.prompt);
+        match readline {
+            Ok(l) => {
+                self.editor.add_history_entry(l.as_ref()); // TODO Validate
+                Some(Ok(l))
+            }
+            Err(error::ReadlineError::Eof) => None,
+            e @ Err(_) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use accept::{AcceptHook, Acceptance};
+    use history::{Direction, Frecency, History, HistoryBackend, VecStore};
+    use completion::{Completer, Descriptor};
+    use std::borrow::Cow;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::time::Duration;
+    use config::{BellStyle, ColorMode, Config, ConfigLoader, LineContinuation, LongPrompt,
+                 OutputStreamType, TabDisplay, TermSupport, UpDown};
+    use highlight::{Color, Highlighter, Style};
+    use hint::{Hinter, HistoryHinter};
+    use history_overlay::HistoryOverlay;
+    use consts::{Key, KeyPress};
+    use keymap::{Cmd, KeyHandler};
+    use line_buffer::{LineBuffer, MAX_LINE};
+    use observer::Observer;
+    use script::Script;
+    use {Position, Rendered, State};
+    use super::{Editor, Result, TextObject};
+    use tty::{Terminal, Term};
+
+    fn init_state<'out>(out: &'out mut Write,
+                        line: &str,
+                        pos: usize,
+                        cols: usize)
                         -> State<'out, 'static> {
-        let term = Terminal::new();
+        let term = Terminal::new(OutputStreamType::Stdout);
         State {
             out: out,
-            prompt: "",
+            template: "",
+            prompt: Cow::Borrowed(""),
             prompt_size: Position::default(),
+            long_prompt: LongPrompt::Truncate,
             line: LineBuffer::init(line, pos),
             cursor: Position::default(),
             cols: cols,
             old_rows: 0,
             history_index: 0,
             snapshot: LineBuffer::with_capacity(100),
-            term: term,
+            history_edits: HistoryOverlay::new(),
+            message: None,
+            description: None,
+            menu: Vec::new(),
+            status: Vec::new(),
+            pending_pairs: 0,
+            mark: None,
+            variables: None,
+            search_match: None,
+            highlighter: None,
+            hinter: None,
+            history: None,
+            observer: None,
+            color_depth: term.color_depth(),
+            rendered: Rendered::new(),
+            echo: None,
+            bell_style: BellStyle::Audible,
+            flash: false,
+            mask: None,
+            tab_display: TabDisplay::Spaces(4),
+            term: Box::new(term),
+            scratch: String::with_capacity(MAX_LINE),
         }
     }
 
@@ -1224,28 +3801,83 @@ mod test {
         }
 
         super::edit_history_next(&mut s, &history, true).unwrap();
-        assert_eq!(line, s.snapshot.as_str());
+        assert_eq!(Some(line), s.history_edits.get(2));
         assert_eq!(1, s.history_index);
         assert_eq!("line1", s.line.as_str());
 
         for _ in 0..2 {
             super::edit_history_next(&mut s, &history, true).unwrap();
-            assert_eq!(line, s.snapshot.as_str());
+            assert_eq!(Some(line), s.history_edits.get(2));
             assert_eq!(0, s.history_index);
             assert_eq!("line0", s.line.as_str());
         }
 
         super::edit_history_next(&mut s, &history, false).unwrap();
-        assert_eq!(line, s.snapshot.as_str());
+        assert_eq!(Some(line), s.history_edits.get(2));
         assert_eq!(1, s.history_index);
         assert_eq!("line1", s.line.as_str());
 
         super::edit_history_next(&mut s, &history, false).unwrap();
-        // assert_eq!(line, s.snapshot);
         assert_eq!(2, s.history_index);
         assert_eq!(line, s.line.as_str());
     }
 
+    #[test]
+    fn edit_history_next_keeps_current_edit() {
+        let mut out = ::std::io::sink();
+        let line = "current edited line";
+        let mut s = init_state(&mut out, line, 6, 80);
+        let mut history = History::new();
+        history.add("line0");
+        s.history_index = history.len();
+
+        super::edit_history_next(&mut s, &history, true).unwrap();
+        assert_eq!("line0", s.line.as_str());
+        // completion/search use their own snapshot
+        s.backup();
+        super::edit_history_next(&mut s, &history, false).unwrap();
+        assert_eq!(1, s.history_index);
+        assert_eq!(line, s.line.as_str());
+        assert_eq!(6, s.line.pos());
+    }
+
+    #[test]
+    fn edit_history_next_keeps_entry_edits() {
+        let mut out = ::std::io::sink();
+        let mut s = init_state(&mut out, "", 0, 80);
+        let mut history = History::new();
+        history.add("line0");
+        history.add("line1");
+        s.history_index = history.len();
+
+        super::edit_history_next(&mut s, &history, true).unwrap();
+        s.line.insert('!');
+        super::edit_history_next(&mut s, &history, true).unwrap();
+        assert_eq!("line0", s.line.as_str());
+        super::edit_history_next(&mut s, &history, false).unwrap();
+        assert_eq!("line1!", s.line.as_str());
+        // originals are untouched
+        assert_eq!("line1", history.get(1).unwrap());
+
+        super::edit_history(&mut s, &history, false).unwrap();
+        assert_eq!(2, s.history_index);
+        assert_eq!("", s.line.as_str());
+        super::edit_history(&mut s, &history, true).unwrap();
+        assert_eq!("line0", s.line.as_str());
+    }
+
+    #[test]
+    fn beginning_end_of_history() {
+        let mut editor = init_editor(&[key!('d'), alt!('<'), alt!('>'), key!(Key::Enter)]);
+        editor.add_history_entry("line0");
+        editor.add_history_entry("line1");
+        // the line being typed is restored at the end of the history
+        assert_eq!("d", editor.readline(">>").unwrap());
+
+        editor.term.keys = vec![alt!('<'), key!(Key::Enter)];
+        assert_eq!("line0", editor.readline(">>").unwrap());
+    }
+
     struct SimpleCompleter;
     impl Completer for SimpleCompleter {
         fn complete(&self, line: &str, _pos: usize) -> Result<(usize, Vec<String>)> {
@@ -1253,6 +3885,42 @@ mod test {
         }
     }
 
+    struct MultiCompleter;
+    impl Completer for MultiCompleter {
+        fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+            let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+            Ok((start, vec![String::from("rust"), String::from("ruby")]))
+        }
+        fn word_end(&self, line: &str, pos: usize) -> usize {
+            line[pos..].find(' ').map_or(line.len(), |i| pos + i)
+        }
+    }
+
+    #[test]
+    fn complete_word_under_cursor() {
+        let mut out = ::std::io::sink();
+        let mut s = init_state(&mut out, "rust x", 2, 80);
+        let keys = &[key!(Key::Tab), key!(Key::Enter)];
+        let mut rdr = keys.iter();
+        let key = super::complete_line(&mut rdr, &mut s, &MultiCompleter, &Config::default())
+            .unwrap();
+        assert_eq!(Some(key!(Key::Enter)), key);
+        assert_eq!("ruby x", s.line.as_str());
+        assert_eq!(4, s.line.pos());
+    }
+
+    #[test]
+    fn complete_prefix_under_cursor() {
+        let mut out = ::std::io::sink();
+        let mut s = init_state(&mut out, "rst x", 1, 80);
+        let mut rdr = [].iter();
+        let config = Config::builder().completion_type(::CompletionType::List).build();
+        let key = super::complete_line(&mut rdr, &mut s, &MultiCompleter, &config).unwrap();
+        assert_eq!(None, key);
+        assert_eq!("rust x", s.line.as_str());
+        assert_eq!(2, s.line.pos());
+    }
+
     #[test]
     fn complete_line() {
         let mut out = ::std::io::sink();
@@ -1267,49 +3935,1603 @@ mod test {
     }
 
     #[test]
-    fn prompt_with_ansi_escape_codes() {
-        let pos = super::calculate_position("\x1b[1;32m>>\x1b[0m ", Position::default(), 80);
-        assert_eq!(3, pos.col);
-        assert_eq!(0, pos.row);
+    fn readlines_until() {
+        use error::ReadlineError;
+
+        let mut editor = init_editor(&[]);
+        let script = "s\nEnter\nf\nEnter\n;\nEnter\nx\nEnter\nCtrl-D\n";
+        editor.set_input_script(Some(Script::parse(script).unwrap()));
+        assert_eq!("s\nf", editor.readlines_until(">>", ";").unwrap());
+        // the lines read before the end of the input
+        assert_eq!("x", editor.readlines_until(">>", ";").unwrap());
+        match editor.readlines_until(">>", ";") {
+            Err(ReadlineError::Eof) => {}
+            result => panic!("{:?}", result),
+        }
     }
 
-    fn assert_line(keys: &[KeyPress], expected_line: &str) {
-        let mut editor = init_editor(keys);
-        let actual_line = editor.readline(&">>").unwrap();
-        assert_eq!(expected_line, actual_line);
+    #[test]
+    fn complete_search_match() {
+        let mut editor = Editor::<SimpleCompleter>::new();
+        editor.set_completer(Some(SimpleCompleter));
+        editor.term.keys.extend_from_slice(&[ctrl!('R'),
+                                             key!('g'),
+                                             key!('o'),
+                                             key!(Key::Tab),
+                                             key!(Key::Enter)]);
+        editor.add_history_entry("cargo tes");
+        editor.add_history_entry("ls");
+        assert_eq!("cargo test", editor.readline(">>").unwrap());
+    }
+
+    #[cfg(feature = "async")]
+    struct SlowCompleter;
+    #[cfg(feature = "async")]
+    impl ::background::AsyncCompleter for SlowCompleter {
+        fn complete(&self,
+                    line: &str,
+                    _: usize,
+                    _: &::background::Cancellation)
+                    -> Result<(usize, Vec<String>)> {
+            ::std::thread::sleep(::std::time::Duration::from_millis(200));
+            Ok((0, vec![line.to_string() + "t"]))
+        }
     }
 
     #[test]
-    fn delete_key() {
-        assert_line(&[key!('a'), key!(Key::Delete), key!(Key::Enter)],
-                    "a");
-        assert_line(&[key!('a'), key!(Key::Left), key!(Key::Delete), key!(Key::Enter)],
-                    "");
+    #[cfg(feature = "async")]
+    fn async_completion() {
+        let mut out = Vec::new();
+        {
+            let mut s = init_state(&mut out, "rus", 3, 80);
+            // no key typed while the candidates are fetched
+            let keys: &[KeyPress] = &[];
+            let mut rdr = keys.iter();
+            let completer = ::background::AsyncCompletion::new(SlowCompleter);
+            let config = Config::builder().completion_type(::CompletionType::List).build();
+            let key = super::complete_line(&mut rdr, &mut s, &completer, &config).unwrap();
+            assert_eq!(None, key);
+            assert_eq!("rust", s.line.as_str());
+            assert!(s.menu.is_empty());
+        }
+        assert!(String::from_utf8(out).unwrap().contains("| Completing..."));
     }
 
     #[test]
-    fn down_key() {
-        assert_line(&[key!(Key::Down), key!(Key::Enter)], "");
+    fn refresh_with_description() {
+        let mut out: Vec<u8> = Vec::new();
+        {
+            let mut s = init_state(&mut out, "sum(", 4, 10);
+            s.set_description(Some(String::from("sum(iterable, start=0)"))).unwrap();
+            assert_eq!(1, s.old_rows);
+            assert_eq!(0, s.cursor.row);
+            assert_eq!(4, s.cursor.col);
+            s.set_description(None).unwrap();
+            assert_eq!(0, s.old_rows);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("sum(\nsum(itera\x1b[1A"));
     }
 
     #[test]
-    fn end_key() {
-        assert_line(&[key!(Key::End), key!(Key::Enter)], "");
+    fn refresh_with_message() {
+        let mut out: Vec<u8> = Vec::new();
+        {
+            let mut s = init_state(&mut out, "ls", 2, 10);
+            s.description = Some(String::from("help"));
+            s.message = Some(String::from("no such file"));
+            s.refresh_line().unwrap();
+            assert_eq!(2, s.old_rows);
+            s.clear_below().unwrap();
+            assert_eq!(0, s.old_rows);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("ls\nno such f\nhelp\x1b[2A"));
     }
 
     #[test]
-    fn home_key() {
-        assert_line(&[key!(Key::Home), key!(Key::Enter)], "");
+    fn shadowed_bindings() {
+        let mut editor = init_editor(&[]);
+        assert!(editor.shadowed_bindings().is_empty());
+        editor.bind_sequence(ctrl!('T'), Cmd::TransposeWords);
+        let shadowed = editor.shadowed_bindings();
+        assert_eq!(1, shadowed.len());
+        assert_eq!("Ctrl-T", shadowed[0].name());
+        assert_eq!(Some(Cmd::TransposeChars), shadowed[0].shadowed);
+        assert!(editor.bindings().iter().any(|b| b.name() == "Meta-T"));
     }
 
     #[test]
-    fn left_key() {
-        assert_line(&[key!(Key::Left), key!(Key::Enter)], "");
+    fn visible_bell() {
+        let mut out: Vec<u8> = Vec::new();
+        {
+            let mut s = init_state(&mut out, "ls", 2, 10);
+            s.set_prompt("> ");
+            s.bell_style = BellStyle::Visible;
+            s.beep().unwrap();
+            assert!(!s.flash);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\x1b[7m> \x1b[27mls"));
     }
 
     #[test]
-    fn meta_backspace_key() {
-        assert_line(&[alt!('\x08'), key!(Key::Enter)], "");
+    fn refresh_masked() {
+        let mut out: Vec<u8> = Vec::new();
+        {
+            let mut s = init_state(&mut out, "pa", 2, 10);
+            s.mask = Some('*');
+            super::edit_insert(&mut s, 'ß').unwrap();
+            assert_eq!(3, s.cursor.col);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("***"));
+        assert!(!out.contains("pa"));
+    }
+
+    #[test]
+    fn max_line_len() {
+        let keys = [key!('a'), key!('b'), key!('c'), ctrl!('Y'), key!(Key::Enter)];
+        let mut editor = init_editor(&keys);
+        editor.reload_config(Config::builder().max_line_len(2).build());
+        assert_eq!("ab", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn readline_with() {
+        use super::Overrides;
+
+        let mut editor = init_editor(&[key!(Key::Up), key!(Key::Enter)]);
+        editor.add_history_entry("ls");
+        let overrides = Overrides { no_history: true, ..Overrides::default() };
+        assert_eq!("", editor.readline_with(">>", overrides).unwrap());
+        assert_eq!(1, editor.get_history().len());
+
+        editor.term.keys = vec![key!('p'), key!('w'), ctrl!('U'), key!('x'), key!(Key::Enter)];
+        let overrides = Overrides { mask: Some('*'), ..Overrides::default() };
+        assert_eq!("x", editor.readline_with(">>", overrides).unwrap());
+        // the password was not killed into the editor's kill ring
+        editor.term.keys = vec![key!(Key::Up), ctrl!('Y'), key!(Key::Enter)];
+        assert_eq!("ls", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn switch_context() {
+        let mut editor = init_editor(&[key!(Key::Up), key!(Key::Enter)]);
+        editor.add_history_entry("break main");
+        editor.bind_sequence(key!(Key::Up), Cmd::BeginningOfHistory);
+
+        editor.switch_context("eval");
+        assert_eq!("eval", editor.context());
+        assert_eq!(0, editor.get_history().len());
+        assert!(editor.shadowed_bindings().is_empty());
+        editor.add_history_entry("1 + 1");
+        assert_eq!("1 + 1", editor.readline(">>").unwrap());
+
+        editor.switch_context(super::DEFAULT_CONTEXT);
+        assert_eq!(Some(Cmd::BeginningOfHistory), editor.keymap.unbind(key!(Key::Up)));
+        assert_eq!("break main", editor.readline(">>").unwrap());
+        assert!(editor.remove_context("eval"));
+        assert!(!editor.remove_context(super::DEFAULT_CONTEXT));
+    }
+
+    #[test]
+    fn refresh_accessible() {
+        use accessible::Echo;
+
+        let mut out: Vec<u8> = Vec::new();
+        {
+            let mut s = init_state(&mut out, "ls", 2, 10);
+            s.echo = Some(Echo::new());
+            s.refresh_line().unwrap();
+            super::edit_insert(&mut s, 'x').unwrap();
+            s.message = Some(String::from("no such file"));
+            s.refresh_line().unwrap();
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!("lsx\r\nno such file\r\nlsx", out);
+    }
+
+    #[test]
+    fn reported_errors() {
+        use error::ReadlineError;
+
+        let config = Config::builder().interrupt_with_line(true).report_suspend(true).build();
+        let mut editor = Editor::<()>::with_config(config.clone());
+        editor.term.keys.extend_from_slice(&[key!('l'), key!('s'), ctrl!('C')]);
+        match editor.readline(">>") {
+            Err(ReadlineError::InterruptedWithLine(line)) => assert_eq!("ls", line),
+            result => panic!("{:?}", result),
+        }
+        let mut editor = Editor::<()>::with_config(config);
+        editor.term.keys.push(ctrl!('C'));
+        match editor.readline(">>") {
+            Err(ReadlineError::Interrupted) => {}
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reported_suspend() {
+        use error::ReadlineError;
+
+        let config = Config::builder().report_suspend(true).build();
+        let mut editor = Editor::<()>::with_config(config);
+        editor.term.keys.push(ctrl!('Z'));
+        match editor.readline(">>") {
+            Err(ReadlineError::Suspended) => {}
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn show_message() {
+        let mut editor = init_editor(&[key!('a'), ctrl!('O'), key!('b'), key!(Key::Enter)]);
+        let messages = editor.message_line();
+        editor.add_command("fail", move |_: KeyPress, _: &mut LineBuffer| {
+            messages.show("failed");
+            false
+        });
+        editor.bind_sequence(ctrl!('O'), Cmd::User(String::from("fail")));
+        editor.show_message("welcome");
+        assert_eq!("ab", editor.readline(">>").unwrap());
+        assert_eq!(None, editor.message_line().take());
+        // displayed below the line, then erased before the next key is rendered
+        let out = editor.term.output.take();
+        let welcome = out.find(">>\nwelcome").unwrap();
+        let failed = out.find(">>a\nfailed").unwrap();
+        let erase = "\x1b[1B\r\x1b[0K";
+        assert!(out[welcome..failed].contains(erase));
+        let end = out.find(">>ab").unwrap();
+        assert!(out[failed..end].contains(erase));
+        assert_eq!(1, out.matches("welcome").count());
+        assert_eq!(1, out.matches("failed").count());
+    }
+
+    #[test]
+    fn history_kept_on_panic() {
+        let mut editor = init_editor(&[ctrl!('O')]);
+        editor.add_history_entry("ls");
+        editor.add_command("panic", |_: KeyPress, _: &mut LineBuffer| -> bool { panic!("hook") });
+        editor.bind_sequence(ctrl!('O'), Cmd::User(String::from("panic")));
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            editor.readline(">>")
+        }));
+        assert!(result.is_err());
+        assert_eq!(Some(&String::from("ls")), editor.get_history().get(0));
+    }
+
+    #[test]
+    fn status_bar() {
+        let mut editor = init_editor(&[key!('a'), ctrl!('O'), key!('b'), key!(Key::Enter)]);
+        editor.set_status_bar(vec![String::from("[INSERT]")]);
+        let status = editor.status_bar();
+        editor.add_command("connect", move |_: KeyPress, _: &mut LineBuffer| {
+            status.set(vec![String::from("[INSERT]"), String::from("connected")]);
+            true
+        });
+        editor.bind_sequence(ctrl!('O'), Cmd::User(String::from("connect")));
+        assert_eq!("ab", editor.readline(">>").unwrap());
+        // displayed during the edit
+        assert_eq!(None, editor.status_bar().take_changed());
+        assert_eq!(2, editor.status_bar().rows().len());
+    }
+
+    #[test]
+    fn pager() {
+        let mut out = ::std::io::sink();
+        let mut s = init_state(&mut out, "", 0, 80);
+        let lines: Vec<String> = (0..50).map(|i| i.to_string()).collect();
+        let keys = &[key!(' '), key!(Key::Enter), key!('q')];
+        let mut rdr = keys.iter();
+        super::pager::page(&mut rdr, &mut s, &lines, 500).unwrap();
+        assert!(s.menu.is_empty());
+        assert_eq!(0, s.old_rows);
+        assert!(rdr.next().is_none());
+    }
+
+    struct BoldHighlighter;
+    impl Highlighter for BoldHighlighter {
+        fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+            Cow::Owned(Style::new().bold().fg(Color::Rgb(255, 0, 0)).paint(line))
+        }
+    }
+
+    #[test]
+    fn refresh_with_highlighter() {
+        let mut out = Vec::new();
+        {
+            let highlighter = BoldHighlighter;
+            let mut s = init_state(&mut out, "line", 4, 80);
+            s.highlighter = Some(&highlighter);
+            s.refresh_line().unwrap();
+            assert_eq!(4, s.cursor.col);
+        }
+        let out = String::from_utf8(out).unwrap();
+        // downgraded to the 256 colors of the dummy terminal
+        assert!(out.contains("\x1b[1;38;5;196mline\x1b[0m"));
+    }
+
+    struct CountingHighlighter(Cell<usize>);
+    impl Highlighter for CountingHighlighter {
+        fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+            self.0.set(self.0.get() + 1);
+            Cow::Borrowed(line)
+        }
+        fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+            Cow::Owned(Style::new().dim().paint(hint))
+        }
+    }
+
+    /// Buffers of the refresh output, of the copy of the line and of its highlighting.
+    fn refresh_buffers(s: &State) -> (*const u8, usize, *const u8, *const u8) {
+        (s.scratch.as_ptr(),
+         s.scratch.capacity(),
+         s.rendered.line.as_ptr(),
+         s.rendered.highlighted.as_ref().unwrap().as_ptr())
+    }
+
+    #[test]
+    fn refresh_allocations() {
+        let mut out = ::std::io::sink();
+        let highlighter = CountingHighlighter(Cell::new(0));
+        let mut s = init_state(&mut out, "echo hello", 10, 80);
+        s.highlighter = Some(&highlighter);
+        // the buffers are allocated by the first refresh, then reused
+        s.refresh_line().unwrap();
+        let buffers = refresh_buffers(&s);
+        for _ in 0..5 {
+            s.line.move_left();
+            s.refresh_line().unwrap();
+            assert_eq!(buffers, refresh_buffers(&s));
+        }
+        s.line.backspace();
+        s.line.insert('x');
+        s.refresh_line().unwrap();
+        assert_eq!(buffers, refresh_buffers(&s));
+        assert_eq!(7, highlighter.0.get());
+    }
+
+    struct PrefixHinter;
+    impl Hinter for PrefixHinter {
+        fn hint(&self, line: &str, pos: usize, _: &HistoryBackend) -> Option<String> {
+            if pos == line.len() && "history".starts_with(line) {
+                Some(String::from(&"history"[pos..]))
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn refresh_with_hint() {
+        let mut out = Vec::new();
+        let highlighter = CountingHighlighter(Cell::new(0));
+        let history: RefCell<Box<HistoryBackend>> = RefCell::new(Box::new(History::new()));
+        {
+            let mut s = init_state(&mut out, "hist", 4, 80);
+            s.highlighter = Some(&highlighter);
+            s.hinter = Some(&PrefixHinter);
+            s.history = Some(&history);
+            s.refresh_line().unwrap();
+            assert_eq!(4, s.cursor.col);
+            // nothing changed: no new call
+            s.refresh_line().unwrap();
+            assert_eq!(1, highlighter.0.get());
+            s.line.move_left();
+            s.refresh_line().unwrap();
+            assert_eq!(2, highlighter.0.get());
+            assert_eq!(None, s.rendered.hint);
+            s.line.move_end();
+            s.refresh_line().unwrap();
+            s.clear_below().unwrap();
+            assert_eq!(None, s.rendered.hint);
+            // the hinter is kept
+            s.refresh_line().unwrap();
+            assert_eq!(Some(String::from("ory")), s.rendered.hint);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("hist\x1b[2mory\x1b[0m"));
+    }
+
+    struct GitHinter;
+    impl Hinter for GitHinter {
+        fn hint(&self, line: &str, pos: usize, _: &HistoryBackend) -> Option<String> {
+            let command = "git commit --amend";
+            if pos == line.len() && pos < command.len() && command.starts_with(line) {
+                Some(String::from(&command[pos..]))
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn accept_hint() {
+        let mut editor = init_editor(&[key!('g'), key!(Key::Right), key!(Key::Enter)]);
+        editor.set_hinter(Some(Box::new(GitHinter)));
+        assert_eq!("git commit --amend", editor.readline(">>").unwrap());
+
+        let mut editor = init_editor(&[key!('g'), alt!('F'), alt!('F'), key!(Key::Enter)]);
+        editor.set_hinter(Some(Box::new(GitHinter)));
+        assert_eq!("git commit", editor.readline(">>").unwrap());
+
+        // Right moves the cursor when it is not at the end of the line
+        let mut editor = init_editor(&[key!('g'),
+                                       key!(Key::Left),
+                                       key!(Key::Right),
+                                       key!('i'),
+                                       key!(Key::Enter)]);
+        editor.set_hinter(Some(Box::new(GitHinter)));
+        assert_eq!("gi", editor.readline(">>").unwrap());
+
+        let mut editor = init_editor(&[key!('g'), key!(Key::Right), key!(Key::Enter)]);
+        editor.set_hinter(Some(Box::new(GitHinter)));
+        editor.bind_hint_sequence(key!(Key::Right), Cmd::Noop);
+        assert_eq!("g", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn history_hinter() {
+        let mut editor = init_editor(&[key!('l'), key!(Key::End), key!(Key::Enter)]);
+        editor.set_hinter(Some(Box::new(HistoryHinter::new())));
+        editor.add_history_entry("ls -l");
+        assert_eq!("ls -l", editor.readline(">>").unwrap());
+        assert_eq!(1, editor.history.len());
+    }
+
+    #[test]
+    fn color_mode() {
+        let config = Config::builder().color_mode(ColorMode::Disabled).build();
+        assert!(!Editor::<()>::with_config(config).colors_enabled());
+        let config = Config::builder().color_mode(ColorMode::Forced).build();
+        assert!(Editor::<()>::with_config(config).colors_enabled());
+    }
+
+    #[test]
+    fn term_support() {
+        let mut editor = Editor::<()>::new();
+        assert!(!editor.is_dumb_terminal());
+        assert!(editor.remove_unsupported_term("emacs"));
+        assert!(!editor.remove_unsupported_term("emacs"));
+        editor.reload_config(Config::builder().term_support(TermSupport::Unsupported).build());
+        assert!(editor.is_dumb_terminal());
+        editor.reload_config(Config::builder().term_support(TermSupport::Supported).build());
+        assert!(!editor.is_dumb_terminal());
+    }
+
+    #[test]
+    fn refresh_with_status_bar() {
+        let mut out = Vec::new();
+        {
+            let mut s = init_state(&mut out, "line", 4, 80);
+            s.description = Some(String::from("help"));
+            s.status = vec![String::from("[INSERT]"), String::from("connected")];
+            s.refresh_line().unwrap();
+            assert_eq!(3, s.old_rows);
+            assert_eq!(0, s.cursor.row);
+            s.clear_below().unwrap();
+            assert_eq!(0, s.old_rows);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("line\nhelp\n[INSERT]\nconnected"));
+    }
+
+    #[test]
+    fn truncate_to_width() {
+        assert_eq!("abc", super::truncate_to_width("abc", 4));
+        assert_eq!("ab", super::truncate_to_width("abc", 3));
+        assert_eq!("a", super::truncate_to_width("a\nbc", 4));
+        assert_eq!("", super::truncate_to_width("\u{3b1}", 1));
+    }
+
+    #[test]
+    fn fit_prompt() {
+        let truncate = LongPrompt::Truncate;
+        assert_eq!(">> ", super::fit_prompt(">> ", 4, truncate));
+        assert_eq!("\u{2026}", super::fit_prompt(">> ", 3, truncate));
+        assert_eq!("", super::fit_prompt(">> ", 1, truncate));
+        assert_eq!("pro\u{2026}", super::fit_prompt("\x1b[1;32mprompt>\x1b[0m ", 8, truncate));
+        assert_eq!("a\nlong prompt> ", super::fit_prompt("a\nlong prompt> ", 8, truncate));
+        assert_eq!("prompt> ", super::fit_prompt("prompt> ", 4, LongPrompt::Wrap));
+    }
+
+    #[test]
+    fn tiny_terminals() {
+        for cols in 1..11 {
+            let mut out = ::std::io::sink();
+            let mut s = init_state(&mut out, "", 0, cols);
+            s.set_prompt("prompt> ");
+            s.cursor = s.prompt_size;
+            assert!(s.prompt_size.col < cols);
+            for c in "a\u{3b1}\u{4e00}bc".chars() {
+                super::edit_insert(&mut s, c).unwrap();
+                assert!(s.cursor.col <= cols);
+            }
+            s.menu.push(String::from("a menu row"));
+            super::edit_move_home(&mut s).unwrap();
+            assert_eq!(s.prompt_size.col, s.cursor.col);
+            super::edit_move_end(&mut s).unwrap();
+            super::edit_backspace(&mut s).unwrap();
+            assert_eq!("a\u{3b1}\u{4e00}b", s.line.as_str());
+            s.clear_below().unwrap();
+        }
+    }
+
+    #[test]
+    fn prompt_variables() {
+        use template::PromptVariables;
+
+        let count = Cell::new(0);
+        let mut variables = PromptVariables::new();
+        variables.set("n",
+                      Some(Box::new(move || {
+                          count.set(count.get() + 1);
+                          count.get().to_string()
+                      })));
+        let mut out = Vec::new();
+        {
+            let mut s = init_state(&mut out, "ls", 2, 80);
+            s.variables = Some(&variables);
+            s.set_prompt("[{n}]> ");
+            assert_eq!(5, s.prompt_size.col);
+            // evaluated again on each refresh
+            s.refresh_line().unwrap();
+            assert_eq!("[2]> ", s.prompt);
+            assert_eq!(7, s.cursor.col);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("[2]> ls"));
+    }
+
+    #[test]
+    fn wrapped_prompt() {
+        let mut out = Vec::new();
+        {
+            let mut s = init_state(&mut out, "", 0, 10);
+            s.set_prompt("a long prompt> ");
+            s.set_long_prompt(LongPrompt::Wrap);
+            assert_eq!(1, s.prompt_size.row);
+            assert_eq!(5, s.prompt_size.col);
+            for c in "abcdef".chars() {
+                super::edit_insert(&mut s, c).unwrap();
+            }
+            assert_eq!(2, s.cursor.row);
+            assert_eq!(1, s.cursor.col);
+            super::edit_move_home(&mut s).unwrap();
+            assert_eq!(1, s.cursor.row);
+            assert_eq!(5, s.cursor.col);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("a long prompt> abcdef"));
+    }
+
+    #[test]
+    fn tabs() {
+        let mut out = Vec::new();
+        {
+            let mut s = init_state(&mut out, "a\tb\t", 2, 80);
+            s.refresh_line().unwrap();
+            assert_eq!(5, s.cursor.col);
+            super::edit_move_end(&mut s).unwrap();
+            assert_eq!(10, s.cursor.col);
+            s.tab_display = TabDisplay::Caret;
+            s.refresh_line().unwrap();
+            assert_eq!(6, s.cursor.col);
+            s.tab_display = TabDisplay::Spaces(8);
+            super::edit_move_home(&mut s).unwrap();
+            super::edit_move_end(&mut s).unwrap();
+            assert_eq!(18, s.cursor.col);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("a    b    "));
+        assert!(out.contains("a^Ib^I"));
+        assert!(!out.contains('\t'));
+    }
+
+    #[test]
+    fn control_chars() {
+        let mut out = Vec::new();
+        {
+            let mut s = init_state(&mut out, "a\x03b\x1b\x7f\u{9b}", 2, 80);
+            s.refresh_line().unwrap();
+            assert_eq!(3, s.cursor.col);
+            super::edit_move_end(&mut s).unwrap();
+            assert_eq!(12, s.cursor.col);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("a\x1b[7m^C\x1b[27mb\x1b[7m^[\x1b[27m"));
+        assert!(out.contains("\x1b[7m^?\x1b[27m\x1b[7mM-^[\x1b[27m"));
+
+        // the control char is returned unchanged
+        let mut editor = init_editor(&[key!('a'), ctrl!('O'), key!(Key::Enter)]);
+        editor.bind_sequence(ctrl!('O'), Cmd::SelfInsert('\x03'));
+        assert_eq!("a\x03", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn search_match() {
+        let mut out = Vec::new();
+        {
+            let mut s = init_state(&mut out, "cd; ls -l", 4, 80);
+            s.search_match = Some((4, 6));
+            s.refresh_prompt_and_line("(reverse-i-search 2/3)`ls': ").unwrap();
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("`ls': cd; \x1b[1mls\x1b[22m -l"));
+    }
+
+    #[test]
+    fn strip_ansi() {
+        assert_eq!("ls", super::strip_ansi("ls"));
+        assert_eq!("ls -l", super::strip_ansi("\x1b[1;32mls\x1b[0m -l"));
+        assert_eq!("link", super::strip_ansi("\x1b]8;;http://a\x1b\\link\x1b]8;;\x07"));
+        assert_eq!("a", super::strip_ansi("\x1b=a\x1b["));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bracketed_paste() {
+        fn keys(text: &str) -> Vec<KeyPress> {
+            let mut keys = vec![key!(Key::BracketedPaste)];
+            keys.extend(text.chars().map(|c| match c {
+                '\x1b' => key!(Key::Esc),
+                '\r' => key!(Key::Enter),
+                c => key!(c),
+            }));
+            keys.push(key!(Key::Enter));
+            keys
+        }
+        let pasted = keys("\x1b[32mecho\x1b[0m a\rb\x1b[201~");
+        let mut editor = init_editor(&pasted);
+        assert_eq!("\x1b[32mecho\x1b[0m a\nb", editor.readline(">>").unwrap());
+        let config = Config::builder().bracketed_paste(true).strip_pasted_ansi(true).build();
+        editor.reload_config(config);
+        assert_eq!("echo a\nb", editor.readline(">>").unwrap());
+
+        editor.set_paste_filter(None);
+        assert_eq!("echo a\rb", editor.readline(">>").unwrap());
+        editor.set_paste_filter(Some(Box::new(|text: &str| text.replace('\r', " && "))));
+        assert_eq!("echo a && b", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn confirm_paste() {
+        let mut editor = init_editor(&[]);
+        editor.reload_config(Config::builder().confirm_paste(true).build());
+        // the first Enter is pasted with the command, the second one is typed
+        editor.set_input_script(Some(Script::parse("l\ns\nEnter\n+50 Enter\n").unwrap()));
+        assert_eq!("ls\n", editor.readline(">>").unwrap());
+        editor.reload_config(Config::default());
+        editor.set_input_script(Some(Script::parse("l\ns\nEnter\n").unwrap()));
+        assert_eq!("ls", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn prompt_with_ansi_escape_codes() {
+        let pos = super::calculate_position("\x1b[1;32m>>\x1b[0m ", Position::default(), 80);
+        assert_eq!(3, pos.col);
+        assert_eq!(0, pos.row);
+    }
+
+    fn assert_line(keys: &[KeyPress], expected_line: &str) {
+        let mut editor = init_editor(keys);
+        let actual_line = editor.readline(&">>").unwrap();
+        assert_eq!(expected_line, actual_line);
+    }
+
+    #[test]
+    fn delete_key() {
+        assert_line(&[key!('a'), key!(Key::Delete), key!(Key::Enter)],
+                    "a");
+        assert_line(&[key!('a'), key!(Key::Left), key!(Key::Delete), key!(Key::Enter)],
+                    "");
+    }
+
+    #[test]
+    fn down_key() {
+        assert_line(&[key!(Key::Down), key!(Key::Enter)], "");
+    }
+
+    #[test]
+    fn end_key() {
+        assert_line(&[key!(Key::End), key!(Key::Enter)], "");
+    }
+
+    #[test]
+    fn home_key() {
+        assert_line(&[key!(Key::Home), key!(Key::Enter)], "");
+    }
+
+    #[test]
+    fn left_key() {
+        assert_line(&[key!(Key::Left), key!(Key::Enter)], "");
+    }
+
+    #[test]
+    fn meta_backspace_key() {
+        assert_line(&[alt!('\x08'), key!(Key::Enter)], "");
+    }
+
+    struct SignatureDescriptor;
+    impl Descriptor for SignatureDescriptor {
+        fn describe(&self, line: &str, pos: usize) -> Option<String> {
+            if line[..pos].ends_with('(') {
+                Some(String::from("f(x)"))
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn descriptor() {
+        let mut editor = init_editor(&[key!('f'), key!('('), key!(')'), key!(Key::Enter)]);
+        editor.set_descriptor(Some(Box::new(SignatureDescriptor)));
+        assert_eq!("f()", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn custom_binding() {
+        let mut editor = init_editor(&[key!('a'), key!(Key::PageUp), key!('b'), key!(Key::Enter)]);
+        editor.bind_sequence(key!(Key::PageUp), Cmd::BeginningOfLine);
+        assert_eq!("ba", editor.readline(">>").unwrap());
+    }
+
+    struct ParenHandler;
+    impl KeyHandler for ParenHandler {
+        fn handle(&self, key: KeyPress, line: &mut LineBuffer) -> bool {
+            if line.char_at_cursor().is_some() {
+                return false;
+            }
+            match key {
+                key!('(') => line.split_insert("(", ")").is_some(),
+                _ => false,
+            }
+        }
+    }
+
+    #[test]
+    fn key_handler() {
+        let mut editor = init_editor(&[key!('f'), key!('('), key!('x'), key!('('), key!(Key::Enter)]);
+        editor.bind_handler(key!('('), Box::new(ParenHandler));
+        assert_eq!("f(x()", editor.readline(">>").unwrap());
+        editor.unbind_handler(key!('('));
+    }
+
+    #[test]
+    fn auto_pair() {
+        let keys = [key!('f'), key!('('), key!('['), key!(Key::Backspace), key!('x'), key!(')'),
+                    key!(Key::Enter)];
+        let config = Config::builder().auto_pair(true).build();
+        let mut editor = Editor::<()>::with_config(config);
+        editor.term.keys.extend_from_slice(&keys);
+        assert_eq!("f(x)", editor.readline(">>").unwrap());
+
+        let mut editor = init_editor(&keys);
+        assert_eq!("f(x)", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn check_balance() {
+        let config = Config::builder().check_balance(true).build();
+        let mut editor = Editor::<()>::with_config(config.clone());
+        editor.term.keys.extend_from_slice(&[key!('('),
+                                             key!(Key::Enter),
+                                             key!(')'),
+                                             key!(Key::Enter)]);
+        assert_eq!("()", editor.readline(">>").unwrap());
+
+        let mut editor = Editor::<()>::with_config(config);
+        editor.term.keys.extend_from_slice(&[key!('['), alt!(Key::Enter)]);
+        assert_eq!("[", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn region() {
+        let mut editor = init_editor(&[key!('a'),
+                                       key!('b'),
+                                       key!(Key::Null),
+                                       ctrl!('A'),
+                                       alt!('W'),
+                                       ctrl!('E'),
+                                       ctrl!('Y'),
+                                       key!(Key::Enter)]);
+        assert_eq!("abab", editor.readline(">>").unwrap());
+
+        let mut editor = init_editor(&[key!('a'),
+                                       key!('b'),
+                                       ctrl!('A'),
+                                       key!(Key::Null),
+                                       ctrl!('F'),
+                                       ctrl!('X'),
+                                       ctrl!('X'),
+                                       ctrl!('W'),
+                                       ctrl!('E'),
+                                       ctrl!('Y'),
+                                       key!(Key::Enter)]);
+        editor.bind_sequence(ctrl!('W'), Cmd::KillRegion);
+        assert_eq!("ba", editor.readline(">>").unwrap());
+
+        let mut out = ::std::io::sink();
+        let mut s = init_state(&mut out, "abc", 1, 80);
+        s.mark = Some(3);
+        let mut ab = String::new();
+        s.render_line(&mut ab);
+        assert_eq!("a\x1b[7mbc\x1b[27m", ab);
+    }
+
+    #[test]
+    fn multi_line_moves() {
+        let config = Config::builder().line_wise_home_end(true).build();
+        let mut editor = Editor::<()>::with_config(config);
+        editor.bind_sequence(alt!('J'), Cmd::SelfInsert('\n'));
+        editor.term.keys.extend_from_slice(&[key!('a'),
+                                             alt!('J'),
+                                             key!('b'),
+                                             key!('c'),
+                                             key!(Key::Up),
+                                             key!('x'),
+                                             key!(Key::Down),
+                                             ctrl!('A'),
+                                             key!('y'),
+                                             key!(Key::Enter)]);
+        assert_eq!("ax\nybc", editor.readline(">>").unwrap());
+
+        let keys =
+            [key!('a'), alt!('J'), key!('b'), key!(Key::Up), key!(Key::Up), key!(Key::Enter)];
+        for &(up_down, line) in &[(UpDown::Lines, "a\nb"),
+                                  (UpDown::History, "ls"),
+                                  (UpDown::HistoryAtEdges, "ls")] {
+            let config = Config::builder().up_down(up_down).build();
+            let mut editor = Editor::<()>::with_config(config);
+            editor.add_history_entry("ls");
+            editor.bind_sequence(alt!('J'), Cmd::SelfInsert('\n'));
+            editor.term.keys.extend_from_slice(&keys);
+            assert_eq!(line, editor.readline(">>").unwrap());
+        }
+    }
+
+    #[test]
+    fn line_continuation() {
+        let keys = [key!('a'), key!('\\'), key!(Key::Enter), key!('b'), key!(Key::Enter)];
+        for &(continuation, line) in &[(LineContinuation::Disabled, "a\\"),
+                                       (LineContinuation::Strip, "a\nb"),
+                                       (LineContinuation::Keep, "a\\\nb")] {
+            let config = Config::builder().line_continuation(continuation).build();
+            let mut editor = Editor::<()>::with_config(config);
+            editor.term.keys.extend_from_slice(&keys);
+            assert_eq!(line, editor.readline(">>").unwrap());
+        }
+        assert!(!super::is_continued("a\\\\"));
+        assert!(super::is_continued("a\\\\\\"));
+    }
+
+    struct AutoPairLoader;
+    impl ConfigLoader for AutoPairLoader {
+        fn load(&self) -> Option<Config> {
+            Some(Config::builder().auto_pair(true).build())
+        }
+    }
+
+    #[test]
+    fn reload_config() {
+        let mut editor = init_editor(&[ctrl!('X'), ctrl!('R'), key!('('), key!(Key::Enter)]);
+        editor.set_config_loader(Some(Box::new(AutoPairLoader)));
+        assert_eq!("()", editor.readline(">>").unwrap());
+        assert!(editor.config.auto_pair());
+
+        editor.reload_config(Config::default());
+        assert!(!editor.config.auto_pair());
+    }
+
+    #[test]
+    fn output_stream() {
+        let config = Config::builder().output_stream(OutputStreamType::Stderr).build();
+        let mut editor = Editor::<()>::with_config(config);
+        assert_eq!(OutputStreamType::Stderr, editor.term.output_stream());
+        editor.term.keys.extend_from_slice(&[key!('a'), key!(Key::Enter)]);
+        assert_eq!("a", editor.readline(">>").unwrap());
+
+        editor.reload_config(Config::default());
+        assert_eq!(OutputStreamType::Stdout, editor.term.output_stream());
+    }
+
+    #[test]
+    fn dabbrev_expand() {
+        let mut editor = init_editor(&[key!('r'),
+                                       key!('u'),
+                                       alt!('/'),
+                                       alt!('/'),
+                                       key!(Key::Enter)]);
+        editor.add_history_entry("ruby");
+        editor.add_history_entry("rust rustc");
+        assert_eq!("rust", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn insert_filter() {
+        use filter::AsciiOnly;
+
+        let mut editor = init_editor(&[key!('h'), key!('é'), key!('1'), key!(Key::Enter)]);
+        editor.set_insert_filter(Some(Box::new(AsciiOnly)));
+        assert_eq!("h1", editor.readline(">>").unwrap());
+
+        let upper = |_: &str, _: usize, ch: char| ch.to_uppercase().next();
+        editor.set_insert_filter(Some(Box::new(upper)));
+        assert_eq!("HÉ1", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn idle_hook() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use idle::Idle;
+
+        let mut editor = init_editor(&[]);
+        editor.reload_config(Config::builder().idle_timeout(20).build());
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        editor.set_idle_hook(Some(Box::new(move |line: &str, _: usize| {
+            counter.set(counter.get() + 1);
+            Idle::Status(vec![format!("{} {}", line, counter.get())])
+        })));
+        editor.set_input_script(Some(Script::parse("a\n+100 b\nEnter\n").unwrap()));
+        assert_eq!("ab", editor.readline(">>").unwrap());
+        // the delay of the script is waited by steps of the timeout
+        assert_eq!(4, calls.get());
+        // no delay, no idle time
+        calls.set(0);
+        editor.set_input_script(Some(Script::parse("c\nEnter\n").unwrap()));
+        assert_eq!("c", editor.readline(">>").unwrap());
+        assert_eq!(0, calls.get());
+    }
+
+    #[test]
+    fn abort() {
+        let keys = [key!('a'),
+                    key!('g'),
+                    ctrl!('G'),
+                    ctrl!('X'),
+                    ctrl!('G'),
+                    key!('b'),
+                    key!(Key::Enter)];
+        let mut editor = init_editor(&keys);
+        editor.bind_keys(&[key!('g'), key!('g')], Cmd::BeginningOfLine);
+        // the pending `g` is dropped
+        assert_eq!("ab", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn key_sequence() {
+        let keys = [key!('a'),
+                    key!('g'),
+                    key!('g'),
+                    key!('b'),
+                    key!('g'),
+                    key!('x'),
+                    key!(Key::Enter)];
+        let mut editor = init_editor(&keys);
+        editor.bind_keys(&[key!('g'), key!('g')], Cmd::BeginningOfLine);
+        assert_eq!("bgxa", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn user_command() {
+        let mut editor = init_editor(&[key!('a'), alt!('D'), ctrl!('O'), key!(Key::Enter)]);
+        editor.add_command("insert-date", |_: KeyPress, line: &mut LineBuffer| {
+            line.yank(" 2017-01-01").is_some()
+        });
+        editor.add_command("upcase-line", |_: KeyPress, line: &mut LineBuffer| {
+            let upper = line.as_str().to_uppercase();
+            let pos = line.pos();
+            line.update(&upper, pos);
+            true
+        });
+        editor.bind_sequence(alt!('D'), Cmd::User(String::from("insert-date")));
+        editor.bind_sequence(ctrl!('O'), Cmd::User(String::from("upcase-line")));
+        assert_eq!("A 2017-01-01", editor.readline(">>").unwrap());
+
+        let mut editor = init_editor(&[ctrl!('O'), key!(Key::Enter)]);
+        editor.bind_sequence(ctrl!('O'), Cmd::User(String::from("no-such-command")));
+        assert_eq!("", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn suspend_raw() {
+        let mut editor = init_editor(&[key!('a'), ctrl!('O'), key!('b'), key!(Key::Enter)]);
+        editor.add_command("report", |_: KeyPress, line: &mut LineBuffer| {
+            let len = ::suspend_raw(|| line.len());
+            line.yank(&format!("{}", len)).is_some()
+        });
+        editor.bind_sequence(ctrl!('O'), Cmd::User(String::from("report")));
+        assert_eq!("a1b", editor.readline(">>").unwrap());
+        assert!(!::tty::take_suspended());
+    }
+
+    struct ConfirmHook {
+        confirmed: Cell<bool>,
+        accepted: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl AcceptHook for ConfirmHook {
+        fn before_accept(&self, line: &str) -> Acceptance {
+            if line.starts_with("rm ") && !self.confirmed.get() {
+                self.confirmed.set(true);
+                Acceptance::Reject(Some(String::from("Press Enter again to confirm")))
+            } else {
+                Acceptance::Replace(format!("{};", line))
+            }
+        }
+        fn after_accept(&self, line: &str) {
+            self.accepted.borrow_mut().push(String::from(line));
+        }
+    }
+
+    #[test]
+    fn accept_hook() {
+        let accepted = Rc::new(RefCell::new(Vec::new()));
+        let mut editor = init_editor(&[key!('r'),
+                                       key!('m'),
+                                       key!(' '),
+                                       key!('x'),
+                                       key!(Key::Enter),
+                                       key!(Key::Enter)]);
+        editor.set_accept_hook(Some(Box::new(ConfirmHook {
+            confirmed: Cell::new(false),
+            accepted: accepted.clone(),
+        })));
+        assert_eq!("rm x;", editor.readline(">>").unwrap());
+        assert_eq!(vec![String::from("rm x;")], *accepted.borrow());
+
+        // the rows of a continued line are accepted together
+        let config = Config::builder().line_continuation(LineContinuation::Strip).build();
+        let mut editor = Editor::<()>::with_config(config);
+        editor.term.keys.extend_from_slice(&[key!('r'),
+                                             key!('m'),
+                                             key!(' '),
+                                             key!('\\'),
+                                             key!(Key::Enter),
+                                             key!('x'),
+                                             key!(Key::Enter),
+                                             key!(Key::Enter)]);
+        editor.set_accept_hook(Some(Box::new(ConfirmHook {
+            confirmed: Cell::new(false),
+            accepted: accepted.clone(),
+        })));
+        assert_eq!("rm \nx;", editor.readline(">>").unwrap());
+        assert_eq!("rm \nx;", accepted.borrow()[1]);
+    }
+
+    #[test]
+    fn yank_last_arg() {
+        let mut editor = init_editor(&[key!('c'),
+                                       key!('d'),
+                                       key!(' '),
+                                       alt!('.'),
+                                       alt!('.'),
+                                       alt!('.'),
+                                       key!(Key::Enter)]);
+        editor.add_history_entry("mkdir 'my dir'");
+        editor.add_history_entry("ls -l");
+        assert_eq!("cd 'my dir'", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn forward_search() {
+        fn search(keys: &[KeyPress]) -> String {
+            let mut editor = init_editor(keys);
+            for entry in &["ls -a", "cd", "ls -l"] {
+                editor.add_history_entry(*entry);
+            }
+            editor.readline(">>").unwrap()
+        }
+        let enter = key!(Key::Enter);
+        assert_eq!("ls -a", search(&[ctrl!('S'), key!('l'), enter]));
+        assert_eq!("ls -l", search(&[ctrl!('S'), key!('l'), ctrl!('S'), enter]));
+        // direction switched in the middle of the search
+        assert_eq!("ls -l", search(&[ctrl!('R'), key!('l'), ctrl!('R'), ctrl!('S'), enter]));
+        // Ctrl-G restores the line and its cursor
+        let keys = [key!('a'), key!('b'), key!(Key::Left), ctrl!('S'), key!('l'), ctrl!('G'),
+                    key!('c'), enter];
+        assert_eq!("acb", search(&keys));
+    }
+
+    #[test]
+    fn non_incremental_search() {
+        fn search(keys: &[KeyPress]) -> String {
+            let mut editor = init_editor(keys);
+            for entry in &["ls -a", "cd", "ls -l"] {
+                editor.add_history_entry(*entry);
+            }
+            editor.readline(">>").unwrap()
+        }
+        let enter = key!(Key::Enter);
+        assert_eq!("ls -l", search(&[alt!('p'), key!('l'), key!('s'), enter, enter]));
+        // the previous string is searched again when none is typed
+        let keys = [alt!('p'), key!('l'), key!('s'), enter, alt!('p'), enter, enter];
+        assert_eq!("ls -a", search(&keys));
+        let keys = [alt!('p'), key!('l'), enter, alt!('p'), enter, alt!('n'), enter, enter];
+        assert_eq!("ls -l", search(&keys));
+        assert_eq!("x", search(&[key!('x'), alt!('p'), key!('l'), key!(Key::Esc), enter]));
+    }
+
+    #[test]
+    fn undo_across_history() {
+        fn edit(keys: &[KeyPress]) -> String {
+            let mut editor = init_editor(keys);
+            editor.add_history_entry("git commit");
+            editor.readline(">>").unwrap()
+        }
+        let (up, down, undo) = (key!(Key::Up), key!(Key::Down), ctrl!('_'));
+        let (backspace, enter) = (key!(Key::Backspace), key!(Key::Enter));
+        assert_eq!("git commit", edit(&[up, backspace, backspace, undo, undo, undo, enter]));
+        // the typed chars of the new line are undone together, after a recall
+        assert_eq!("", edit(&[key!('l'), key!('s'), up, backspace, down, undo, enter]));
+        // the changes of an entry are kept while another one is displayed
+        assert_eq!("git commi", edit(&[up, backspace, backspace, down, up, undo, enter]));
+    }
+
+    #[test]
+    fn ranked_search() {
+        let keys = [ctrl!('R'), key!('l'), key!('s'), key!(Key::Enter)];
+        let entries = ["ls -a", "ls -l", "ls -a", "cd", "ls -l"];
+        let mut editor = init_editor(&keys);
+        for entry in &entries {
+            editor.add_history_entry(*entry);
+        }
+        assert_eq!("ls -l", editor.readline(">>").unwrap());
+
+        let mut editor = init_editor(&keys);
+        for entry in &entries {
+            editor.add_history_entry(*entry);
+        }
+        editor.set_history_scorer(Some(Box::new(Frecency::new(10))));
+        editor.add_history_entry("ls -a");
+        editor.add_history_entry("pwd");
+        assert_eq!("ls -a", editor.readline(">>").unwrap());
+        // Ctrl-R moves to the next ranked match, staying on the last one
+        let mut editor = init_editor(&[ctrl!('R'),
+                                       key!('l'),
+                                       ctrl!('R'),
+                                       ctrl!('R'),
+                                       key!(Key::Enter)]);
+        for entry in &entries {
+            editor.add_history_entry(*entry);
+        }
+        editor.set_history_scorer(Some(Box::new(Frecency::new(10))));
+        assert_eq!("ls -a", editor.readline(">>").unwrap());
+    }
+
+    /// History of an application, keeping its entries in upper case
+    /// and searching them ignoring the case.
+    #[derive(Default)]
+    struct AppHistory(Vec<String>);
+
+    impl HistoryBackend for AppHistory {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+        fn get(&self, index: usize) -> Option<&String> {
+            self.0.get(index)
+        }
+        fn add(&mut self, line: &str) -> bool {
+            self.0.push(line.to_uppercase());
+            true
+        }
+        fn clear(&mut self) {
+            self.0.clear()
+        }
+        fn load(&mut self, _: &::std::path::Path) -> Result<()> {
+            Ok(())
+        }
+        fn save(&self, _: &::std::path::Path) -> Result<()> {
+            Ok(())
+        }
+        fn search(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+            if term.is_empty() || start >= self.0.len() {
+                return None;
+            }
+            let term = term.to_uppercase();
+            let contains = |index: &usize| self.0[*index].contains(&term);
+            match dir {
+                Direction::Reverse => (0..start + 1).rev().find(contains),
+                Direction::Forward => (start..self.0.len()).find(contains),
+            }
+        }
+    }
+
+    #[test]
+    fn history_backend() {
+        fn edit(keys: &[KeyPress]) -> String {
+            let mut editor = init_editor(keys);
+            editor.set_history(Box::new(AppHistory::default()));
+            editor.add_history_entry("ls");
+            editor.add_history_entry("cd");
+            assert_eq!(2, editor.get_history().len());
+            editor.readline(">>").unwrap()
+        }
+        let enter = key!(Key::Enter);
+        assert_eq!("LS", edit(&[key!(Key::Up), key!(Key::Up), enter]));
+        assert_eq!("LS", edit(&[ctrl!('R'), key!('L'), enter]));
+        assert_eq!("CD", edit(&[alt!('.'), enter]));
+        // the match in lower case is found but not located in the entry:
+        // the cursor is moved to its end
+        assert_eq!("LS", edit(&[ctrl!('R'), key!('l'), enter]));
+        assert_eq!("LxS", edit(&[ctrl!('R'), key!('l'), key!(Key::Left), key!('x'), enter]));
+    }
+
+    #[test]
+    fn stored_search() {
+        fn search(keys: &[KeyPress]) -> String {
+            let config = Config::builder().max_history_size(1).build();
+            let mut editor = Editor::<()>::with_config(config);
+            editor.term.keys.extend_from_slice(keys);
+            editor.set_history(Box::new(VecStore::new(&["ls -a", "ls -l", "cd"])));
+            editor.readline(">>").unwrap()
+        }
+        let enter = key!(Key::Enter);
+        // the matches are only kept by the store
+        assert_eq!("ls -l", search(&[ctrl!('R'), key!('l'), enter]));
+        assert_eq!("ls -a", search(&[ctrl!('R'), key!('l'), ctrl!('R'), enter]));
+        // staying on the last match
+        assert_eq!("ls -a", search(&[ctrl!('R'), key!('l'), ctrl!('R'), ctrl!('R'), enter]));
+        assert_eq!("ls -l", search(&[ctrl!('R'), key!('l'), ctrl!('R'), ctrl!('S'), enter]));
+    }
+
+    #[test]
+    fn registers() {
+        let mut editor = init_editor(&[key!('a'),
+                                       key!(' '),
+                                       key!('b'),
+                                       ctrl!('X'),
+                                       key!('"'),
+                                       key!('r'),
+                                       ctrl!('W'),
+                                       ctrl!('W'),
+                                       ctrl!('X'),
+                                       key!('"'),
+                                       key!('r'),
+                                       ctrl!('Y'),
+                                       ctrl!('Y'),
+                                       key!(Key::Enter)]);
+        assert_eq!("a ba b", editor.readline(">>").unwrap());
+        assert_eq!(Some("a b"), editor.register('r'));
+        assert_eq!(vec![('r', "a b")], editor.registers());
+    }
+
+    #[test]
+    fn repeat_last_change() {
+        let mut editor = init_editor(&[key!('a'),
+                                       key!('b'),
+                                       key!(Key::Left),
+                                       ctrl!('X'),
+                                       key!('z'),
+                                       key!(Key::Enter)]);
+        assert_eq!("aabb", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn command_palette() {
+        let mut editor = init_editor(&[key!('a'),
+                                       key!('b'),
+                                       ctrl!('X'),
+                                       ctrl!('P'),
+                                       key!('h'),
+                                       key!('o'),
+                                       key!('m'),
+                                       key!('e'),
+                                       key!(Key::Backspace),
+                                       key!(Key::Backspace),
+                                       key!(Key::Backspace),
+                                       key!(Key::Backspace),
+                                       key!('b'),
+                                       key!('e'),
+                                       key!('g'),
+                                       key!(Key::Down),
+                                       key!(Key::Enter),
+                                       key!('c'),
+                                       key!(Key::Enter)]);
+        assert_eq!("cab", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn read_key() {
+        let mut editor = init_editor(&[key!('y'), key!(Key::Esc)]);
+        assert_eq!(Some(key!('y')), editor.read_key(-1).unwrap());
+
+        let mut editor = init_editor(&[]);
+        assert_eq!(None, editor.read_key(0).unwrap());
+    }
+
+    #[test]
+    fn prompt_confirm() {
+        let mut editor = init_editor(&[key!('x'), key!('N')]);
+        assert_eq!(false, editor.prompt_confirm("Continue? ", true).unwrap());
+        let mut editor = init_editor(&[key!(Key::Enter)]);
+        assert_eq!(true, editor.prompt_confirm("Continue? ", true).unwrap());
+        let mut editor = init_editor(&[ctrl!('C')]);
+        assert!(editor.prompt_confirm("Continue? ", true).is_err());
+    }
+
+    #[test]
+    fn prompt_select() {
+        let options = ["red", "green", "blue"];
+        let mut editor = init_editor(&[key!(Key::Down), key!(Key::Down), key!(Key::Up),
+                                       key!(Key::Enter)]);
+        assert_eq!(1, editor.prompt_select("Color: ", &options).unwrap());
+        let mut editor = init_editor(&[key!('3'), key!('4'), key!(Key::Enter)]);
+        assert_eq!(2, editor.prompt_select("Color: ", &options).unwrap());
+        let mut editor = init_editor(&[key!(Key::Esc)]);
+        assert!(editor.prompt_select("Color: ", &options).is_err());
+    }
+
+    #[test]
+    fn prompt_number() {
+        let mut editor = init_editor(&[key!('1'), key!('x'), key!('2'), key!(Key::Enter),
+                                       key!(Key::Backspace), key!(Key::Up), key!(Key::Up),
+                                       key!(Key::Enter)]);
+        assert_eq!(3u8, editor.prompt_number("Count: ", 0, 10).unwrap());
+        let mut editor = init_editor(&[key!(Key::Down), key!(Key::Down), key!(Key::Enter)]);
+        assert_eq!(9u8, editor.prompt_number("Count: ", 0, 10).unwrap());
+    }
+
+    #[test]
+    fn fuzzy_search() {
+        let config = Config::builder().fuzzy_search(true).build();
+        let mut editor = Editor::<()>::with_config(config);
+        editor.term.keys.extend_from_slice(&[key!('g'),
+                                             ctrl!('R'),
+                                             key!('c'),
+                                             key!('o'),
+                                             key!(Key::Down),
+                                             key!(Key::Enter),
+                                             key!(Key::Enter)]);
+        editor.add_history_entry("git checkout");
+        editor.add_history_entry("cargo");
+        editor.add_history_entry("git commit");
+        assert_eq!("git checkout", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn input_script() {
+        let mut editor = init_editor(&[key!('x'), key!(Key::Enter)]);
+        let mut script = Script::parse("a\nb\nCtrl-A\nc\nEnter\nd\n").unwrap();
+        script.set_timing(false);
+        editor.set_input_script(Some(script));
+        assert_eq!("cab", editor.readline(">>").unwrap());
+        assert!(editor.readline(">>").is_err());
+        editor.set_input_script(None);
+        assert_eq!("x", editor.readline(">>").unwrap());
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        keys: Cell<usize>,
+        cmds: Cell<usize>,
+        replayed: Cell<usize>,
+        refreshes: Cell<usize>,
+    }
+
+    impl Observer for Rc<Counter> {
+        fn key_decoded(&self, _: &str, _: KeyPress) {
+            self.keys.set(self.keys.get() + 1);
+        }
+        fn command_dispatched(&self, key: Option<KeyPress>, _: &Cmd) {
+            self.cmds.set(self.cmds.get() + 1);
+            if key.is_none() {
+                self.replayed.set(self.replayed.get() + 1);
+            }
+        }
+        fn refreshed(&self, _: Duration) {
+            self.refreshes.set(self.refreshes.get() + 1);
+        }
+    }
+
+    #[test]
+    fn observer() {
+        let counter = Rc::new(Counter::default());
+        let mut editor = init_editor(&[key!(Key::Null),
+                                       key!('a'),
+                                       ctrl!('X'),
+                                       key!('z'),
+                                       key!(Key::Enter)]);
+        editor.set_observer(Some(Box::new(counter.clone())));
+        assert_eq!("aa", editor.readline(">>").unwrap());
+        assert_eq!(5, counter.keys.get());
+        // the repeated change is dispatched too, without a key (unlike Ctrl-Space)
+        assert_eq!(5, counter.cmds.get());
+        assert_eq!(1, counter.replayed.get());
+        assert!(counter.refreshes.get() > 0);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn alternate_screen() {
+        let config = Config::builder().alternate_screen(true).build();
+        let mut editor = Editor::<()>::with_config(config);
+        editor.term.keys.push(key!(Key::Enter));
+        assert_eq!("", editor.readline(">>").unwrap());
+        assert!(editor.term.alternate_screen.get());
+        // a temporary editor doesn't leave the alternate screen of another one
+        drop(Editor::<()>::new());
+        assert!(editor.term.alternate_screen.get());
+        // left while a subprocess is run
+        let alternate_screen = editor.term.alternate_screen.clone();
+        assert!(!editor.suspend_raw(|| alternate_screen.get()));
+        assert!(editor.term.alternate_screen.get());
+        editor.reload_config(Config::default());
+        assert!(!editor.term.alternate_screen.get());
+        assert!(editor.suspend_raw(|| true));
+        assert!(!editor.term.alternate_screen.get());
+    }
+
+    #[test]
+    fn esc_mode() {
+        use config::EscMode;
+
+        let modes = [(EscMode::Timeout, 100), (EscMode::Escape, 0), (EscMode::Meta, -1)];
+        for &(mode, timeout) in &modes {
+            let config = Config::builder().keyseq_timeout(100).esc_mode(mode).build();
+            assert_eq!(timeout, config.esc_timeout());
+            let mut editor = Editor::<()>::with_config(config);
+            editor.add_history_entry("ls");
+            editor.term.keys.extend_from_slice(&[key!('a'),
+                                                 ctrl!('R'),
+                                                 key!('l'),
+                                                 key!(Key::Enter)]);
+            assert_eq!("ls", editor.readline(">>").unwrap());
+            // the keys read while editing and searching
+            assert_eq!(vec![timeout; 4], *editor.term.timeouts.borrow());
+        }
+    }
+
+    #[test]
+    fn dev_tty() {
+        let config = Config::builder().dev_tty(true).build();
+        let mut editor = Editor::<()>::with_config(config.clone());
+        assert!(editor.term.dev_tty);
+        editor.term.keys.extend_from_slice(&[key!('a'), key!(Key::Enter)]);
+        assert_eq!("a", editor.readline(">>").unwrap());
+        // the terminal is reopened without /dev/tty, then with it again
+        editor.reload_config(Config::default());
+        assert!(!editor.term.dev_tty);
+        editor.reload_config(config);
+        assert!(editor.term.dev_tty);
+        editor.term.keys = vec![key!('b'), key!(Key::Enter)];
+        assert_eq!("b", editor.readline(">>").unwrap());
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> ::std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn masked_read_not_observed() {
+        use super::Overrides;
+        use script::Recorder;
+        let counter = Rc::new(Counter::default());
+        let recorded = Buffer::default();
+        let accepted = Rc::new(RefCell::new(Vec::new()));
+        let mut editor = init_editor(&[key!('p'), key!('w'), key!(Key::Enter)]);
+        editor.set_observer(Some(Box::new(counter.clone())));
+        editor.set_recorder(Some(Recorder::new(recorded.clone())));
+        editor.set_accept_hook(Some(Box::new(ConfirmHook {
+            confirmed: Cell::new(false),
+            accepted: accepted.clone(),
+        })));
+        let overrides = Overrides { mask: Some('*'), ..Overrides::default() };
+        assert_eq!("pw", editor.readline_with(">>", overrides).unwrap());
+        assert!(recorded.0.borrow().is_empty());
+        assert_eq!(0, counter.keys.get());
+        assert_eq!(0, counter.cmds.get());
+        assert_eq!(0, counter.refreshes.get());
+        assert!(accepted.borrow().is_empty());
+    }
+
+    #[cfg(feature = "perf")]
+    #[test]
+    fn timings() {
+        let mut editor = init_editor(&[key!('a'), key!(Key::Left), key!(Key::Enter)]);
+        assert_eq!("a", editor.readline(">>").unwrap());
+        assert_eq!("a", editor.readline(">>").unwrap());
+        assert_eq!(6, editor.timings().len());
+        let keys: Vec<KeyPress> = editor.timings().iter().map(|timing| timing.key).collect();
+        assert_eq!(vec![key!('a'), key!(Key::Left), key!(Key::Enter)], &keys[..3]);
+        editor.clear_timings();
+        assert!(editor.timings().is_empty());
+    }
+
+    #[test]
+    fn insert_pasted() {
+        let counter = Rc::new(Counter::default());
+        let mut editor = init_editor(&[key!('a'),
+                                       key!('b'),
+                                       key!('c'),
+                                       ctrl!('A'),
+                                       key!('d'),
+                                       key!('e'),
+                                       key!(Key::Enter)]);
+        editor.set_observer(Some(Box::new(counter.clone())));
+        assert_eq!("deabc", editor.readline(">>").unwrap());
+        assert_eq!(7, counter.keys.get());
+        // the buffered chars are inserted with one refresh, until a key bound to a command
+        // (and the line is displayed before, and refreshed by Ctrl-A and Enter)
+        assert_eq!(5, counter.refreshes.get());
+        editor.reload_config(Config::builder().max_line_len(4).build());
+        assert_eq!("dabc", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn kill_text_object() {
+        let mut editor = init_editor(&[key!('f'),
+                                       key!('('),
+                                       key!('a'),
+                                       key!(','),
+                                       key!('b'),
+                                       key!(')'),
+                                       key!(Key::Left),
+                                       ctrl!('X'),
+                                       key!('i'),
+                                       key!('x'),
+                                       key!(Key::Enter)]);
+        editor.bind_ctrl_x_sequence(key!('i'), Cmd::KillTextObject(TextObject::Inner('(')));
+        assert_eq!("f(x)", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn insert_completions() {
+        let mut editor = init_editor(&[key!('r'), key!('u'), alt!('*'), key!(Key::Enter)]);
+        editor.set_completer(Some(()));
+        assert_eq!("ru", editor.readline(">>").unwrap());
+
+        let mut out = ::std::io::sink();
+        let mut s = init_state(&mut out, "x ru", 4, 80);
+        super::insert_completions(&mut s, &MultiCompleter).unwrap();
+        assert_eq!("x rust ruby", s.line.as_str());
+    }
+
+    #[test]
+    fn insert_comment() {
+        let mut editor = init_editor(&[key!('a'), key!(Key::Home), alt!('#')]);
+        assert_eq!("#a", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn revert_line() {
+        let mut editor = init_editor(&[key!(Key::Up),
+                                       key!('!'),
+                                       alt!('R'),
+                                       key!(Key::Enter)]);
+        editor.add_history_entry("line0");
+        assert_eq!("line0", editor.readline(">>").unwrap());
+    }
+
+    #[test]
+    fn revert_all_at_newline() {
+        let config = Config::builder().revert_all_at_newline(false).build();
+        let mut editor = Editor::<()>::with_config(config);
+        editor.term.keys.extend_from_slice(&[key!(Key::Up),
+                                             key!('!'),
+                                             key!(Key::Up),
+                                             key!('?'),
+                                             key!(Key::Enter)]);
+        editor.add_history_entry("line0");
+        editor.add_history_entry("line1");
+        assert_eq!("line0?", editor.readline(">>").unwrap());
+        assert_eq!("line0", editor.history.get(0).unwrap());
+        assert_eq!("line1!", editor.history.get(1).unwrap());
     }
 
     #[test]
@@ -14,6 +14,7 @@ pub enum WordAction {
 pub struct LineBuffer {
     buf: String, // Edited line buffer
     pos: usize, // Current cursor position (byte position)
+    max_len: usize, // Maximum length of the buffer (in bytes)
 }
 
 impl LineBuffer {
@@ -22,15 +23,36 @@ impl LineBuffer {
         LineBuffer {
             buf: String::with_capacity(capacity),
             pos: 0,
+            max_len: capacity,
         }
     }
 
-    #[cfg(test)]
+    /// Change the maximum length of the buffer, in bytes
+    /// (the current content is kept, even if it is longer).
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+    }
+
+    /// Create a buffer containing `line`, with the cursor at `pos`
+    /// (to test custom commands, see `assert_invariants`).
     pub fn init(line: &str, pos: usize) -> LineBuffer {
-        LineBuffer {
-            buf: String::from(line),
-            pos: pos,
-        }
+        let mut lb = Self::with_capacity(MAX_LINE);
+        lb.update(line, pos);
+        lb
+    }
+
+    /// Panic unless the cursor is on a char boundary, at most at the end of the line.
+    /// The edit commands keep these invariants, and the custom ones must too
+    /// (assert them in the tests of a `KeyHandler`).
+    pub fn assert_invariants(&self) {
+        assert!(self.pos <= self.buf.len(),
+                "cursor {} after the end of {:?}",
+                self.pos,
+                self.buf);
+        assert!(self.buf.is_char_boundary(self.pos),
+                "cursor {} inside a char of {:?}",
+                self.pos,
+                self.buf);
     }
 
     /// Extracts a string slice containing the entire buffer.
@@ -65,7 +87,7 @@ impl LineBuffer {
     pub fn update(&mut self, buf: &str, pos: usize) {
         assert!(pos <= buf.len());
         self.buf.clear();
-        let max = self.buf.capacity();
+        let max = floor_char_boundary(buf, self.max_len);
         if buf.len() > max {
             self.buf.push_str(&buf[..max]);
             if pos > max {
@@ -87,7 +109,7 @@ impl LineBuffer {
     }
 
     /// Returns the character at current cursor position.
-    fn char_at_cursor(&self) -> Option<char> {
+    pub fn char_at_cursor(&self) -> Option<char> {
         if self.pos == self.buf.len() {
             None
         } else {
@@ -95,7 +117,7 @@ impl LineBuffer {
         }
     }
     /// Returns the character just before the current cursor position.
-    fn char_before_cursor(&self) -> Option<char> {
+    pub fn char_before_cursor(&self) -> Option<char> {
         if self.pos == 0 {
             None
         } else {
@@ -109,7 +131,7 @@ impl LineBuffer {
     /// `true` when the character has been appended to the end of the line.
     pub fn insert(&mut self, ch: char) -> Option<bool> {
         let shift = ch.len_utf8();
-        if self.buf.len() + shift > self.buf.capacity() {
+        if self.buf.len() + shift > self.max_len {
             return None;
         }
         let push = self.pos == self.buf.len();
@@ -127,7 +149,7 @@ impl LineBuffer {
     /// `true` when the character has been appended to the end of the line.
     pub fn yank(&mut self, text: &str) -> Option<bool> {
         let shift = text.len();
-        if text.is_empty() || (self.buf.len() + shift) > self.buf.capacity() {
+        if text.is_empty() || (self.buf.len() + shift) > self.max_len {
             return None;
         }
         let pos = self.pos;
@@ -136,6 +158,30 @@ impl LineBuffer {
         Some(push)
     }
 
+    /// Insert `before` and `after` at current position,
+    /// leaving the cursor between them.
+    /// Return `None` when maximum buffer size has been reached,
+    /// `true` when the text has been appended to the end of the line.
+    pub fn split_insert(&mut self, before: &str, after: &str) -> Option<bool> {
+        let shift = before.len() + after.len();
+        if (self.buf.len() + shift) > self.max_len {
+            return None;
+        }
+        let pos = self.pos;
+        let push = self.insert_str(pos, after);
+        self.insert_str(pos, before);
+        self.pos += before.len();
+        Some(push)
+    }
+
+    /// Delete the text between [`start`..`end`] and return it.
+    /// The cursor is moved to `start`.
+    pub fn delete_range(&mut self, start: usize, end: usize) -> String {
+        let text = self.buf.drain(start..end).collect();
+        self.pos = start;
+        text
+    }
+
     /// Delete previously yanked text and yank/paste `text` at current position.
     pub fn yank_pop(&mut self, yank_size: usize, text: &str) -> Option<bool> {
         self.buf.drain((self.pos - yank_size)..self.pos);
@@ -183,6 +229,68 @@ impl LineBuffer {
         }
     }
 
+    /// Start and end (newline excluded) of the line of a multi-line buffer
+    /// which contains `pos`.
+    pub fn line_range(&self, pos: usize) -> (usize, usize) {
+        let start = self.buf[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.buf[pos..].find('\n').map_or(self.buf.len(), |i| pos + i);
+        (start, end)
+    }
+
+    /// Move cursor to the start of the current line of a multi-line buffer.
+    pub fn move_line_start(&mut self) -> bool {
+        let (start, _) = self.line_range(self.pos);
+        if self.pos > start {
+            self.pos = start;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move cursor to the end of the current line of a multi-line buffer.
+    pub fn move_line_end(&mut self) -> bool {
+        let (_, end) = self.line_range(self.pos);
+        if self.pos < end {
+            self.pos = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move cursor to the same column (in chars) of the previous line of a multi-line buffer
+    /// (or to its end if it is shorter).
+    /// Return `false` when the cursor is on the first line.
+    pub fn move_line_up(&mut self) -> bool {
+        let (start, _) = self.line_range(self.pos);
+        if start == 0 {
+            return false;
+        }
+        let column = self.buf[start..self.pos].chars().count();
+        let (prev_start, prev_end) = self.line_range(start - 1);
+        self.pos = self.column_pos(prev_start, prev_end, column);
+        true
+    }
+
+    /// Move cursor to the same column (in chars) of the next line of a multi-line buffer
+    /// (or to its end if it is shorter).
+    /// Return `false` when the cursor is on the last line.
+    pub fn move_line_down(&mut self) -> bool {
+        let (start, end) = self.line_range(self.pos);
+        if end == self.buf.len() {
+            return false;
+        }
+        let column = self.buf[start..self.pos].chars().count();
+        let (next_start, next_end) = self.line_range(end + 1);
+        self.pos = self.column_pos(next_start, next_end, column);
+        true
+    }
+
+    fn column_pos(&self, start: usize, end: usize, column: usize) -> usize {
+        self.buf[start..end].char_indices().nth(column).map_or(end, |(i, _)| start + i)
+    }
+
     /// Delete the character at the right of the cursor without altering the cursor
     /// position. Basically this is what happens with the "Delete" keyboard key.
     pub fn delete(&mut self) -> bool {
@@ -227,6 +335,23 @@ impl LineBuffer {
         }
     }
 
+    /// Kill the line of a multi-line buffer containing the cursor, with its newline.
+    /// The cursor is moved to the start of the next line (or of the previous one).
+    pub fn kill_whole_line(&mut self) -> Option<String> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let (mut start, mut end) = self.line_range(self.pos);
+        if end < self.buf.len() {
+            end += 1;
+        } else if start > 0 {
+            start -= 1;
+        }
+        let text = self.buf.drain(start..end).collect();
+        self.pos = self.line_range(start).0;
+        Some(text)
+    }
+
     /// Exchange the char before cursor with the character at cursor.
     pub fn transpose_chars(&mut self) -> bool {
         if self.pos == 0 || self.buf.chars().count() < 2 {
@@ -429,6 +554,14 @@ impl LineBuffer {
     }
 }
 
+/// Largest char boundary of `s` which is not after `index`.
+pub fn floor_char_boundary(s: &str, index: usize) -> usize {
+    if index >= s.len() {
+        return s.len();
+    }
+    (0..index + 1).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0)
+}
+
 impl Deref for LineBuffer {
     type Target = str;
 
@@ -457,9 +590,117 @@ fn insert_str(buf: &mut String, idx: usize, s: &str) {
 }
 
 #[cfg(test)]
-mod test {
+pub mod test {
+    #[cfg(feature = "proptests")]
+    use proptest::prelude::*;
+    #[cfg(feature = "proptests")]
+    use kill_ring::{KillRing, Mode};
+    #[cfg(feature = "proptests")]
+    use super::floor_char_boundary;
     use super::{LineBuffer, MAX_LINE, WordAction};
 
+    /// Apply the edit `op` (modulo the number of edits), inserting `c` if it inserts.
+    #[cfg(feature = "proptests")]
+    pub fn edit(s: &mut LineBuffer, op: u8, c: char) {
+        use std::iter;
+
+        match op % 20 {
+            0 => {
+                s.insert(c);
+            }
+            1 => {
+                s.yank(&iter::repeat(c).take(2).collect::<String>());
+            }
+            2 => {
+                s.split_insert("(", &c.to_string());
+            }
+            3 => {
+                s.delete();
+            }
+            4 => {
+                s.backspace();
+            }
+            5 => {
+                s.move_left();
+            }
+            6 => {
+                s.move_right();
+            }
+            7 => {
+                s.move_home();
+            }
+            8 => {
+                s.move_end();
+            }
+            9 => {
+                s.move_to_prev_word();
+            }
+            10 => {
+                s.move_to_next_word();
+            }
+            11 => {
+                s.kill_line();
+            }
+            12 => {
+                s.discard_line();
+            }
+            13 => {
+                s.transpose_chars();
+            }
+            14 => {
+                s.transpose_words();
+            }
+            15 => {
+                s.delete_word();
+            }
+            16 => {
+                s.delete_prev_word(char::is_whitespace);
+            }
+            17 => {
+                s.edit_word(WordAction::UPPERCASE);
+            }
+            18 => {
+                s.move_line_up();
+            }
+            _ => {
+                s.kill_whole_line();
+            }
+        }
+    }
+
+    #[cfg(feature = "proptests")]
+    proptest! {
+        #[test]
+        fn cursor_on_char_boundary(line in "\\PC*",
+                                   pos in 0usize..64,
+                                   edits in prop::collection::vec(any::<(u8, char)>(), 0..32)) {
+            let mut s = LineBuffer::init(&line, floor_char_boundary(&line, pos));
+            for (op, c) in edits {
+                edit(&mut s, op, c);
+                s.assert_invariants();
+            }
+        }
+
+        #[test]
+        fn kill_yank(line in "[a-z é\\n]*", pos in 0usize..64, op in 0u8..4) {
+            let pos = floor_char_boundary(&line, pos);
+            let mut s = LineBuffer::init(&line, pos);
+            let mut kill_ring = KillRing::new(4);
+            let killed = match op {
+                0 => s.kill_line(),
+                1 => s.discard_line(),
+                2 => s.delete_word(),
+                _ => s.delete_prev_word(char::is_whitespace),
+            };
+            if let Some(text) = killed {
+                kill_ring.kill(&text, Mode::Append);
+                let yanked = kill_ring.yank().unwrap().clone();
+                s.yank(&yanked);
+            }
+            prop_assert_eq!(line.as_str(), s.as_str());
+        }
+    }
+
     #[test]
     fn insert() {
         let mut s = LineBuffer::with_capacity(MAX_LINE);
@@ -480,6 +721,44 @@ mod test {
         assert_eq!(false, push);
     }
 
+    #[test]
+    fn max_len() {
+        let mut s = LineBuffer::with_capacity(MAX_LINE);
+        s.set_max_len(3);
+        assert_eq!(Some(true), s.insert('α'));
+        assert_eq!(None, s.insert('β'));
+        assert_eq!(None, s.yank("ab"));
+        assert_eq!(Some(true), s.yank("a"));
+        // truncated on a char boundary
+        s.update("aαβ", 5);
+        assert_eq!("aα", s.buf);
+        assert_eq!(3, s.pos);
+    }
+
+    #[test]
+    fn split_insert() {
+        let mut s = LineBuffer::init("f", 1);
+        let push = s.split_insert("(", ")").unwrap();
+        assert_eq!("f()", s.buf);
+        assert_eq!(2, s.pos);
+        assert_eq!(true, push);
+        assert_eq!(Some('('), s.char_before_cursor());
+        assert_eq!(Some(')'), s.char_at_cursor());
+
+        let push = s.split_insert("\"", "\"").unwrap();
+        assert_eq!("f(\"\")", s.buf);
+        assert_eq!(3, s.pos);
+        assert_eq!(false, push);
+    }
+
+    #[test]
+    fn delete_range() {
+        let mut s = LineBuffer::init("f(a, b)", 3);
+        assert_eq!("a, b", s.delete_range(2, 6));
+        assert_eq!("f()", s.buf);
+        assert_eq!(2, s.pos);
+    }
+
     #[test]
     fn moves() {
         let mut s = LineBuffer::init("αß", 4);
@@ -533,6 +812,40 @@ mod test {
         assert_eq!(Some("αß".to_string()), text);
     }
 
+    #[test]
+    fn line_moves() {
+        let mut s = LineBuffer::init("ab\nαßγ\nc", 5);
+        assert_eq!((3, 9), s.line_range(5));
+        assert!(s.move_line_up());
+        assert_eq!(1, s.pos);
+        assert!(!s.move_line_up());
+        assert!(s.move_line_down());
+        assert_eq!(5, s.pos);
+        assert!(s.move_line_down());
+        assert_eq!(11, s.pos);
+        assert!(!s.move_line_down());
+
+        s.pos = 5;
+        assert!(s.move_line_start());
+        assert_eq!(3, s.pos);
+        assert!(!s.move_line_start());
+        assert!(s.move_line_end());
+        assert_eq!(9, s.pos);
+    }
+
+    #[test]
+    fn kill_whole_line() {
+        let mut s = LineBuffer::init("ab\ncd\nef", 4);
+        assert_eq!(Some("cd\n".to_string()), s.kill_whole_line());
+        assert_eq!("ab\nef", s.buf);
+        assert_eq!(3, s.pos);
+        assert_eq!(Some("\nef".to_string()), s.kill_whole_line());
+        assert_eq!("ab", s.buf);
+        assert_eq!(0, s.pos);
+        assert_eq!(Some("ab".to_string()), s.kill_whole_line());
+        assert_eq!(None, s.kill_whole_line());
+    }
+
     #[test]
     fn transpose() {
         let mut s = LineBuffer::init("aßc", 1);
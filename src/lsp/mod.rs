@@ -0,0 +1,528 @@
+//! Completions, hints and descriptions provided by a language server
+//! over stdio (`lsp` feature).
+//!
+//! The edited line, preceded by the context given by the application
+//! (the definitions already entered in a REPL, ...), is the content of
+//! an untitled document opened in the server.
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use completion::{Completer, Descriptor};
+use hint::Hinter;
+use history::HistoryBackend;
+use json::{self, Value, object};
+use super::Result;
+
+const DOCUMENT_URI: &'static str = "untitled:rustyline";
+
+/// Connection to a language server, shared by the hooks of the editor:
+/// give a clone to `set_completer`, `set_hinter` and `set_descriptor`.
+///
+/// The completions and descriptions are requested synchronously, with a timeout,
+/// the hints in the background (the line is refreshed once they are received).
+#[derive(Clone)]
+pub struct LspBridge(Rc<Bridge>);
+
+struct Bridge {
+    connection: RefCell<Connection>,
+    context: RefCell<String>,
+    timeout: RefCell<Duration>,
+}
+
+struct Connection {
+    child: Option<Child>, // `None` when connected to streams
+    output: Box<Write>,
+    messages: Receiver<Value>,
+    next_id: u64,
+    version: u64,
+    text: String, // Content of the document known by the server
+    hint: Option<HintRequest>,
+}
+
+/// Completion requested for a hint, not waited for.
+struct HintRequest {
+    id: u64,
+    text: String, // Content of the document, the cursor being at its end
+    sent: Instant,
+    result: Option<Value>, // `None` until the response is received
+}
+
+impl LspBridge {
+    /// Start the language server `command` and open a document of `language_id`
+    /// ("python", "rust", ...) in it.
+    pub fn spawn(command: &mut Command, language_id: &str) -> Result<LspBridge> {
+        let mut child = try!(command.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn());
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        LspBridge::connect(stdout, Box::new(stdin), Some(child), language_id)
+    }
+
+    /// Talk to a language server over the `input` and `output` streams.
+    fn connect<R: Read + Send + 'static>(input: R,
+                                         output: Box<Write>,
+                                         child: Option<Child>,
+                                         language_id: &str)
+                                         -> Result<LspBridge> {
+        let mut input = BufReader::new(input);
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            while let Some(message) = read_message(&mut input) {
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut connection = Connection {
+            child: child,
+            output: output,
+            messages: rx,
+            next_id: 0,
+            version: 0,
+            text: String::new(),
+            hint: None,
+        };
+
+        let params = object(vec![("processId", Value::Null),
+                                 ("rootUri", Value::Null),
+                                 ("capabilities", object(vec![]))]);
+        let id = try!(connection.request("initialize", params));
+        if try!(connection.response(id, Duration::from_secs(10))).is_none() {
+            connection.kill();
+            let msg = "language server not initialized";
+            return Err(io::Error::new(io::ErrorKind::TimedOut, msg).into());
+        }
+        try!(connection.notify("initialized", object(vec![])));
+        let document = object(vec![("uri", Value::from(DOCUMENT_URI)),
+                                   ("languageId", Value::from(language_id)),
+                                   ("version", Value::from(0)),
+                                   ("text", Value::from(""))]);
+        try!(connection.notify("textDocument/didOpen", object(vec![("textDocument", document)])));
+
+        Ok(LspBridge(Rc::new(Bridge {
+            connection: RefCell::new(connection),
+            context: RefCell::new(String::new()),
+            timeout: RefCell::new(Duration::from_millis(500)),
+        })))
+    }
+
+    /// Text preceding the edited line in the document (empty by default).
+    pub fn set_context(&self, context: &str) {
+        *self.0.context.borrow_mut() = String::from(context);
+    }
+
+    /// Maximum time to wait for a response (500ms by default):
+    /// no completion, hint or description is shown when it runs out.
+    pub fn set_timeout(&self, timeout: Duration) {
+        *self.0.timeout.borrow_mut() = timeout;
+    }
+
+    /// Send `method` for the cursor `pos`ition in `line` and return the result.
+    fn request(&self, method: &str, line: &str, pos: usize) -> Result<Option<Value>> {
+        let mut connection = self.0.connection.borrow_mut();
+        let id = try!(self.send(&mut connection, method, line, pos));
+        connection.response(id, *self.0.timeout.borrow())
+    }
+
+    /// Send `method` for the cursor `pos`ition in `line` and return the id of the request.
+    fn send(&self,
+            connection: &mut Connection,
+            method: &str,
+            line: &str,
+            pos: usize)
+            -> Result<u64> {
+        let context = self.0.context.borrow();
+        try!(connection.sync(&format!("{}{}", context, line)));
+        let (row, col) = position(&format!("{}{}", context, &line[..pos]));
+        let position = object(vec![("line", Value::from(row)), ("character", Value::from(col))]);
+        let params = object(vec![("textDocument", object(vec![("uri", Value::from(DOCUMENT_URI))])),
+                                 ("position", position)]);
+        connection.request(method, params)
+    }
+}
+
+impl Completer for LspBridge {
+    fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>)> {
+        let start = word_start(line, pos);
+        let candidates = match try!(self.request("textDocument/completion", line, pos)) {
+            Some(result) => candidates(&result, &line[start..pos]),
+            None => Vec::new(),
+        };
+        Ok((start, candidates))
+    }
+    fn word_start(&self, line: &str, pos: usize) -> usize {
+        word_start(line, pos)
+    }
+}
+
+/// Suggest the end of the first completion of the word before the cursor.
+/// The keys are not blocked while the completions are requested:
+/// the hint is displayed once they are received.
+impl Hinter for LspBridge {
+    fn hint(&self, line: &str, pos: usize, _: &HistoryBackend) -> Option<String> {
+        let start = word_start(line, pos);
+        if pos < line.len() || start == pos {
+            return None;
+        }
+        let mut connection = self.0.connection.borrow_mut();
+        if connection.receive_hint().is_err() {
+            return None;
+        }
+        let text = format!("{}{}", self.0.context.borrow(), line);
+        let requested = match connection.hint {
+            Some(ref hint) if hint.text == text => Some(hint.result.clone()),
+            _ => None,
+        };
+        match requested {
+            Some(Some(result)) => {
+                candidates(&result, &line[start..pos])
+                    .into_iter()
+                    .find(|candidate| candidate.len() > pos - start)
+                    .map(|candidate| String::from(&candidate[pos - start..]))
+            }
+            // not received yet
+            Some(None) => None,
+            None => {
+                if let Ok(id) = self.send(&mut connection, "textDocument/completion", line, pos) {
+                    connection.hint = Some(HintRequest {
+                        id: id,
+                        text: text,
+                        sent: Instant::now(),
+                        result: None,
+                    });
+                }
+                None
+            }
+        }
+    }
+    fn is_pending(&self) -> bool {
+        let mut connection = self.0.connection.borrow_mut();
+        if connection.receive_hint().is_err() {
+            return false;
+        }
+        match connection.hint {
+            Some(ref hint) => {
+                hint.result.is_none() && hint.sent.elapsed() < *self.0.timeout.borrow()
+            }
+            None => false,
+        }
+    }
+}
+
+/// Show the signature of the function called at the cursor.
+impl Descriptor for LspBridge {
+    fn describe(&self, line: &str, pos: usize) -> Option<String> {
+        let result = match self.request("textDocument/signatureHelp", line, pos) {
+            Ok(Some(result)) => result,
+            _ => return None,
+        };
+        let active = result.get("activeSignature").and_then(Value::as_u64).unwrap_or(0);
+        result.get("signatures")
+            .and_then(Value::as_array)
+            .and_then(|signatures| signatures.get(active as usize))
+            .and_then(|signature| signature.get("label"))
+            .and_then(Value::as_str)
+            .map(String::from)
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        let connection = self.connection.get_mut();
+        let _ = connection.notify("exit", Value::Null);
+        connection.kill();
+    }
+}
+
+impl Connection {
+    fn kill(&mut self) {
+        if let Some(ref mut child) = self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    fn send(&mut self, message: Value) -> Result<()> {
+        let body = message.to_string();
+        try!(write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body));
+        try!(self.output.flush());
+        Ok(())
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<()> {
+        self.send(object(vec![("jsonrpc", Value::from("2.0")),
+                              ("method", Value::from(method)),
+                              ("params", params)]))
+    }
+
+    /// Send a request and return its id.
+    fn request(&mut self, method: &str, params: Value) -> Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        try!(self.send(object(vec![("jsonrpc", Value::from("2.0")),
+                                   ("id", Value::from(id)),
+                                   ("method", Value::from(method)),
+                                   ("params", params)])));
+        Ok(id)
+    }
+
+    /// Wait at most `timeout` for the result of the request `id`
+    /// (`None` on timeout or error).
+    fn response(&mut self, id: u64, timeout: Duration) -> Result<Option<Value>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            let message = match try!(self.receive(deadline - now)) {
+                Some(message) => message,
+                None => return Ok(None),
+            };
+            if message.get("id").and_then(Value::as_u64) == Some(id) {
+                return Ok(message.get("result").cloned());
+            }
+            self.keep_hint(message);
+        }
+    }
+
+    /// Wait at most `timeout` for a response
+    /// (the requests from the server are acknowledged, the notifications ignored).
+    fn receive(&mut self, timeout: Duration) -> Result<Option<Value>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            let timeout = if now < deadline { deadline - now } else { Duration::from_millis(0) };
+            let message = match self.messages.recv_timeout(timeout) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => {
+                    let msg = "language server exited";
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, msg).into());
+                }
+            };
+            if message.get("method").is_none() {
+                return Ok(Some(message));
+            }
+            if let Some(id) = message.get("id") {
+                try!(self.send(object(vec![("jsonrpc", Value::from("2.0")),
+                                           ("id", id.clone()),
+                                           ("result", Value::Null)])));
+            }
+        }
+    }
+
+    /// Keep the result of `response` if it answers the hint request.
+    fn keep_hint(&mut self, response: Value) {
+        if let Some(ref mut hint) = self.hint {
+            if response.get("id").and_then(Value::as_u64) == Some(hint.id) {
+                hint.result = Some(response.get("result").cloned().unwrap_or(Value::Null));
+            }
+        }
+    }
+
+    /// Keep the result of the hint request if it has been received, without waiting.
+    fn receive_hint(&mut self) -> Result<()> {
+        while self.hint.as_ref().map_or(false, |hint| hint.result.is_none()) {
+            match try!(self.receive(Duration::from_millis(0))) {
+                Some(response) => self.keep_hint(response),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the whole document if it changed.
+    fn sync(&mut self, text: &str) -> Result<()> {
+        if self.text == text {
+            return Ok(());
+        }
+        self.text = String::from(text);
+        self.version += 1;
+        let document = object(vec![("uri", Value::from(DOCUMENT_URI)),
+                                   ("version", Value::from(self.version))]);
+        let changes = Value::Array(vec![object(vec![("text", Value::from(text))])]);
+        self.notify("textDocument/didChange",
+                    object(vec![("textDocument", document), ("contentChanges", changes)]))
+    }
+}
+
+/// Read a message framed by a `Content-Length` header
+/// (`Value::Null` if it is not valid JSON, `None` at the end of the stream).
+fn read_message<R: BufRead>(reader: &mut R) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        match reader.read_line(&mut header) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => {}
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if header.to_lowercase().starts_with("content-length:") {
+            length = header[15..].trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    if reader.read_exact(&mut body).is_err() {
+        return None;
+    }
+    Some(String::from_utf8(body).ok().and_then(|body| json::parse(&body)).unwrap_or(Value::Null))
+}
+
+/// Line and column (in UTF-16 code units) of the end of `text`.
+fn position(text: &str) -> (u64, u64) {
+    let row = text.matches('\n').count();
+    let last = text.rfind('\n').map_or(text, |i| &text[i + 1..]);
+    (row as u64, last.encode_utf16().count() as u64)
+}
+
+/// Start of the identifier before `pos`.
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| c.is_alphanumeric() || c == '_')
+        .last()
+        .map_or(pos, |(i, _)| i)
+}
+
+/// Texts of the completion items starting with `word`.
+fn candidates(result: &Value, word: &str) -> Vec<String> {
+    let items = match result.as_array().or_else(|| result.get("items").and_then(Value::as_array)) {
+        Some(items) => items,
+        None => return Vec::new(),
+    };
+    let snippet = Value::from(2);
+    items.iter()
+        .filter_map(|item| {
+            let text = item.get("textEdit").and_then(|edit| edit.get("newText"));
+            let text = match item.get("insertText") {
+                // snippets placeholders are not supported
+                Some(_) if item.get("insertTextFormat") == Some(&snippet) => None,
+                Some(insert) => text.or(Some(insert)),
+                None => text,
+            };
+            text.or_else(|| item.get("label")).and_then(Value::as_str)
+        })
+        .filter(|text| text.starts_with(word))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    use completion::{Completer, Descriptor};
+    use hint::Hinter;
+    use history::History;
+    use json::{self, Value, object};
+    use super::LspBridge;
+
+    /// Language server answering with canned results until it exits.
+    fn serve(stream: TcpStream) {
+        let mut input = BufReader::new(stream.try_clone().unwrap());
+        let mut output = stream;
+        while let Some(message) = super::read_message(&mut input) {
+            let result = match message.get("method").and_then(Value::as_str) {
+                Some("exit") => break,
+                Some("initialize") => object(vec![("capabilities", object(vec![]))]),
+                Some("textDocument/completion") => {
+                    json::parse(r#"[{"label": "print"}, {"label": "println"}]"#).unwrap()
+                }
+                Some("textDocument/signatureHelp") => {
+                    json::parse(r#"{"signatures": [{"label": "print(value)"}]}"#).unwrap()
+                }
+                _ => Value::Null,
+            };
+            let id = match message.get("id") {
+                Some(id) => id.clone(),
+                None => continue, // notification
+            };
+            let body = object(vec![("jsonrpc", Value::from("2.0")),
+                                   ("id", id),
+                                   ("result", result)])
+                .to_string();
+            write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        }
+    }
+
+    #[test]
+    fn stub_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = listener.accept().unwrap().0;
+        let server = thread::spawn(move || serve(server));
+        let input = stream.try_clone().unwrap();
+        let bridge = LspBridge::connect(input, Box::new(stream), None, "python").unwrap();
+        bridge.set_timeout(Duration::from_secs(10));
+        bridge.set_context("x = 1\n");
+
+        assert_eq!((0, vec![String::from("print"), String::from("println")]),
+                   bridge.complete("pr", 2).unwrap());
+        assert_eq!(Some(String::from("print(value)")), bridge.describe("print(", 6));
+        // the hint is received in the background
+        let history = History::new();
+        assert_eq!(None, bridge.hint("pr", 2, &history));
+        while bridge.is_pending() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(Some(String::from("int")), bridge.hint("pr", 2, &history));
+        drop(bridge);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn read_message() {
+        let input = "Content-Length: 8\r\n\r\n{\"id\":1}\
+                     Content-Type: x\r\ncontent-length: 2\r\n\r\n[]";
+        let mut reader = Cursor::new(input.as_bytes());
+        assert_eq!(Some(json::object(vec![("id", json::Value::from(1))])),
+                   super::read_message(&mut reader));
+        assert_eq!(Some(json::Value::Array(vec![])), super::read_message(&mut reader));
+        assert_eq!(None, super::read_message(&mut reader));
+    }
+
+    #[test]
+    fn position() {
+        assert_eq!((0, 0), super::position(""));
+        assert_eq!((1, 3), super::position("x = 1\nfoo"));
+        assert_eq!((0, 3), super::position("é😀"));
+    }
+
+    #[test]
+    fn word_start() {
+        assert_eq!(4, super::word_start("foo(ba", 6));
+        assert_eq!(4, super::word_start("foo(", 4));
+        assert_eq!(3, super::word_start("ab ", 3));
+    }
+
+    #[test]
+    fn candidates() {
+        let result = json::parse(r#"{"items": [
+            {"label": "print"},
+            {"label": "println", "insertText": "println"},
+            {"label": "pr()", "insertText": "pr($1)", "insertTextFormat": 2},
+            {"label": "x", "textEdit": {"newText": "private"}},
+            {"label": "len"}]}"#)
+            .unwrap();
+        assert_eq!(vec!["print", "println", "pr()", "private"],
+                   super::candidates(&result, "pr"));
+        let result = json::parse(r#"[{"label": "len"}]"#).unwrap();
+        assert_eq!(vec!["len"], super::candidates(&result, ""));
+        assert!(super::candidates(&json::Value::Null, "").is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+//! Transient message and status bar displayed below the edited line
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Handle shared with the hooks (completer, accept hook, user commands, ...)
+/// to display a message (like an error) below the edited line
+/// until the next key is pressed, instead of printing it over the display.
+/// See `Editor::message_line`.
+#[derive(Clone, Debug, Default)]
+pub struct MessageLine {
+    message: Rc<RefCell<Option<String>>>,
+}
+
+impl MessageLine {
+    pub fn new() -> MessageLine {
+        MessageLine::default()
+    }
+
+    /// Display `message` (replacing the previous one) once the current command is done.
+    pub fn show<S: Into<String>>(&self, message: S) {
+        *self.message.borrow_mut() = Some(message.into());
+    }
+
+    /// Remove the message not displayed yet, if any.
+    pub fn clear(&self) {
+        *self.message.borrow_mut() = None;
+    }
+
+    /// Return the message not displayed yet, if any, and remove it
+    /// (the editor takes it to display it).
+    pub fn take(&self) -> Option<String> {
+        self.message.borrow_mut().take()
+    }
+}
+
+/// Handle shared with the hooks to replace the rows displayed at the bottom
+/// of the edited line, even while a line is edited.
+/// See `Editor::status_bar`.
+#[derive(Clone, Debug, Default)]
+pub struct StatusBar {
+    rows: Rc<RefCell<Vec<String>>>,
+    changed: Rc<Cell<bool>>,
+}
+
+impl StatusBar {
+    pub fn new() -> StatusBar {
+        StatusBar::default()
+    }
+
+    /// Replace the rows (an empty `rows` removes the status bar),
+    /// displayed once the current command is done.
+    pub fn set(&self, rows: Vec<String>) {
+        *self.rows.borrow_mut() = rows;
+        self.changed.set(true);
+    }
+
+    /// Return the rows.
+    pub fn rows(&self) -> Vec<String> {
+        self.rows.borrow().clone()
+    }
+
+    /// Return the rows if they have been replaced since the last call
+    /// (the editor takes them to display them).
+    pub fn take_changed(&self) -> Option<Vec<String>> {
+        if self.changed.get() {
+            self.changed.set(false);
+            Some(self.rows())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MessageLine, StatusBar};
+
+    #[test]
+    fn shared() {
+        let messages = MessageLine::new();
+        let hook = messages.clone();
+        hook.show("first");
+        hook.show("second");
+        assert_eq!(Some(String::from("second")), messages.take());
+        assert_eq!(None, messages.take());
+        hook.show("third");
+        messages.clear();
+        assert_eq!(None, hook.take());
+    }
+
+    #[test]
+    fn status_bar() {
+        let status = StatusBar::new();
+        let hook = status.clone();
+        assert_eq!(None, status.take_changed());
+        hook.set(vec![String::from("[INSERT]")]);
+        assert_eq!(Some(vec![String::from("[INSERT]")]), status.take_changed());
+        assert_eq!(None, status.take_changed());
+        assert_eq!(vec![String::from("[INSERT]")], hook.rows());
+    }
+}
@@ -0,0 +1,72 @@
+//! Observation of the editor activity (for metrics or debugging key bindings)
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::time::Duration;
+
+use consts::KeyPress;
+use keymap::{Cmd, key_name};
+
+/// Name of the environment variable activating the `DebugLogger`.
+pub const LOG_ENV_VAR: &'static str = "RUSTYLINE_LOG";
+
+/// Receives the events of the edition loop.
+/// All methods do nothing by default.
+pub trait Observer {
+    /// Called when `key` has been decoded from the raw terminal input `seq`
+    /// (empty when the raw input is not available).
+    fn key_decoded(&self, seq: &str, key: KeyPress) {
+        let _ = (seq, key);
+    }
+    /// Called before `cmd` is executed.
+    /// `key` is `None` when the command is not bound to the key just read
+    /// (replayed change, command palette selection).
+    fn command_dispatched(&self, key: Option<KeyPress>, cmd: &Cmd) {
+        let _ = (key, cmd);
+    }
+    /// Called after the line has been refreshed, with the time it took.
+    fn refreshed(&self, duration: Duration) {
+        let _ = duration;
+    }
+}
+
+impl Observer for () {}
+
+/// Observer appending each event to a file, one per line.
+pub struct DebugLogger {
+    file: File,
+}
+
+impl DebugLogger {
+    pub fn new(file: File) -> DebugLogger {
+        DebugLogger { file: file }
+    }
+
+    /// Log to the file named by the `RUSTYLINE_LOG` environment variable, if set.
+    pub fn from_env() -> Option<DebugLogger> {
+        let path = match env::var_os(LOG_ENV_VAR) {
+            Some(ref path) if !path.is_empty() => path.clone(),
+            _ => return None,
+        };
+        OpenOptions::new().create(true).append(true).open(path).ok().map(DebugLogger::new)
+    }
+
+    fn log(&self, line: &str) {
+        // logging must not break the edition
+        let _ = writeln!(&self.file, "{}", line);
+    }
+}
+
+impl Observer for DebugLogger {
+    fn key_decoded(&self, seq: &str, key: KeyPress) {
+        self.log(&format!("key {:?} -> {}", seq, key_name(key)));
+    }
+    fn command_dispatched(&self, key: Option<KeyPress>, cmd: &Cmd) {
+        let key = key.map_or(String::from("(replayed)"), key_name);
+        self.log(&format!("cmd {} -> {:?}", key, cmd));
+    }
+    fn refreshed(&self, duration: Duration) {
+        let micros = duration.as_secs() * 1_000_000 + (duration.subsec_nanos() / 1000) as u64;
+        self.log(&format!("refresh {}us", micros));
+    }
+}
@@ -0,0 +1,70 @@
+//! Minimal pager displaying long texts below the edited line
+use std::cmp;
+
+use consts::{Key, KeyPress};
+use tty::RawReader;
+use super::Result;
+use {State, calculate_position};
+
+/// Display `lines` one screen at a time below the edited line,
+/// without dumping them in the terminal scrollback.
+/// Space: next page, Enter: next line, b: previous page, q: quit.
+/// The edited line is restored when the user quits or goes past the end.
+pub fn page<R: RawReader>(rdr: &mut R,
+                          s: &mut State,
+                          lines: &[String],
+                          keyseq_timeout: i32)
+                          -> Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let mut top = 0;
+    loop {
+        // keep the edited line, the status bar and the pager status row visible
+        let input_rows = calculate_position(&s.displayed().0, s.prompt_size, s.cols).row + 1;
+        let reserved = input_rows + s.status.len() + 1;
+        let height = cmp::max(1, s.term.get_rows().saturating_sub(reserved));
+        let end = cmp::min(top + height, lines.len());
+        s.menu.clear();
+        s.menu.extend(lines[top..end].iter().cloned());
+        let status = if end < lines.len() {
+            format!("--More-- ({}%)", end * 100 / lines.len())
+        } else {
+            String::from("(END)")
+        };
+        s.menu.push(status);
+        try!(s.refresh_line());
+
+        match try!(rdr.next_key(keyseq_timeout)) {
+            key!(' ') |
+            key!('f') |
+            key!('y') |
+            key!('Y') |
+            key!(Key::PageDown) => {
+                if end == lines.len() {
+                    break;
+                }
+                top = end;
+            }
+            key!(Key::Enter) |
+            key!('j') |
+            key!(Key::Down) => {
+                if end == lines.len() {
+                    break;
+                }
+                top += 1;
+            }
+            key!('b') |
+            key!(Key::PageUp) => {
+                top = top.saturating_sub(height);
+            }
+            key!('k') |
+            key!(Key::Up) => {
+                top = top.saturating_sub(1);
+            }
+            _ => break,
+        }
+    }
+    s.menu.clear();
+    s.refresh_line()
+}
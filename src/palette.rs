@@ -0,0 +1,102 @@
+//! Command palette listing the named commands below the edited line
+use std::cmp;
+
+use consts::{Key, KeyPress};
+use keymap::{Cmd, Keymap, COMMANDS};
+use tty::RawReader;
+use super::Result;
+use {State, calculate_position};
+
+/// Commands whose name contains `pattern` (case insensitive), in `COMMANDS` order.
+pub fn filter(pattern: &str) -> Vec<&'static (&'static str, Cmd)> {
+    let pattern = pattern.to_lowercase();
+    COMMANDS.iter().filter(|&&(name, _)| name.contains(pattern.as_str())).collect()
+}
+
+/// Let the user select a command by typing part of its name:
+/// Up/Down (or Ctrl-P/Ctrl-N) move the selection, Enter accepts it
+/// and Esc (or Ctrl-G) cancels.
+/// Each command is listed with the keys it is currently bound to.
+pub fn select<R: RawReader>(rdr: &mut R,
+                            s: &mut State,
+                            keymap: &Keymap,
+                            keyseq_timeout: i32)
+                            -> Result<Option<Cmd>> {
+    let mut pattern = String::new();
+    let mut selected = 0;
+    let mut choice = None;
+    loop {
+        let matches = filter(&pattern);
+        // keep the edited line, the status bar and the palette prompt visible
+        let input_rows = calculate_position(&s.displayed().0, s.prompt_size, s.cols).row + 1;
+        let reserved = input_rows + s.status.len() + 1;
+        let height = cmp::max(1, s.term.get_rows().saturating_sub(reserved));
+        let top = if selected < height { 0 } else { selected + 1 - height };
+        s.menu.clear();
+        s.menu.push(format!("Command: {}", pattern));
+        for (i, &&(name, ref cmd)) in matches.iter().enumerate().skip(top).take(height) {
+            let marker = if i == selected { '>' } else { ' ' };
+            let bindings = keymap.bindings(cmd);
+            if bindings.is_empty() {
+                s.menu.push(format!("{} {}", marker, name));
+            } else {
+                s.menu.push(format!("{} {} ({})", marker, name, bindings.join(", ")));
+            }
+        }
+        try!(s.refresh_line());
+
+        match try!(rdr.next_key(keyseq_timeout)) {
+            key!(Key::Enter) |
+            ctrl!('J') => {
+                if let Some(&&(_, ref cmd)) = matches.get(selected) {
+                    choice = Some(cmd.clone());
+                    break;
+                }
+                try!(s.beep());
+            }
+            key!(Key::Up) |
+            ctrl!('P') => {
+                selected = selected.saturating_sub(1);
+            }
+            key!(Key::Down) |
+            ctrl!('N') => {
+                if selected + 1 < matches.len() {
+                    selected += 1;
+                }
+            }
+            key!(Key::Backspace) |
+            ctrl!('H') => {
+                pattern.pop();
+                selected = 0;
+            }
+            key!(c) => {
+                pattern.push(c);
+                selected = 0;
+            }
+            key!(Key::Esc) |
+            ctrl!('G') |
+            ctrl!('C') => break,
+            _ => try!(s.beep()),
+        }
+    }
+    s.menu.clear();
+    try!(s.refresh_line());
+    Ok(choice)
+}
+
+#[cfg(test)]
+mod tests {
+    use keymap::Cmd;
+    use super::filter;
+
+    #[test]
+    fn filter_by_name() {
+        let matches = filter("WORD");
+        assert!(matches.len() > 1);
+        assert!(matches.iter().all(|&&(name, _)| name.contains("word")));
+        let matches = filter("beginning-of-l");
+        assert_eq!(1, matches.len());
+        assert_eq!(Cmd::BeginningOfLine, matches[0].1);
+        assert!(filter("no such command").is_empty());
+    }
+}
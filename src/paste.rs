@@ -0,0 +1,98 @@
+//! Transformation of the text pasted in bracketed paste mode before its insertion
+//! (see `Config::bracketed_paste` and `Editor::set_paste_filter`)
+
+/// Hook transforming the pasted text.
+pub trait PasteFilter {
+    /// Return the text inserted instead of the pasted `text`
+    /// (already without its escape sequences with `Config::strip_pasted_ansi`).
+    fn filter(&self, text: &str) -> String;
+}
+
+impl<F> PasteFilter for F
+    where F: Fn(&str) -> String
+{
+    fn filter(&self, text: &str) -> String {
+        self(text)
+    }
+}
+
+/// Normalization of the pasted text, the default filter of the editor:
+/// by default, only its CRLF and CR line endings are converted to LF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasteNormalizer {
+    newlines: bool,
+    trim_trailing_whitespace: bool,
+    strip_final_newline: bool,
+}
+
+impl PasteNormalizer {
+    pub fn new() -> PasteNormalizer {
+        PasteNormalizer {
+            newlines: true,
+            trim_trailing_whitespace: false,
+            strip_final_newline: false,
+        }
+    }
+
+    /// Whether the CRLF and CR line endings are converted to LF.
+    pub fn newlines(mut self, yes: bool) -> PasteNormalizer {
+        self.newlines = yes;
+        self
+    }
+
+    /// Whether the trailing spaces and tabs of each line are removed.
+    pub fn trim_trailing_whitespace(mut self, yes: bool) -> PasteNormalizer {
+        self.trim_trailing_whitespace = yes;
+        self
+    }
+
+    /// Whether the newlines ending the text are removed
+    /// (so that a copied line is not followed by an empty one).
+    pub fn strip_final_newline(mut self, yes: bool) -> PasteNormalizer {
+        self.strip_final_newline = yes;
+        self
+    }
+}
+
+impl Default for PasteNormalizer {
+    fn default() -> PasteNormalizer {
+        PasteNormalizer::new()
+    }
+}
+
+impl PasteFilter for PasteNormalizer {
+    fn filter(&self, text: &str) -> String {
+        let mut text = if self.newlines {
+            text.replace("\r\n", "\n").replace('\r', "\n")
+        } else {
+            text.to_owned()
+        };
+        if self.trim_trailing_whitespace {
+            text = text.split('\n')
+                .map(|line| line.trim_right_matches(|c| c == ' ' || c == '\t'))
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        if self.strip_final_newline {
+            let len = text.trim_right_matches('\n').len();
+            text.truncate(len);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PasteFilter, PasteNormalizer};
+
+    #[test]
+    fn normalizer() {
+        let text = "if a: \r\n\tb\t\r\rc  \n";
+        assert_eq!("if a: \n\tb\t\n\nc  \n", PasteNormalizer::new().filter(text));
+        assert_eq!(text, PasteNormalizer::new().newlines(false).filter(text));
+        let trim = PasteNormalizer::new().trim_trailing_whitespace(true);
+        assert_eq!("if a:\n\tb\n\nc\n", trim.filter(text));
+        assert_eq!("if a:\n\tb\n\nc", trim.strip_final_newline(true).filter(text));
+        assert_eq!("ls", PasteNormalizer::new().strip_final_newline(true).filter("ls\r\n\r\n"));
+    }
+}
@@ -0,0 +1,278 @@
+//! Timings of each keystroke (`perf` feature): decoding of the key, execution of
+//! the command, application hooks, rendering and flush of the output
+//! (see `Editor::timings`, or set `RUSTYLINE_PERF=<file>` to append a report at exit)
+use std::cell::RefCell;
+use std::collections::vec_deque::{self, VecDeque};
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::mem;
+use std::time::{Duration, Instant};
+
+use consts::{Key, KeyPress};
+use keymap::key_name;
+
+/// Name of the environment variable naming the file the report is appended to.
+pub const PERF_ENV_VAR: &'static str = "RUSTYLINE_PERF";
+
+/// Part of the processing of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Decoding of the key from the escape sequence (once its first byte is read)
+    Decode,
+    /// Execution of the command bound to the key (without the other stages)
+    Dispatch,
+    /// Hinter, highlighter, descriptor and completer
+    Hooks,
+    /// Rendering of the line (without the hooks and the flush)
+    Render,
+    /// Write and flush of the output to the terminal
+    Flush,
+}
+
+const STAGES: [Stage; 5] = [Stage::Decode, Stage::Dispatch, Stage::Hooks, Stage::Render,
+                            Stage::Flush];
+
+/// Time spent on a key, by stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyTiming {
+    pub key: KeyPress,
+    pub decode: Duration,
+    pub dispatch: Duration,
+    pub hooks: Duration,
+    pub render: Duration,
+    pub flush: Duration,
+}
+
+impl KeyTiming {
+    fn new(key: KeyPress) -> KeyTiming {
+        let zero = Duration::from_secs(0);
+        KeyTiming {
+            key: key,
+            decode: zero,
+            dispatch: zero,
+            hooks: zero,
+            render: zero,
+            flush: zero,
+        }
+    }
+
+    /// Time spent in `stage`.
+    pub fn get(&self, stage: Stage) -> Duration {
+        match stage {
+            Stage::Decode => self.decode,
+            Stage::Dispatch => self.dispatch,
+            Stage::Hooks => self.hooks,
+            Stage::Render => self.render,
+            Stage::Flush => self.flush,
+        }
+    }
+
+    fn get_mut(&mut self, stage: Stage) -> &mut Duration {
+        match stage {
+            Stage::Decode => &mut self.decode,
+            Stage::Dispatch => &mut self.dispatch,
+            Stage::Hooks => &mut self.hooks,
+            Stage::Render => &mut self.render,
+            Stage::Flush => &mut self.flush,
+        }
+    }
+
+    /// Time spent on the key, from its decoding to the read of the next one.
+    pub fn total(&self) -> Duration {
+        self.decode + self.dispatch + self.hooks + self.render + self.flush
+    }
+}
+
+impl Default for KeyTiming {
+    fn default() -> KeyTiming {
+        KeyTiming::new(key!(Key::Null))
+    }
+}
+
+/// Timings of the last keys (the older ones are dropped).
+#[derive(Debug)]
+pub struct Timings {
+    keys: VecDeque<KeyTiming>,
+    capacity: usize,
+}
+
+impl Timings {
+    pub fn new(capacity: usize) -> Timings {
+        Timings {
+            keys: VecDeque::new(),
+            capacity: capacity,
+        }
+    }
+
+    pub fn push(&mut self, timing: KeyTiming) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.keys.len() == self.capacity {
+            self.keys.pop_front();
+        }
+        self.keys.push_back(timing);
+    }
+
+    /// Timings of the keys, from the oldest.
+    pub fn iter(&self) -> vec_deque::Iter<KeyTiming> {
+        self.keys.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear()
+    }
+
+    /// Write the mean, median, 99th percentile and maximum of each stage (in µs),
+    /// then the keys with the longest total.
+    pub fn report(&self, w: &mut Write) -> io::Result<()> {
+        try!(writeln!(w, "{} keys", self.keys.len()));
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+        try!(writeln!(w, "{:<10}{:>10}{:>10}{:>10}{:>10}", "(µs)", "mean", "p50", "p99", "max"));
+        for &stage in &STAGES {
+            let mut micros: Vec<u64> = self.keys.iter().map(|t| to_micros(t.get(stage))).collect();
+            micros.sort();
+            let mean = micros.iter().fold(0, |sum, &m| sum + m) / micros.len() as u64;
+            let percentile = |p: usize| micros[(micros.len() - 1) * p / 100];
+            try!(writeln!(w,
+                          "{:<10}{:>10}{:>10}{:>10}{:>10}",
+                          format!("{:?}", stage).to_lowercase(),
+                          mean,
+                          percentile(50),
+                          percentile(99),
+                          micros[micros.len() - 1]));
+        }
+        let mut slowest: Vec<&KeyTiming> = self.keys.iter().collect();
+        slowest.sort_by(|a, b| b.total().cmp(&a.total()));
+        try!(writeln!(w, "slowest keys:"));
+        for timing in slowest.iter().take(5) {
+            try!(writeln!(w, "{:<10}{:>10}", key_name(timing.key), to_micros(timing.total())));
+        }
+        Ok(())
+    }
+
+    /// Append the report to the file named by the `RUSTYLINE_PERF` environment variable,
+    /// if set.
+    pub fn report_to_env_file(&self) {
+        let path = match env::var_os(PERF_ENV_VAR) {
+            Some(ref path) if !path.is_empty() => path.clone(),
+            _ => return,
+        };
+        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+            // reporting must not break the application
+            let _ = self.report(&mut file);
+        }
+    }
+}
+
+fn to_micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + (duration.subsec_nanos() / 1000) as u64
+}
+
+/// `a - b`, or zero when `b` is longer.
+fn saturating_sub(a: Duration, b: Duration) -> Duration {
+    if a > b { a - b } else { Duration::from_secs(0) }
+}
+
+/// Timing of the key being processed by this thread.
+struct Current {
+    key: Option<(Instant, KeyTiming)>,
+    decode: Duration, // of the key being read
+    nested: Duration, // time of the stages run by the running one
+    done: Vec<KeyTiming>,
+}
+
+thread_local!(static CURRENT: RefCell<Current> = RefCell::new(Current {
+    key: None,
+    decode: Duration::from_secs(0),
+    nested: Duration::from_secs(0),
+    done: Vec::new(),
+}));
+
+/// Count the time spent decoding the key about to be read.
+pub fn decoded(duration: Duration) {
+    CURRENT.with(|current| current.borrow_mut().decode = duration);
+}
+
+/// Start the timing of `key`, just read.
+pub fn key_read(key: KeyPress) {
+    key_done();
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        let mut timing = KeyTiming::new(key);
+        timing.decode = mem::replace(&mut current.decode, Duration::from_secs(0));
+        current.key = Some((Instant::now(), timing));
+    });
+}
+
+/// End the timing of the current key, if any (before waiting for the next one):
+/// the time not spent in the other stages is counted as dispatch.
+pub fn key_done() {
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        if let Some((start, mut timing)) = current.key.take() {
+            let others = timing.hooks + timing.render + timing.flush;
+            timing.dispatch = saturating_sub(start.elapsed(), others);
+            current.done.push(timing);
+        }
+    });
+}
+
+/// Timings of the keys done since the last call.
+pub fn take_done() -> Vec<KeyTiming> {
+    CURRENT.with(|current| mem::replace(&mut current.borrow_mut().done, Vec::new()))
+}
+
+/// Run `f`, counting its duration in `stage` of the current key
+/// (without the nested stages).
+pub fn timed<T, F: FnOnce() -> T>(stage: Stage, f: F) -> T {
+    let outer = CURRENT.with(|current| {
+        mem::replace(&mut current.borrow_mut().nested, Duration::from_secs(0))
+    });
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        let own = saturating_sub(elapsed, current.nested);
+        if let Some((_, ref mut timing)) = current.key {
+            *timing.get_mut(stage) += own;
+        }
+        current.nested = outer + elapsed;
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{KeyTiming, Stage, Timings};
+
+    #[test]
+    fn report() {
+        let mut timings = Timings::new(2);
+        for micros in 1..4 {
+            let mut timing = KeyTiming::default();
+            timing.render = Duration::new(0, micros * 1000);
+            timings.push(timing);
+        }
+        assert_eq!(2, timings.len());
+        assert_eq!(Duration::new(0, 2000), timings.iter().next().unwrap().get(Stage::Render));
+        let mut report = Vec::new();
+        timings.report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("2 keys\n"));
+        assert!(report.contains("\nrender             2         2         2         3\n"));
+    }
+}
@@ -0,0 +1,149 @@
+//! Byte-level pre-processing of the terminal input, before the keys are decoded
+//! (to strip or translate the bytes of a transport protocol).
+use remote::WindowSize;
+
+/// Transformation of the bytes read from the terminal (unix only).
+pub trait InputPreprocessor {
+    /// Called with each chunk of bytes read: append the bytes to decode to `output`.
+    /// A protocol sequence may span several chunks, so nothing may be appended
+    /// until the rest of it has been read.
+    fn process(&mut self, input: &[u8], output: &mut Vec<u8>);
+}
+
+const IAC: u8 = 255;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const WILL: u8 = 251;
+const DONT: u8 = 254;
+const NAWS: u8 = 31;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TelnetState {
+    Data,
+    Cr, // after a carriage return (followed by a NUL or a line feed)
+    Iac, // after an IAC
+    Option, // after WILL, WONT, DO or DONT
+    Sub, // in a subnegotiation
+    SubIac, // after an IAC in a subnegotiation
+}
+
+/// Strip the telnet commands (IAC sequences) and subnegotiations,
+/// and decode CR NUL and CR LF as a single CR.
+#[derive(Debug)]
+pub struct TelnetFilter {
+    state: TelnetState,
+    sub: Vec<u8>, // bytes of the current subnegotiation
+    size: Option<WindowSize>,
+}
+
+impl TelnetFilter {
+    pub fn new() -> TelnetFilter {
+        TelnetFilter {
+            state: TelnetState::Data,
+            sub: Vec::new(),
+            size: None,
+        }
+    }
+
+    /// Filter updating `size` with the window sizes sent by the client
+    /// (NAWS subnegotiations, once the server has sent IAC DO NAWS).
+    pub fn with_window_size(size: WindowSize) -> TelnetFilter {
+        TelnetFilter { size: Some(size), ..TelnetFilter::new() }
+    }
+
+    fn end_sub(&mut self) {
+        if let Some(ref size) = self.size {
+            if self.sub.len() == 5 && self.sub[0] == NAWS {
+                let cols = (self.sub[1] as usize) << 8 | self.sub[2] as usize;
+                let rows = (self.sub[3] as usize) << 8 | self.sub[4] as usize;
+                size.set(cols, rows);
+            }
+        }
+        self.sub.clear();
+    }
+}
+
+impl Default for TelnetFilter {
+    fn default() -> TelnetFilter {
+        TelnetFilter::new()
+    }
+}
+
+impl InputPreprocessor for TelnetFilter {
+    fn process(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        for &b in input {
+            self.state = match (self.state, b) {
+                (TelnetState::Cr, 0) |
+                (TelnetState::Cr, b'\n') => TelnetState::Data,
+                (TelnetState::Data, IAC) |
+                (TelnetState::Cr, IAC) => TelnetState::Iac,
+                (TelnetState::Data, b'\r') |
+                (TelnetState::Cr, b'\r') => {
+                    output.push(b);
+                    TelnetState::Cr
+                }
+                (TelnetState::Data, _) |
+                (TelnetState::Cr, _) => {
+                    output.push(b);
+                    TelnetState::Data
+                }
+                (TelnetState::Iac, IAC) => {
+                    // escaped 255 data byte
+                    output.push(b);
+                    TelnetState::Data
+                }
+                (TelnetState::Iac, SB) => TelnetState::Sub,
+                (TelnetState::Iac, b) if b >= WILL && b <= DONT => TelnetState::Option,
+                (TelnetState::Iac, _) |
+                (TelnetState::Option, _) => TelnetState::Data,
+                (TelnetState::Sub, IAC) => TelnetState::SubIac,
+                (TelnetState::Sub, b) |
+                (TelnetState::SubIac, b @ IAC) => {
+                    self.sub.push(b);
+                    TelnetState::Sub
+                }
+                (TelnetState::SubIac, SE) => {
+                    self.end_sub();
+                    TelnetState::Data
+                }
+                (TelnetState::SubIac, _) => TelnetState::Sub,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use remote::WindowSize;
+    use super::{InputPreprocessor, TelnetFilter};
+
+    fn process(filter: &mut TelnetFilter, input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        filter.process(input, &mut output);
+        output
+    }
+
+    #[test]
+    fn telnet_filter() {
+        let mut filter = TelnetFilter::new();
+        // DO ECHO, then the terminal type subnegotiation
+        let input = b"\xff\xfd\x01l\xff\xfa\x18\x00xterm\xff\xf0s";
+        assert_eq!(b"ls", &process(&mut filter, input)[..]);
+        assert_eq!(b"\r", &process(&mut filter, b"\r\x00")[..]);
+        assert_eq!(b"\r\xff", &process(&mut filter, b"\r\n\xff\xff")[..]);
+        // split in several chunks
+        assert!(process(&mut filter, b"\xff").is_empty());
+        assert!(process(&mut filter, b"\xfb").is_empty());
+        assert_eq!(b"a", &process(&mut filter, b"\x03a")[..]);
+    }
+
+    #[test]
+    fn naws() {
+        let size = WindowSize::default();
+        let mut filter = TelnetFilter::with_window_size(size.clone());
+        // 255 columns (escaped IAC) and 50 rows, in two chunks
+        assert!(process(&mut filter, b"\xff\xfa\x1f\x00\xff\xff\x00").is_empty());
+        assert_eq!(b"x", &process(&mut filter, b"\x32\xff\xf0x")[..]);
+        assert_eq!((255, 50), size.get());
+    }
+}
@@ -0,0 +1,266 @@
+//! Yes/no, choice and number prompts built on the line rendering
+//! (see `Editor::prompt_confirm`)
+use std::cmp;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use consts::{Key, KeyPress};
+use error::ReadlineError;
+use tty::RawReader;
+use super::Result;
+use {State, calculate_position};
+use {edit_backspace, edit_insert, edit_move_end, edit_move_home, edit_move_left,
+     edit_move_right};
+
+/// Numbers read by `Editor::prompt_number`.
+pub trait Number: FromStr + PartialOrd + Display + Copy {
+    /// Number incremented (or decremented) by one step, with Up (or Down).
+    fn step(self, up: bool) -> Self;
+}
+
+macro_rules! number {
+    ($($t:ty, $one:expr);*) => {
+        $(
+        impl Number for $t {
+            fn step(self, up: bool) -> $t {
+                if up { self + $one } else { self - $one }
+            }
+        }
+        )*
+    }
+}
+
+number!(i8, 1; i16, 1; i32, 1; i64, 1; isize, 1; u8, 1; u16, 1; u32, 1; u64, 1; usize, 1;
+        f32, 1.; f64, 1.);
+
+/// Answer of a yes/no question typed on a whole line (`default` when empty),
+/// or `None` when it is neither yes nor no.
+pub fn parse_confirm(answer: &str, default: bool) -> Option<bool> {
+    match answer.trim().to_lowercase().as_str() {
+        "" => Some(default),
+        "y" | "yes" => Some(true),
+        "n" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Index of the option chosen on a whole line, by number (from 1) or by name.
+pub fn parse_select(answer: &str, options: &[&str]) -> Option<usize> {
+    let answer = answer.trim();
+    match answer.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= options.len() => Some(n - 1),
+        _ => options.iter().position(|option| *option == answer),
+    }
+}
+
+/// Number typed in `input`, or the error displayed below it
+/// when it is not a number between `min` and `max` (inclusive).
+pub fn parse_number<T: Number>(input: &str, min: T, max: T) -> ::std::result::Result<T, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(String::from("A number is required"));
+    }
+    match input.parse::<T>() {
+        Ok(n) if n < min || n > max => Err(format!("Must be between {} and {}", min, max)),
+        Ok(n) => Ok(n),
+        Err(_) => Err(String::from("Not a valid number")),
+    }
+}
+
+/// Let the user type a number between `min` and `max`:
+/// only the chars of numbers are inserted, Up/Down increment/decrement it (within the range)
+/// and the validation error (if any) is displayed below the line.
+/// Enter accepts a valid number and Ctrl-C (or Esc) interrupts the prompt.
+pub fn number<R: RawReader, T: Number>(rdr: &mut R,
+                                       s: &mut State,
+                                       min: T,
+                                       max: T,
+                                       keyseq_timeout: i32)
+                                       -> Result<T> {
+    try!(s.refresh_line());
+    loop {
+        let key = try!(rdr.next_key(keyseq_timeout));
+        match key {
+            key!(Key::Enter) |
+            ctrl!('J') => {
+                match parse_number(&s.line, min, max) {
+                    Ok(n) => {
+                        try!(s.set_description(None));
+                        return Ok(n);
+                    }
+                    Err(error) => {
+                        try!(s.set_description(Some(error)));
+                        try!(s.beep());
+                        continue;
+                    }
+                }
+            }
+            key!(c) if c.is_digit(10) || "+-.eE".contains(c) => try!(edit_insert(s, c)),
+            key!(Key::Backspace) |
+            ctrl!('H') => try!(edit_backspace(s)),
+            key!(Key::Left) |
+            ctrl!('B') => try!(edit_move_left(s)),
+            key!(Key::Right) |
+            ctrl!('F') => try!(edit_move_right(s)),
+            key!(Key::Home) |
+            ctrl!('A') => try!(edit_move_home(s)),
+            key!(Key::End) |
+            ctrl!('E') => try!(edit_move_end(s)),
+            key!(Key::Up) |
+            key!(Key::Down) => {
+                let up = key == key!(Key::Up);
+                let n = match s.line.trim().parse::<T>() {
+                    // no overflow at the bounds of the type
+                    Ok(n) if up && n >= max => max,
+                    Ok(n) if !up && n <= min => min,
+                    Ok(n) => n.step(up),
+                    // start from the nearest bound
+                    Err(_) => if up { min } else { max },
+                };
+                let n = if n < min {
+                    min
+                } else if n > max {
+                    max
+                } else {
+                    n
+                };
+                let text = n.to_string();
+                s.line.update(&text, text.len());
+                try!(s.refresh_line());
+            }
+            key!(Key::Esc) |
+            ctrl!('G') |
+            ctrl!('C') => return Err(ReadlineError::Interrupted),
+            ctrl!('D') => return Err(ReadlineError::Eof),
+            _ => {
+                try!(s.beep());
+                continue;
+            }
+        }
+        // the error is updated as the number is typed (but not required yet)
+        let error = if s.line.is_empty() {
+            None
+        } else {
+            parse_number(&s.line, min, max).err()
+        };
+        try!(s.set_description(error));
+    }
+}
+
+/// Wait for `y` or `n` (Enter for `default`), then display the answer after the prompt.
+pub fn confirm<R: RawReader>(rdr: &mut R,
+                             s: &mut State,
+                             default: bool,
+                             keyseq_timeout: i32)
+                             -> Result<bool> {
+    try!(s.refresh_line());
+    let answer;
+    loop {
+        match try!(rdr.next_key(keyseq_timeout)) {
+            key!('y') | key!('Y') => answer = true,
+            key!('n') | key!('N') => answer = false,
+            key!(Key::Enter) |
+            ctrl!('J') => answer = default,
+            ctrl!('C') => return Err(ReadlineError::Interrupted),
+            ctrl!('D') => return Err(ReadlineError::Eof),
+            _ => {
+                try!(s.beep());
+                continue;
+            }
+        }
+        break;
+    }
+    let text = if answer { "yes" } else { "no" };
+    s.line.update(text, text.len());
+    try!(s.refresh_line());
+    Ok(answer)
+}
+
+/// Let the user choose one of `options` listed below the prompt:
+/// Up/Down (or Ctrl-P/Ctrl-N) move the selection, a digit selects the matching
+/// option and Enter accepts it. Esc (or Ctrl-G, Ctrl-C) interrupts the prompt.
+pub fn select<R: RawReader>(rdr: &mut R,
+                            s: &mut State,
+                            options: &[&str],
+                            keyseq_timeout: i32)
+                            -> Result<usize> {
+    let mut selected = 0;
+    loop {
+        // keep the prompt visible
+        let input_rows = calculate_position(&s.displayed().0, s.prompt_size, s.cols).row + 1;
+        let height = cmp::max(1, s.term.get_rows().saturating_sub(input_rows));
+        let top = if selected < height { 0 } else { selected + 1 - height };
+        s.menu.clear();
+        for (i, option) in options.iter().enumerate().skip(top).take(height) {
+            let marker = if i == selected { '>' } else { ' ' };
+            s.menu.push(format!("{} {}. {}", marker, i + 1, option));
+        }
+        try!(s.refresh_line());
+
+        match try!(rdr.next_key(keyseq_timeout)) {
+            key!(Key::Enter) |
+            ctrl!('J') => break,
+            key!(Key::Up) |
+            ctrl!('P') => {
+                selected = selected.saturating_sub(1);
+            }
+            key!(Key::Down) |
+            ctrl!('N') => {
+                if selected + 1 < options.len() {
+                    selected += 1;
+                }
+            }
+            key!(c) if c.is_digit(10) => {
+                match parse_select(&c.to_string(), options) {
+                    Some(index) => selected = index,
+                    None => try!(s.beep()),
+                }
+            }
+            key!(Key::Esc) |
+            ctrl!('G') |
+            ctrl!('C') => {
+                s.menu.clear();
+                try!(s.refresh_line());
+                return Err(ReadlineError::Interrupted);
+            }
+            ctrl!('D') => return Err(ReadlineError::Eof),
+            _ => try!(s.beep()),
+        }
+    }
+    s.menu.clear();
+    s.line.update(options[selected], options[selected].len());
+    try!(s.refresh_line());
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_confirm() {
+        assert_eq!(Some(true), super::parse_confirm("\n", true));
+        assert_eq!(Some(false), super::parse_confirm(" \n", false));
+        assert_eq!(Some(true), super::parse_confirm("Yes\n", false));
+        assert_eq!(Some(false), super::parse_confirm("n", true));
+        assert_eq!(None, super::parse_confirm("maybe", true));
+    }
+
+    #[test]
+    fn parse_number() {
+        assert_eq!(Ok(42), super::parse_number(" 42\n", 0, 100));
+        assert_eq!(Ok(-1.5), super::parse_number("-1.5", -2., 2.));
+        assert!(super::parse_number("", 0, 100).is_err());
+        assert!(super::parse_number("4x", 0, 100).is_err());
+        assert_eq!(Err(String::from("Must be between 0 and 100")),
+                   super::parse_number("101", 0, 100));
+    }
+
+    #[test]
+    fn parse_select() {
+        let options = ["red", "green", "blue"];
+        assert_eq!(Some(0), super::parse_select("1\n", &options));
+        assert_eq!(Some(2), super::parse_select("blue", &options));
+        assert_eq!(None, super::parse_select("4", &options));
+        assert_eq!(None, super::parse_select("0", &options));
+        assert_eq!(None, super::parse_select("", &options));
+    }
+}
@@ -0,0 +1,68 @@
+//! Terminals of remote clients (like the connections of a telnet or SSH server),
+//! implemented by the application (see `Editor::with_terminal`).
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Size of a remote terminal, shared by its `Term` and the protocol which updates it
+/// (like the telnet NAWS option, see `preprocess::TelnetFilter::with_window_size`).
+#[derive(Clone, Debug)]
+pub struct WindowSize {
+    size: Rc<Cell<(usize, usize)>>,
+    changed: Rc<Cell<bool>>,
+}
+
+impl WindowSize {
+    pub fn new(cols: usize, rows: usize) -> WindowSize {
+        WindowSize {
+            size: Rc::new(Cell::new((cols, rows))),
+            changed: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Number of columns and rows.
+    pub fn get(&self) -> (usize, usize) {
+        self.size.get()
+    }
+
+    /// Resize: the line is redisplayed when the next key is pressed.
+    /// Zero values (unknown dimensions) are ignored.
+    pub fn set(&self, cols: usize, rows: usize) {
+        let (old_cols, old_rows) = self.size.get();
+        let size = (if cols > 0 { cols } else { old_cols }, if rows > 0 { rows } else { old_rows });
+        if size != (old_cols, old_rows) {
+            self.size.set(size);
+            self.changed.set(true);
+        }
+    }
+
+    /// Tell if the size changed since the last call.
+    pub fn take_changed(&self) -> bool {
+        let changed = self.changed.get();
+        self.changed.set(false);
+        changed
+    }
+}
+
+impl Default for WindowSize {
+    fn default() -> WindowSize {
+        WindowSize::new(80, 24)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WindowSize;
+
+    #[test]
+    fn window_size() {
+        let size = WindowSize::default();
+        let shared = size.clone();
+        assert!(!size.take_changed());
+        shared.set(100, 0);
+        assert_eq!((100, 24), size.get());
+        assert!(size.take_changed());
+        assert!(!size.take_changed());
+        shared.set(100, 24);
+        assert!(!size.take_changed());
+    }
+}
@@ -0,0 +1,210 @@
+//! Scripted input (keys replayed from a file for demos and regression tests)
+//! and recording of the keys read by the editor.
+//!
+//! A script contains one key per line, named like in the command palette
+//! (`a`, `Space`, `Ctrl-A`, `Meta-B`, `Enter`, ...), optionally preceded by
+//! the delay in milliseconds to wait before the key is read (`+120 Ctrl-A`).
+//! Blank lines and lines starting with `# ` are ignored.
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use consts::KeyPress;
+use keymap::{key_name, parse_key_name};
+use super::Result;
+
+/// Keys read instead of the terminal input.
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    keys: VecDeque<(u64, KeyPress)>, // (delay in ms, key)
+    timing: bool,
+}
+
+impl Script {
+    /// Load the script stored in the file at `path`.
+    pub fn open<P: AsRef<Path> + ?Sized>(path: &P) -> Result<Script> {
+        let mut file = try!(File::open(path));
+        let mut text = String::new();
+        try!(file.read_to_string(&mut text));
+        Script::parse(&text)
+    }
+
+    /// Parse the content of a script.
+    pub fn parse(text: &str) -> Result<Script> {
+        let mut keys = VecDeque::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("# ") {
+                continue;
+            }
+            let (delay, name) = match line.find(' ') {
+                Some(sp) if line.starts_with('+') => {
+                    (line[1..sp].parse().ok(), line[sp + 1..].trim())
+                }
+                _ => (Some(0), line),
+            };
+            match (delay, parse_key_name(name)) {
+                (Some(delay), Some(key)) => keys.push_back((delay, key)),
+                _ => {
+                    let msg = format!("invalid key at line {}: {}", i + 1, line);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg).into());
+                }
+            }
+        }
+        Ok(Script {
+            keys: keys,
+            timing: true,
+        })
+    }
+
+    /// Tell if the delays are honored (`true` by default).
+    /// Disable them to replay a script as fast as possible.
+    pub fn set_timing(&mut self, timing: bool) {
+        self.timing = timing;
+    }
+
+    /// Number of keys not yet read.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Tell if all the keys have been read.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Wait at most `timeout_ms` (forever if negative) for the delay
+    /// of the next key and tell if it has elapsed.
+    pub fn poll(&mut self, timeout_ms: i32) -> bool {
+        let timing = self.timing;
+        let delay = match self.keys.front_mut() {
+            Some(&mut (ref mut delay, _)) => delay,
+            None => return false,
+        };
+        if !timing || *delay == 0 {
+            return true;
+        }
+        if timeout_ms < 0 || *delay <= timeout_ms as u64 {
+            thread::sleep(Duration::from_millis(*delay));
+            *delay = 0;
+            true
+        } else {
+            thread::sleep(Duration::from_millis(timeout_ms as u64));
+            *delay -= timeout_ms as u64;
+            false
+        }
+    }
+
+    /// Wait for the delay of the next key and return it.
+    pub fn next_key(&mut self) -> Option<KeyPress> {
+        self.keys.pop_front().map(|(delay, key)| {
+            if self.timing && delay > 0 {
+                thread::sleep(Duration::from_millis(delay));
+            }
+            key
+        })
+    }
+}
+
+/// Record the keys read by the editor, with their timing, in the script format.
+pub struct Recorder {
+    out: Box<Write>,
+    last: Option<Instant>,
+}
+
+impl Recorder {
+    pub fn new<W: Write + 'static>(out: W) -> Recorder {
+        Recorder {
+            out: Box::new(out),
+            last: None,
+        }
+    }
+
+    /// Record the keys in the file at `path` (truncated if it exists).
+    pub fn create<P: AsRef<Path> + ?Sized>(path: &P) -> Result<Recorder> {
+        let file = try!(File::create(path));
+        Ok(Recorder::new(file))
+    }
+
+    /// Append `key` preceded by the delay since the previous key.
+    pub fn record(&mut self, key: KeyPress) -> Result<()> {
+        let now = Instant::now();
+        let delay = match self.last {
+            Some(last) => {
+                let elapsed = now.duration_since(last);
+                elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64
+            }
+            None => 0,
+        };
+        self.last = Some(now);
+        try!(write!(self.out, "+{} {}\n", delay, key_name(key)));
+        try!(self.out.flush());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use consts::{Key, KeyPress};
+    use super::{Recorder, Script};
+
+    #[test]
+    fn parse() {
+        let mut script = Script::parse("# demo\na\n+20 Space\n\nCtrl-A\n+0 #\n").unwrap();
+        script.set_timing(false);
+        assert_eq!(4, script.len());
+        assert_eq!(Some(key!('a')), script.next_key());
+        assert_eq!(Some(key!(' ')), script.next_key());
+        assert_eq!(Some(ctrl!('A')), script.next_key());
+        assert_eq!(Some(key!('#')), script.next_key());
+        assert_eq!(None, script.next_key());
+
+        let mut script = Script::parse("a\n+50 b\n").unwrap();
+        assert!(script.poll(0));
+        script.next_key();
+        assert!(!script.poll(10));
+        assert!(script.poll(40));
+        script.set_timing(false);
+        assert!(script.poll(0));
+        script.next_key();
+        assert!(!script.poll(0));
+
+        assert!(Script::parse("a\nabc").is_err());
+        assert!(Script::parse("+x a").is_err());
+    }
+
+    #[derive(Clone)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn record() {
+        let buffer = Buffer(Rc::new(RefCell::new(Vec::new())));
+        let mut recorder = Recorder::new(buffer.clone());
+        recorder.record(key!('a')).unwrap();
+        recorder.record(key!(Key::Enter)).unwrap();
+        let text = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert!(text.starts_with("+0 a\n+"));
+        assert!(text.ends_with(" Enter\n"));
+
+        let mut script = Script::parse(&text).unwrap();
+        script.set_timing(false);
+        let keys: Vec<KeyPress> = (0..2).filter_map(|_| script.next_key()).collect();
+        assert_eq!(vec![key!('a'), key!(Key::Enter)], keys);
+    }
+}
@@ -0,0 +1,133 @@
+//! Splitting of a line into words with POSIX-shell-like quoting rules,
+//! shared by the filename completion and the last argument yanking
+//! so that they agree on the word boundaries.
+use std::borrow::Cow::{self, Borrowed, Owned};
+
+/// Word of a line, with its quotes and escapes removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    /// Start (in bytes) of the word in the line, quotes included
+    pub start: usize,
+    /// End (in bytes) of the word in the line, quotes included
+    pub end: usize,
+    pub value: String,
+    /// Quote left open at the end of the line (the word is being typed)
+    pub open_quote: Option<char>,
+}
+
+/// Split `line` into words separated by whitespaces.
+/// Text between single quotes is taken literally, a backslash escapes
+/// the next character (only `$`, `` ` ``, `"`, `\` and newline between double quotes).
+pub fn split(line: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if word.is_none() && !c.is_whitespace() {
+            word = Some(Word {
+                start: i,
+                end: i,
+                value: String::new(),
+                open_quote: None,
+            });
+        }
+        if escaped {
+            escaped = false;
+            let value = &mut word.as_mut().unwrap().value;
+            if quote == Some('"') && !is_escapable(c) {
+                value.push('\\');
+            }
+            if c != '\n' {
+                value.push(c);
+            }
+            continue;
+        }
+        match quote {
+            Some('\'') if c == '\'' => quote = None,
+            Some('\'') => word.as_mut().unwrap().value.push(c),
+            Some(_) if c == '"' => quote = None,
+            Some(_) if c == '\\' => escaped = true,
+            Some(_) => word.as_mut().unwrap().value.push(c),
+            None if c.is_whitespace() => {
+                if let Some(mut w) = word.take() {
+                    w.end = i;
+                    words.push(w);
+                }
+            }
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '\\' => escaped = true,
+            None => word.as_mut().unwrap().value.push(c),
+        }
+    }
+    if let Some(mut w) = word {
+        w.end = line.len();
+        w.open_quote = quote;
+        words.push(w);
+    }
+    words
+}
+
+fn is_escapable(c: char) -> bool {
+    c == '$' || c == '`' || c == '"' || c == '\\' || c == '\n'
+}
+
+/// Quote `word` (if needed) so that it is split as a single word.
+pub fn quote(word: &str) -> Cow<str> {
+    let safe = |c: char| c.is_alphanumeric() || "-_./:=@%+,".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        return Borrowed(word);
+    }
+    Owned(format!("'{}'", word.replace('\'', "'\\''")))
+}
+
+/// Escape `text` to be inserted after an opening `quote`.
+pub fn escape_quoted(text: &str, quote: char) -> Cow<str> {
+    if quote == '"' && text.contains(is_escapable) {
+        let mut escaped = String::with_capacity(text.len() + 1);
+        for c in text.chars() {
+            if is_escapable(c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        Owned(escaped)
+    } else {
+        Borrowed(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Word, escape_quoted, quote, split};
+
+    fn values(line: &str) -> Vec<String> {
+        split(line).into_iter().map(|w| w.value).collect()
+    }
+
+    #[test]
+    fn split_words() {
+        assert_eq!(vec!["ls", "-l", "a b", "c'd", "e\\f$", "g h"],
+                   values(r#" ls  -l 'a b' "c'd" "e\f\$" g\ h"#));
+        assert!(split("  ").is_empty());
+        assert_eq!(vec!["a", "b\"c"], values(r#"a 'b"'c"#));
+        assert_eq!(vec![Word {
+                            start: 3,
+                            end: 8,
+                            value: String::from("my d"),
+                            open_quote: Some('"'),
+                        }],
+                   split("cd \"my d").split_off(1));
+    }
+
+    #[test]
+    fn quote_words() {
+        assert_eq!("file.txt", quote("file.txt"));
+        assert_eq!("'a b'", quote("a b"));
+        assert_eq!("''", quote(""));
+        assert_eq!(r#"'it'\''s'"#, quote("it's"));
+        assert_eq!(vec!["it's", "a b"], values(&format!("{} {}", quote("it's"), quote("a b"))));
+        assert_eq!(r#"a\"b\$"#, escape_quoted("a\"b$", '"'));
+        assert_eq!("a\"b$", escape_quoted("a\"b$", '\''));
+    }
+}
@@ -0,0 +1,289 @@
+//! History backed by an SQLite database (`sqlite` feature),
+//! so that several processes share their entries as they are accepted.
+use std::path::Path;
+use std::time::Duration;
+use std::usize;
+
+use rusqlite::Connection;
+use rusqlite::types::ToSql;
+
+use ::Result;
+use config::Config;
+use error::ReadlineError;
+use history::{Direction, History, HistoryBackend, HistoryScorer};
+
+/// Entries are stored in the `history` table, with the time they were added.
+///
+/// Only the most recent ones (`Config::max_history_size`) are kept in memory,
+/// to be recalled; the incremental search and `HistoryHinter` search the whole table.
+pub struct SqliteHistory {
+    conn: Connection,
+    max_len: usize,
+    history: History,
+    error: Option<ReadlineError>,
+}
+
+impl SqliteHistory {
+    /// Open (or create) the database at `path`.
+    pub fn open<P: AsRef<Path> + ?Sized>(path: &P) -> Result<SqliteHistory> {
+        let conn = try!(Connection::open(path.as_ref()));
+        // another process may be writing
+        try!(conn.busy_timeout(Duration::from_secs(5)));
+        SqliteHistory::init(conn)
+    }
+
+    /// Create a database living only as long as the history.
+    pub fn open_in_memory() -> Result<SqliteHistory> {
+        let conn = try!(Connection::open_in_memory());
+        SqliteHistory::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<SqliteHistory> {
+        try!(conn.execute_batch("CREATE TABLE IF NOT EXISTS history (
+                                     id INTEGER PRIMARY KEY AUTOINCREMENT,
+                                     entry TEXT NOT NULL,
+                                     timestamp INTEGER NOT NULL
+                                         DEFAULT (strftime('%s', 'now'))
+                                 );
+                                 CREATE INDEX IF NOT EXISTS history_entry ON history (entry);
+                                 CREATE INDEX IF NOT EXISTS history_timestamp
+                                     ON history (timestamp);"));
+        let history = History::new();
+        let mut sqlite = SqliteHistory {
+            conn: conn,
+            max_len: Config::default().max_history_size(),
+            history: history,
+            error: None,
+        };
+        try!(sqlite.reload());
+        Ok(sqlite)
+    }
+
+    /// Replace the entries kept in memory by the most recent ones of the database
+    /// (to get the entries added by other processes).
+    pub fn reload(&mut self) -> Result<()> {
+        let entries = try!(self.recent(self.max_len));
+        self.history.clear();
+        for entry in entries {
+            self.history.add(entry);
+        }
+        Ok(())
+    }
+
+    /// Return the number of entries of the database.
+    pub fn count(&self) -> Result<usize> {
+        let no_params: &[&ToSql] = &[];
+        let count: i64 = try!(self.conn
+            .query_row("SELECT COUNT(*) FROM history", no_params, |row| row.get(0)));
+        Ok(count as usize)
+    }
+
+    /// Return the most recent entries of the database (at most `max_len`), oldest first.
+    fn recent(&self, max_len: usize) -> Result<Vec<String>> {
+        let mut stmt = try!(self.conn.prepare("SELECT entry FROM (
+                                                   SELECT id, entry FROM history
+                                                   ORDER BY id DESC LIMIT ?1
+                                               ) ORDER BY id"));
+        let max_len = max_len as i64;
+        let rows = try!(stmt.query_map(&[&max_len], |row| row.get(0)));
+        let mut entries = Vec::new();
+        for entry in rows {
+            entries.push(try!(entry));
+        }
+        Ok(entries)
+    }
+
+    fn append(&self, entry: &str) -> Result<()> {
+        try!(self.conn.execute("INSERT INTO history (entry) VALUES (?1)", &[&entry]));
+        Ok(())
+    }
+}
+
+impl HistoryBackend for SqliteHistory {
+    fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    fn get(&self, index: usize) -> Option<&String> {
+        self.history.get(index)
+    }
+
+    fn add(&mut self, line: &str) -> bool {
+        if !self.history.add(line) {
+            return false;
+        }
+        if let Err(error) = self.append(line) {
+            // the entry is only kept in memory
+            self.error = Some(error);
+        }
+        true
+    }
+
+    fn clear(&mut self) {
+        if let Err(error) = self.conn.execute_batch("DELETE FROM history") {
+            // like `add`, the entries in memory are cleared anyway
+            self.error = Some(error.into());
+        }
+        self.history.clear()
+    }
+
+    /// Add the entries of the file at `path` to the database.
+    fn load(&mut self, path: &Path) -> Result<()> {
+        let mut file = History::new();
+        file.set_max_len(usize::MAX);
+        try!(file.load(path));
+        for entry in &file {
+            try!(self.append(entry));
+        }
+        self.reload()
+    }
+
+    /// Save all the entries of the database in the file at `path`.
+    fn save(&self, path: &Path) -> Result<()> {
+        let mut file = History::new();
+        file.set_max_len(usize::MAX);
+        for entry in try!(self.recent(usize::MAX)) {
+            file.add(entry);
+        }
+        file.save(path)
+    }
+
+    fn search(&self, term: &str, start: usize, dir: Direction) -> Option<usize> {
+        self.history.search(term, start, dir)
+    }
+
+    fn is_ranked(&self) -> bool {
+        self.history.is_ranked()
+    }
+
+    fn ranked_matches(&self, filter: &Fn(&str) -> bool) -> Vec<usize> {
+        self.history.ranked_matches(filter)
+    }
+
+    fn is_truncated(&self) -> bool {
+        true
+    }
+
+    fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+        let mut stmt = try!(self.conn.prepare(&format!("SELECT entry FROM history
+                                                        WHERE entry GLOB ?1
+                                                        GROUP BY entry ORDER BY MAX(id) DESC
+                                                        LIMIT {}",
+                                                       limit)));
+        let pattern = glob_prefix(prefix);
+        let rows = try!(stmt.query_map(&[&pattern], |row| row.get(0)));
+        let mut entries = Vec::new();
+        for entry in rows {
+            entries.push(try!(entry));
+        }
+        Ok(entries)
+    }
+
+    fn search_backward(&self, term: &str, limit: usize) -> Result<Vec<String>> {
+        let mut stmt = try!(self.conn.prepare(&format!("SELECT entry FROM history
+                                                        WHERE instr(entry, ?1) > 0
+                                                        ORDER BY id DESC LIMIT {}",
+                                                       limit)));
+        let rows = try!(stmt.query_map(&[&term], |row| row.get(0)));
+        let mut entries = Vec::new();
+        for entry in rows {
+            entries.push(try!(entry));
+        }
+        Ok(entries)
+    }
+
+    fn set_scorer(&mut self, scorer: Option<Box<HistoryScorer>>) {
+        self.history.set_scorer(scorer)
+    }
+
+    /// Apply the history settings of `config`, reloading the most recent entries.
+    fn apply_config(&mut self, config: &Config) {
+        self.history.apply_config(config);
+        self.max_len = config.max_history_size();
+        if let Err(error) = self.reload() {
+            // the entries in memory are only the ones already loaded
+            self.error = Some(error);
+        }
+    }
+
+    fn take_error(&mut self) -> Option<ReadlineError> {
+        self.error.take()
+    }
+}
+
+/// `GLOB` pattern matching the entries starting with `prefix`
+/// (case-sensitive, unlike `LIKE`).
+fn glob_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        match c {
+            '*' => pattern.push_str("[*]"),
+            '?' => pattern.push_str("[?]"),
+            '[' => pattern.push_str("[[]"),
+            _ => pattern.push(c),
+        }
+    }
+    pattern.push('*');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+    use config::Config;
+    use history::HistoryBackend;
+    use super::SqliteHistory;
+
+    #[test]
+    fn history() {
+        let mut history = SqliteHistory::open_in_memory().unwrap();
+        for entry in &["ls", "cd *", "cat", "cd *", "cd ~"] {
+            assert!(history.add(entry));
+        }
+        history.apply_config(&Config::builder().max_history_size(3).build());
+        assert_eq!(3, history.len());
+        assert_eq!(Some(&String::from("cat")), history.get(0));
+        assert!(history.is_truncated());
+        assert_eq!(vec!["cd ~", "cd *"], history.search_prefix("cd", 5).unwrap());
+        assert_eq!(vec!["cd *"], history.search_prefix("cd *", 5).unwrap());
+        assert!(history.search_prefix("C", 5).unwrap().is_empty());
+        assert_eq!(vec!["cd ~", "cd *", "cd *"], history.search_backward("d", 5).unwrap());
+        assert_eq!(vec!["ls"], history.search_backward("l", 5).unwrap());
+        assert_eq!(5, history.count().unwrap());
+        history.clear();
+        assert_eq!(0, history.count().unwrap());
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn shared() {
+        let dir = tempdir::TempDir::new("rustyline").unwrap();
+        let path = dir.path().join("history.sqlite3");
+        let mut first = SqliteHistory::open(&path).unwrap();
+        let mut second = SqliteHistory::open(&path).unwrap();
+        assert!(first.add("ls"));
+        assert!(second.add("cd"));
+        assert!(first.take_error().is_none());
+        assert!(second.take_error().is_none());
+        // searched in the database
+        assert_eq!(vec!["cd"], first.search_backward("d", 5).unwrap());
+        assert_eq!(vec!["ls"], second.search_prefix("l", 5).unwrap());
+        // recalled once reloaded
+        assert_eq!(1, first.len());
+        first.reload().unwrap();
+        assert_eq!(2, first.len());
+        assert_eq!(Some(&String::from("cd")), first.get(1));
+        assert_eq!(2, SqliteHistory::open(&path).unwrap().len());
+    }
+
+    #[test]
+    fn error() {
+        let mut history = SqliteHistory::open_in_memory().unwrap();
+        history.conn.execute_batch("DROP TABLE history").unwrap();
+        // kept in memory
+        assert!(history.add("ls"));
+        assert_eq!(1, history.len());
+        assert!(history.take_error().is_some());
+        assert!(history.take_error().is_none());
+    }
+}
@@ -0,0 +1,95 @@
+//! Prompts with variables, like `"{user}@{host} {cwd}> "`, evaluated on each refresh
+//! of the line (see `Editor::set_prompt_variable`)
+use std::collections::HashMap;
+
+/// Provider of the value of a prompt variable.
+pub trait PromptVariable {
+    /// Current value, displayed instead of `{name}` in the prompt
+    /// (called on each refresh, it must be cheap).
+    fn value(&self) -> String;
+}
+
+impl<F> PromptVariable for F
+    where F: Fn() -> String
+{
+    fn value(&self) -> String {
+        self()
+    }
+}
+
+/// Variables of the prompt templates, by name.
+#[derive(Default)]
+pub struct PromptVariables {
+    variables: HashMap<String, Box<PromptVariable>>,
+}
+
+impl PromptVariables {
+    pub fn new() -> PromptVariables {
+        PromptVariables::default()
+    }
+
+    /// Evaluate `{name}` with `variable`, or stop evaluating it with `None`.
+    /// Return the previous provider of the variable, if any.
+    pub fn set(&mut self,
+               name: &str,
+               variable: Option<Box<PromptVariable>>)
+               -> Option<Box<PromptVariable>> {
+        match variable {
+            Some(variable) => self.variables.insert(String::from(name), variable),
+            None => self.variables.remove(name),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty()
+    }
+
+    /// Replace each `{name}` of `template` by the value of the variable:
+    /// the unknown variables are kept as they are, and `{{` and `}}` are braces.
+    pub fn expand(&self, template: &str) -> String {
+        let mut expanded = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(open) = rest.find(|c| c == '{' || c == '}') {
+            expanded.push_str(&rest[..open]);
+            rest = &rest[open..];
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                expanded.push_str(&rest[..1]);
+                rest = &rest[2..];
+                continue;
+            }
+            let close = if rest.starts_with('{') { rest.find('}') } else { None };
+            let variable = close.and_then(|close| {
+                self.variables.get(&rest[1..close]).map(|variable| (close, variable))
+            });
+            match variable {
+                Some((close, variable)) => {
+                    expanded.push_str(&variable.value());
+                    rest = &rest[close + 1..];
+                }
+                None => {
+                    expanded.push_str(&rest[..1]);
+                    rest = &rest[1..];
+                }
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PromptVariables;
+
+    #[test]
+    fn expand() {
+        let mut variables = PromptVariables::new();
+        assert_eq!("{user}> ", variables.expand("{user}> "));
+        assert!(variables.set("user", Some(Box::new(|| String::from("root")))).is_none());
+        variables.set("cwd", Some(Box::new(|| String::from("/tmp"))));
+        assert_eq!("root@host /tmp> ", variables.expand("{user}@host {cwd}> "));
+        assert_eq!("{user} {x} } {", variables.expand("{{user}} {x} } {"));
+        assert!(variables.set("cwd", None).is_some());
+        assert_eq!("{cwd}", variables.expand("{cwd}"));
+    }
+}
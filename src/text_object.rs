@@ -0,0 +1,113 @@
+//! Text objects (like vi's `iw`, `aw`, `i"`, `a(`)
+use auto_pair::{self, DEFAULT_PAIRS};
+use dabbrev::is_word_char;
+
+/// Part of the line around the cursor, on which an editing command operates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextObject {
+    /// The word (or the run of white spaces) under the cursor
+    InnerWord,
+    /// The word under the cursor with the white spaces following (or preceding) it
+    AWord,
+    /// The text between the brackets or quotes `char` enclosing the cursor
+    Inner(char),
+    /// The text between the brackets or quotes `char` enclosing the cursor, them included
+    Around(char),
+}
+
+impl TextObject {
+    /// Return the byte range [start, end) of the text object at `pos` in `line`.
+    pub fn range(&self, line: &str, pos: usize) -> Option<(usize, usize)> {
+        match *self {
+            TextObject::InnerWord => inner_word(line, pos),
+            TextObject::AWord => {
+                inner_word(line, pos).map(|(start, end)| {
+                    let trailing = end + spaces(&line[end..]);
+                    if trailing > end {
+                        (start, trailing)
+                    } else {
+                        let leading = line[..start].trim_right().len();
+                        (leading, end)
+                    }
+                })
+            }
+            TextObject::Inner(c) => {
+                enclosing(line, pos, c).map(|(open, _, start, end)| (start + open.len_utf8(), end))
+            }
+            TextObject::Around(c) => {
+                enclosing(line, pos, c)
+                    .map(|(_, close, start, end)| (start, end + close.len_utf8()))
+            }
+        }
+    }
+}
+
+fn spaces(s: &str) -> usize {
+    s.len() - s.trim_left().len()
+}
+
+/// Word or white spaces under the cursor.
+fn inner_word(line: &str, pos: usize) -> Option<(usize, usize)> {
+    let c = match line[pos..].chars().next() {
+        Some(c) => c,
+        None => return None,
+    };
+    let same_class = |other: char| if c.is_whitespace() {
+        other.is_whitespace()
+    } else if is_word_char(c) {
+        is_word_char(other)
+    } else {
+        !other.is_whitespace() && !is_word_char(other)
+    };
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| same_class(c))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let end = line[pos..].find(|c: char| !same_class(c)).map_or(line.len(), |i| pos + i);
+    Some((start, end))
+}
+
+/// Brackets or quotes `c` enclosing `pos` with their positions.
+fn enclosing(line: &str, pos: usize, c: char) -> Option<(char, char, usize, usize)> {
+    let (open, close) = match DEFAULT_PAIRS.iter().find(|&&(open, close)| open == c || close == c) {
+        Some(&pair) => pair,
+        None => return None,
+    };
+    let range = if open == close {
+        auto_pair::enclosing_quotes(line, pos, open)
+    } else {
+        auto_pair::enclosing_pair(line, pos, open, close)
+    };
+    range.map(|(start, end)| (open, close, start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TextObject;
+
+    #[test]
+    fn words() {
+        let line = "ls  foo_bar -l";
+        assert_eq!(Some((4, 11)), TextObject::InnerWord.range(line, 6));
+        assert_eq!(Some((2, 4)), TextObject::InnerWord.range(line, 3));
+        assert_eq!(Some((4, 12)), TextObject::AWord.range(line, 4));
+        assert_eq!(Some((13, 14)), TextObject::AWord.range(line, 13));
+        assert_eq!(Some((12, 13)), TextObject::InnerWord.range(line, 12));
+        assert_eq!(None, TextObject::InnerWord.range(line, 14));
+    }
+
+    #[test]
+    fn pairs() {
+        let line = r#"echo "a (b)" (c "d")"#;
+        assert_eq!(Some((6, 11)), TextObject::Inner('"').range(line, 7));
+        assert_eq!(Some((5, 12)), TextObject::Around('"').range(line, 7));
+        assert_eq!(Some((9, 10)), TextObject::Inner('(').range(line, 9));
+        assert_eq!(Some((13, 20)), TextObject::Around(')').range(line, 15));
+        assert_eq!(None, TextObject::Inner('[').range(line, 9));
+        let line = "(é)";
+        assert_eq!(Some((1, 3)), TextObject::Inner('(').range(line, 1));
+        assert_eq!(Some((0, 4)), TextObject::Around('(').range(line, 1));
+    }
+}
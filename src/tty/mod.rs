@@ -1,43 +1,548 @@
 //! This module implements and describes common TTY methods & traits
-use std::io::Write;
+#[cfg(unix)]
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::cmp;
+#[cfg(unix)]
+use std::collections::VecDeque;
+use std::fmt;
+#[cfg(all(unix, not(test)))]
+use std::fs::File;
+#[cfg(unix)]
+use std::io::Read;
+use std::io::{self, Write};
+use std::rc::Rc;
+#[cfg(all(unix, not(test)))]
+use std::sync::Arc;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
 use ::Result;
-use consts::KeyPress;
+use config::{Config, CursorShape, OutputStreamType};
+use consts::{Key, KeyPress};
+use error::ReadlineError;
+use highlight::ColorDepth;
+use key_table::KeyTable;
+use observer::Observer;
+use preprocess::InputPreprocessor;
+use script::{Recorder, Script};
+
+/// `TERM` values of the terminals which do not support line editing (by default).
+pub static UNSUPPORTED_TERMS: [&'static str; 3] = ["dumb", "cons25", "emacs"];
 
-pub trait RawMode: Copy + Sized {
+/// Terminal mode saved when RAW mode is enabled (see `Term::enable_raw_mode`).
+pub trait RawMode {
     /// Disable RAW mode for the terminal.
     fn disable_raw_mode(&self) -> Result<()>;
 }
 
-pub trait RawReader: Sized {
+/// Reader of the keys typed on a terminal in RAW mode (see `Term::create_reader`).
+pub trait RawReader {
     /// Blocking read of key pressed.
+    /// `timeout_ms` is the time to wait for the rest of an escape sequence
+    /// after an Esc (see `Config::esc_timeout`).
     fn next_key(&mut self, timeout_ms: i32) -> Result<KeyPress>;
-    /// For CTRL-V support
-    #[cfg(unix)]
-    fn next_char(&mut self) -> Result<char>;
+    /// For CTRL-V support: the next char typed, as is.
+    /// By default, it is not supported: an error is returned.
+    fn next_char(&mut self) -> Result<char> {
+        Err(io::Error::new(io::ErrorKind::Other, "quoted insert not supported").into())
+    }
+    /// Raw input (escape sequence) the last key was decoded from, if available.
+    fn last_sequence(&self) -> &str {
+        ""
+    }
+    /// Wait at most `timeout_ms` for a key to be available
+    /// (`false` when the time ran out).
+    /// By default, keys are never reported available.
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool> {
+        thread::sleep(Duration::from_millis(cmp::max(0, timeout_ms) as u64));
+        Ok(false)
+    }
+    /// Tell if `poll` waits for the keys to be typed
+    /// (`false` when they are all available at once, or at the end of the input).
+    fn can_wait(&self) -> bool {
+        true
+    }
+}
+
+impl<R: RawReader + ?Sized> RawReader for Box<R> {
+    fn next_key(&mut self, timeout_ms: i32) -> Result<KeyPress> {
+        (**self).next_key(timeout_ms)
+    }
+    fn next_char(&mut self) -> Result<char> {
+        (**self).next_char()
+    }
+    fn last_sequence(&self) -> &str {
+        (**self).last_sequence()
+    }
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool> {
+        (**self).poll(timeout_ms)
+    }
+    fn can_wait(&self) -> bool {
+        (**self).can_wait()
+    }
+}
+
+/// Reader returning the keys of a script (if any) instead of the terminal ones,
+/// and reporting the keys read to the recorder and the observer (if set).
+pub struct ScriptedReader<'a, R> {
+    inner: R,
+    script: Option<&'a mut Script>,
+    recorder: Option<&'a mut Recorder>,
+    observer: Option<&'a Observer>,
+}
+
+impl<'a, R: RawReader> ScriptedReader<'a, R> {
+    pub fn new(inner: R,
+               script: Option<&'a mut Script>,
+               recorder: Option<&'a mut Recorder>,
+               observer: Option<&'a Observer>)
+               -> ScriptedReader<'a, R> {
+        ScriptedReader {
+            inner: inner,
+            script: script,
+            recorder: recorder,
+            observer: observer,
+        }
+    }
+
+    fn record(&mut self, key: KeyPress) -> Result<()> {
+        if let Some(observer) = self.observer {
+            observer.key_decoded(self.last_sequence(), key);
+        }
+        match self.recorder {
+            Some(ref mut recorder) => recorder.record(key),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, R: RawReader> RawReader for ScriptedReader<'a, R> {
+    fn next_key(&mut self, timeout_ms: i32) -> Result<KeyPress> {
+        // the wait for the key is not part of the timing of the previous one
+        #[cfg(feature = "perf")]
+        ::perf::key_done();
+        let key = match self.script {
+            // the end of the script is the end of the input
+            Some(ref mut script) => try!(script.next_key().ok_or(ReadlineError::Eof)),
+            None => try!(self.inner.next_key(timeout_ms)),
+        };
+        #[cfg(feature = "perf")]
+        ::perf::key_read(key);
+        try!(self.record(key));
+        Ok(key)
+    }
+    fn next_char(&mut self) -> Result<char> {
+        let c = match self.script {
+            Some(ref mut script) => {
+                match try!(script.next_key().ok_or(ReadlineError::Eof)) {
+                    key!(Key::Char(c)) => c,
+                    ctrl!(Key::Char(c)) => ((c as u8) & 0x1f) as char,
+                    key!(Key::Enter) => '\r',
+                    key!(Key::Tab) => '\t',
+                    key!(Key::Esc) => '\x1b',
+                    key!(Key::Backspace) => '\x7f',
+                    _ => '\0',
+                }
+            }
+            None => try!(self.inner.next_char()),
+        };
+        try!(self.record(::consts::char_to_key_press(c)));
+        Ok(c)
+    }
+    fn last_sequence(&self) -> &str {
+        match self.script {
+            Some(_) => "",
+            None => self.inner.last_sequence(),
+        }
+    }
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool> {
+        #[cfg(feature = "perf")]
+        {
+            if timeout_ms != 0 {
+                ::perf::key_done();
+            }
+        }
+        match self.script {
+            Some(ref mut script) => Ok(script.poll(timeout_ms)),
+            None => self.inner.poll(timeout_ms),
+        }
+    }
+    fn can_wait(&self) -> bool {
+        match self.script {
+            // waiting for the delay of the next key
+            Some(ref script) => !script.is_empty(),
+            None => self.inner.can_wait(),
+        }
+    }
+}
+
+/// Writer to the stream the editor renders to.
+pub enum OutputWriter {
+    Stream(OutputStreamType),
+    /// Controlling terminal opened by the editor (see `Config::dev_tty`).
+    #[cfg(all(unix, not(test)))]
+    Tty(Arc<File>),
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            OutputWriter::Stream(OutputStreamType::Stdout) => io::stdout().write(buf),
+            OutputWriter::Stream(OutputStreamType::Stderr) => io::stderr().write(buf),
+            #[cfg(all(unix, not(test)))]
+            OutputWriter::Tty(ref tty) => (&**tty).write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            OutputWriter::Stream(OutputStreamType::Stdout) => io::stdout().flush(),
+            OutputWriter::Stream(OutputStreamType::Stderr) => io::stderr().flush(),
+            #[cfg(all(unix, not(test)))]
+            OutputWriter::Tty(ref tty) => (&**tty).flush(),
+        }
+    }
+}
+
+/// Input preprocessor shared by the terminal and its readers
+/// (see `Editor::set_input_preprocessor`).
+#[derive(Clone, Default)]
+pub struct SharedPreprocessor(Option<Rc<RefCell<Box<InputPreprocessor>>>>);
+
+impl SharedPreprocessor {
+    pub fn new(preprocessor: Option<Box<InputPreprocessor>>) -> SharedPreprocessor {
+        SharedPreprocessor(preprocessor.map(|p| Rc::new(RefCell::new(p))))
+    }
+}
+
+impl fmt::Debug for SharedPreprocessor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedPreprocessor({})", self.0.is_some())
+    }
+}
+
+/// Escape sequences registered by the application, shared by the terminal and its readers
+/// (see `Editor::bind_escape_sequence`).
+pub type SharedKeyTable = Rc<RefCell<KeyTable>>;
+
+/// Bytes read by chunks but not decoded yet,
+/// kept by the terminal for the next line (typed ahead).
+#[cfg(unix)]
+pub type PendingInput = Rc<RefCell<VecDeque<u8>>>;
+
+/// Reader of the bytes of `inner` transformed by the preprocessor (if any).
+#[cfg(unix)]
+pub struct Preprocessed<R> {
+    inner: R,
+    preprocessor: SharedPreprocessor,
+    pending: PendingInput,
+}
+
+#[cfg(unix)]
+impl<R: Read> Preprocessed<R> {
+    pub fn new(inner: R,
+               preprocessor: SharedPreprocessor,
+               pending: PendingInput)
+               -> Preprocessed<R> {
+        Preprocessed {
+            inner: inner,
+            preprocessor: preprocessor,
+            pending: pending,
+        }
+    }
+
+    /// Tell if processed bytes are available without reading `inner`.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.borrow().is_empty()
+    }
+
+    /// Reference to the underlying reader.
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Push back bytes read ahead, to be read first (by the next reader too).
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn unread(&self, bytes: &[u8]) {
+        let mut pending = self.pending.borrow_mut();
+        for &b in bytes.iter().rev() {
+            pending.push_front(b);
+        }
+    }
+}
+
+#[cfg(unix)]
+impl<R: Read> Read for Preprocessed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.preprocessor.0.is_none() && !self.has_pending() {
+            return self.inner.read(buf);
+        }
+        let mut pending = self.pending.borrow_mut();
+        while pending.is_empty() {
+            let mut input = [0; 64];
+            let n = try!(self.inner.read(&mut input));
+            if n == 0 {
+                return Ok(0);
+            }
+            match self.preprocessor.0 {
+                Some(ref preprocessor) => {
+                    let mut output = Vec::new();
+                    preprocessor.borrow_mut().process(&input[..n], &mut output);
+                    pending.extend(output);
+                }
+                None => pending.extend(input[..n].iter().cloned()),
+            }
+        }
+        let n = cmp::min(buf.len(), pending.len());
+        for (dst, src) in buf.iter_mut().zip(pending.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+/// Terminal size queried once, then the same for all the rendering until a SIGWINCH
+/// (see `Term::update_size`).
+#[cfg(unix)]
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(test, allow(dead_code))]
+pub struct SizeCache(Cell<Option<(usize, usize)>>);
+
+#[cfg(unix)]
+#[cfg_attr(test, allow(dead_code))]
+impl SizeCache {
+    /// Size cached, or given by `query` if it is not known yet.
+    pub fn get<F: FnOnce() -> (usize, usize)>(&self, query: F) -> (usize, usize) {
+        match self.0.get() {
+            Some(size) => size,
+            None => {
+                let size = query();
+                self.0.set(Some(size));
+                size
+            }
+        }
+    }
+
+    /// Cache the size given by `query`.
+    pub fn update<F: FnOnce() -> (usize, usize)>(&self, query: F) {
+        self.0.set(Some(query()));
+    }
+
+    /// Forget the size, queried again by the next `get`.
+    pub fn invalidate(&self) {
+        self.0.set(None);
+    }
+
+    /// Take the SIGWINCH `flag` set by the signal handler,
+    /// and cache the size given by `query` if it was set.
+    pub fn sigwinch<F: FnOnce() -> (usize, usize)>(&self, flag: &AtomicBool, query: F) -> bool {
+        let resized = flag.compare_and_swap(true, false, Ordering::SeqCst);
+        if resized {
+            self.update(query);
+        }
+        resized
+    }
 }
 
-/// Terminal contract
+/// Check the `error` of a read (or of a wait for input) on the terminal:
+/// an interruption by a signal is retried (`Ok`), unless the terminal has been resized
+/// (`sigwinch`) or the process continued in the foreground (`sigcont`),
+/// for the line to be redisplayed before waiting for a key again.
+#[cfg(unix)]
+#[cfg_attr(test, allow(dead_code))]
+pub fn retry_interrupted(error: io::Error,
+                         sigwinch: &AtomicBool,
+                         sigcont: &AtomicBool)
+                         -> io::Result<()> {
+    if error.kind() == io::ErrorKind::Interrupted && !sigwinch.load(Ordering::SeqCst) &&
+       !sigcont.load(Ordering::SeqCst) {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+/// Terminal contract: the input, the output and the capabilities of a terminal.
+/// Implemented by the terminal of each platform, and by the application
+/// for a terminal of its own (see `Editor::with_terminal`).
+/// The clones of a terminal are expected to read and render on the same terminal.
 pub trait Term: Clone {
+    /// Reader of the keys typed on the terminal.
     type Reader: RawReader;
-    type Mode;
+    /// Mode restored when the edition ends.
+    type Mode: RawMode;
 
-    fn new() -> Self;
+    /// Apply the settings of `config` which concern the terminal (output stream, `/dev/tty`,
+    /// size from the environment), when the editor is created or its configuration reloaded.
+    /// By default, they are ignored.
+    fn apply_config(&mut self, config: &Config) {
+        let _ = config;
+    }
+    /// Override the detection of an unsupported terminal (see `Config::term_support`).
+    /// By default, it is ignored: the terminal is described by `is_unsupported`.
+    fn set_unsupported(&mut self, unsupported: bool) {
+        let _ = unsupported;
+    }
     /// Check if current terminal can provide a rich line-editing user interface.
     fn is_unsupported(&self) -> bool;
     /// check if stdin is connected to a terminal.
     fn is_stdin_tty(&self) -> bool;
+    /// Check if output supports colors.
+    fn colors_enabled(&self) -> bool;
+    /// Number of colors supported by the terminal.
+    fn color_depth(&self) -> ColorDepth;
     /// Get the number of columns in the current terminal.
     fn get_columns(&self) -> usize;
     /// Get the number of rows in the current terminal.
     fn get_rows(&self) -> usize;
+    /// Query the size of the terminal again, for the terminals caching it between two SIGWINCH.
+    /// By default, the size is queried on each call of `get_columns` and `get_rows`.
+    fn update_size(&self) {}
     /// Check if a SIGWINCH signal has been received
+    /// (if the terminal has been resized since the last call).
     fn sigwinch(&self) -> bool;
+    /// Check if the process has been continued (SIGCONT) since the last call,
+    /// RAW mode being enabled again (unix only).
+    fn continued(&self) -> bool {
+        false
+    }
+    /// Query the column of the cursor (`None` when the terminal does not report it).
+    fn cursor_column(&self, w: &mut Write) -> Option<usize> {
+        let _ = w;
+        None
+    }
+    /// Check if the process can be stopped (Ctrl-Z) while a line is edited on this terminal,
+    /// unix only. By default, it cannot: the bell rings.
+    fn can_suspend(&self) -> bool {
+        false
+    }
     /// Enable RAW mode for the terminal.
     fn enable_raw_mode(&self) -> Result<Self::Mode>;
     /// Create a RAW reader
     fn create_reader(&self) -> Result<Self::Reader>;
+    /// Create a writer to the output stream (the editor renders with ANSI escape sequences,
+    /// except on the Windows console).
+    fn create_writer(&self) -> Box<Write>;
     /// Clear the screen. Used to handle ctrl+l
     fn clear_screen(&mut self, w: &mut Write) -> Result<()>;
+    /// Switch to the alternate screen buffer.
+    fn enter_alternate_screen(&mut self, w: &mut Write) -> Result<()>;
+    /// Switch back to the main screen buffer (if the alternate one is active).
+    fn leave_alternate_screen(&mut self, w: &mut Write) -> Result<()>;
+    /// Change the shape of the cursor.
+    fn set_cursor_shape(&self, w: &mut Write, shape: CursorShape) -> Result<()>;
+    /// Console rendered to with the console API rather than ANSI escape sequences.
+    #[cfg(windows)]
+    #[doc(hidden)]
+    fn console(&self) -> Option<&Console> {
+        None
+    }
+}
+
+/// Object-safe part of `Term` used while a line is edited,
+/// so that the state of the edition does not depend on the type of the terminal.
+pub trait DynTerm {
+    fn color_depth(&self) -> ColorDepth;
+    fn get_columns(&self) -> usize;
+    fn get_rows(&self) -> usize;
+    fn update_size(&self);
+    fn sigwinch(&self) -> bool;
+    fn continued(&self) -> bool;
+    fn cursor_column(&self, w: &mut Write) -> Option<usize>;
+    fn clear_screen(&mut self, w: &mut Write) -> Result<()>;
+    fn set_cursor_shape(&self, w: &mut Write, shape: CursorShape) -> Result<()>;
+    #[cfg(windows)]
+    fn console(&self) -> Option<&Console>;
+}
+
+impl<T: Term> DynTerm for T {
+    fn color_depth(&self) -> ColorDepth {
+        Term::color_depth(self)
+    }
+    fn get_columns(&self) -> usize {
+        Term::get_columns(self)
+    }
+    fn get_rows(&self) -> usize {
+        Term::get_rows(self)
+    }
+    fn update_size(&self) {
+        Term::update_size(self)
+    }
+    fn sigwinch(&self) -> bool {
+        Term::sigwinch(self)
+    }
+    fn continued(&self) -> bool {
+        Term::continued(self)
+    }
+    fn cursor_column(&self, w: &mut Write) -> Option<usize> {
+        Term::cursor_column(self, w)
+    }
+    fn clear_screen(&mut self, w: &mut Write) -> Result<()> {
+        Term::clear_screen(self, w)
+    }
+    fn set_cursor_shape(&self, w: &mut Write, shape: CursorShape) -> Result<()> {
+        Term::set_cursor_shape(self, w, shape)
+    }
+    #[cfg(windows)]
+    fn console(&self) -> Option<&Console> {
+        Term::console(self)
+    }
+}
+
+/// Resolve the terminal size from the size queried from the terminal (if any)
+/// and the `COLUMNS` / `LINES` environment variables,
+/// which are used as a fallback (or first when `prefer_env` is set) before 80x24.
+pub fn win_size(queried: Option<(usize, usize)>,
+                env_size: (Option<usize>, Option<usize>),
+                prefer_env: bool)
+                -> (usize, usize) {
+    let (env_cols, env_rows) = env_size;
+    match queried {
+        Some((cols, rows)) if prefer_env => (env_cols.unwrap_or(cols), env_rows.unwrap_or(rows)),
+        Some(size) => size,
+        None => (env_cols.unwrap_or(80), env_rows.unwrap_or(24)),
+    }
+}
+
+/// Values of the `COLUMNS` and `LINES` environment variables (if valid).
+#[cfg(not(test))]
+pub fn env_win_size() -> (Option<usize>, Option<usize>) {
+    use std::env;
+
+    fn var(name: &str) -> Option<usize> {
+        env::var(name).ok().and_then(|v| v.trim().parse().ok()).and_then(|n| {
+            if n > 0 { Some(n) } else { None }
+        })
+    }
+    (var("COLUMNS"), var("LINES"))
+}
+
+/// Column (starting from 0) of a cursor position report (`ESC [ row ; col R`)
+/// ending `bytes`, and the index of its first byte.
+#[cfg(unix)]
+#[cfg_attr(test, allow(dead_code))]
+pub fn cursor_report(bytes: &[u8]) -> Option<(usize, usize)> {
+    if bytes.last() != Some(&b'R') {
+        return None;
+    }
+    let start = match bytes.iter().rposition(|&b| b == b'\x1b') {
+        Some(start) => start,
+        None => return None,
+    };
+    let report = match ::std::str::from_utf8(&bytes[start + 1..bytes.len() - 1]) {
+        Ok(report) if report.starts_with('[') => &report[1..],
+        _ => return None,
+    };
+    let mut fields = report.split(';');
+    match (fields.next().map(str::parse::<usize>),
+           fields.next().map(str::parse::<usize>),
+           fields.next()) {
+        (Some(Ok(_)), Some(Ok(col)), None) if col > 0 => Some((col - 1, start)),
+        _ => None,
+    }
 }
 
 // If on Windows platform import Windows TTY module
@@ -50,6 +555,8 @@ pub use self::windows::*;
 // If on Unix platform import Unix TTY module
 // and re-export into mod.rs scope
 #[cfg(all(unix, not(test)))]
+mod sys;
+#[cfg(all(unix, not(test)))]
 mod unix;
 #[cfg(all(unix, not(test)))]
 pub use self::unix::*;
@@ -58,3 +565,129 @@ pub use self::unix::*;
 mod test;
 #[cfg(test)]
 pub use self::test::*;
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn preprocessed() {
+        use std::io::Read;
+        use preprocess::TelnetFilter;
+        use super::{Preprocessed, SharedPreprocessor};
+
+        let input: &[u8] = b"\xff\xfb\x01ls\r\n";
+        let filter = SharedPreprocessor::new(Some(Box::new(TelnetFilter::new())));
+        let mut reader = Preprocessed::new(input, filter, Default::default());
+        let mut buf = [0; 2];
+        assert_eq!(2, reader.read(&mut buf).unwrap());
+        assert_eq!(b"ls", &buf);
+        assert!(reader.has_pending());
+        assert_eq!(1, reader.read(&mut buf).unwrap());
+        assert_eq!(b'\r', buf[0]);
+        assert_eq!(0, reader.read(&mut buf).unwrap());
+
+        // the bytes read ahead are kept for the next reader
+        let pending = Default::default();
+        let input: &[u8] = b"ab\rcd\r";
+        let filter = SharedPreprocessor::new(Some(Box::new(TelnetFilter::new())));
+        let mut reader = Preprocessed::new(input, filter, pending);
+        let mut buf = [0; 1];
+        assert_eq!(1, reader.read(&mut buf).unwrap());
+        let pending = reader.pending.clone();
+        assert_eq!(5, pending.borrow().len());
+        let mut reader = Preprocessed::new(&b""[..], SharedPreprocessor::default(), pending);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(b"b\rcd\r", &rest[..]);
+    }
+
+    #[test]
+    fn next_char_unsupported() {
+        use consts::{Key, KeyPress};
+        use super::RawReader;
+
+        // the reader of the keys of a slice does not implement `next_char`
+        let keys = [key!('a')];
+        let mut reader = keys.iter();
+        assert!(reader.next_char().is_err());
+        assert_eq!(key!('a'), reader.next_key(0).unwrap());
+    }
+
+    #[test]
+    fn win_size() {
+        assert_eq!((100, 40), super::win_size(Some((100, 40)), (Some(60), None), false));
+        assert_eq!((60, 40), super::win_size(Some((100, 40)), (Some(60), None), true));
+        assert_eq!((60, 24), super::win_size(None, (Some(60), None), false));
+        assert_eq!((80, 24), super::win_size(None, (None, None), true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn size_cache() {
+        use std::cell::Cell;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use super::SizeCache;
+
+        // size of the terminal, and number of ioctls
+        let size = Cell::new((80, 24));
+        let queries = Cell::new(0);
+        let query = || {
+            queries.set(queries.get() + 1);
+            size.get()
+        };
+        let cache = SizeCache::default();
+        assert_eq!((80, 24), cache.get(&query));
+        size.set((100, 30));
+        // the width stays the same during a refresh
+        assert_eq!((80, 24), cache.get(&query));
+        assert_eq!(1, queries.get());
+
+        let sigwinch = AtomicBool::new(false);
+        assert!(!cache.sigwinch(&sigwinch, &query));
+        assert_eq!((80, 24), cache.get(&query));
+        sigwinch.store(true, Ordering::SeqCst);
+        assert!(cache.sigwinch(&sigwinch, &query));
+        assert!(!sigwinch.load(Ordering::SeqCst));
+        assert_eq!((100, 30), cache.get(&query));
+        assert_eq!(2, queries.get());
+
+        cache.invalidate();
+        size.set((60, 20));
+        assert_eq!((60, 20), cache.get(&query));
+        assert_eq!(3, queries.get());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retry_interrupted() {
+        use std::io;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use super::retry_interrupted;
+
+        let interrupted = || io::Error::from(io::ErrorKind::Interrupted);
+        let sigwinch = AtomicBool::new(false);
+        let sigcont = AtomicBool::new(false);
+        assert!(retry_interrupted(interrupted(), &sigwinch, &sigcont).is_ok());
+        let error = io::Error::from(io::ErrorKind::BrokenPipe);
+        assert!(retry_interrupted(error, &sigwinch, &sigcont).is_err());
+        // redisplayed after a resize or in the foreground
+        sigwinch.store(true, Ordering::SeqCst);
+        let error = retry_interrupted(interrupted(), &sigwinch, &sigcont).unwrap_err();
+        assert_eq!(io::ErrorKind::Interrupted, error.kind());
+        // the flags are left to the terminal
+        assert!(sigwinch.load(Ordering::SeqCst));
+        sigwinch.store(false, Ordering::SeqCst);
+        sigcont.store(true, Ordering::SeqCst);
+        assert!(retry_interrupted(interrupted(), &sigwinch, &sigcont).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cursor_report() {
+        assert_eq!(Some((0, 0)), super::cursor_report(b"\x1b[12;1R"));
+        // keys typed before the report
+        assert_eq!(Some((79, 2)), super::cursor_report(b"ab\x1b[3;80R"));
+        assert_eq!(None, super::cursor_report(b"\x1b[3;80"));
+        assert_eq!(None, super::cursor_report(b"\x1b[1;5R\x1b[3R"));
+        assert_eq!(None, super::cursor_report(b"\x1bOR"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Wrappers of the termios, poll and signal functions used by the unix backend:
+//! implemented with nix by default, or with libc only (`minimal` feature,
+//! for static or constrained targets).
+use libc;
+
+pub use self::imp::*;
+
+/// Disable BREAK interrupt, CR to NL conversion on input, input parity check,
+/// strip high bit (bit 8), output flow control, echoing, canonical mode,
+/// extended input processing and signals in `mode`, to read one char at a time.
+macro_rules! make_raw {
+    ($mode:expr) => {{
+        let mut raw = $mode;
+        raw.c_iflag = raw.c_iflag & !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
+        // we don't want raw output, it turns newlines into straight linefeeds
+        // raw.c_oflag = raw.c_oflag & !(OPOST); // disable all output processing
+        raw.c_cflag = raw.c_cflag | (CS8); // character-size mark (8 bits)
+        raw.c_lflag = raw.c_lflag & !(ECHO | ICANON | IEXTEN | ISIG);
+        raw.c_cc[VMIN] = 1; // One character-at-a-time input
+        raw.c_cc[VTIME] = 0; // with blocking read
+        raw
+    }}
+}
+
+#[cfg(all(feature = "nix", not(feature = "minimal")))]
+mod imp {
+    use libc;
+    use nix;
+    use nix::errno::Errno;
+    use nix::poll;
+    use nix::sys::signal;
+    use nix::sys::termios::{self, BRKINT, CS8, ECHO, ICANON, ICRNL, IEXTEN, INPCK, ISIG,
+                            ISTRIP, IXON, VMIN, VTIME};
+    use ::Result;
+    use error::ReadlineError;
+
+    pub type Termios = termios::Termios;
+
+    pub fn tcgetattr(fd: libc::c_int) -> Result<Termios> {
+        Ok(try!(termios::tcgetattr(fd)))
+    }
+
+    pub fn tcsetattr(fd: libc::c_int, mode: &Termios) -> Result<()> {
+        Ok(try!(termios::tcsetattr(fd, termios::TCSADRAIN, mode)))
+    }
+
+    pub fn make_raw(mode: &Termios) -> Termios {
+        make_raw!(*mode)
+    }
+
+    /// Disable the echo of the input in `mode`.
+    pub fn make_silent(mode: &Termios) -> Termios {
+        let mut silent = *mode;
+        silent.c_lflag = silent.c_lflag & !ECHO;
+        silent
+    }
+
+    /// Wait at most `timeout_ms` for input on `fd`.
+    pub fn poll(fd: libc::c_int, timeout_ms: i32) -> Result<bool> {
+        let mut fds = [poll::PollFd::new(fd, poll::POLLIN, poll::EventFlags::empty())];
+        match poll::poll(&mut fds, timeout_ms) {
+            Ok(n) => Ok(n > 0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub unsafe fn set_handler(sig: libc::c_int, handler: extern "C" fn(libc::c_int)) {
+        let action = signal::SigAction::new(signal::SigHandler::Handler(handler),
+                                            signal::SaFlags::empty(),
+                                            signal::SigSet::empty());
+        let _ = signal::sigaction(sig, &action);
+    }
+
+    pub fn suspend() -> Result<()> {
+        // For macos:
+        try!(signal::kill(nix::unistd::getppid(), signal::SIGTSTP));
+        try!(signal::kill(nix::unistd::getpid(), signal::SIGTSTP));
+        Ok(())
+    }
+
+    pub fn not_a_tty() -> ReadlineError {
+        nix::Error::from_errno(Errno::ENOTTY).into()
+    }
+}
+
+#[cfg(any(feature = "minimal", not(feature = "nix")))]
+mod imp {
+    use std::io;
+    use std::mem;
+    use std::ptr;
+    use libc;
+    use libc::{BRKINT, CS8, ECHO, ICANON, ICRNL, IEXTEN, INPCK, ISIG, ISTRIP, IXON, VMIN, VTIME};
+    use ::Result;
+    use error::ReadlineError;
+
+    pub type Termios = libc::termios;
+
+    fn check(ret: libc::c_int) -> Result<libc::c_int> {
+        if ret < 0 {
+            Err(io::Error::last_os_error().into())
+        } else {
+            Ok(ret)
+        }
+    }
+
+    pub fn tcgetattr(fd: libc::c_int) -> Result<Termios> {
+        unsafe {
+            let mut mode: Termios = mem::zeroed();
+            try!(check(libc::tcgetattr(fd, &mut mode)));
+            Ok(mode)
+        }
+    }
+
+    pub fn tcsetattr(fd: libc::c_int, mode: &Termios) -> Result<()> {
+        try!(check(unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, mode) }));
+        Ok(())
+    }
+
+    pub fn make_raw(mode: &Termios) -> Termios {
+        make_raw!(*mode)
+    }
+
+    /// Disable the echo of the input in `mode`.
+    pub fn make_silent(mode: &Termios) -> Termios {
+        let mut silent = *mode;
+        silent.c_lflag = silent.c_lflag & !ECHO;
+        silent
+    }
+
+    /// Wait at most `timeout_ms` for input on `fd`.
+    pub fn poll(fd: libc::c_int, timeout_ms: i32) -> Result<bool> {
+        let mut fds = [libc::pollfd {
+                           fd: fd,
+                           events: libc::POLLIN,
+                           revents: 0,
+                       }];
+        let n = try!(check(unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout_ms) }));
+        Ok(n > 0)
+    }
+
+    pub unsafe fn set_handler(sig: libc::c_int, handler: extern "C" fn(libc::c_int)) {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(sig, &action, ptr::null_mut());
+    }
+
+    pub fn suspend() -> Result<()> {
+        // For macos:
+        try!(check(unsafe { libc::kill(libc::getppid(), libc::SIGTSTP) }));
+        try!(check(unsafe { libc::kill(libc::getpid(), libc::SIGTSTP) }));
+        Ok(())
+    }
+
+    pub fn not_a_tty() -> ReadlineError {
+        io::Error::from_raw_os_error(libc::ENOTTY).into()
+    }
+}
+
+/// Signals whose default action terminates the process (with a terminal to restore).
+pub static TERMINATING_SIGNALS: [libc::c_int; 4] = [libc::SIGINT,
+                                                   libc::SIGTERM,
+                                                   libc::SIGQUIT,
+                                                   libc::SIGHUP];
@@ -1,19 +1,27 @@
 //! Tests specific definitions
-use std::io::Write;
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
 use std::iter::IntoIterator;
+use std::rc::Rc;
 use std::slice::Iter;
 use std::vec::IntoIter;
 
 #[cfg(windows)]
 use winapi;
 
-use consts::KeyPress;
+use config::{Config, CursorShape, OutputStreamType};
+use consts::{Key, KeyPress};
+use highlight::ColorDepth;
 use ::error::ReadlineError;
 use ::Result;
-use super::{RawMode, RawReader, Term};
+use super::{RawMode, RawReader, SharedKeyTable, SharedPreprocessor, Term};
 
 pub type Mode = ();
 
+/// The console API is not called by the tests.
+#[cfg(windows)]
+pub type Console = DummyTerminal;
+
 impl RawMode for Mode {
     fn disable_raw_mode(&self) -> Result<()> {
         Ok(())
@@ -27,9 +35,24 @@ impl<'a> RawReader for Iter<'a, KeyPress> {
             None => Err(ReadlineError::Eof),
         }
     }
-    #[cfg(unix)]
-    fn next_char(&mut self) -> Result<char> {
-        unimplemented!();
+    /// The keys are typed all at once.
+    fn poll(&mut self, _: i32) -> Result<bool> {
+        Ok(self.len() > 0)
+    }
+    fn can_wait(&self) -> bool {
+        false
+    }
+}
+
+/// Char of a key typed as is (a pasted text),
+/// `ReadlineError::Eof` for the keys without a char.
+fn key_char(key: KeyPress) -> Result<char> {
+    match key.key {
+        Key::Char(c) => Ok(c),
+        Key::Enter => Ok('\r'),
+        Key::Esc => Ok('\x1b'),
+        Key::Tab => Ok('\t'),
+        _ => Err(ReadlineError::Eof),
     }
 }
 
@@ -40,20 +63,100 @@ impl RawReader for IntoIter<KeyPress> {
             None => Err(ReadlineError::Eof),
         }
     }
-    #[cfg(unix)]
+    fn poll(&mut self, _: i32) -> Result<bool> {
+        Ok(self.len() > 0)
+    }
+    fn can_wait(&self) -> bool {
+        false
+    }
+    fn next_char(&mut self) -> Result<char> {
+        match self.next() {
+            Some(key) => key_char(key),
+            None => Err(ReadlineError::Eof),
+        }
+    }
+}
+
+/// Reader of the keys of the test terminal, keeping the timeouts of `next_key`.
+pub struct DummyReader {
+    keys: IntoIter<KeyPress>,
+    timeouts: Rc<RefCell<Vec<i32>>>,
+}
+
+impl RawReader for DummyReader {
+    fn next_key(&mut self, timeout_ms: i32) -> Result<KeyPress> {
+        self.timeouts.borrow_mut().push(timeout_ms);
+        self.keys.next_key(timeout_ms)
+    }
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool> {
+        self.keys.poll(timeout_ms)
+    }
+    fn can_wait(&self) -> bool {
+        false
+    }
     fn next_char(&mut self) -> Result<char> {
-        unimplemented!();
+        self.keys.next_char()
     }
 }
 
 pub type Terminal = DummyTerminal;
 
+/// Bytes rendered to the terminal, kept to check the display.
+#[derive(Clone, Debug, Default)]
+pub struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    /// Return the bytes rendered since the last call.
+    pub fn take(&self) -> String {
+        let bytes = ::std::mem::replace(&mut *self.0.borrow_mut(), Vec::new());
+        String::from_utf8(bytes).unwrap()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 #[derive(Clone,Debug)]
 pub struct DummyTerminal {
     pub keys: Vec<KeyPress>,
+    pub unsupported: bool,
+    pub stream: OutputStreamType,
+    pub alternate_screen: Rc<Cell<bool>>,
+    pub dev_tty: bool,
+    pub output: Output,
+    /// Timeouts given to the readers to tell a lone Esc from the Meta prefix.
+    pub timeouts: Rc<RefCell<Vec<i32>>>,
 }
 
 impl DummyTerminal {
+    pub fn new(stream: OutputStreamType) -> DummyTerminal {
+        DummyTerminal {
+            keys: Vec::new(),
+            unsupported: false,
+            stream: stream,
+            alternate_screen: Rc::new(Cell::new(false)),
+            dev_tty: false,
+            output: Output::default(),
+            timeouts: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    pub fn output_stream(&self) -> OutputStreamType {
+        self.stream
+    }
+
+    pub fn set_input_preprocessor(&mut self, _: SharedPreprocessor) {}
+
+    pub fn set_key_table(&mut self, _: SharedKeyTable) {}
+
     #[cfg(windows)]
     pub fn get_console_screen_buffer_info(&self) -> Result<winapi::CONSOLE_SCREEN_BUFFER_INFO> {
         let dw_size = winapi::COORD { X: 80, Y: 24 };
@@ -89,18 +192,23 @@ impl DummyTerminal {
 }
 
 impl Term for DummyTerminal {
-    type Reader = IntoIter<KeyPress>;
+    type Reader = DummyReader;
     type Mode = Mode;
 
-    fn new() -> DummyTerminal {
-        DummyTerminal { keys: Vec::new() }
+    fn apply_config(&mut self, config: &Config) {
+        self.stream = config.output_stream();
+        self.dev_tty = config.dev_tty();
+    }
+
+    fn set_unsupported(&mut self, unsupported: bool) {
+        self.unsupported = unsupported;
     }
 
     // Init checks:
 
     /// Check if current terminal can provide a rich line-editing user interface.
     fn is_unsupported(&self) -> bool {
-        false
+        self.unsupported
     }
 
     /// check if stdin is connected to a terminal.
@@ -108,6 +216,14 @@ impl Term for DummyTerminal {
         true
     }
 
+    fn colors_enabled(&self) -> bool {
+        true
+    }
+
+    fn color_depth(&self) -> ColorDepth {
+        ColorDepth::Ansi256
+    }
+
     // Interactive loop:
 
     /// Get the number of columns in the current terminal.
@@ -125,23 +241,87 @@ impl Term for DummyTerminal {
         false
     }
 
+    fn continued(&self) -> bool {
+        false
+    }
+
+    fn cursor_column(&self, _: &mut Write) -> Option<usize> {
+        None
+    }
+
     fn enable_raw_mode(&self) -> Result<Mode> {
         Ok(())
     }
 
     /// Create a RAW reader
-    fn create_reader(&self) -> Result<IntoIter<KeyPress>> {
-        Ok(self.keys.clone().into_iter())
+    fn create_reader(&self) -> Result<DummyReader> {
+        Ok(DummyReader {
+            keys: self.keys.clone().into_iter(),
+            timeouts: self.timeouts.clone(),
+        })
     }
 
+    fn create_writer(&self) -> Box<Write> {
+        Box::new(self.output.clone())
+    }
 
     /// Clear the screen. Used to handle ctrl+l
     fn clear_screen(&mut self, _: &mut Write) -> Result<()> {
         Ok(())
     }
+
+    fn enter_alternate_screen(&mut self, _: &mut Write) -> Result<()> {
+        self.alternate_screen.set(true);
+        Ok(())
+    }
+
+    fn leave_alternate_screen(&mut self, _: &mut Write) -> Result<()> {
+        self.alternate_screen.set(false);
+        Ok(())
+    }
+
+    fn set_cursor_shape(&self, _: &mut Write, _: CursorShape) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn console(&self) -> Option<&Console> {
+        Some(self)
+    }
 }
 
 #[cfg(unix)]
 pub fn suspend() -> Result<()> {
     Ok(())
 }
+
+#[cfg(unix)]
+pub fn restore_terminal() {}
+
+thread_local!(static SUSPENDED: Cell<bool> = Cell::new(false));
+
+#[cfg(unix)]
+pub fn suspend_raw<R, F: FnOnce() -> R>(f: F) -> R {
+    let result = f();
+    SUSPENDED.with(|suspended| suspended.set(true));
+    result
+}
+
+pub fn take_suspended() -> bool {
+    SUSPENDED.with(|suspended| {
+        let taken = suspended.get();
+        suspended.set(false);
+        taken
+    })
+}
+
+pub fn install_restore_handlers() {}
+
+pub fn without_echo<R, F: FnOnce() -> R>(f: F) -> R {
+    f()
+}
+
+/// The terminal is always supported unless forced otherwise.
+pub fn is_unsupported_term(_: &[String]) -> bool {
+    false
+}
@@ -1,45 +1,62 @@
 //! Unix specific definitions
 use std;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
 use std::io::{self, Read, Write};
-use std::sync;
+use std::os::unix::io::AsRawFd;
+use std::sync::{self, Arc};
 use std::sync::atomic;
+#[cfg(feature = "perf")]
+use std::time::Instant;
 use libc;
-use nix;
-use nix::poll;
-use nix::sys::signal;
-use nix::sys::termios;
 
 use char_iter;
+use config::{Config, CursorShape, OutputStreamType};
+use decode;
 use consts::{self, Key, KeyPress};
+use highlight::ColorDepth;
 use ::Result;
 use ::error;
-use super::{RawMode, RawReader, Term};
+use super::{cursor_report, retry_interrupted, OutputWriter, PendingInput, Preprocessed, RawMode,
+            RawReader, SharedKeyTable, SharedPreprocessor, SizeCache, Term};
+use super::sys;
 
 const STDIN_FILENO: libc::c_int = libc::STDIN_FILENO;
 const STDOUT_FILENO: libc::c_int = libc::STDOUT_FILENO;
+const STDERR_FILENO: libc::c_int = libc::STDERR_FILENO;
+
+/// Query the terminal size on the output stream, then on stdin and the other stream
+/// (the output may be redirected while stdin is the terminal).
+fn get_win_size(out_fd: libc::c_int, prefer_env: bool) -> (usize, usize) {
+    let queried = [out_fd, STDIN_FILENO, STDOUT_FILENO, STDERR_FILENO]
+        .iter()
+        .filter_map(|&fd| ioctl_win_size(fd))
+        .next();
+    super::win_size(queried, super::env_win_size(), prefer_env)
+}
 
-/// Unsupported Terminals that don't support RAW mode
-static UNSUPPORTED_TERM: [&'static str; 3] = ["dumb", "cons25", "emacs"];
-
-fn get_win_size() -> (usize, usize) {
+fn ioctl_win_size(fd: libc::c_int) -> Option<(usize, usize)> {
     use std::mem::zeroed;
 
     unsafe {
         let mut size: libc::winsize = zeroed();
-        match libc::ioctl(STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) {
-            0 => (size.ws_col as usize, size.ws_row as usize), // TODO getCursorPosition
-            _ => (80, 24),
+        match libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) {
+            // some pseudo terminals report a zero size
+            0 if size.ws_col > 0 && size.ws_row > 0 => {
+                Some((size.ws_col as usize, size.ws_row as usize)) // TODO getCursorPosition
+            }
+            _ => None,
         }
     }
 }
 
-/// Check TERM environment variable to see if current term is in our
-/// unsupported list
-fn is_unsupported_term() -> bool {
+/// Check TERM environment variable to see if current term is in the
+/// `unsupported` list
+pub fn is_unsupported_term(unsupported: &[String]) -> bool {
     use std::ascii::AsciiExt;
     match std::env::var("TERM") {
         Ok(term) => {
-            for iter in &UNSUPPORTED_TERM {
+            for iter in unsupported {
                 if (*iter).eq_ignore_ascii_case(&term) {
                     return true;
                 }
@@ -56,32 +73,157 @@ fn is_a_tty(fd: libc::c_int) -> bool {
     unsafe { libc::isatty(fd) != 0 }
 }
 
-pub type Mode = termios::Termios;
+/// Terminal mode of the input descriptor (stdin or `/dev/tty`).
+#[derive(Clone, Copy)]
+pub struct Mode {
+    termios: sys::Termios,
+    fd: libc::c_int,
+}
 
 impl RawMode for Mode {
     /// Disable RAW mode for the terminal.
     fn disable_raw_mode(&self) -> Result<()> {
-        try!(termios::tcsetattr(STDIN_FILENO, termios::TCSADRAIN, self));
-        Ok(())
+        RAW_MODE.store(false, atomic::Ordering::SeqCst);
+        sys::tcsetattr(self.fd, &self.termios)
+    }
+}
+
+/// Terminal mode (and descriptor) saved by the last `enable_raw_mode`,
+/// only valid while `RAW_MODE` is set.
+static mut ORIGINAL_MODE: Option<(sys::Termios, libc::c_int)> = None;
+static RAW_MODE: atomic::AtomicBool = atomic::ATOMIC_BOOL_INIT;
+/// Number of local terminals in the alternate screen buffer.
+static ALTERNATE_SCREEN: atomic::AtomicUsize = atomic::ATOMIC_USIZE_INIT;
+/// Descriptor the alternate screen buffer was entered on.
+static ALTERNATE_SCREEN_FD: atomic::AtomicIsize = atomic::ATOMIC_ISIZE_INIT;
+
+const ENTER_ALTERNATE_SCREEN: &'static [u8] = b"\x1b[?1049h";
+const LEAVE_ALTERNATE_SCREEN: &'static [u8] = b"\x1b[?1049l";
+
+/// Restore the terminal mode saved when RAW mode was enabled
+/// (and the main screen buffer).
+/// Async-signal-safe: does nothing when RAW mode is not active.
+pub fn restore_terminal() {
+    if RAW_MODE.swap(false, atomic::Ordering::SeqCst) {
+        if let Some((termios, fd)) = unsafe { ORIGINAL_MODE } {
+            let _ = sys::tcsetattr(fd, &termios);
+        }
+    }
+    if ALTERNATE_SCREEN.swap(0, atomic::Ordering::SeqCst) > 0 {
+        let fd = ALTERNATE_SCREEN_FD.load(atomic::Ordering::SeqCst) as libc::c_int;
+        unsafe {
+            libc::write(fd,
+                        LEAVE_ALTERNATE_SCREEN.as_ptr() as *const libc::c_void,
+                        LEAVE_ALTERNATE_SCREEN.len());
+        }
+    }
+}
+
+/// Tell if the last local terminal in the alternate screen buffer is leaving it
+/// (the count may have been reset by `restore_terminal`).
+fn release_alternate_screen() -> bool {
+    let mut count = ALTERNATE_SCREEN.load(atomic::Ordering::SeqCst);
+    while count > 0 {
+        let previous =
+            ALTERNATE_SCREEN.compare_and_swap(count, count - 1, atomic::Ordering::SeqCst);
+        if previous == count {
+            return count == 1;
+        }
+        count = previous;
+    }
+    false
+}
+
+/// Set when the terminal has been handed over by `suspend_raw`.
+static SUSPENDED: atomic::AtomicBool = atomic::ATOMIC_BOOL_INIT;
+
+/// Run `f` in the terminal mode saved when RAW mode was enabled, then enable RAW mode again.
+pub fn suspend_raw<R, F: FnOnce() -> R>(f: F) -> R {
+    let (original, fd) = match unsafe { ORIGINAL_MODE } {
+        Some((termios, fd)) if RAW_MODE.load(atomic::Ordering::SeqCst) => {
+            (termios, fd)
+        }
+        _ => return f(),
+    };
+    let raw = sys::tcgetattr(fd);
+    RAW_MODE.store(false, atomic::Ordering::SeqCst);
+    let _ = sys::tcsetattr(fd, &original);
+    let result = f();
+    if let Ok(raw) = raw {
+        if sys::tcsetattr(fd, &raw).is_ok() {
+            RAW_MODE.store(true, atomic::Ordering::SeqCst);
+        }
+    }
+    SUSPENDED.store(true, atomic::Ordering::SeqCst);
+    result
+}
+
+/// Tell if the terminal has been handed over by `suspend_raw` since the last call.
+pub fn take_suspended() -> bool {
+    SUSPENDED.swap(false, atomic::Ordering::SeqCst)
+}
+
+/// Run `f` with the echo of stdin disabled (if it is a terminal), like `getpass`.
+pub fn without_echo<R, F: FnOnce() -> R>(f: F) -> R {
+    if !is_a_tty(STDIN_FILENO) {
+        return f();
+    }
+    let original = match sys::tcgetattr(STDIN_FILENO) {
+        Ok(termios) => termios,
+        Err(_) => return f(),
+    };
+    let _ = sys::tcsetattr(STDIN_FILENO, &sys::make_silent(&original));
+    let result = f();
+    let _ = sys::tcsetattr(STDIN_FILENO, &original);
+    result
+}
+
+static RESTORE_HANDLERS_ONCE: sync::Once = sync::ONCE_INIT;
+
+/// Install handlers for SIGINT, SIGTERM, SIGQUIT and SIGHUP which restore
+/// the terminal mode before letting the signal terminate the process.
+pub fn install_restore_handlers() {
+    RESTORE_HANDLERS_ONCE.call_once(|| unsafe {
+        for &sig in &sys::TERMINATING_SIGNALS {
+            sys::set_handler(sig, restore_handler);
+        }
+    });
+}
+
+extern "C" fn restore_handler(sig: libc::c_int) {
+    restore_terminal();
+    // re-raise with the default disposition so that the exit status is preserved
+    unsafe {
+        libc::signal(sig, libc::SIG_DFL);
+        libc::raise(sig);
     }
 }
 
 // Rust std::io::Stdin is buffered with no way to know if bytes are available.
 // So we use low-level stuff instead...
-struct StdinRaw {}
+struct StdinRaw {
+    fd: libc::c_int,
+}
 
 impl Read for StdinRaw {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         loop {
             let res = unsafe {
-                libc::read(STDIN_FILENO,
+                libc::read(self.fd,
                            buf.as_mut_ptr() as *mut libc::c_void,
                            buf.len() as libc::size_t)
             };
             if res == -1 {
                 let error = io::Error::last_os_error();
-                if error.kind() != io::ErrorKind::Interrupted {
-                    return Err(error);
+                if error.raw_os_error() == Some(libc::EIO) {
+                    // the terminal is closed (hang up): end of input
+                    return Ok(0);
+                } else if error.kind() == io::ErrorKind::WouldBlock {
+                    // O_NONBLOCK inherited from a parent process: wait for input
+                    // (then read again, like after an interruption by a signal)
+                    try!(wait_input(self.fd));
+                } else {
+                    try!(retry_interrupted(error, &SIGWINCH, &SIGCONT));
                 }
             } else {
                 return Ok(res as usize);
@@ -90,148 +232,131 @@ impl Read for StdinRaw {
     }
 }
 
+/// Wait for input on `fd` (see `retry_interrupted` for an interruption by a signal).
+fn wait_input(fd: libc::c_int) -> io::Result<()> {
+    let mut fds = [libc::pollfd {
+                       fd: fd,
+                       events: libc::POLLIN,
+                       revents: 0,
+                   }];
+    if unsafe { libc::poll(fds.as_mut_ptr(), 1, -1) } == -1 {
+        try!(retry_interrupted(io::Error::last_os_error(), &SIGWINCH, &SIGCONT));
+    }
+    Ok(())
+}
+
 /// Console input reader
 pub struct PosixRawReader {
-    chars: char_iter::Chars<StdinRaw>,
+    chars: char_iter::Chars<Preprocessed<StdinRaw>>,
+    seq: String, // chars read for the last key
+    key_table: SharedKeyTable,
+    replay: VecDeque<char>, // chars read ahead, not part of the last key
 }
 
 impl PosixRawReader {
-    pub fn new() -> Result<PosixRawReader> {
-        let stdin = StdinRaw {};
-        Ok(PosixRawReader { chars: char_iter::chars(stdin) })
-    }
-
-    fn escape_sequence(&mut self) -> Result<KeyPress> {
-        // try to match the next several characters against known escape sequences
-        match try!(self.next_char()) {
-            '[' => match try!(self.next_char()) {
-                '1' => match try!(self.next_char()) {
-                    ';' => match try!(self.next_char()) {
-                        '3' => match try!(self.next_char()) {
-                            'A' => Ok(alt!(Key::Up)),
-                            'B' => Ok(alt!(Key::Down)),
-                            'C' => Ok(alt!(Key::Right)),
-                            'D' => Ok(alt!(Key::Left)),
-                            'F' => Ok(alt!(Key::End)),
-                            'H' => Ok(alt!(Key::Home)),
-                            _ => Ok(key!(Key::Unknown)),
-                        },
-                        '5' => match try!(self.next_char()) {
-                            'A' => Ok(ctrl!(Key::Up)),
-                            'B' => Ok(ctrl!(Key::Down)),
-                            'C' => Ok(ctrl!(Key::Right)),
-                            'D' => Ok(ctrl!(Key::Left)),
-                            'F' => Ok(ctrl!(Key::End)),
-                            'H' => Ok(ctrl!(Key::Home)),
-                            _ => Ok(key!(Key::Unknown)),
-                        },
-                        _ => Ok(key!(Key::Unknown)),
-                    },
-                    '~' => Ok(key!(Key::Home)),
-                    _ => Ok(key!(Key::Unknown)),
-                },
-                '3' => match try!(self.next_char()) {
-                    '~' => Ok(key!(Key::Delete)),
-                    _ => Ok(key!(Key::Unknown)),
-                },
-                '4' => match try!(self.next_char()) {
-                    '~' => Ok(key!(Key::End)), // xterm
-                    _ => Ok(key!(Key::Unknown)),
-                },
-                '5' => match try!(self.next_char()) {
-                    '~' => Ok(key!(Key::PageUp)),
-                    _ => Ok(key!(Key::Unknown)),
-                },
-                '6' => match try!(self.next_char()) {
-                    '~' => Ok(key!(Key::PageDown)),
-                    _ => Ok(key!(Key::Unknown)),
-                },
-                '7' => match try!(self.next_char()) {
-                    '~' => Ok(key!(Key::Home)),
-                    _ => Ok(key!(Key::Unknown)),
-                },
-                '8' => match try!(self.next_char()) {
-                    '~' => Ok(key!(Key::End)),
-                    _ => Ok(key!(Key::Unknown)),
-                },
-                'A' => Ok(key!(Key::Up)),
-                'B' => Ok(key!(Key::Down)),
-                'C' => Ok(key!(Key::Right)),
-                'D' => Ok(key!(Key::Left)),
-                'F' => Ok(key!(Key::End)),
-                'H' => Ok(key!(Key::Home)),
-                _ => Ok(key!(Key::Unknown)),
-            },
-            'O' => match try!(self.next_char()) {
-                'A' => Ok(key!(Key::Up)),
-                'B' => Ok(key!(Key::Down)),
-                'C' => Ok(key!(Key::Right)),
-                'D' => Ok(key!(Key::Left)),
-                'F' => Ok(key!(Key::End)),
-                'H' => Ok(key!(Key::Home)),
-                _ => Ok(key!(Key::Unknown)),
-            },
-            '\x08' => Ok(alt!('\x08') ), // Backspace
-            '<' => Ok(alt!('<') ),
-            '>' => Ok(alt!('>') ),
-            'b' | 'B' => Ok(alt!('B') ),
-            'c' | 'C' => Ok(alt!('C') ),
-            'd' | 'D' => Ok(alt!('D') ),
-            'f' | 'F' => Ok(alt!('F') ),
-            'l' | 'L' => Ok(alt!('L') ),
-            't' | 'T' => Ok(alt!('T') ),
-            'u' | 'U' => Ok(alt!('U') ),
-            'y' | 'Y' => Ok(alt!('Y') ),
-            '\x7f' => Ok(alt!('\x7f') ), // Delete
-            _ => {
-                // writeln!(io::stderr(), "key: {:?}, seq1: {:?}", key!(Key::Esc,) seq1).unwrap();
-                Ok(key!(Key::Unknown))
-            }
+    fn new(fd: libc::c_int,
+           preprocessor: SharedPreprocessor,
+           pending: PendingInput,
+           key_table: SharedKeyTable)
+           -> Result<PosixRawReader> {
+        let input = Preprocessed::new(StdinRaw { fd: fd }, preprocessor, pending);
+        Ok(PosixRawReader {
+            chars: char_iter::chars(input),
+            seq: String::new(),
+            key_table: key_table,
+            replay: VecDeque::new(),
+        })
+    }
+
+    /// Match the next characters against the sequences registered by the application
+    /// (the longest one wins). The characters which are not part of it are read again.
+    fn custom_sequence(&mut self) -> Result<Option<KeyPress>> {
+        let table = self.key_table.clone();
+        let (key, rest) = try!(decode::custom_sequence(self, &table.borrow()));
+        let len = self.seq.len() - rest.len();
+        self.seq.truncate(len);
+        for c in rest.chars().rev() {
+            self.replay.push_front(c);
         }
+        Ok(key)
+    }
+}
+
+impl decode::CharSource for PosixRawReader {
+    fn next_char(&mut self) -> Result<char> {
+        RawReader::next_char(self)
+    }
+
+    fn poll(&mut self) -> Result<bool> {
+        RawReader::poll(self, 0)
+    }
+}
+
+impl Drop for PosixRawReader {
+    fn drop(&mut self) {
+        // the chars read ahead are kept for the next line
+        let replay: String = self.replay.drain(..).collect();
+        self.chars.get_ref().unread(replay.as_bytes());
     }
 }
 
 impl RawReader for PosixRawReader {
     fn next_key(&mut self, timeout_ms: i32) -> Result<KeyPress> {
+        self.seq.clear();
         let c = try!(self.next_char());
+        #[cfg(feature = "perf")]
+        let start = Instant::now();
 
         let mut key = consts::char_to_key_press(c);
-        if key == key!(Key::Esc) {
-            let mut fds =
-                [poll::PollFd::new(STDIN_FILENO, poll::POLLIN, poll::EventFlags::empty())];
-            match poll::poll(&mut fds, timeout_ms) {
-                Ok(n) if n == 0 => {
-                    // single escape
-                }
-                Ok(_) => {
-                    // escape sequence
-                    key = try!(self.escape_sequence())
-                }
-                // Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                Err(e) => return Err(e.into()),
-            }
+        // a single escape when nothing follows it in time
+        if key == key!(Key::Esc) && try!(self.poll(timeout_ms)) {
+            // escape sequence
+            key = match try!(self.custom_sequence()) {
+                Some(key) => key,
+                None => try!(decode::escape_sequence(self)),
+            };
         }
+        #[cfg(feature = "perf")]
+        ::perf::decoded(start.elapsed());
         Ok(key)
     }
 
     fn next_char(&mut self) -> Result<char> {
-        match self.chars.next() {
-            Some(c) => Ok(try!(c)),
-            None => Err(error::ReadlineError::Eof),
+        let c = match self.replay.pop_front() {
+            Some(c) => c,
+            None => {
+                match self.chars.next() {
+                    Some(c) => try!(c),
+                    None => return Err(error::ReadlineError::Eof),
+                }
+            }
+        };
+        self.seq.push(c);
+        Ok(c)
+    }
+
+    fn last_sequence(&self) -> &str {
+        &self.seq
+    }
+
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool> {
+        let input = self.chars.get_ref();
+        if !self.replay.is_empty() || input.has_pending() {
+            return Ok(true);
         }
+        sys::poll(input.get_ref().fd, timeout_ms)
     }
 }
 
 
 static SIGWINCH_ONCE: sync::Once = sync::ONCE_INIT;
 static SIGWINCH: atomic::AtomicBool = atomic::ATOMIC_BOOL_INIT;
+static SIGCONT: atomic::AtomicBool = atomic::ATOMIC_BOOL_INIT;
 
 fn install_sigwinch_handler() {
     SIGWINCH_ONCE.call_once(|| unsafe {
-        let sigwinch = signal::SigAction::new(signal::SigHandler::Handler(sigwinch_handler),
-                                              signal::SaFlags::empty(),
-                                              signal::SigSet::empty());
-        let _ = signal::sigaction(signal::SIGWINCH, &sigwinch);
+        sys::set_handler(libc::SIGWINCH, sigwinch_handler);
+        sys::set_handler(libc::SIGCONT, sigcont_handler);
     });
 }
 
@@ -239,27 +364,151 @@ extern "C" fn sigwinch_handler(_: libc::c_int) {
     SIGWINCH.store(true, atomic::Ordering::SeqCst);
 }
 
+/// Enable RAW mode again when the process is continued in the foreground,
+/// the shell having restored the mode it saved (or the cooked one).
+extern "C" fn sigcont_handler(_: libc::c_int) {
+    if RAW_MODE.load(atomic::Ordering::SeqCst) {
+        if let Some((termios, fd)) = unsafe { ORIGINAL_MODE } {
+            let _ = sys::tcsetattr(fd, &sys::make_raw(&termios));
+        }
+    }
+    SIGCONT.store(true, atomic::Ordering::SeqCst);
+}
+
+/// Time to wait for the report of the cursor position.
+const CURSOR_REPORT_TIMEOUT_MS: i32 = 100;
+
+
 pub type Terminal = PosixTerminal;
 
 #[derive(Clone,Debug)]
 pub struct PosixTerminal {
     unsupported: bool,
     stdin_isatty: bool,
+    stream: OutputStreamType,
+    out_fd: libc::c_int,
+    out_isatty: bool,
+    /// `/dev/tty` is used when stdin or the output stream is redirected (see `Config::dev_tty`).
+    dev_tty: bool,
+    /// Controlling terminal opened for the input and the rendering.
+    tty: Option<Arc<File>>,
+    /// Error of the opening of `/dev/tty` (reported when the raw mode is enabled).
+    tty_errno: Option<i32>,
+    preprocessor: SharedPreprocessor,
+    key_table: SharedKeyTable,
+    pending: PendingInput,
+    prefer_env_size: bool,
+    /// The alternate screen buffer has been entered by this terminal.
+    alternate_screen: bool,
+    /// Size queried by the last `update_size` (the same for all the rendering until a SIGWINCH).
+    size: SizeCache,
+}
+
+/// Descriptor of the output stream.
+fn stream_fd(stream: OutputStreamType) -> libc::c_int {
+    match stream {
+        OutputStreamType::Stdout => STDOUT_FILENO,
+        OutputStreamType::Stderr => STDERR_FILENO,
+    }
+}
+
+impl PosixTerminal {
+    pub fn new(stream: OutputStreamType) -> PosixTerminal {
+        let out_fd = stream_fd(stream);
+        PosixTerminal {
+            unsupported: false, // decided by the editor
+            stdin_isatty: is_a_tty(STDIN_FILENO),
+            stream: stream,
+            out_fd: out_fd,
+            out_isatty: is_a_tty(out_fd),
+            dev_tty: false,
+            tty: None,
+            tty_errno: None,
+            preprocessor: SharedPreprocessor::default(),
+            key_table: SharedKeyTable::default(),
+            pending: PendingInput::default(),
+            prefer_env_size: false,
+            alternate_screen: false,
+            size: SizeCache::default(),
+        }
+    }
+
+    /// Render to `stream`.
+    fn set_output_stream(&mut self, stream: OutputStreamType) {
+        self.stream = stream;
+        self.out_fd = stream_fd(stream);
+        self.out_isatty = is_a_tty(self.out_fd);
+    }
+
+    /// Open `/dev/tty` when stdin or the output stream is redirected.
+    fn set_dev_tty(&mut self, yes: bool) {
+        self.dev_tty = yes;
+        self.tty = None;
+        self.tty_errno = None;
+        self.size.invalidate();
+        if !yes || (self.stdin_isatty && self.out_isatty) {
+            return;
+        }
+        match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+            Ok(tty) => self.tty = Some(Arc::new(tty)),
+            Err(e) => self.tty_errno = Some(e.raw_os_error().unwrap_or(libc::ENXIO)),
+        }
+    }
+
+    /// Transform the bytes read before the keys are decoded.
+    pub fn set_input_preprocessor(&mut self, preprocessor: SharedPreprocessor) {
+        self.preprocessor = preprocessor;
+    }
+
+    /// Decode the escape sequences of `table` too.
+    pub fn set_key_table(&mut self, table: SharedKeyTable) {
+        self.key_table = table;
+    }
+
+    /// Install the SIGWINCH handler once the terminal is known to be supported
+    /// (`set_dev_tty` is called first).
+    fn watch_size(&self) {
+        if !self.unsupported && ((self.stdin_isatty && self.out_isatty) || self.tty.is_some()) {
+            install_sigwinch_handler();
+        }
+    }
+
+    fn in_fd(&self) -> libc::c_int {
+        match self.tty {
+            Some(ref tty) => tty.as_raw_fd(),
+            None => STDIN_FILENO,
+        }
+    }
+
+    fn out_fd(&self) -> libc::c_int {
+        match self.tty {
+            Some(ref tty) => tty.as_raw_fd(),
+            None => self.out_fd,
+        }
+    }
+
+    fn win_size(&self) -> (usize, usize) {
+        self.size.get(|| get_win_size(self.out_fd(), self.prefer_env_size))
+    }
 }
 
 impl Term for PosixTerminal {
     type Reader = PosixRawReader;
     type Mode = Mode;
 
-    fn new() -> PosixTerminal {
-        let term = PosixTerminal {
-            unsupported: is_unsupported_term(),
-            stdin_isatty: is_a_tty(STDIN_FILENO),
-        };
-        if !term.unsupported && term.stdin_isatty && is_a_tty(STDOUT_FILENO) {
-            install_sigwinch_handler();
+    /// `/dev/tty` is opened again when the output stream or `Config::dev_tty` changes.
+    fn apply_config(&mut self, config: &Config) {
+        if config.output_stream() != self.stream || config.dev_tty() != self.dev_tty {
+            self.set_output_stream(config.output_stream());
+            self.set_dev_tty(config.dev_tty());
         }
-        term
+        self.prefer_env_size = config.prefer_env_size();
+        self.size.invalidate();
+    }
+
+    fn set_unsupported(&mut self, unsupported: bool) {
+        self.unsupported = unsupported;
+        self.watch_size();
     }
 
     // Init checks:
@@ -269,58 +518,123 @@ impl Term for PosixTerminal {
         self.unsupported
     }
 
-    /// check if stdin is connected to a terminal.
+    /// check if stdin (or `/dev/tty` when requested) is connected to a terminal.
     fn is_stdin_tty(&self) -> bool {
-        self.stdin_isatty
+        self.stdin_isatty || self.tty.is_some() || self.tty_errno.is_some()
+    }
+
+    /// check if the output stream is connected to a terminal supporting ANSI colors.
+    fn colors_enabled(&self) -> bool {
+        !self.unsupported && (self.out_isatty || self.tty.is_some())
+    }
+
+    /// Guess the number of colors from `TERM` and `COLORTERM`.
+    fn color_depth(&self) -> ColorDepth {
+        let term = std::env::var("TERM").ok();
+        let colorterm = std::env::var("COLORTERM").ok();
+        ColorDepth::from_env(term.as_ref().map(|s| s.as_str()),
+                             colorterm.as_ref().map(|s| s.as_str()))
     }
 
     // Interactive loop:
 
     /// Try to get the number of columns in the current terminal,
-    /// or assume 80 if it fails.
+    /// or use `COLUMNS` or assume 80 if it fails.
     fn get_columns(&self) -> usize {
-        let (cols, _) = get_win_size();
-        cols
+        self.win_size().0
     }
 
     /// Try to get the number of rows in the current terminal,
-    /// or assume 24 if it fails.
+    /// or use `LINES` or assume 24 if it fails.
     fn get_rows(&self) -> usize {
-        let (_, rows) = get_win_size();
-        rows
+        self.win_size().1
+    }
+
+    /// TIOCGWINSZ
+    fn update_size(&self) {
+        self.size.update(|| get_win_size(self.out_fd(), self.prefer_env_size));
+    }
+
+    /// SIGTSTP
+    fn can_suspend(&self) -> bool {
+        true
     }
 
     fn enable_raw_mode(&self) -> Result<Mode> {
-        use nix::errno::Errno::ENOTTY;
-        use nix::sys::termios::{BRKINT, CS8, ECHO, ICANON, ICRNL, IEXTEN, INPCK, ISIG, ISTRIP,
-                                IXON, /* OPOST, */ VMIN, VTIME};
-        if !self.stdin_isatty {
-            try!(Err(nix::Error::from_errno(ENOTTY)));
+        if let Some(errno) = self.tty_errno {
+            let e = io::Error::from_raw_os_error(errno);
+            return Err(io::Error::new(e.kind(), format!("cannot open /dev/tty: {}", e)).into());
+        }
+        if !self.is_stdin_tty() {
+            return Err(sys::not_a_tty());
         }
-        let original_mode = try!(termios::tcgetattr(STDIN_FILENO));
-        let mut raw = original_mode;
-        // disable BREAK interrupt, CR to NL conversion on input,
-        // input parity check, strip high bit (bit 8), output flow control
-        raw.c_iflag = raw.c_iflag & !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
-        // we don't want raw output, it turns newlines into straight linefeeds
-        // raw.c_oflag = raw.c_oflag & !(OPOST); // disable all output processing
-        raw.c_cflag = raw.c_cflag | (CS8); // character-size mark (8 bits)
-        // disable echoing, canonical mode, extended input processing and signals
-        raw.c_lflag = raw.c_lflag & !(ECHO | ICANON | IEXTEN | ISIG);
-        raw.c_cc[VMIN] = 1; // One character-at-a-time input
-        raw.c_cc[VTIME] = 0; // with blocking read
-        try!(termios::tcsetattr(STDIN_FILENO, termios::TCSADRAIN, &raw));
-        Ok(original_mode)
+        let fd = self.in_fd();
+        let termios = try!(sys::tcgetattr(fd));
+        let raw = sys::make_raw(&termios);
+        unsafe {
+            ORIGINAL_MODE = Some((termios, fd));
+        }
+        try!(sys::tcsetattr(fd, &raw));
+        RAW_MODE.store(true, atomic::Ordering::SeqCst);
+        Ok(Mode {
+            termios: termios,
+            fd: fd,
+        })
     }
 
     /// Create a RAW reader
     fn create_reader(&self) -> Result<PosixRawReader> {
-        PosixRawReader::new()
+        PosixRawReader::new(self.in_fd(),
+                            self.preprocessor.clone(),
+                            self.pending.clone(),
+                            self.key_table.clone())
+    }
+
+    /// Write to `/dev/tty` when it is used.
+    fn create_writer(&self) -> Box<Write> {
+        Box::new(match self.tty {
+            Some(ref tty) => OutputWriter::Tty(tty.clone()),
+            None => OutputWriter::Stream(self.stream),
+        })
     }
 
     /// Check if a SIGWINCH signal has been received
     fn sigwinch(&self) -> bool {
-        SIGWINCH.compare_and_swap(true, false, atomic::Ordering::SeqCst)
+        self.size.sigwinch(&SIGWINCH, || get_win_size(self.out_fd(), self.prefer_env_size))
+    }
+
+    fn continued(&self) -> bool {
+        SIGCONT.swap(false, atomic::Ordering::SeqCst)
+    }
+
+    /// DSR: the keys typed before the report are kept for the reader.
+    fn cursor_column(&self, w: &mut Write) -> Option<usize> {
+        if self.preprocessor.0.is_some() ||
+           !(self.out_isatty || self.tty.is_some()) ||
+           !RAW_MODE.load(atomic::Ordering::SeqCst) {
+            return None;
+        }
+        if w.write_all(b"\x1b[6n").and_then(|_| w.flush()).is_err() {
+            return None;
+        }
+        let fd = self.in_fd();
+        let mut input = Vec::new();
+        let mut column = None;
+        while let Ok(true) = sys::poll(fd, CURSOR_REPORT_TIMEOUT_MS) {
+            let mut byte = 0u8;
+            let n = unsafe { libc::read(fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+            if n != 1 {
+                break;
+            }
+            input.push(byte);
+            if let Some((col, start)) = cursor_report(&input) {
+                input.truncate(start);
+                column = Some(col);
+                break;
+            }
+        }
+        self.pending.borrow_mut().extend(input);
+        column
     }
 
     /// Clear the screen. Used to handle ctrl+l
@@ -329,24 +643,62 @@ impl Term for PosixTerminal {
         try!(w.flush());
         Ok(())
     }
+
+    /// smcup
+    fn enter_alternate_screen(&mut self, w: &mut Write) -> Result<()> {
+        if self.alternate_screen {
+            return Ok(());
+        }
+        self.alternate_screen = true;
+        if ALTERNATE_SCREEN.fetch_add(1, atomic::Ordering::SeqCst) > 0 {
+            // already entered by another editor
+            return Ok(());
+        }
+        ALTERNATE_SCREEN_FD.store(self.out_fd() as isize, atomic::Ordering::SeqCst);
+        try!(w.write_all(ENTER_ALTERNATE_SCREEN));
+        try!(w.flush());
+        Ok(())
+    }
+
+    /// rmcup
+    fn leave_alternate_screen(&mut self, w: &mut Write) -> Result<()> {
+        if !self.alternate_screen {
+            return Ok(());
+        }
+        self.alternate_screen = false;
+        // the main screen buffer is restored when the last editor leaves
+        if release_alternate_screen() {
+            try!(w.write_all(LEAVE_ALTERNATE_SCREEN));
+            try!(w.flush());
+        }
+        Ok(())
+    }
+
+    /// DECSCUSR
+    fn set_cursor_shape(&self, w: &mut Write, shape: CursorShape) -> Result<()> {
+        if self.out_isatty || self.tty.is_some() {
+            try!(write!(w, "\x1b[{} q", shape as u8));
+            try!(w.flush());
+        }
+        Ok(())
+    }
 }
 
 #[cfg(unix)]
 pub fn suspend() -> Result<()> {
-    // For macos:
-    try!(signal::kill(nix::unistd::getppid(), signal::SIGTSTP));
-    try!(signal::kill(nix::unistd::getpid(), signal::SIGTSTP));
-    Ok(())
+    sys::suspend()
 }
 
 #[cfg(all(unix,test))]
 mod test {
     #[test]
     fn test_unsupported_term() {
+        let unsupported = vec![String::from("dumb")];
         ::std::env::set_var("TERM", "xterm");
-        assert_eq!(false, super::is_unsupported_term());
+        assert_eq!(false, super::is_unsupported_term(&unsupported));
 
         ::std::env::set_var("TERM", "dumb");
-        assert_eq!(true, super::is_unsupported_term());
+        assert_eq!(true, super::is_unsupported_term(&unsupported));
+        assert_eq!(false, super::is_unsupported_term(&[]));
     }
 }
@@ -7,13 +7,16 @@ use std::sync::atomic;
 use kernel32;
 use winapi;
 
+use config::{Config, CursorShape, OutputStreamType};
 use consts::{self, Key, KeyPress};
+use highlight::ColorDepth;
 use ::error;
 use ::Result;
-use super::{RawMode, RawReader, Term};
+use super::{OutputWriter, RawMode, RawReader, SharedKeyTable, SharedPreprocessor, Term};
 
 const STDIN_FILENO: winapi::DWORD = winapi::STD_INPUT_HANDLE;
 const STDOUT_FILENO: winapi::DWORD = winapi::STD_OUTPUT_HANDLE;
+const STDERR_FILENO: winapi::DWORD = winapi::STD_ERROR_HANDLE;
 
 fn get_std_handle(fd: winapi::DWORD) -> Result<winapi::HANDLE> {
     let handle = unsafe { kernel32::GetStdHandle(fd) };
@@ -39,12 +42,15 @@ macro_rules! check {
     };
 }
 
-fn get_win_size(handle: winapi::HANDLE) -> (usize, usize) {
+fn get_win_size(handle: winapi::HANDLE, prefer_env: bool) -> (usize, usize) {
     let mut info = unsafe { mem::zeroed() };
-    match unsafe { kernel32::GetConsoleScreenBufferInfo(handle, &mut info) } {
-        0 => (80, 24),
-        _ => (info.dwSize.X as usize, (1 + info.srWindow.Bottom - info.srWindow.Top) as usize),
-    }
+    let queried = match unsafe { kernel32::GetConsoleScreenBufferInfo(handle, &mut info) } {
+        0 => None,
+        _ => {
+            Some((info.dwSize.X as usize, (1 + info.srWindow.Bottom - info.srWindow.Top) as usize))
+        }
+    };
+    super::win_size(queried, super::env_win_size(), prefer_env)
 }
 
 fn get_console_mode(handle: winapi::HANDLE) -> Result<winapi::DWORD> {
@@ -136,6 +142,8 @@ impl RawReader for ConsoleRawReader {
                     winapi::VK_NEXT => return Ok(key!(Key::PageDown)),
                     _ => continue,
                 };
+            } else if utf16 == 27 && esc_seen {
+                return Ok(alt!(Key::Esc));
             } else if utf16 == 27 {
                 esc_seen = true;
                 continue;
@@ -148,23 +156,26 @@ impl RawReader for ConsoleRawReader {
                 }
                 let c = try!(orc.unwrap());
                 if meta {
-                    match c {
-                        'b' | 'B' => return Ok(alt!(Key::Char('B')) ),
-                        'c' | 'C' => return Ok(alt!(Key::Char('C')) ),
-                        'd' | 'D' => return Ok(alt!(Key::Char('D')) ),
-                        'f' | 'F' => return Ok(alt!(Key::Char('F')) ),
-                        'l' | 'L' => return Ok(alt!(Key::Char('L')) ),
-                        't' | 'T' => return Ok(alt!(Key::Char('T')) ),
-                        'u' | 'U' => return Ok(alt!(Key::Char('U')) ),
-                        'y' | 'Y' => return Ok(alt!(Key::Char('Y')) ),
-                        _ => return Ok(key!(Key::Unknown)),
-                    }
+                    // Meta followed by any char (in its case) or Ctrl key
+                    let mut key = consts::char_to_key_press(c);
+                    key.alt = true;
+                    return Ok(key);
                 } else {
                     return Ok(consts::char_to_key_press(c));
                 }
             }
         }
     }
+    fn poll(&mut self, timeout_ms: i32) -> Result<bool> {
+        // any input event (not only a key) is reported
+        let timeout = if timeout_ms < 0 {
+            winapi::INFINITE
+        } else {
+            timeout_ms as winapi::DWORD
+        };
+        let res = unsafe { kernel32::WaitForSingleObject(self.handle, timeout) };
+        Ok(res == winapi::WAIT_OBJECT_0)
+    }
 }
 
 impl Iterator for ConsoleRawReader {
@@ -177,6 +188,39 @@ impl Iterator for ConsoleRawReader {
     }
 }
 
+/// Console mode is restored by Windows when the process exits.
+pub fn install_restore_handlers() {}
+
+// the terminal is not handed over by `suspend_raw` (unix only)
+pub fn take_suspended() -> bool {
+    false
+}
+
+/// Run `f` with the echo of the console input disabled.
+pub fn without_echo<R, F: FnOnce() -> R>(f: F) -> R {
+    let handle = match get_std_handle(STDIN_FILENO) {
+        Ok(handle) => handle,
+        Err(_) => return f(),
+    };
+    let original = match get_console_mode(handle) {
+        Ok(mode) => mode,
+        Err(_) => return f(),
+    };
+    unsafe {
+        kernel32::SetConsoleMode(handle, original & !winapi::wincon::ENABLE_ECHO_INPUT);
+    }
+    let result = f();
+    unsafe {
+        kernel32::SetConsoleMode(handle, original);
+    }
+    result
+}
+
+/// Windows consoles do not use `TERM`.
+pub fn is_unsupported_term(_: &[String]) -> bool {
+    false
+}
+
 static SIGWINCH: atomic::AtomicBool = atomic::ATOMIC_BOOL_INIT;
 
 pub type Terminal = Console;
@@ -185,10 +229,45 @@ pub type Terminal = Console;
 pub struct Console {
     stdin_isatty: bool,
     stdin_handle: winapi::HANDLE,
+    stream: OutputStreamType,
+    /// Handle of the output stream (stdout or stderr).
     stdout_handle: winapi::HANDLE,
+    prefer_env_size: bool,
+    unsupported: bool,
 }
 
 impl Console {
+    pub fn new(stream: OutputStreamType) -> Console {
+        use std::ptr;
+        let stdin_handle = get_std_handle(STDIN_FILENO);
+        let stdin_isatty = match stdin_handle {
+            Ok(handle) => {
+                // If this function doesn't fail then fd is a TTY
+                get_console_mode(handle).is_ok()
+            }
+            Err(_) => false,
+        };
+
+        let out_fd = match stream {
+            OutputStreamType::Stdout => STDOUT_FILENO,
+            OutputStreamType::Stderr => STDERR_FILENO,
+        };
+        let stdout_handle = get_std_handle(out_fd).unwrap_or(ptr::null_mut());
+        Console {
+            stdin_isatty: stdin_isatty,
+            stdin_handle: stdin_handle.unwrap_or(ptr::null_mut()),
+            stream: stream,
+            stdout_handle: stdout_handle,
+            prefer_env_size: false,
+            unsupported: false,
+        }
+    }
+
+    pub fn set_input_preprocessor(&mut self, _: SharedPreprocessor) {}
+
+    // the console keys are not decoded from escape sequences
+    pub fn set_key_table(&mut self, _: SharedKeyTable) {}
+
     pub fn get_console_screen_buffer_info(&self) -> Result<winapi::CONSOLE_SCREEN_BUFFER_INFO> {
         let mut info = unsafe { mem::zeroed() };
         check!(kernel32::GetConsoleScreenBufferInfo(self.stdout_handle, &mut info));
@@ -218,49 +297,54 @@ impl Term for Console {
     type Reader = ConsoleRawReader;
     type Mode = Mode;
 
-    fn new() -> Console {
-        use std::ptr;
-        let stdin_handle = get_std_handle(STDIN_FILENO);
-        let stdin_isatty = match stdin_handle {
-            Ok(handle) => {
-                // If this function doesn't fail then fd is a TTY
-                get_console_mode(handle).is_ok()
-            }
-            Err(_) => false,
-        };
-
-        let stdout_handle = get_std_handle(STDOUT_FILENO).unwrap_or(ptr::null_mut());
-        Console {
-            stdin_isatty: stdin_isatty,
-            stdin_handle: stdin_handle.unwrap_or(ptr::null_mut()),
-            stdout_handle: stdout_handle,
+    /// `Config::dev_tty` is not supported: the console is always used.
+    fn apply_config(&mut self, config: &Config) {
+        if config.output_stream() != self.stream {
+            let unsupported = self.unsupported;
+            *self = Console::new(config.output_stream());
+            self.unsupported = unsupported;
         }
+        self.prefer_env_size = config.prefer_env_size();
+    }
+
+    fn set_unsupported(&mut self, unsupported: bool) {
+        self.unsupported = unsupported;
     }
 
     /// Checking for an unsupported TERM in windows is a no-op
+    /// (only the editor can force the fallback mode)
     fn is_unsupported(&self) -> bool {
-        false
+        self.unsupported
     }
 
     fn is_stdin_tty(&self) -> bool {
         self.stdin_isatty
     }
 
+    /// The console does not interpret ANSI escape codes.
+    fn colors_enabled(&self) -> bool {
+        false
+    }
+
+    fn color_depth(&self) -> ColorDepth {
+        ColorDepth::Ansi16
+    }
+
     // pub fn install_sigwinch_handler(&mut self) {
     // See ReadConsoleInputW && WINDOW_BUFFER_SIZE_EVENT
     // }
 
     /// Try to get the number of columns in the current terminal,
-    /// or assume 80 if it fails.
+    /// or use `COLUMNS` or assume 80 if it fails.
     fn get_columns(&self) -> usize {
-        let (cols, _) = get_win_size(self.stdout_handle);
+        let (cols, _) = get_win_size(self.stdout_handle, self.prefer_env_size);
         cols
     }
 
     /// Try to get the number of rows in the current terminal,
-    /// or assume 24 if it fails.
+    /// or use `LINES` or assume 24 if it fails.
     fn get_rows(&self) -> usize {
-        let (_, rows) = get_win_size(self.stdout_handle);
+        let (_, rows) = get_win_size(self.stdout_handle, self.prefer_env_size);
         rows
     }
 
@@ -292,10 +376,22 @@ impl Term for Console {
         ConsoleRawReader::new()
     }
 
+    fn create_writer(&self) -> Box<Write> {
+        Box::new(OutputWriter::Stream(self.stream))
+    }
+
     fn sigwinch(&self) -> bool {
         SIGWINCH.compare_and_swap(true, false, atomic::Ordering::SeqCst)
     }
 
+    fn continued(&self) -> bool {
+        false
+    }
+
+    fn cursor_column(&self, _: &mut Write) -> Option<usize> {
+        self.get_console_screen_buffer_info().ok().map(|info| info.dwCursorPosition.X as usize)
+    }
+
     /// Clear the screen. Used to handle ctrl+l
     fn clear_screen(&mut self, _: &mut Write) -> Result<()> {
         let info = try!(self.get_console_screen_buffer_info());
@@ -310,4 +406,31 @@ impl Term for Console {
                                                      &mut _count));
         Ok(())
     }
+
+    // never used (see `Config::alternate_screen`)
+    fn enter_alternate_screen(&mut self, _: &mut Write) -> Result<()> {
+        Ok(())
+    }
+
+    fn leave_alternate_screen(&mut self, _: &mut Write) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_cursor_shape(&self, _: &mut Write, shape: CursorShape) -> Result<()> {
+        let size = match shape {
+            CursorShape::BlinkingBlock |
+            CursorShape::Block => 100,
+            _ => 25, // console default
+        };
+        let info = winapi::CONSOLE_CURSOR_INFO {
+            dwSize: size,
+            bVisible: winapi::TRUE,
+        };
+        check!(kernel32::SetConsoleCursorInfo(self.stdout_handle, &info));
+        Ok(())
+    }
+
+    fn console(&self) -> Option<&Console> {
+        Some(self)
+    }
 }